@@ -0,0 +1,46 @@
+-- 引入版本化迁移之前的数据库（版本 0）：只有三张表，没有 schema_version
+CREATE TABLE IF NOT EXISTS notes (
+    id INTEGER PRIMARY KEY,
+    content TEXT NOT NULL,
+    screenshot_path TEXT,
+    created_at INTEGER NOT NULL,
+    updated_at INTEGER,
+    is_pinned BOOLEAN NOT NULL DEFAULT 0,
+    color TEXT,
+    category_id INTEGER,
+    deleted_at INTEGER,
+    FOREIGN KEY (category_id) REFERENCES categories (id)
+);
+
+CREATE TABLE IF NOT EXISTS note_reminders (
+    id INTEGER PRIMARY KEY,
+    note_id INTEGER NOT NULL,
+    reminder_time INTEGER NOT NULL,
+    completed BOOLEAN NOT NULL DEFAULT 0,
+    FOREIGN KEY (note_id) REFERENCES notes (id)
+);
+
+CREATE TABLE IF NOT EXISTS categories (
+    id INTEGER PRIMARY KEY,
+    name TEXT NOT NULL,
+    color TEXT
+);
+
+CREATE INDEX IF NOT EXISTS idx_notes_created_at ON notes (created_at);
+CREATE INDEX IF NOT EXISTS idx_notes_is_pinned ON notes (is_pinned);
+CREATE INDEX IF NOT EXISTS idx_notes_category_id ON notes (category_id);
+CREATE INDEX IF NOT EXISTS idx_notes_deleted_at ON notes (deleted_at);
+CREATE INDEX IF NOT EXISTS idx_reminders_time ON note_reminders (reminder_time);
+CREATE INDEX IF NOT EXISTS idx_reminders_note_id ON note_reminders (note_id);
+
+INSERT INTO categories (id, name, color) VALUES (1, '工作', '#BBDEFB');
+INSERT INTO categories (id, name, color) VALUES (2, '生活', NULL);
+
+INSERT INTO notes (id, content, screenshot_path, created_at, updated_at, is_pinned, color, category_id, deleted_at)
+VALUES (1, '购物清单 #生活' || char(10) || '牛奶、面包，见 [[周计划]]', NULL, 1700000000, 1700000100, 1, '#FFF9C4', 2, NULL);
+INSERT INTO notes (id, content, screenshot_path, created_at, updated_at, is_pinned, color, category_id, deleted_at)
+VALUES (2, '周计划' || char(10) || '周一开会 #工作 #会议', NULL, 1700000200, NULL, 0, NULL, 1, NULL);
+INSERT INTO notes (id, content, screenshot_path, created_at, updated_at, is_pinned, color, category_id, deleted_at)
+VALUES (3, '已删除的便签', NULL, 1700000300, NULL, 0, NULL, NULL, 1700000400);
+
+INSERT INTO note_reminders (id, note_id, reminder_time, completed) VALUES (1, 2, 1700086400, 0);
@@ -0,0 +1,157 @@
+use sha2::{Digest, Sha256};
+use std::fs;
+use std::io::{Error as IoError, ErrorKind};
+use std::path::{Path, PathBuf};
+
+// 附件按内容哈希存放在数据目录下：stickynotes/attachments/ab/abcdef....png
+const ATTACHMENTS_DIR: &str = "attachments";
+
+// 获取附件存储目录
+pub fn attachments_dir() -> Result<PathBuf, IoError> {
+    let mut path = match dirs::data_dir() {
+        Some(data_dir) => data_dir,
+        None => return Err(IoError::new(ErrorKind::NotFound, "无法找到数据目录")),
+    };
+    path.push("stickynotes");
+    path.push(ATTACHMENTS_DIR);
+
+    // 确保目录存在
+    if !path.exists() {
+        fs::create_dir_all(&path)?;
+    }
+
+    Ok(path)
+}
+
+// 计算内容的 SHA-256 哈希（十六进制）
+pub fn hash_bytes(bytes: &[u8]) -> String {
+    format!("{:x}", Sha256::digest(bytes))
+}
+
+// 根据哈希和扩展名得到文件路径，按哈希前两位分目录避免单目录文件过多
+pub fn blob_path(hash: &str, extension: &str) -> Result<PathBuf, IoError> {
+    let mut path = attachments_dir()?;
+    path.push(&hash[..2]);
+    path.push(format!("{}.{}", hash, extension));
+    Ok(path)
+}
+
+// 从附件文件路径中取出内容哈希，路径不在附件存储目录中时返回 None
+pub fn hash_from_blob_path(path: &Path) -> Option<String> {
+    if !path.starts_with(attachments_dir().ok()?) {
+        return None;
+    }
+    let hash = path.file_stem()?.to_str()?;
+    if hash.len() == 64 && hash.chars().all(|c| c.is_ascii_hexdigit()) {
+        Some(hash.to_string())
+    } else {
+        None
+    }
+}
+
+// 便签内容中引用附件使用的 URL，与前端 convertFileSrc 生成的 asset: 地址一致
+// （路径按 encodeURIComponent 的规则编码）
+pub fn asset_url(path: &Path) -> String {
+    let mut url = String::from("asset://localhost/");
+    for byte in path.to_string_lossy().bytes() {
+        if byte.is_ascii_alphanumeric() || b"-_.!~*'()".contains(&byte) {
+            url.push(byte as char);
+        } else {
+            url.push_str(&format!("%{:02X}", byte));
+        }
+    }
+    url
+}
+
+// 写入附件文件，相同内容只写一次，返回哈希和文件路径
+pub fn write_blob(bytes: &[u8], extension: &str) -> Result<(String, PathBuf), IoError> {
+    let hash = hash_bytes(bytes);
+    let path = blob_path(&hash, extension)?;
+
+    if !path.exists() {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+
+        // 先写临时文件再重命名，避免中途失败留下不完整的文件
+        let tmp_path = path.with_extension(format!("{}.tmp", extension));
+        fs::write(&tmp_path, bytes)?;
+        fs::rename(&tmp_path, &path)?;
+    }
+
+    Ok((hash, path))
+}
+
+// 删除附件文件，文件不存在时忽略
+pub fn remove_blob(path: &Path) -> Result<(), IoError> {
+    match fs::remove_file(path) {
+        Ok(_) => Ok(()),
+        Err(e) if e.kind() == ErrorKind::NotFound => Ok(()),
+        Err(e) => Err(e),
+    }
+}
+
+// 列出存储目录中的所有附件文件
+pub fn list_blobs() -> Result<Vec<PathBuf>, IoError> {
+    let mut blobs = Vec::new();
+    for shard in fs::read_dir(attachments_dir()?)? {
+        let shard = shard?.path();
+        if !shard.is_dir() {
+            continue;
+        }
+        for entry in fs::read_dir(&shard)? {
+            let path = entry?.path();
+            if path.is_file() {
+                blobs.push(path);
+            }
+        }
+    }
+    Ok(blobs)
+}
+
+// 根据 MIME 类型确定扩展名
+pub fn extension_for_mime(mime: &str) -> &'static str {
+    match mime {
+        "image/png" => "png",
+        "image/jpeg" => "jpg",
+        "image/gif" => "gif",
+        "image/webp" => "webp",
+        "image/bmp" => "bmp",
+        "image/svg+xml" => "svg",
+        "application/pdf" => "pdf",
+        "text/plain" => "txt",
+        _ => "bin",
+    }
+}
+
+// 根据文件扩展名推断 MIME 类型
+pub fn mime_for_path(path: &Path) -> &'static str {
+    let extension = path.extension()
+        .map(|e| e.to_string_lossy().to_lowercase())
+        .unwrap_or_default();
+
+    match extension.as_str() {
+        "png" => "image/png",
+        "jpg" | "jpeg" => "image/jpeg",
+        "gif" => "image/gif",
+        "webp" => "image/webp",
+        "bmp" => "image/bmp",
+        "svg" => "image/svg+xml",
+        "pdf" => "application/pdf",
+        "txt" => "text/plain",
+        _ => "application/octet-stream",
+    }
+}
+
+// 解码 data URL（data:image/png;base64,...），返回 MIME 类型和内容
+pub fn decode_data_url(data: &str) -> Result<(String, Vec<u8>), IoError> {
+    let (mime, encoded) = match data.strip_prefix("data:").and_then(|rest| rest.split_once(',')) {
+        Some((header, encoded)) => (header.trim_end_matches(";base64").to_string(), encoded),
+        None => ("image/png".to_string(), data),
+    };
+
+    let bytes = base64::decode(encoded)
+        .map_err(|e| IoError::new(ErrorKind::InvalidData, format!("base64解码失败: {}", e)))?;
+
+    Ok((mime, bytes))
+}
@@ -0,0 +1,293 @@
+use std::collections::HashSet;
+use std::io::{Error as IoError, ErrorKind};
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, PoisonError};
+use std::time::Duration;
+use chrono::Datelike;
+use once_cell::sync::Lazy;
+use serde::{Deserialize, Serialize};
+use tauri::{AppHandle, Manager, Runtime};
+use crate::backup;
+use crate::db;
+use crate::lock;
+use crate::settings::{AutoBackup, BackupInterval, Settings};
+use crate::tray;
+
+// 检查是否需要自动备份的间隔（秒）
+const CHECK_INTERVAL_SECS: u64 = 10 * 60;
+
+// 同一时间只运行一次备份，定时任务和手动触发不会重叠
+static BACKUP_RUNNING: Lazy<Mutex<()>> = Lazy::new(|| Mutex::new(()));
+
+// 自动备份状态，保存在配置目录中，用于判断下次备份时间和在托盘中显示
+#[derive(Serialize, Deserialize, Clone, Default)]
+pub struct BackupStatus {
+    pub last_success: Option<i64>,      // 最近一次成功备份的时间（Unix时间戳）
+    pub last_snapshot: Option<String>,  // 最近一次成功备份的文件路径
+    pub last_error: Option<String>,     // 最近一次失败的原因，成功后清空
+}
+
+// 启动自动备份服务，按设置中的间隔在备份目录中创建快照并清理过期快照
+pub fn start_auto_backup_service<R: Runtime>(app: AppHandle<R>) {
+    std::thread::spawn(move || loop {
+        std::thread::sleep(Duration::from_secs(CHECK_INTERVAL_SECS));
+
+        let config = Settings::load().unwrap_or_default().backup;
+        // 锁定时数据库已关闭，解锁后的下一次检查再备份
+        if !config.enabled || lock::is_locked() {
+            continue;
+        }
+
+        let status = load_status().unwrap_or_default();
+        if !is_due(&status, config.interval, chrono::Utc::now().timestamp()) {
+            continue;
+        }
+        if let Err(e) = run_backup(&app, &config) {
+            log::warn!("自动备份失败: {}", e);
+        }
+    });
+}
+
+fn is_due(status: &BackupStatus, interval: BackupInterval, now: i64) -> bool {
+    let period = match interval {
+        BackupInterval::Daily => 24 * 60 * 60,
+        BackupInterval::Weekly => 7 * 24 * 60 * 60,
+    };
+    status.last_success.map_or(true, |last| now - last >= period)
+}
+
+// 创建一次快照并按保留规则清理旧快照，结果写入备份状态并更新托盘
+fn run_backup<R: Runtime>(app: &AppHandle<R>, config: &AutoBackup) -> Result<BackupStatus, IoError> {
+    let _running = match BACKUP_RUNNING.try_lock() {
+        Ok(guard) => guard,
+        Err(_) => return Err(IoError::new(ErrorKind::WouldBlock, "备份正在进行中")),
+    };
+
+    let mut status = load_status().unwrap_or_default();
+    let result = backup_dir(config).and_then(|dir| {
+        let snapshot = backup::create_snapshot(&dir)?;
+        if let Err(e) = apply_retention(&dir, config.keep_daily, config.keep_weekly) {
+            log::warn!("清理旧备份失败: {}", e);
+        }
+        Ok(snapshot)
+    });
+
+    match &result {
+        Ok(snapshot) => {
+            log::info!("自动备份完成: {}", snapshot.display());
+            status.last_success = Some(chrono::Utc::now().timestamp());
+            status.last_snapshot = Some(snapshot.display().to_string());
+            status.last_error = None;
+        }
+        Err(e) => status.last_error = Some(e.to_string()),
+    }
+    save_status(&status)?;
+
+    tray::update_last_backup(app, status.last_success);
+    let _ = app.emit_all("backup-status-changed", status.clone());
+    result.map(|_| status)
+}
+
+// 轮换数据库密钥后，用新密钥重新加密备份目录中的所有快照，失败的快照保持不变并记录警告
+pub fn rekey_snapshots(old_key: &str, new_key: &str) -> Result<(), IoError> {
+    // 等待进行中的备份完成，它可能是用旧密钥导出的
+    let _running = BACKUP_RUNNING.lock().unwrap_or_else(PoisonError::into_inner);
+
+    let dir = backup_dir(&Settings::load().unwrap_or_default().backup)?;
+    if !dir.exists() {
+        return Ok(());
+    }
+    for path in std::fs::read_dir(&dir)?.filter_map(|entry| entry.ok()).map(|entry| entry.path()) {
+        if backup::snapshot_time(&path).is_none() {
+            continue;
+        }
+        if let Err(e) = backup::rekey_snapshot(&path, old_key, new_key) {
+            log::warn!("重新加密快照 {} 失败: {}", path.display(), e);
+        }
+    }
+    Ok(())
+}
+
+// 快照保存目录，未设置时使用数据目录下的 stickynotes/backups
+fn backup_dir(config: &AutoBackup) -> Result<PathBuf, IoError> {
+    if let Some(directory) = config.directory.as_deref().filter(|d| !d.trim().is_empty()) {
+        return Ok(PathBuf::from(directory));
+    }
+
+    let mut path = match dirs::data_dir() {
+        Some(data_dir) => data_dir,
+        None => return Err(IoError::new(ErrorKind::NotFound, "无法找到数据目录")),
+    };
+    path.push("stickynotes");
+    path.push("backups");
+    Ok(path)
+}
+
+// 保留最近 keep_daily 天中每天最新的快照和最近 keep_weekly 周中每周最新的快照，
+// 最新的一份快照总是保留，目录中的其他文件不受影响
+fn apply_retention(dir: &Path, keep_daily: u32, keep_weekly: u32) -> Result<usize, IoError> {
+    let mut snapshots: Vec<(chrono::NaiveDateTime, PathBuf)> = std::fs::read_dir(dir)?
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.path())
+        .filter_map(|path| backup::snapshot_time(&path).map(|time| (time, path)))
+        .collect();
+    // 从新到旧，每天（每周）遇到的第一份就是当天（当周）最新的快照
+    snapshots.sort_by(|a, b| b.0.cmp(&a.0));
+
+    let mut days = HashSet::new();
+    let mut weeks = HashSet::new();
+    let mut removed = 0;
+    for (index, (time, path)) in snapshots.iter().enumerate() {
+        let day = time.date();
+        let week = (day.iso_week().year(), day.iso_week().week());
+
+        let mut keep = index == 0;
+        if !days.contains(&day) && days.len() < keep_daily as usize {
+            days.insert(day);
+            keep = true;
+        }
+        if !weeks.contains(&week) && weeks.len() < keep_weekly as usize {
+            weeks.insert(week);
+            keep = true;
+        }
+
+        if !keep {
+            std::fs::remove_file(path)?;
+            removed += 1;
+        }
+    }
+
+    Ok(removed)
+}
+
+// ======= 备份状态 =======
+
+fn status_path() -> Result<PathBuf, IoError> {
+    let mut path = match dirs::config_dir() {
+        Some(config_dir) => config_dir,
+        None => return Err(IoError::new(ErrorKind::NotFound, "无法找到配置目录")),
+    };
+    path.push("stickynotes");
+    path.push("backup_status.json");
+    Ok(path)
+}
+
+pub fn load_status() -> Result<BackupStatus, IoError> {
+    let path = status_path()?;
+    if !path.exists() {
+        return Ok(BackupStatus::default());
+    }
+    let content = std::fs::read_to_string(path)?;
+    serde_json::from_str(&content)
+        .map_err(|e| IoError::new(ErrorKind::InvalidData, format!("备份状态文件已损坏: {}", e)))
+}
+
+fn save_status(status: &BackupStatus) -> Result<(), IoError> {
+    let path = status_path()?;
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    let content = serde_json::to_string_pretty(status)
+        .map_err(|e| IoError::new(ErrorKind::Other, format!("序列化备份状态失败: {}", e)))?;
+    std::fs::write(path, content)
+}
+
+// ======= 命令 =======
+
+#[tauri::command]
+pub async fn get_backup_status() -> Result<BackupStatus, String> {
+    load_status().map_err(|e| e.to_string())
+}
+
+// 立即按当前设置创建一份快照，不论是否启用了自动备份
+#[tauri::command]
+pub async fn run_backup_now(app: AppHandle) -> Result<BackupStatus, String> {
+    if lock::is_locked() {
+        return Err("应用已锁定".to_string());
+    }
+
+    let config = Settings::load().map_err(|e| e.to_string())?.backup;
+    db::run_blocking(move || run_backup(&app, &config)).await
+        .map_err(|e| format!("备份失败: {}", e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // 在目录中创建指定时间的快照文件，内容不影响保留规则
+    fn snapshot(dir: &Path, time: &str) -> PathBuf {
+        let path = dir.join(format!("stickynotes-{}.zip", time));
+        std::fs::write(&path, b"snapshot").unwrap();
+        path
+    }
+
+    fn remaining(dir: &Path) -> Vec<String> {
+        let mut names: Vec<String> = std::fs::read_dir(dir).unwrap()
+            .map(|entry| entry.unwrap().file_name().to_string_lossy().into_owned())
+            .collect();
+        names.sort();
+        names
+    }
+
+    #[test]
+    fn keeps_latest_snapshot_of_each_recent_day() {
+        let dir = tempfile::tempdir().unwrap();
+        snapshot(dir.path(), "20240510-080000");
+        snapshot(dir.path(), "20240510-200000");
+        snapshot(dir.path(), "20240509-120000");
+        snapshot(dir.path(), "20240508-120000");
+        snapshot(dir.path(), "20240507-120000");
+        std::fs::write(dir.path().join("notes.txt"), b"").unwrap();
+        std::fs::write(dir.path().join("stickynotes-manual.zip"), b"").unwrap();
+
+        assert_eq!(apply_retention(dir.path(), 3, 0).unwrap(), 2);
+        assert_eq!(remaining(dir.path()), [
+            "notes.txt",
+            "stickynotes-20240508-120000.zip",
+            "stickynotes-20240509-120000.zip",
+            "stickynotes-20240510-200000.zip",
+            "stickynotes-manual.zip",
+        ]);
+    }
+
+    #[test]
+    fn keeps_latest_snapshot_of_each_recent_week() {
+        let dir = tempfile::tempdir().unwrap();
+        snapshot(dir.path(), "20240515-120000");  // 第 20 周
+        snapshot(dir.path(), "20240513-120000");
+        snapshot(dir.path(), "20240508-120000");  // 第 19 周
+        snapshot(dir.path(), "20240506-120000");
+        snapshot(dir.path(), "20240430-120000");  // 第 18 周
+
+        assert_eq!(apply_retention(dir.path(), 1, 2).unwrap(), 3);
+        assert_eq!(remaining(dir.path()), [
+            "stickynotes-20240508-120000.zip",
+            "stickynotes-20240515-120000.zip",
+        ]);
+    }
+
+    #[test]
+    fn weeks_follow_iso_week_across_year_boundary() {
+        let dir = tempfile::tempdir().unwrap();
+        snapshot(dir.path(), "20250101-120000");  // 2025 年第 1 周从 2024-12-30 开始
+        snapshot(dir.path(), "20241230-120000");
+        snapshot(dir.path(), "20241229-120000");  // 2024 年第 52 周
+
+        assert_eq!(apply_retention(dir.path(), 0, 2).unwrap(), 1);
+        assert_eq!(remaining(dir.path()), [
+            "stickynotes-20241229-120000.zip",
+            "stickynotes-20250101-120000.zip",
+        ]);
+    }
+
+    #[test]
+    fn newest_snapshot_is_always_kept() {
+        let dir = tempfile::tempdir().unwrap();
+        snapshot(dir.path(), "20240510-080000");
+        snapshot(dir.path(), "20240509-080000");
+
+        assert_eq!(apply_retention(dir.path(), 0, 0).unwrap(), 1);
+        assert_eq!(remaining(dir.path()), ["stickynotes-20240510-080000.zip"]);
+    }
+}
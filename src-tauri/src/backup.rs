@@ -0,0 +1,655 @@
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{BufReader, BufWriter, Error as IoError, ErrorKind, Read, Write};
+use std::path::{Path, PathBuf};
+use aes_gcm::aead::generic_array::GenericArray;
+use aes_gcm::aead::rand_core::RngCore;
+use aes_gcm::aead::stream::{DecryptorBE32, EncryptorBE32};
+use aes_gcm::aead::{KeyInit, OsRng, Payload};
+use aes_gcm::{Aes256Gcm, Key};
+use rusqlite::{params, Connection};
+use serde::{Deserialize, Serialize};
+use tauri::{AppHandle, Manager};
+use zip::write::FileOptions;
+use zip::{CompressionMethod, ZipArchive, ZipWriter};
+use crate::attachments;
+use crate::db::{self, Category, Note, NoteReminder, Recurrence};
+use crate::key_manager::{self, KdfParams};
+use crate::lock;
+use crate::migrations;
+
+// 备份文件格式：
+//   文件头 = 魔数(6) + 格式版本(1) + Argon2 参数(3 × u32 LE) + 盐(16) + 流加密 nonce(7)
+//   之后是 AES-256-GCM 分块加密（STREAM 构造）的 zip 包，每块都以文件头作为附加数据
+// zip 包内含 manifest.json、未加密的 notes.db 和 attachments/ 下的所有图片
+const MAGIC: &[u8; 6] = b"SNBAK\0";
+const FORMAT_VERSION: u8 = 1;
+const SALT_LENGTH: usize = 16;
+const STREAM_NONCE_LENGTH: usize = 7;
+const HEADER_LENGTH: usize = MAGIC.len() + 1 + 12 + SALT_LENGTH + STREAM_NONCE_LENGTH;
+
+// 每块明文的大小，密文块额外带 16 字节认证标签
+const CHUNK_SIZE: usize = 64 * 1024;
+const TAG_LENGTH: usize = 16;
+
+const MANIFEST_ENTRY: &str = "manifest.json";
+const DATABASE_ENTRY: &str = "notes.db";
+const ATTACHMENTS_PREFIX: &str = "attachments/";
+
+// 自动备份快照的文件名：stickynotes-<本地时间>.zip
+const SNAPSHOT_PREFIX: &str = "stickynotes-";
+const SNAPSHOT_TIME_FORMAT: &str = "%Y%m%d-%H%M%S";
+const SNAPSHOT_EXTENSION: &str = "zip";
+
+// 备份文件中的 Argon2 参数最多为当前默认值的倍数，避免恶意文件让解密耗尽内存或时间
+const MAX_KDF_FACTOR: u32 = 4;
+
+// 备份密码的最小长度
+const MIN_PASSPHRASE_CHARS: usize = 8;
+
+// 备份内容说明，恢复前展示给用户
+#[derive(Serialize, Deserialize)]
+pub struct BackupManifest {
+    pub format_version: u8,
+    pub app_version: String,
+    pub created_at: i64,
+    pub schema_version: u32,
+    pub note_count: i64,        // 未删除的便签数量
+    pub category_count: i64,
+    pub attachment_count: usize,
+    pub attachment_bytes: u64,
+}
+
+// 恢复方式
+#[derive(Deserialize, Clone, Copy)]
+#[serde(rename_all = "lowercase")]
+pub enum RestoreMode {
+    Replace,  // 用备份替换当前所有数据
+    Merge,    // 将备份中的便签添加到当前数据中，已存在的便签跳过
+}
+
+#[derive(Serialize, Default)]
+pub struct RestoreSummary {
+    pub notes_imported: usize,
+    pub notes_skipped: usize,
+    pub categories_created: usize,
+    pub attachments_restored: usize,
+}
+
+// 临时工作目录，离开作用域时删除（其中有未加密的数据）
+struct WorkDir(PathBuf);
+
+impl WorkDir {
+    fn new() -> Result<Self, IoError> {
+        let mut path = match dirs::data_dir() {
+            Some(data_dir) => data_dir,
+            None => return Err(IoError::new(ErrorKind::NotFound, "无法找到数据目录")),
+        };
+        path.push("stickynotes");
+        path.push(format!(".backup-{}", chrono::Utc::now().timestamp_millis()));
+        std::fs::create_dir_all(&path)?;
+        Ok(WorkDir(path))
+    }
+
+    fn join(&self, name: &str) -> PathBuf {
+        self.0.join(name)
+    }
+}
+
+impl Drop for WorkDir {
+    fn drop(&mut self) {
+        if let Err(e) = std::fs::remove_dir_all(&self.0) {
+            log::warn!("删除临时目录 {} 失败: {}", self.0.display(), e);
+        }
+    }
+}
+
+// ======= 创建备份 =======
+
+pub fn create_backup(dest: &Path, passphrase: &str) -> Result<BackupManifest, IoError> {
+    let work = WorkDir::new()?;
+    let plain_db = work.join(DATABASE_ENTRY);
+    db::export_plaintext(&plain_db)?;
+
+    let blobs = list_blob_files()?;
+    let manifest = build_manifest(&blobs)?;
+
+    let zip_path = work.join("backup.zip");
+    write_archive(&zip_path, &manifest, &plain_db, &blobs)?;
+
+    // 先写临时文件，加密完成后再替换目标文件
+    let temp_dest = dest.with_extension("tmp");
+    encrypt_file(&zip_path, &temp_dest, passphrase)?;
+    std::fs::rename(&temp_dest, dest)?;
+
+    Ok(manifest)
+}
+
+// ======= 自动备份快照 =======
+
+// 在指定目录中创建快照：数据库保持用当前密钥加密，附件原样打包，返回快照文件路径
+// 快照不需要备份密码，只能用当前的数据库密钥打开（轮换密钥时由 rekey_snapshot 重新加密）
+pub fn create_snapshot(dir: &Path) -> Result<PathBuf, IoError> {
+    std::fs::create_dir_all(dir)?;
+    let work = WorkDir::new()?;
+    let encrypted_db = work.join(DATABASE_ENTRY);
+    db::export_encrypted(&encrypted_db)?;
+
+    let blobs = list_blob_files()?;
+    let manifest = build_manifest(&blobs)?;
+
+    let dest = dir.join(format!(
+        "{}{}.{}",
+        SNAPSHOT_PREFIX,
+        chrono::Local::now().format(SNAPSHOT_TIME_FORMAT),
+        SNAPSHOT_EXTENSION,
+    ));
+    let temp_dest = dest.with_extension("tmp");
+    write_archive(&temp_dest, &manifest, &encrypted_db, &blobs)?;
+    std::fs::rename(&temp_dest, &dest)?;
+
+    Ok(dest)
+}
+
+// 用新密钥重新加密快照中的数据库，其他条目原样复制，已经使用新密钥的快照不做修改
+pub fn rekey_snapshot(path: &Path, old_key: &str, new_key: &str) -> Result<(), IoError> {
+    let work = WorkDir::new()?;
+    let mut archive = ZipArchive::new(File::open(path)?).map_err(zip_error)?;
+    let db_file = work.join(DATABASE_ENTRY);
+    std::io::copy(
+        &mut archive.by_name(DATABASE_ENTRY).map_err(zip_error)?,
+        &mut File::create(&db_file)?,
+    )?;
+    db::rekey_database_file(&db_file, old_key, new_key)?;
+
+    let temp_path = path.with_extension("tmp");
+    let mut zip = ZipWriter::new(BufWriter::new(File::create(&temp_path)?));
+    for index in 0..archive.len() {
+        let entry = archive.by_index_raw(index).map_err(zip_error)?;
+        if entry.name() == DATABASE_ENTRY {
+            drop(entry);
+            let deflated = FileOptions::default().compression_method(CompressionMethod::Deflated);
+            zip.start_file(DATABASE_ENTRY, deflated).map_err(zip_error)?;
+            std::io::copy(&mut File::open(&db_file)?, &mut zip)?;
+        } else {
+            zip.raw_copy_file(entry).map_err(zip_error)?;
+        }
+    }
+    zip.finish().map_err(zip_error)?.flush()?;
+
+    std::fs::rename(&temp_path, path)
+}
+
+// 从快照文件名中解析创建时间（本地时间），不是快照的文件返回 None
+pub fn snapshot_time(path: &Path) -> Option<chrono::NaiveDateTime> {
+    let name = path.file_name()?.to_str()?;
+    let time = name.strip_prefix(SNAPSHOT_PREFIX)?
+        .strip_suffix(SNAPSHOT_EXTENSION)?
+        .strip_suffix('.')?;
+    chrono::NaiveDateTime::parse_from_str(time, SNAPSHOT_TIME_FORMAT).ok()
+}
+
+// ======= 打包 =======
+
+fn list_blob_files() -> Result<Vec<PathBuf>, IoError> {
+    Ok(attachments::list_blobs()?
+        .into_iter()
+        .filter(|path| blob_entry_name(path).is_some())
+        .collect())
+}
+
+fn build_manifest(blobs: &[PathBuf]) -> Result<BackupManifest, IoError> {
+    let stats = db::get_database_stats()?;
+    Ok(BackupManifest {
+        format_version: FORMAT_VERSION,
+        app_version: env!("CARGO_PKG_VERSION").to_string(),
+        created_at: chrono::Utc::now().timestamp(),
+        schema_version: stats.schema_version,
+        note_count: stats.note_count,
+        category_count: stats.category_count,
+        attachment_count: blobs.len(),
+        attachment_bytes: blobs.iter()
+            .filter_map(|path| std::fs::metadata(path).ok())
+            .map(|meta| meta.len())
+            .sum(),
+    })
+}
+
+fn write_archive(zip_path: &Path, manifest: &BackupManifest, db_file: &Path, blobs: &[PathBuf]) -> Result<(), IoError> {
+    let mut zip = ZipWriter::new(BufWriter::new(File::create(zip_path)?));
+    let deflated = FileOptions::default().compression_method(CompressionMethod::Deflated);
+    // 图片本身已经压缩过
+    let stored = FileOptions::default().compression_method(CompressionMethod::Stored);
+
+    zip.start_file(MANIFEST_ENTRY, deflated).map_err(zip_error)?;
+    serde_json::to_writer_pretty(&mut zip, manifest)
+        .map_err(|e| IoError::new(ErrorKind::Other, format!("写入备份清单失败: {}", e)))?;
+
+    zip.start_file(DATABASE_ENTRY, deflated).map_err(zip_error)?;
+    std::io::copy(&mut File::open(db_file)?, &mut zip)?;
+
+    for path in blobs {
+        if let Some(name) = blob_entry_name(path) {
+            zip.start_file(name, stored).map_err(zip_error)?;
+            std::io::copy(&mut File::open(path)?, &mut zip)?;
+        }
+    }
+
+    zip.finish().map_err(zip_error)?.flush()
+}
+
+// 附件在 zip 中的路径：attachments/<哈希>.<扩展名>，跳过写入中途留下的临时文件
+fn blob_entry_name(path: &Path) -> Option<String> {
+    let file_name = path.file_name()?.to_str()?;
+    parse_blob_name(file_name)?;
+    Some(format!("{}{}", ATTACHMENTS_PREFIX, file_name))
+}
+
+// 解析附件文件名，返回哈希和扩展名
+fn parse_blob_name(file_name: &str) -> Option<(&str, &str)> {
+    let (hash, extension) = file_name.split_once('.')?;
+    let valid = hash.len() == 64
+        && hash.chars().all(|c| c.is_ascii_hexdigit())
+        && !extension.is_empty()
+        && extension.chars().all(|c| c.is_ascii_alphanumeric());
+    valid.then_some((hash, extension))
+}
+
+// ======= 查看和恢复备份 =======
+
+// 解密备份并校验内容，返回备份说明
+pub fn inspect_backup(path: &Path, passphrase: &str) -> Result<BackupManifest, IoError> {
+    let work = WorkDir::new()?;
+    let (_, manifest) = open_archive(path, passphrase, &work)?;
+    Ok(manifest)
+}
+
+pub fn restore_backup(path: &Path, passphrase: &str, mode: RestoreMode) -> Result<RestoreSummary, IoError> {
+    let work = WorkDir::new()?;
+    let (mut archive, _) = open_archive(path, passphrase, &work)?;
+
+    let plain_db = work.join(DATABASE_ENTRY);
+    std::io::copy(
+        &mut archive.by_name(DATABASE_ENTRY).map_err(zip_error)?,
+        &mut File::create(&plain_db)?,
+    )?;
+
+    match mode {
+        RestoreMode::Replace => {
+            let mut summary = RestoreSummary::default();
+            // 先放回附件，替换后的数据库引用的图片都能找到
+            summary.attachments_restored = restore_attachments(&mut archive)?;
+            db::replace_database(&plain_db)?;
+            Ok(summary)
+        }
+        RestoreMode::Merge => merge_database(&plain_db, &mut archive),
+    }
+}
+
+// 解密并打开备份，校验清单、数据库和每个附件的哈希
+fn open_archive(path: &Path, passphrase: &str, work: &WorkDir) -> Result<(ZipArchive<File>, BackupManifest), IoError> {
+    let zip_path = work.join("backup.zip");
+    decrypt_file(path, &zip_path, passphrase)?;
+
+    let mut archive = ZipArchive::new(File::open(&zip_path)?).map_err(zip_error)?;
+    let manifest: BackupManifest = serde_json::from_reader(archive.by_name(MANIFEST_ENTRY).map_err(zip_error)?)
+        .map_err(|e| IoError::new(ErrorKind::InvalidData, format!("备份清单无效: {}", e)))?;
+    if manifest.schema_version > migrations::latest_version() {
+        return Err(IoError::new(ErrorKind::InvalidData, "备份来自更新版本的应用，请先升级"));
+    }
+    archive.by_name(DATABASE_ENTRY)
+        .map_err(|_| IoError::new(ErrorKind::InvalidData, "备份中缺少数据库"))?;
+
+    let names: Vec<String> = archive.file_names()
+        .filter(|name| name.starts_with(ATTACHMENTS_PREFIX))
+        .map(String::from)
+        .collect();
+    if names.len() != manifest.attachment_count {
+        return Err(IoError::new(ErrorKind::InvalidData, "备份中的附件数量与清单不符"));
+    }
+    for name in &names {
+        read_blob(&mut archive, name)?;
+    }
+
+    Ok((archive, manifest))
+}
+
+// 读取 zip 中的附件并校验哈希，返回哈希、扩展名和内容
+fn read_blob(archive: &mut ZipArchive<File>, name: &str) -> Result<(String, String, Vec<u8>), IoError> {
+    let (hash, extension) = name.strip_prefix(ATTACHMENTS_PREFIX)
+        .and_then(parse_blob_name)
+        .ok_or_else(|| IoError::new(ErrorKind::InvalidData, format!("备份中的附件名无效: {}", name)))?;
+    let (hash, extension) = (hash.to_string(), extension.to_string());
+
+    let mut bytes = Vec::new();
+    archive.by_name(name).map_err(zip_error)?.read_to_end(&mut bytes)?;
+    if attachments::hash_bytes(&bytes) != hash {
+        return Err(IoError::new(ErrorKind::InvalidData, format!("备份中的附件已损坏: {}", name)));
+    }
+    Ok((hash, extension, bytes))
+}
+
+// 将备份中的附件写回附件存储，已存在的文件不会重复写入
+fn restore_attachments(archive: &mut ZipArchive<File>) -> Result<usize, IoError> {
+    let names: Vec<String> = archive.file_names()
+        .filter(|name| name.starts_with(ATTACHMENTS_PREFIX))
+        .map(String::from)
+        .collect();
+
+    for name in &names {
+        let (_, extension, bytes) = read_blob(archive, name)?;
+        attachments::write_blob(&bytes, &extension)?;
+    }
+    Ok(names.len())
+}
+
+// 将备份中未删除的便签作为新便签添加，分类按名称合并，
+// 清单条目、提醒、标签和附件随便签一起导入
+fn merge_database(plain_db: &Path, archive: &mut ZipArchive<File>) -> Result<RestoreSummary, IoError> {
+    let sql_error = |e: rusqlite::Error| IoError::new(ErrorKind::Other, format!("读取备份数据库失败: {}", e));
+    let mut backup = Connection::open(plain_db).map_err(sql_error)?;
+    migrations::migrate(&mut backup, None)?;
+
+    let mut summary = RestoreSummary::default();
+    let category_map = merge_categories(&backup, &mut summary)?;
+
+    let notes = {
+        let mut stmt = backup.prepare(
+            "SELECT id, content, screenshot_path, created_at, updated_at, is_pinned, color, category_id, deleted_at, position, note_type, content_format, archived_at
+             FROM notes WHERE deleted_at IS NULL ORDER BY id"
+        ).map_err(sql_error)?;
+        let notes = stmt.query_map([], |row| db::note_from_row(row, 0)).map_err(sql_error)?
+            .collect::<rusqlite::Result<Vec<Note>>>().map_err(sql_error)?;
+        notes
+    };
+
+    for note in notes {
+        let old_id = note.id.unwrap_or_default();
+        if db::find_note_id(&note.content, note.created_at)?.is_some() {
+            summary.notes_skipped += 1;
+            continue;
+        }
+
+        let new_id = db::save_note(&Note {
+            id: None,
+            category_id: note.category_id.and_then(|id| category_map.get(&id).copied()),
+            position: 0,
+            ..note
+        })?;
+        summary.notes_imported += 1;
+
+        let mut stmt = backup.prepare(
+            "SELECT content, done, due_at FROM checklist_items WHERE note_id = ?1 ORDER BY position"
+        ).map_err(sql_error)?;
+        let items = stmt.query_map(params![old_id], |row| Ok((row.get::<_, String>(0)?, row.get::<_, bool>(1)?, row.get::<_, Option<i64>>(2)?)))
+            .map_err(sql_error)?
+            .collect::<rusqlite::Result<Vec<_>>>().map_err(sql_error)?;
+        for (content, done, due_at) in items {
+            let item_id = db::add_checklist_item(new_id, &content, due_at)?;
+            if done {
+                db::toggle_checklist_item(item_id)?;
+            }
+        }
+
+        let mut stmt = backup.prepare(
+            "SELECT reminder_time, completed, rrule, timezone, dtstart FROM note_reminders WHERE note_id = ?1"
+        ).map_err(sql_error)?;
+        let reminders = stmt.query_map(params![old_id], |row| {
+            let reminder_time: i64 = row.get(0)?;
+            let recurrence = match row.get::<_, Option<String>>(2)? {
+                Some(rrule) => Some(Recurrence {
+                    rrule,
+                    timezone: row.get(3)?,
+                    dtstart: row.get::<_, Option<i64>>(4)?.unwrap_or(reminder_time),
+                }),
+                None => None,
+            };
+            Ok(NoteReminder { id: None, note_id: new_id, reminder_time, completed: row.get(1)?, recurrence })
+        })
+            .map_err(sql_error)?
+            .collect::<rusqlite::Result<Vec<_>>>().map_err(sql_error)?;
+        for reminder in reminders {
+            db::save_reminder(&reminder)?;
+        }
+
+        // 内容中的 #标签在保存时已同步，这里只补充手动添加的标签
+        let mut stmt = backup.prepare(
+            "SELECT t.name FROM note_tags nt JOIN tags t ON t.id = nt.tag_id WHERE nt.note_id = ?1 AND nt.source = 'manual'"
+        ).map_err(sql_error)?;
+        let tags = stmt.query_map(params![old_id], |row| row.get::<_, String>(0))
+            .map_err(sql_error)?
+            .collect::<rusqlite::Result<Vec<_>>>().map_err(sql_error)?;
+        for tag in tags {
+            db::add_tag_to_note(new_id, &tag)?;
+        }
+
+        let mut stmt = backup.prepare(
+            "SELECT a.hash, a.extension, a.mime FROM note_attachments na JOIN attachments a ON a.id = na.attachment_id WHERE na.note_id = ?1"
+        ).map_err(sql_error)?;
+        let note_attachments = stmt.query_map(params![old_id], |row| Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?, row.get::<_, String>(2)?)))
+            .map_err(sql_error)?
+            .collect::<rusqlite::Result<Vec<_>>>().map_err(sql_error)?;
+        for (hash, extension, mime) in note_attachments {
+            let name = format!("{}{}.{}", ATTACHMENTS_PREFIX, hash, extension);
+            match read_blob(archive, &name) {
+                Ok((_, _, bytes)) => {
+                    db::add_attachment(&bytes, &mime, Some(new_id))?;
+                    summary.attachments_restored += 1;
+                }
+                Err(e) => log::warn!("便签 {} 的附件 {} 无法恢复: {}", old_id, hash, e),
+            }
+        }
+    }
+
+    Ok(summary)
+}
+
+// 按名称和父分类合并分类，返回备份中的分类ID到当前分类ID的映射
+fn merge_categories(backup: &Connection, summary: &mut RestoreSummary) -> Result<HashMap<i64, i64>, IoError> {
+    let sql_error = |e: rusqlite::Error| IoError::new(ErrorKind::Other, format!("读取备份分类失败: {}", e));
+    let mut stmt = backup.prepare("SELECT id, name, color, parent_id FROM categories ORDER BY position")
+        .map_err(sql_error)?;
+    let mut pending = stmt.query_map([], |row| Ok((
+            row.get::<_, i64>(0)?,
+            row.get::<_, String>(1)?,
+            row.get::<_, Option<String>>(2)?,
+            row.get::<_, Option<i64>>(3)?,
+        )))
+        .map_err(sql_error)?
+        .collect::<rusqlite::Result<Vec<_>>>().map_err(sql_error)?;
+
+    let existing = db::get_all_categories()?;
+    let mut map = HashMap::new();
+
+    // 父分类先于子分类处理，父分类缺失的分类放到顶级
+    while !pending.is_empty() {
+        let ready: Vec<_> = pending.iter()
+            .filter(|(_, _, _, parent)| parent.map_or(true, |p| map.contains_key(&p) || !pending.iter().any(|c| c.0 == p)))
+            .cloned()
+            .collect();
+        if ready.is_empty() {
+            break;
+        }
+        for (id, name, color, parent) in &ready {
+            let parent_id = parent.and_then(|p| map.get(&p).copied());
+            let found = existing.iter()
+                .find(|c| c.name == *name && c.parent_id == parent_id)
+                .and_then(|c| c.id);
+            let new_id = match found {
+                Some(found) => found,
+                None => {
+                    summary.categories_created += 1;
+                    db::save_category(&Category {
+                        id: None,
+                        name: name.clone(),
+                        color: color.clone(),
+                        parent_id,
+                        position: 0,
+                    })?
+                }
+            };
+            map.insert(*id, new_id);
+        }
+        pending.retain(|c| !map.contains_key(&c.0));
+    }
+
+    Ok(map)
+}
+
+// ======= 加密 =======
+
+fn encode_header(kdf: KdfParams, salt: &[u8; SALT_LENGTH], nonce: &[u8; STREAM_NONCE_LENGTH]) -> Vec<u8> {
+    let mut header = Vec::with_capacity(HEADER_LENGTH);
+    header.extend_from_slice(MAGIC);
+    header.push(FORMAT_VERSION);
+    header.extend_from_slice(&kdf.memory_kib.to_le_bytes());
+    header.extend_from_slice(&kdf.iterations.to_le_bytes());
+    header.extend_from_slice(&kdf.parallelism.to_le_bytes());
+    header.extend_from_slice(salt);
+    header.extend_from_slice(nonce);
+    header
+}
+
+fn decode_header(header: &[u8]) -> Result<(KdfParams, &[u8], &[u8]), IoError> {
+    if header.len() != HEADER_LENGTH || !header.starts_with(MAGIC) {
+        return Err(IoError::new(ErrorKind::InvalidData, "不是有效的备份文件"));
+    }
+    if header[MAGIC.len()] > FORMAT_VERSION {
+        return Err(IoError::new(ErrorKind::InvalidData, "备份格式版本过高，请先升级应用"));
+    }
+
+    let u32_at = |offset: usize| u32::from_le_bytes([header[offset], header[offset + 1], header[offset + 2], header[offset + 3]]);
+    let params_start = MAGIC.len() + 1;
+    let kdf = KdfParams {
+        memory_kib: u32_at(params_start),
+        iterations: u32_at(params_start + 4),
+        parallelism: u32_at(params_start + 8),
+    };
+    let limit = KdfParams::current();
+    let in_range = |value: u32, current: u32| value >= 1 && value <= current.saturating_mul(MAX_KDF_FACTOR);
+    if !in_range(kdf.memory_kib, limit.memory_kib)
+        || !in_range(kdf.iterations, limit.iterations)
+        || !in_range(kdf.parallelism, limit.parallelism)
+    {
+        return Err(IoError::new(ErrorKind::InvalidData, "备份文件的密钥派生参数超出允许范围"));
+    }
+    let salt_start = params_start + 12;
+    let nonce_start = salt_start + SALT_LENGTH;
+    Ok((kdf, &header[salt_start..nonce_start], &header[nonce_start..]))
+}
+
+fn encrypt_file(plain: &Path, dest: &Path, passphrase: &str) -> Result<(), IoError> {
+    let kdf = KdfParams::current();
+    let mut salt = [0u8; SALT_LENGTH];
+    let mut nonce = [0u8; STREAM_NONCE_LENGTH];
+    OsRng.fill_bytes(&mut salt);
+    OsRng.fill_bytes(&mut nonce);
+    let header = encode_header(kdf, &salt, &nonce);
+
+    let key = key_manager::derive_wrapping_key(passphrase.as_bytes(), &salt, kdf)?;
+    let cipher = Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(&key));
+    let mut encryptor = EncryptorBE32::from_aead(cipher, GenericArray::from_slice(&nonce));
+
+    let mut input = BufReader::new(File::open(plain)?);
+    let mut output = BufWriter::new(File::create(dest)?);
+    output.write_all(&header)?;
+
+    // 预读下一块，以便对最后一块使用 encrypt_last
+    let mut chunk = read_chunk(&mut input, CHUNK_SIZE)?;
+    loop {
+        let next = read_chunk(&mut input, CHUNK_SIZE)?;
+        let payload = Payload { msg: &chunk, aad: &header };
+        if next.is_empty() {
+            let ciphertext = encryptor.encrypt_last(payload).map_err(|_| crypto_error("加密备份失败"))?;
+            output.write_all(&ciphertext)?;
+            break;
+        }
+        let ciphertext = encryptor.encrypt_next(payload).map_err(|_| crypto_error("加密备份失败"))?;
+        output.write_all(&ciphertext)?;
+        chunk = next;
+    }
+
+    output.flush()
+}
+
+fn decrypt_file(path: &Path, dest: &Path, passphrase: &str) -> Result<(), IoError> {
+    let mut input = BufReader::new(File::open(path)?);
+    let header = read_chunk(&mut input, HEADER_LENGTH)?;
+    let (kdf, salt, nonce) = decode_header(&header)?;
+
+    let key = key_manager::derive_wrapping_key(passphrase.as_bytes(), salt, kdf)?;
+    let cipher = Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(&key));
+    let mut decryptor = DecryptorBE32::from_aead(cipher, GenericArray::from_slice(nonce));
+
+    let mut output = BufWriter::new(File::create(dest)?);
+    let mut chunk = read_chunk(&mut input, CHUNK_SIZE + TAG_LENGTH)?;
+    loop {
+        let next = read_chunk(&mut input, CHUNK_SIZE + TAG_LENGTH)?;
+        let payload = Payload { msg: &chunk, aad: &header };
+        // 第一块认证失败通常是密码错误，之后的失败说明文件损坏或被截断
+        if next.is_empty() {
+            let plaintext = decryptor.decrypt_last(payload).map_err(|_| crypto_error("备份密码错误或文件已损坏"))?;
+            output.write_all(&plaintext)?;
+            break;
+        }
+        let plaintext = decryptor.decrypt_next(payload).map_err(|_| crypto_error("备份密码错误或文件已损坏"))?;
+        output.write_all(&plaintext)?;
+        chunk = next;
+    }
+
+    output.flush()
+}
+
+// 读取至多 size 字节，只有到达文件末尾时才会少于 size
+fn read_chunk(reader: &mut impl Read, size: usize) -> Result<Vec<u8>, IoError> {
+    let mut buffer = Vec::with_capacity(size);
+    reader.take(size as u64).read_to_end(&mut buffer)?;
+    Ok(buffer)
+}
+
+fn crypto_error(message: &str) -> IoError {
+    IoError::new(ErrorKind::InvalidData, message)
+}
+
+fn zip_error(e: zip::result::ZipError) -> IoError {
+    IoError::new(ErrorKind::InvalidData, format!("备份文件格式错误: {}", e))
+}
+
+// ======= 命令 =======
+
+#[tauri::command]
+pub async fn create_backup_archive(path: String, passphrase: String) -> Result<BackupManifest, String> {
+    if passphrase.chars().count() < MIN_PASSPHRASE_CHARS {
+        return Err(format!("备份密码至少需要 {} 个字符", MIN_PASSPHRASE_CHARS));
+    }
+    if lock::is_locked() {
+        return Err("应用已锁定".to_string());
+    }
+
+    db::run_blocking(move || create_backup(Path::new(&path), &passphrase)).await
+        .map_err(|e| format!("创建备份失败: {}", e))
+}
+
+#[tauri::command]
+pub async fn inspect_backup_archive(path: String, passphrase: String) -> Result<BackupManifest, String> {
+    db::run_blocking(move || inspect_backup(Path::new(&path), &passphrase)).await
+        .map_err(|e| format!("读取备份失败: {}", e))
+}
+
+#[tauri::command]
+pub async fn restore_backup_archive(
+    path: String,
+    passphrase: String,
+    mode: RestoreMode,
+    app: AppHandle,
+) -> Result<RestoreSummary, String> {
+    if lock::is_locked() {
+        return Err("应用已锁定".to_string());
+    }
+
+    let summary = db::run_blocking(move || restore_backup(Path::new(&path), &passphrase, mode)).await
+        .map_err(|e| format!("恢复备份失败: {}", e))?;
+    // 通知所有窗口重新加载数据
+    let _ = app.emit_all("data-restored", ());
+    Ok(summary)
+}
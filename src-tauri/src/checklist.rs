@@ -0,0 +1,27 @@
+// 文本便签转换为清单时使用的解析逻辑，SqliteStore 和 MemoryStore 共用
+
+// 解析 Markdown 任务列表行（- [ ] 或 - [x]），返回条目内容和完成状态
+pub fn parse_checklist_line(line: &str) -> Option<(String, bool)> {
+    let rest = line.trim_start().strip_prefix(['-', '*', '+'])?.strip_prefix(' ')?.trim_start();
+    let (done, text) = if let Some(text) = rest.strip_prefix("[ ]") {
+        (false, text)
+    } else if let Some(text) = rest.strip_prefix("[x]").or_else(|| rest.strip_prefix("[X]")) {
+        (true, text)
+    } else {
+        return None;
+    };
+    Some((text.trim().to_string(), done))
+}
+
+// 将便签内容拆分为保留的文本和任务列表条目
+pub fn split_checklist_content(content: &str) -> (String, Vec<(String, bool)>) {
+    let mut items = Vec::new();
+    let mut remaining = Vec::new();
+    for line in content.lines() {
+        match parse_checklist_line(line) {
+            Some(item) => items.push(item),
+            None => remaining.push(line),
+        }
+    }
+    (remaining.join("\n").trim().to_string(), items)
+}
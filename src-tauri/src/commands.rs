@@ -1,20 +1,18 @@
-use crate::db::{Database, Note, Category};
-use std::sync::Mutex;
+use crate::db::{self, Note, NoteReminder, NoteType, ContentFormat, Category, ChecklistProgress, ChildCategoryAction, NoteSortOption, Recurrence};
+use crate::attachments;
+use crate::lock;
+use crate::recurrence;
+use crate::render;
+use crate::store::{NoteStore, StoreState};
+use std::collections::HashMap;
 use tauri::State;
 use std::path::PathBuf;
 use tauri::api::shell;
 use std::fs;
-use base64;
-use image;
-use chrono::Local;
-use uuid::Uuid;
-use rusqlite::params;
-use std::time::{SystemTime, Duration};
+use std::io::{Error as IoError, ErrorKind};
 use serde::{Deserialize, Serialize};
 use chrono::Utc;
 
-pub struct DatabaseState(pub Mutex<Database>);
-
 // 前端响应类型
 #[derive(Serialize, Deserialize)]
 pub struct NoteResponse {
@@ -27,6 +25,17 @@ pub struct NoteResponse {
     pub color: Option<String>,
     pub category_id: Option<i64>,
     pub deleted_at: Option<u64>,
+    pub archived_at: Option<u64>,
+    pub position: i64,
+    pub note_type: String,  // "text" | "checklist"
+    pub content_format: String,  // "plain" | "markdown"
+    pub progress: Option<ChecklistProgressResponse>,  // 仅清单便签有值
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct ChecklistProgressResponse {
+    pub done: i64,
+    pub total: i64,
 }
 
 #[derive(Serialize, Deserialize)]
@@ -35,6 +44,8 @@ pub struct NoteReminderResponse {
     pub note_id: i64,
     pub reminder_time: u64,
     pub completed: bool,
+    pub rrule: Option<String>,
+    pub timezone: Option<String>,
 }
 
 #[derive(Serialize, Deserialize)]
@@ -42,661 +53,1142 @@ pub struct CategoryResponse {
     pub id: i64,
     pub name: String,
     pub color: Option<String>,
+    pub parent_id: Option<i64>,
+    pub position: i64,
 }
 
-#[tauri::command]
-pub async fn add_note(
-    content: String,
-    screenshot_path: Option<String>,
-    db: State<'_, DatabaseState>,
-) -> Result<i64, String> {
-    db.0.lock()
-        .unwrap()
-        .add_note(&content, screenshot_path.as_deref())
-        .map_err(|e| e.to_string())
+#[derive(Serialize, Deserialize)]
+pub struct CategoryTreeResponse {
+    #[serde(flatten)]
+    pub category: CategoryResponse,
+    pub children: Vec<CategoryTreeResponse>,
 }
 
 #[tauri::command]
-pub async fn get_notes(db: State<'_, DatabaseState>) -> Result<Vec<NoteResponse>, String> {
-    db.0.lock()
-        .unwrap()
-        .get_notes()
-        .map(|notes| {
-            notes
-                .into_iter()
-                .map(|note| NoteResponse {
-                    id: note.id.unwrap_or(0),
-                    content: note.content,
-                    screenshot_path: note.screenshot_path,
-                    created_at: note.created_at as u64,
-                    updated_at: note.updated_at.map(|t| t as u64),
-                    is_pinned: note.is_pinned,
-                    color: note.color,
-                    category_id: note.category_id,
-                    deleted_at: note.deleted_at.map(|t| t as u64),
-                })
-                .collect()
-        })
+pub async fn open_link(url: String, app_handle: tauri::AppHandle) -> Result<(), String> {
+    shell::open(&app_handle.shell_scope(), url, None)
         .map_err(|e| e.to_string())
 }
 
-#[tauri::command]
-pub async fn delete_note(id: i64, db: State<'_, DatabaseState>) -> Result<(), String> {
-    db.0.lock()
-        .unwrap()
-        .delete_note(id)
-        .map_err(|e| e.to_string())
+#[derive(Serialize, Deserialize)]
+pub struct AttachmentResponse {
+    pub id: i64,
+    pub hash: String,
+    pub mime: String,
+    pub size: i64,
+    pub path: String,
+    pub created_at: u64,
 }
 
-#[tauri::command]
-pub async fn update_note(id: i64, content: String, db: State<'_, DatabaseState>) -> Result<(), String> {
-    db.0.lock()
-        .unwrap()
-        .update_note(id, &content)
-        .map_err(|e| e.to_string())
+fn attachment_to_response(attachment: db::Attachment) -> Result<AttachmentResponse, String> {
+    let path = attachment.path().map_err(|e| format!("获取附件路径失败: {}", e))?;
+    Ok(AttachmentResponse {
+        id: attachment.id,
+        hash: attachment.hash,
+        mime: attachment.mime,
+        size: attachment.size,
+        path: path.to_string_lossy().to_string(),
+        created_at: attachment.created_at as u64,
+    })
 }
 
+// 保存粘贴的图片（data URL），note_id 不为空时关联到便签
 #[tauri::command]
-pub async fn open_link(url: String, app_handle: tauri::AppHandle) -> Result<(), String> {
-    shell::open(&app_handle.shell_scope(), url, None)
-        .map_err(|e| e.to_string())
+pub async fn save_pasted_image(image_data: String, note_id: Option<i64>, store: State<'_, StoreState>) -> Result<AttachmentResponse, String> {
+    let (mime, bytes) = attachments::decode_data_url(&image_data)
+        .map_err(|e| format!("解析图片数据失败: {}", e))?;
+    
+    match store.run(move |store| store.add_attachment(&bytes, &mime, note_id)).await {
+        Ok(attachment) => attachment_to_response(attachment),
+        Err(e) => Err(format!("保存图片失败: {}", e)),
+    }
 }
 
+// 保存拖入的文件，note_id 不为空时关联到便签
 #[tauri::command]
-pub async fn save_pasted_image(
-    image_data: String,
-    app_handle: tauri::AppHandle,
-    db: State<'_, DatabaseState>,
-) -> Result<String, String> {
-    // 获取应用数据目录
-    let app_dir = tauri::api::path::app_data_dir(&app_handle.config())
-        .ok_or("无法获取应用数据目录")?;
-    let images_dir = app_dir.join("images");
+pub async fn handle_file_drop(file_path: String, note_id: Option<i64>, store: State<'_, StoreState>) -> Result<AttachmentResponse, String> {
+    let path = PathBuf::from(&file_path);
+    let mime = attachments::mime_for_path(&path);
+    let bytes = db::run_blocking(move || fs::read(&path)).await
+        .map_err(|e| format!("读取文件失败: {}", e))?;
     
-    // 创建图片存储目录
-    fs::create_dir_all(&images_dir).map_err(|e| e.to_string())?;
-
-    // 解码base64图片数据
-    let image_data = image_data.replace("data:image/png;base64,", "");
-    let image_bytes = base64::decode(image_data).map_err(|e| e.to_string())?;
-
-    // 生成唯一文件名
-    let timestamp = Local::now().format("%Y%m%d_%H%M%S");
-    let unique_id = Uuid::new_v4().to_string().split('-').next().unwrap();
-    let filename = format!("paste_{}_{}.png", timestamp, unique_id);
-    let file_path = images_dir.join(&filename);
-
-    // 保存图片文件
-    fs::write(&file_path, image_bytes).map_err(|e| e.to_string())?;
+    match store.run(move |store| store.add_attachment(&bytes, mime, note_id)).await {
+        Ok(attachment) => attachment_to_response(attachment),
+        Err(e) => Err(format!("保存文件失败: {}", e)),
+    }
+}
 
-    // 返回相对路径
-    Ok(format!("./images/{}", filename))
+// 附件相关命令
+#[tauri::command]
+pub async fn get_note_attachments(note_id: i64, store: State<'_, StoreState>) -> Result<Vec<AttachmentResponse>, String> {
+    match store.run(move |store| store.get_note_attachments(note_id)).await {
+        Ok(attachments) => attachments.into_iter().map(attachment_to_response).collect(),
+        Err(e) => Err(format!("获取附件失败: {}", e)),
+    }
 }
 
 #[tauri::command]
-pub async fn handle_file_drop(
-    file_path: String,
-    app_handle: tauri::AppHandle,
-    db: State<'_, DatabaseState>,
-) -> Result<String, String> {
-    // 获取应用数据目录
-    let app_dir = tauri::api::path::app_data_dir(&app_handle.config())
-        .ok_or("无法获取应用数据目录")?;
-    let images_dir = app_dir.join("images");
-    
-    // 创建图片存储目录
-    fs::create_dir_all(&images_dir).map_err(|e| e.to_string())?;
+pub async fn add_note_attachment(note_id: i64, attachment_id: i64, store: State<'_, StoreState>) -> Result<bool, String> {
+    store.run(move |store| store.attach_to_note(note_id, attachment_id)).await
+        .map(|_| true)
+        .map_err(|e| format!("添加附件失败: {}", e))
+}
 
-    // 读取源文件
-    let image = image::open(&file_path).map_err(|e| e.to_string())?;
+#[tauri::command]
+pub async fn remove_note_attachment(note_id: i64, attachment_id: i64, store: State<'_, StoreState>) -> Result<bool, String> {
+    store.run(move |store| store.detach_from_note(note_id, attachment_id)).await
+        .map(|_| true)
+        .map_err(|e| format!("移除附件失败: {}", e))
+}
 
-    // 生成唯一文件名
-    let timestamp = Local::now().format("%Y%m%d_%H%M%S");
-    let unique_id = Uuid::new_v4().to_string().split('-').next().unwrap();
-    let filename = format!("drop_{}_{}.png", timestamp, unique_id);
-    let file_path = images_dir.join(&filename);
+// 删除没有被任何便签引用的附件文件，返回删除的文件数量
+#[tauri::command]
+pub async fn collect_attachment_garbage(store: State<'_, StoreState>) -> Result<usize, String> {
+    store.run(|store| store.collect_attachment_garbage()).await
+        .map_err(|e| format!("清理附件失败: {}", e))
+}
 
-    // 保存为PNG格式
-    image.save(&file_path).map_err(|e| e.to_string())?;
+#[derive(Serialize, Deserialize)]
+pub struct SearchResultResponse {
+    pub note: NoteResponse,
+    pub snippet: String,
+    pub rank: f64,
+}
 
-    // 返回相对路径
-    Ok(format!("./images/{}", filename))
+// 全文搜索便签，按相关度排序并返回高亮摘要
+#[tauri::command]
+pub async fn search_notes(
+    query: String,
+    category_id: Option<i64>,
+    limit: Option<i64>,
+    store: State<'_, StoreState>,
+) -> Result<Vec<SearchResultResponse>, String> {
+    let results = store.run(move |store| {
+        let results = store.search_notes(&query, category_id, limit)?;
+        let progress = store.get_checklist_progress(&checklist_ids(results.iter().map(|r| &r.note)))?;
+        Ok(results.into_iter().map(|result| SearchResultResponse {
+            note: note_to_response(result.note, &progress),
+            snippet: result.snippet,
+            rank: result.rank,
+        }).collect())
+    }).await;
+    
+    results.map_err(|e| format!("搜索便签失败: {}", e))
 }
 
+// 更换数据库加密密钥，启用主密码时需要提供主密码，并返回新的恢复密钥
 #[tauri::command]
-pub async fn update_note_color(id: i64, color: String, db: State<'_, DatabaseState>) -> Result<(), String> {
-    db.0.lock()
-        .unwrap()
-        .update_note_color(id, &color)
-        .map_err(|e| e.to_string())
+pub async fn rotate_encryption_key(password: Option<String>, store: State<'_, StoreState>) -> Result<Option<String>, String> {
+    if lock::is_locked() {
+        return Err("应用已锁定".to_string());
+    }
+    
+    store.run(move |store| store.rotate_encryption_key(password.as_deref())).await
+        .map_err(|e| format!("更换加密密钥失败: {}", e))
 }
 
+// 便签相关命令
 #[tauri::command]
-pub async fn add_category(
-    name: String,
-    color: String,
-    db: State<'_, DatabaseState>,
+pub async fn create_note(
+    content: String,
+    screenshot_path: Option<String>,
+    category_id: Option<i64>,
+    note_type: Option<NoteType>,
+    content_format: Option<ContentFormat>,
+    store: State<'_, StoreState>,
 ) -> Result<i64, String> {
-    db.0.lock()
-        .unwrap()
-        .add_category(&name, &color)
-        .map_err(|e| e.to_string())
+    let now = Utc::now().timestamp() as i64;
+    
+    let note = db::Note {
+        id: None,
+        content,
+        screenshot_path,
+        created_at: now,
+        updated_at: Some(now),
+        is_pinned: false,
+        color: None,
+        category_id,
+        deleted_at: None,
+        archived_at: None,
+        position: 0,  // 插入时由存储确定
+        note_type: note_type.unwrap_or(NoteType::Text),
+        content_format: content_format.unwrap_or(ContentFormat::Plain),
+    };
+    
+    store.run(move |store| store.save_note(&note)).await
+        .map_err(|e| format!("创建便签失败: {}", e))
 }
 
 #[tauri::command]
-pub async fn get_categories(db: State<'_, DatabaseState>) -> Result<Vec<Category>, String> {
-    db.0.lock()
-        .unwrap()
-        .get_categories()
-        .map_err(|e| e.to_string())
+pub async fn update_note(
+    id: i64, 
+    content: String, 
+    screenshot_path: Option<String>,
+    is_pinned: bool,
+    color: Option<String>,
+    category_id: Option<i64>,
+    content_format: Option<ContentFormat>,
+    store: State<'_, StoreState>,
+) -> Result<bool, String> {
+    modify_note(&store, id, move |note| {
+        note.content = content;
+        note.screenshot_path = screenshot_path;
+        note.is_pinned = is_pinned;
+        note.color = color;
+        note.category_id = category_id;
+        if let Some(content_format) = content_format {
+            note.content_format = content_format;
+        }
+    }).await.map(|_| true)
 }
 
+// 将内容渲染为清理过的 HTML，用于编辑时的预览
 #[tauri::command]
-pub async fn update_category(
-    id: i64,
-    name: String,
-    color: String,
-    db: State<'_, DatabaseState>,
-) -> Result<(), String> {
-    db.0.lock()
-        .unwrap()
-        .update_category(id, &name, &color)
-        .map_err(|e| e.to_string())
+pub async fn render_content(content: String, content_format: Option<ContentFormat>) -> Result<String, String> {
+    db::run_blocking(move || Ok(render::render_html(&content, content_format.unwrap_or(ContentFormat::Markdown)))).await
+        .map_err(|e| format!("渲染内容失败: {}", e))
 }
 
+// 按便签保存的格式渲染便签内容
 #[tauri::command]
-pub async fn delete_category(id: i64, db: State<'_, DatabaseState>) -> Result<(), String> {
-    db.0.lock()
-        .unwrap()
-        .delete_category(id)
-        .map_err(|e| e.to_string())
+pub async fn render_note_html(id: i64, store: State<'_, StoreState>) -> Result<String, String> {
+    let note = store.run(move |store| store.get_note(id)).await
+        .map_err(|e| format!("获取便签失败: {}", e))?
+        .ok_or_else(|| "便签不存在".to_string())?;
+    
+    Ok(render::render_html(&note.content, note.content_format))
 }
 
-#[tauri::command]
-pub async fn update_category_order(
+// 只修改便签的部分字段，其余字段保持不变
+async fn modify_note(
+    store: &State<'_, StoreState>,
     id: i64,
-    new_order: i32,
-    db: State<'_, DatabaseState>,
+    modify: impl FnOnce(&mut Note) + Send + 'static,
 ) -> Result<(), String> {
-    db.0.lock()
-        .unwrap()
-        .update_category_order(id, new_order)
-        .map_err(|e| e.to_string())
+    store.run(move |store| {
+        let mut note = match store.get_note(id)? {
+            Some(note) => note,
+            None => return Err(IoError::new(ErrorKind::NotFound, "便签不存在")),
+        };
+        
+        modify(&mut note);
+        note.updated_at = Some(Utc::now().timestamp());
+        store.save_note(&note).map(|_| ())
+    }).await.map_err(|e| format!("更新便签失败: {}", e))
 }
 
 #[tauri::command]
-pub async fn get_notes_by_category(
-    category_id: i64,
-    db: State<'_, DatabaseState>,
-) -> Result<Vec<NoteResponse>, String> {
-    db.0.lock()
-        .unwrap()
-        .get_notes_by_category(category_id)
-        .map(|notes| {
-            notes
-                .into_iter()
-                .map(|note| NoteResponse {
-                    id: note.id,
-                    content: note.content,
-                    screenshot_path: note.screenshot_path,
-                    created_at: note.created_at
-                        .duration_since(std::time::UNIX_EPOCH)
-                        .unwrap()
-                        .as_secs(),
-                    color: note.color,
-                    category_id: note.category_id,
-                })
-                .collect()
-        })
-        .map_err(|e| e.to_string())
+pub async fn update_note_color(id: i64, color: Option<String>, store: State<'_, StoreState>) -> Result<bool, String> {
+    modify_note(&store, id, move |note| note.color = color).await.map(|_| true)
 }
 
 #[tauri::command]
-pub async fn update_note_category(
-    id: i64,
-    category_id: Option<i64>,
-    db: State<'_, DatabaseState>,
-) -> Result<(), String> {
-    db.0.lock()
-        .unwrap()
-        .update_note_category(id, category_id)
-        .map_err(|e| e.to_string())
+pub async fn update_note_category(id: i64, category_id: Option<i64>, store: State<'_, StoreState>) -> Result<bool, String> {
+    modify_note(&store, id, move |note| note.category_id = category_id).await.map(|_| true)
 }
 
-#[derive(serde::Serialize)]
-pub struct NoteResponse {
-    pub id: i64,
-    pub content: String,
-    pub screenshot_path: Option<String>,
-    pub created_at: u64,
-    pub updated_at: u64,
-    pub deleted_at: Option<u64>,
-    pub color: Option<String>,
-    pub category_id: Option<i64>,
-    pub is_pinned: bool,
+// 切换置顶状态，返回新的状态
+#[tauri::command]
+pub async fn toggle_note_pin(id: i64, store: State<'_, StoreState>) -> Result<bool, String> {
+    store.run(move |store| store.toggle_note_pin(id)).await
+        .map_err(|e| format!("切换置顶失败: {}", e))
 }
 
-#[derive(serde::Deserialize)]
-pub enum NoteSortOption {
-    CreatedTimeDesc,   // 创建时间降序
-    CreatedTimeAsc,    // 创建时间升序
-    UpdatedTimeDesc,   // 修改时间降序
-    UpdatedTimeAsc,    // 修改时间升序
+#[tauri::command]
+pub async fn get_note_by_id(id: i64, store: State<'_, StoreState>) -> Result<Option<NoteResponse>, String> {
+    let note = store.run(move |store| match store.get_note(id)? {
+        Some(note) => Ok(with_progress(store, vec![note])?.pop()),
+        None => Ok(None),
+    }).await;
+    
+    match note {
+        Ok(note) => Ok(note),
+        Err(e) => Err(format!("获取便签失败: {}", e)),
+    }
 }
 
 #[tauri::command]
 pub async fn get_notes_sorted(
-    sort_by: NoteSortOption,
-    category_id: Option<i64>,
-    db: State<'_, DatabaseState>
+    sort_by: Option<NoteSortOption>,
+    limit: Option<i64>,
+    offset: Option<i64>,
+    search: Option<String>,
+    tag_ids: Option<Vec<i64>>,
+    store: State<'_, StoreState>,
 ) -> Result<Vec<NoteResponse>, String> {
-    let conn = db.0.lock().unwrap();
+    let sort_by = sort_by.unwrap_or(NoteSortOption::CreatedTimeDesc);
+    let tag_ids = tag_ids.unwrap_or_default();
     
-    let mut query = String::from(
-        "SELECT id, content, screenshot_path, color, category_id, created_at, updated_at, deleted_at, is_pinned 
-         FROM notes WHERE deleted_at IS NULL"
-    );
+    let notes = store.run(move |store| {
+        let notes = store.get_notes_sorted(&sort_by, limit, offset, search.as_deref(), &tag_ids)?;
+        with_progress(store, notes)
+    }).await;
     
-    if let Some(cat_id) = category_id {
-        query.push_str(" AND category_id = ?");
+    match notes {
+        Ok(notes) => Ok(notes),
+        Err(e) => Err(format!("获取便签失败: {}", e)),
     }
+}
+
+// 归档相关命令
+#[tauri::command]
+pub async fn archive_notes(ids: Vec<i64>, store: State<'_, StoreState>) -> Result<bool, String> {
+    let now = Utc::now().timestamp();
     
-    query.push_str(" ORDER BY is_pinned DESC, ");
-    query.push_str(match sort_by {
-        NoteSortOption::CreatedTimeDesc => "created_at DESC",
-        NoteSortOption::CreatedTimeAsc => "created_at ASC",
-        NoteSortOption::UpdatedTimeDesc => "updated_at DESC",
-        NoteSortOption::UpdatedTimeAsc => "updated_at ASC",
-    });
-
-    let mut stmt = conn.prepare(&query).map_err(|e| e.to_string())?;
-    
-    let rows = if let Some(cat_id) = category_id {
-        stmt.query_map(params![cat_id], |row| {
-            Ok(NoteResponse {
-                id: row.get(0)?,
-                content: row.get(1)?,
-                screenshot_path: row.get(2)?,
-                color: row.get(3)?,
-                category_id: row.get(4)?,
-                created_at: row.get::<_, std::time::SystemTime>(5)?
-                    .duration_since(std::time::UNIX_EPOCH)
-                    .unwrap()
-                    .as_secs(),
-                updated_at: row.get::<_, std::time::SystemTime>(6)?
-                    .duration_since(std::time::UNIX_EPOCH)
-                    .unwrap()
-                    .as_secs(),
-                deleted_at: row.get::<_, Option<std::time::SystemTime>>(7)?
-                    .map(|t| t.duration_since(std::time::UNIX_EPOCH).unwrap().as_secs()),
-                is_pinned: row.get(8)?,
-            })
-        })
-    } else {
-        stmt.query_map([], |row| {
-            Ok(NoteResponse {
-                id: row.get(0)?,
-                content: row.get(1)?,
-                screenshot_path: row.get(2)?,
-                color: row.get(3)?,
-                category_id: row.get(4)?,
-                created_at: row.get::<_, std::time::SystemTime>(5)?
-                    .duration_since(std::time::UNIX_EPOCH)
-                    .unwrap()
-                    .as_secs(),
-                updated_at: row.get::<_, std::time::SystemTime>(6)?
-                    .duration_since(std::time::UNIX_EPOCH)
-                    .unwrap()
-                    .as_secs(),
-                deleted_at: row.get::<_, Option<std::time::SystemTime>>(7)?
-                    .map(|t| t.duration_since(std::time::UNIX_EPOCH).unwrap().as_secs()),
-                is_pinned: row.get(8)?,
-            })
-        })
-    }.map_err(|e| e.to_string())?;
+    store.run(move |store| store.archive_notes(&ids, now)).await
+        .map(|_| true)
+        .map_err(|e| format!("归档便签失败: {}", e))
+}
 
-    let mut notes = Vec::new();
-    for note in rows {
-        notes.push(note.map_err(|e| e.to_string())?);
+#[tauri::command]
+pub async fn unarchive_notes(ids: Vec<i64>, store: State<'_, StoreState>) -> Result<bool, String> {
+    store.run(move |store| store.unarchive_notes(&ids)).await
+        .map(|_| true)
+        .map_err(|e| format!("取消归档失败: {}", e))
+}
+
+#[tauri::command]
+pub async fn get_archived_notes(store: State<'_, StoreState>) -> Result<Vec<NoteResponse>, String> {
+    match store.run(|store| with_progress(store, store.get_archived_notes()?)).await {
+        Ok(notes) => Ok(notes),
+        Err(e) => Err(format!("获取归档便签失败: {}", e)),
     }
+}
 
-    Ok(notes)
+// 清单相关命令
+#[derive(Serialize, Deserialize)]
+pub struct ChecklistItemResponse {
+    pub id: i64,
+    pub note_id: i64,
+    pub content: String,
+    pub done: bool,
+    pub position: i64,
+    pub due_at: Option<u64>,
+    pub created_at: u64,
+}
+
+impl From<db::ChecklistItem> for ChecklistItemResponse {
+    fn from(item: db::ChecklistItem) -> Self {
+        ChecklistItemResponse {
+            id: item.id,
+            note_id: item.note_id,
+            content: item.content,
+            done: item.done,
+            position: item.position,
+            due_at: item.due_at.map(|ts| ts as u64),
+            created_at: item.created_at as u64,
+        }
+    }
 }
 
 #[tauri::command]
-pub async fn search_notes(query: String, category_id: Option<i64>) -> Result<Vec<Note>, String> {
-    let conn = DB_POOL.get().map_err(|e| e.to_string())?;
-    let mut stmt_str = String::from(
-        "SELECT id, content, screenshot_path, color, category_id, created_at FROM notes WHERE content LIKE ?"
-    );
-    
-    if let Some(cat_id) = category_id {
-        stmt_str.push_str(" AND category_id = ?");
+pub async fn get_checklist_items(note_id: i64, store: State<'_, StoreState>) -> Result<Vec<ChecklistItemResponse>, String> {
+    match store.run(move |store| store.get_checklist_items(note_id)).await {
+        Ok(items) => Ok(items.into_iter().map(ChecklistItemResponse::from).collect()),
+        Err(e) => Err(format!("获取清单条目失败: {}", e)),
     }
-    stmt_str.push_str(" ORDER BY created_at DESC");
+}
 
-    let mut stmt = conn.prepare(&stmt_str).map_err(|e| e.to_string())?;
-    
-    let search_pattern = format!("%{}%", query);
-    let rows = if let Some(cat_id) = category_id {
-        stmt.query_map(params![search_pattern, cat_id], |row| {
-            Ok(Note {
-                id: row.get(0)?,
-                content: row.get(1)?,
-                screenshot_path: row.get(2)?,
-                color: row.get(3)?,
-                category_id: row.get(4)?,
-                created_at: row.get(5)?,
-            })
-        })
-    } else {
-        stmt.query_map(params![search_pattern], |row| {
-            Ok(Note {
-                id: row.get(0)?,
-                content: row.get(1)?,
-                screenshot_path: row.get(2)?,
-                color: row.get(3)?,
-                category_id: row.get(4)?,
-                created_at: row.get(5)?,
-            })
-        })
-    }.map_err(|e| e.to_string())?;
+#[tauri::command]
+pub async fn add_checklist_item(
+    note_id: i64,
+    content: String,
+    due_at: Option<u64>,
+    store: State<'_, StoreState>,
+) -> Result<i64, String> {
+    store.run(move |store| store.add_checklist_item(note_id, &content, due_at.map(|ts| ts as i64))).await
+        .map_err(|e| format!("添加清单条目失败: {}", e))
+}
 
-    let mut notes = Vec::new();
-    for note in rows {
-        notes.push(note.map_err(|e| e.to_string())?);
+#[tauri::command]
+pub async fn update_checklist_item(
+    id: i64,
+    content: String,
+    due_at: Option<u64>,
+    store: State<'_, StoreState>,
+) -> Result<bool, String> {
+    match store.run(move |store| store.update_checklist_item(id, &content, due_at.map(|ts| ts as i64))).await {
+        Ok(_) => Ok(true),
+        Err(e) => Err(format!("更新清单条目失败: {}", e)),
     }
+}
 
-    Ok(notes)
+// 切换条目完成状态，返回新的状态
+#[tauri::command]
+pub async fn toggle_checklist_item(id: i64, store: State<'_, StoreState>) -> Result<bool, String> {
+    store.run(move |store| store.toggle_checklist_item(id)).await
+        .map_err(|e| format!("更新清单条目失败: {}", e))
 }
 
+// 保存拖拽排序结果，item_ids 为按新顺序排列的条目ID
 #[tauri::command]
-pub async fn toggle_note_pin(id: i64, db: State<'_, DatabaseState>) -> Result<(), String> {
-    db.0.lock()
-        .unwrap()
-        .toggle_note_pin(id)
-        .map_err(|e| e.to_string())
+pub async fn reorder_checklist_items(note_id: i64, item_ids: Vec<i64>, store: State<'_, StoreState>) -> Result<bool, String> {
+    match store.run(move |store| store.reorder_checklist_items(note_id, &item_ids)).await {
+        Ok(_) => Ok(true),
+        Err(e) => Err(format!("保存条目顺序失败: {}", e)),
+    }
 }
 
-// 软删除便签
 #[tauri::command]
-pub async fn move_notes_to_trash(ids: Vec<i64>, db: State<'_, DatabaseState>) -> Result<(), String> {
-    db.0.lock()
-        .unwrap()
-        .move_notes_to_trash(&ids)
-        .map_err(|e| e.to_string())
+pub async fn delete_checklist_item(id: i64, store: State<'_, StoreState>) -> Result<bool, String> {
+    match store.run(move |store| store.delete_checklist_item(id)).await {
+        Ok(_) => Ok(true),
+        Err(e) => Err(format!("删除清单条目失败: {}", e)),
+    }
 }
 
-// 从回收站恢复便签
+// 将文本便签中的 - [ ] 任务列表转换为清单条目
 #[tauri::command]
-pub async fn restore_notes_from_trash(ids: Vec<i64>, db: State<'_, DatabaseState>) -> Result<(), String> {
-    db.0.lock()
-        .unwrap()
-        .restore_notes_from_trash(&ids)
-        .map_err(|e| e.to_string())
+pub async fn convert_note_to_checklist(id: i64, store: State<'_, StoreState>) -> Result<bool, String> {
+    match store.run(move |store| store.convert_to_checklist(id)).await {
+        Ok(_) => Ok(true),
+        Err(e) => Err(format!("转换清单失败: {}", e)),
+    }
+}
+
+// 链接相关命令
+#[derive(Serialize, Deserialize)]
+pub struct NoteLinkResponse {
+    pub source_id: i64,
+    pub source_title: String,
+    pub target_id: Option<i64>,
+    pub target_title: String,
+    pub dangling: bool,
+}
+
+impl From<db::NoteLink> for NoteLinkResponse {
+    fn from(link: db::NoteLink) -> Self {
+        NoteLinkResponse {
+            source_id: link.source_id,
+            source_title: link.source_title,
+            target_id: link.target_id,
+            target_title: link.target_title,
+            dangling: link.dangling,
+        }
+    }
 }
 
-// 永久删除便签
 #[tauri::command]
-pub async fn permanently_delete_notes(ids: Vec<i64>, db: State<'_, DatabaseState>) -> Result<(), String> {
-    db.0.lock()
-        .unwrap()
-        .permanently_delete_notes(&ids)
-        .map_err(|e| e.to_string())
+pub async fn get_note_links(note_id: i64, store: State<'_, StoreState>) -> Result<Vec<NoteLinkResponse>, String> {
+    match store.run(move |store| store.get_outgoing_links(note_id)).await {
+        Ok(links) => Ok(links.into_iter().map(NoteLinkResponse::from).collect()),
+        Err(e) => Err(format!("获取便签链接失败: {}", e)),
+    }
 }
 
-// 获取回收站中的便签
-#[tauri::command]
-pub async fn get_trash_notes(db: State<'_, DatabaseState>) -> Result<Vec<NoteResponse>, String> {
-    db.0.lock()
-        .unwrap()
-        .get_trash_notes()
-        .map(|notes| {
-            notes
-                .into_iter()
-                .map(|note| NoteResponse {
-                    id: note.id,
-                    content: note.content,
-                    screenshot_path: note.screenshot_path,
-                    created_at: note.created_at
-                        .duration_since(std::time::UNIX_EPOCH)
-                        .unwrap()
-                        .as_secs(),
-                    updated_at: note.updated_at
-                        .duration_since(std::time::UNIX_EPOCH)
-                        .unwrap()
-                        .as_secs(),
-                    deleted_at: note.deleted_at.map(|t| {
-                        t.duration_since(std::time::UNIX_EPOCH)
-                            .unwrap()
-                            .as_secs()
-                    }),
-                    color: note.color,
-                    category_id: note.category_id,
-                    is_pinned: note.is_pinned,
-                })
-                .collect()
-        })
-        .map_err(|e| e.to_string())
+#[tauri::command]
+pub async fn get_note_backlinks(note_id: i64, store: State<'_, StoreState>) -> Result<Vec<NoteLinkResponse>, String> {
+    match store.run(move |store| store.get_backlinks(note_id)).await {
+        Ok(links) => Ok(links.into_iter().map(NoteLinkResponse::from).collect()),
+        Err(e) => Err(format!("获取反向链接失败: {}", e)),
+    }
 }
 
-// 清理回收站中超过30天的便签
+// 回收站相关命令
+// 返回因此失效的链接，即其他便签中指向被删除便签的链接
 #[tauri::command]
-pub async fn cleanup_trash(db: State<'_, DatabaseState>) -> Result<(), String> {
-    db.0.lock()
-        .unwrap()
-        .cleanup_trash()
-        .map_err(|e| e.to_string())
+pub async fn move_notes_to_trash(ids: Vec<i64>, store: State<'_, StoreState>) -> Result<Vec<NoteLinkResponse>, String> {
+    let now = Utc::now().timestamp();
+    
+    let dangling = store.run(move |store| {
+        for id in &ids {
+            store.move_note_to_trash(*id, now)?;
+        }
+        
+        let mut dangling = Vec::new();
+        for id in &ids {
+            dangling.extend(store.get_backlinks(*id)?);
+        }
+        Ok(dangling)
+    }).await;
+    
+    match dangling {
+        Ok(links) => Ok(links.into_iter().map(NoteLinkResponse::from).collect()),
+        Err(e) => Err(format!("移动到回收站失败: {}", e)),
+    }
 }
 
-#[derive(serde::Serialize)]
-pub struct ReminderResponse {
-    pub id: i64,
-    pub note_id: i64,
-    pub remind_at: u64,
-    pub is_triggered: bool,
-    pub created_at: u64,
-    pub note_content: String,
+#[tauri::command]
+pub async fn restore_notes_from_trash(ids: Vec<i64>, store: State<'_, StoreState>) -> Result<bool, String> {
+    store.run(move |store| ids.into_iter().try_for_each(|id| store.restore_note_from_trash(id))).await
+        .map(|_| true)
+        .map_err(|e| format!("恢复便签失败: {}", e))
 }
 
 #[tauri::command]
-pub async fn add_reminder(note_id: i64, remind_at: u64, db: State<'_, DatabaseState>) -> Result<i64, String> {
-    let remind_time = SystemTime::UNIX_EPOCH + Duration::from_secs(remind_at);
-    db.0.lock()
-        .unwrap()
-        .add_reminder(note_id, remind_time)
-        .map_err(|e| e.to_string())
+pub async fn permanently_delete_notes(ids: Vec<i64>, store: State<'_, StoreState>) -> Result<bool, String> {
+    store.run(move |store| ids.into_iter().try_for_each(|id| store.delete_note_permanently(id))).await
+        .map(|_| true)
+        .map_err(|e| format!("永久删除便签失败: {}", e))
 }
 
 #[tauri::command]
-pub async fn get_pending_reminders(db: State<'_, DatabaseState>) -> Result<Vec<ReminderResponse>, String> {
-    db.0.lock()
-        .unwrap()
-        .get_pending_reminders()
-        .map(|reminders| {
-            reminders
-                .into_iter()
-                .map(|(reminder, content)| ReminderResponse {
-                    id: reminder.id,
-                    note_id: reminder.note_id,
-                    remind_at: reminder.remind_at
-                        .duration_since(std::time::UNIX_EPOCH)
-                        .unwrap()
-                        .as_secs(),
-                    is_triggered: reminder.is_triggered,
-                    created_at: reminder.created_at
-                        .duration_since(std::time::UNIX_EPOCH)
-                        .unwrap()
-                        .as_secs(),
-                    note_content: content,
-                })
-                .collect()
-        })
-        .map_err(|e| e.to_string())
+pub async fn get_trash_notes(store: State<'_, StoreState>) -> Result<Vec<NoteResponse>, String> {
+    match store.run(|store| with_progress(store, store.get_trash_notes()?)).await {
+        Ok(notes) => Ok(notes),
+        Err(e) => Err(format!("获取回收站便签失败: {}", e)),
+    }
 }
 
+// 清理回收站中超过指定天数（默认30天）的便签，返回删除数量
 #[tauri::command]
-pub async fn mark_reminder_triggered(id: i64, db: State<'_, DatabaseState>) -> Result<(), String> {
-    db.0.lock()
-        .unwrap()
-        .mark_reminder_triggered(id)
-        .map_err(|e| e.to_string())
+pub async fn cleanup_trash(days: Option<i64>, store: State<'_, StoreState>) -> Result<i32, String> {
+    let days = days.unwrap_or(30);
+    store.run(move |store| store.cleanup_trash(days)).await
+        .map_err(|e| format!("清理回收站失败: {}", e))
+}
+
+// 列表中清单便签的ID
+fn checklist_ids<'a>(notes: impl Iterator<Item = &'a Note>) -> Vec<i64> {
+    notes.filter(|n| n.note_type == NoteType::Checklist).filter_map(|n| n.id).collect()
 }
 
+// 转换便签列表，并附上清单便签的完成进度
+fn with_progress(store: &dyn NoteStore, notes: Vec<Note>) -> Result<Vec<NoteResponse>, IoError> {
+    let progress = store.get_checklist_progress(&checklist_ids(notes.iter()))?;
+    Ok(notes.into_iter().map(|note| note_to_response(note, &progress)).collect())
+}
+
+fn note_to_response(note: Note, progress: &HashMap<i64, ChecklistProgress>) -> NoteResponse {
+    let note_progress = match note.note_type {
+        NoteType::Checklist => {
+            let p = note.id.and_then(|id| progress.get(&id)).copied().unwrap_or_default();
+            Some(ChecklistProgressResponse { done: p.done, total: p.total })
+        }
+        NoteType::Text => None,
+    };
+    
+    NoteResponse {
+        id: note.id.unwrap_or(0),
+        content: note.content,
+        screenshot_path: note.screenshot_path,
+        created_at: note.created_at as u64,
+        updated_at: note.updated_at.map(|ts| ts as u64),
+        is_pinned: note.is_pinned,
+        color: note.color,
+        category_id: note.category_id,
+        deleted_at: note.deleted_at.map(|ts| ts as u64),
+        archived_at: note.archived_at.map(|ts| ts as u64),
+        position: note.position,
+        note_type: note.note_type.as_str().to_string(),
+        content_format: note.content_format.as_str().to_string(),
+        progress: note_progress,
+    }
+}
+
+fn category_to_response(category: Category) -> CategoryResponse {
+    CategoryResponse {
+        id: category.id.unwrap_or(0),
+        name: category.name,
+        color: category.color,
+        parent_id: category.parent_id,
+        position: category.position,
+    }
+}
+
+fn category_tree_to_response(node: db::CategoryNode) -> CategoryTreeResponse {
+    CategoryTreeResponse {
+        category: category_to_response(node.category),
+        children: node.children.into_iter().map(category_tree_to_response).collect(),
+    }
+}
+
+// 分类相关命令
 #[tauri::command]
-pub async fn delete_reminder(id: i64, db: State<'_, DatabaseState>) -> Result<(), String> {
-    db.0.lock()
-        .unwrap()
-        .delete_reminder(id)
-        .map_err(|e| e.to_string())
+pub async fn create_category(
+    name: String,
+    color: Option<String>,
+    parent_id: Option<i64>,
+    store: State<'_, StoreState>,
+) -> Result<i64, String> {
+    let category = Category {
+        id: None,
+        name,
+        color,
+        parent_id,
+        position: 0,  // 插入时排在同级分类末尾
+    };
+    
+    store.run(move |store| store.save_category(&category)).await
+        .map_err(|e| format!("创建分类失败: {}", e))
 }
 
 #[tauri::command]
-pub async fn get_reminders_by_note(note_id: i64, db: State<'_, DatabaseState>) -> Result<Vec<ReminderResponse>, String> {
-    db.0.lock()
-        .unwrap()
-        .get_reminders_by_note(note_id)
-        .map(|reminders| {
-            reminders
-                .into_iter()
-                .map(|reminder| ReminderResponse {
-                    id: reminder.id,
-                    note_id: reminder.note_id,
-                    remind_at: reminder.remind_at
-                        .duration_since(std::time::UNIX_EPOCH)
-                        .unwrap()
-                        .as_secs(),
-                    is_triggered: reminder.is_triggered,
-                    created_at: reminder.created_at
-                        .duration_since(std::time::UNIX_EPOCH)
-                        .unwrap()
-                        .as_secs(),
-                    note_content: String::new(), // 这里不需要内容
-                })
-                .collect()
+pub async fn update_category(id: i64, name: String, color: Option<String>, store: State<'_, StoreState>) -> Result<bool, String> {
+    store.run(move |store| {
+        let existing = store.get_all_categories()?
+            .into_iter()
+            .find(|c| c.id == Some(id))
+            .ok_or_else(|| IoError::new(ErrorKind::NotFound, "分类不存在"))?;
+        
+        store.save_category(&Category {
+            id: Some(id),
+            name,
+            color,
+            parent_id: existing.parent_id,
+            position: existing.position,
         })
-        .map_err(|e| e.to_string())
+    }).await
+        .map(|_| true)
+        .map_err(|e| format!("更新分类失败: {}", e))
 }
 
 #[tauri::command]
-pub async fn update_reminder(id: i64, remind_at: u64, db: State<'_, DatabaseState>) -> Result<(), String> {
-    let remind_time = SystemTime::UNIX_EPOCH + Duration::from_secs(remind_at);
-    db.0.lock()
-        .unwrap()
-        .update_reminder(id, remind_time)
-        .map_err(|e| e.to_string())
+pub async fn get_all_categories(store: State<'_, StoreState>) -> Result<Vec<CategoryResponse>, String> {
+    match store.run(|store| store.get_all_categories()).await {
+        Ok(categories) => Ok(categories.into_iter().map(category_to_response).collect()),
+        Err(e) => Err(format!("获取分类失败: {}", e)),
+    }
 }
 
-// 初始化数据库
 #[tauri::command]
-pub fn init_database() -> Result<bool, String> {
-    match db::init_db() {
+pub async fn get_category_tree(store: State<'_, StoreState>) -> Result<Vec<CategoryTreeResponse>, String> {
+    match store.run(|store| store.get_category_tree()).await {
+        Ok(tree) => Ok(tree.into_iter().map(category_tree_to_response).collect()),
+        Err(e) => Err(format!("获取分类树失败: {}", e)),
+    }
+}
+
+// 移动分类及其子分类，parent_id 为空时移动到顶级
+#[tauri::command]
+pub async fn move_category(id: i64, parent_id: Option<i64>, store: State<'_, StoreState>) -> Result<bool, String> {
+    match store.run(move |store| store.move_category(id, parent_id)).await {
         Ok(_) => Ok(true),
-        Err(e) => Err(format!("初始化数据库失败: {}", e)),
+        Err(e) => Err(format!("移动分类失败: {}", e)),
     }
 }
 
-// 便签相关命令
+// 删除分类，delete_children 为 true 时连同子分类一起删除，否则子分类上移一级
 #[tauri::command]
-pub fn create_note(content: String, screenshot_path: Option<String>, category_id: Option<i64>) -> Result<i64, String> {
-    let now = Utc::now().timestamp() as i64;
-    
-    let note = db::Note {
-        id: None,
-        content,
-        screenshot_path,
-        created_at: now,
-        updated_at: Some(now),
-        is_pinned: false,
-        color: None,
-        category_id,
-        deleted_at: None,
+pub async fn delete_category(id: i64, delete_children: Option<bool>, store: State<'_, StoreState>) -> Result<bool, String> {
+    let children = if delete_children.unwrap_or(false) {
+        ChildCategoryAction::Delete
+    } else {
+        ChildCategoryAction::Reparent
     };
     
-    match db::save_note(&note) {
-        Ok(id) => Ok(id),
-        Err(e) => Err(format!("创建便签失败: {}", e)),
+    match store.run(move |store| store.delete_category(id, children)).await {
+        Ok(_) => Ok(true),
+        Err(e) => Err(format!("删除分类失败: {}", e)),
     }
 }
 
 #[tauri::command]
-pub fn update_note(
-    id: i64, 
-    content: String, 
-    screenshot_path: Option<String>,
-    is_pinned: bool,
-    color: Option<String>,
-    category_id: Option<i64>
+pub async fn get_notes_in_category(
+    category_id: i64,
+    include_descendants: Option<bool>,
+    sort_by: Option<NoteSortOption>,
+    store: State<'_, StoreState>,
+) -> Result<Vec<NoteResponse>, String> {
+    let include_descendants = include_descendants.unwrap_or(true);
+    let sort_by = sort_by.unwrap_or(NoteSortOption::CreatedTimeDesc);
+    
+    let notes = store.run(move |store| {
+        with_progress(store, store.get_notes_in_category(category_id, include_descendants, &sort_by)?)
+    }).await;
+    
+    match notes {
+        Ok(notes) => Ok(notes),
+        Err(e) => Err(format!("获取分类便签失败: {}", e)),
+    }
+}
+
+#[tauri::command]
+pub async fn update_category_order(id: i64, new_order: i32, store: State<'_, StoreState>) -> Result<bool, String> {
+    match store.run(move |store| store.update_category_order(id, new_order)).await {
+        Ok(_) => Ok(true),
+        Err(e) => Err(format!("更新分类排序失败: {}", e)),
+    }
+}
+
+// 保存拖拽排序结果，两个列表分别为按新顺序排列的分类ID和便签ID
+#[tauri::command]
+pub async fn save_manual_order(
+    category_ids: Option<Vec<i64>>,
+    note_ids: Option<Vec<i64>>,
+    store: State<'_, StoreState>,
 ) -> Result<bool, String> {
-    let now = Utc::now().timestamp() as i64;
+    let category_ids = category_ids.unwrap_or_default();
+    let note_ids = note_ids.unwrap_or_default();
     
-    // 先获取现有便签
-    let existing_note = match db::get_note(id) {
-        Ok(Some(note)) => note,
-        Ok(None) => return Err("便签不存在".to_string()),
-        Err(e) => return Err(format!("获取便签失败: {}", e)),
+    match store.run(move |store| store.save_manual_order(&category_ids, &note_ids)).await {
+        Ok(_) => Ok(true),
+        Err(e) => Err(format!("保存排序失败: {}", e)),
+    }
+}
+
+// 提醒相关命令
+#[derive(Serialize, Deserialize, Clone)]
+pub struct ReminderResponse {
+    pub id: i64,
+    pub note_id: i64,
+    pub remind_at: u64,
+    pub is_triggered: bool,
+    pub note_content: String,
+    pub rrule: Option<String>,
+}
+
+// 即将到来的一次提醒，重复提醒会展开为多次
+#[derive(Serialize, Deserialize)]
+pub struct ReminderOccurrenceResponse {
+    pub reminder_id: i64,
+    pub note_id: i64,
+    pub remind_at: u64,
+    pub note_content: String,
+    pub rrule: Option<String>,
+}
+
+pub fn reminder_to_response(reminder: NoteReminder, note: Note) -> ReminderResponse {
+    ReminderResponse {
+        id: reminder.id.unwrap_or(0),
+        note_id: reminder.note_id,
+        remind_at: reminder.reminder_time as u64,
+        is_triggered: reminder.completed,
+        note_content: note.content,
+        rrule: reminder.recurrence.map(|r| r.rrule),
+    }
+}
+
+fn note_reminder_to_response(reminder: NoteReminder) -> NoteReminderResponse {
+    NoteReminderResponse {
+        id: reminder.id.unwrap_or(0),
+        note_id: reminder.note_id,
+        reminder_time: reminder.reminder_time as u64,
+        completed: reminder.completed,
+        rrule: reminder.recurrence.as_ref().map(|r| r.rrule.clone()),
+        timezone: reminder.recurrence.and_then(|r| r.timezone),
+    }
+}
+
+// 根据命令参数构造重复规则，返回规则和第一次提醒的时间；
+// 第一次提醒是 remind_at 当时或之后第一个符合规则的时间，remind_at 决定每次提醒的当地时刻
+fn build_recurrence(remind_at: i64, rrule: Option<String>, timezone: Option<String>) -> Result<(Option<Recurrence>, i64), String> {
+    let rrule = match rrule.filter(|rule| !rule.trim().is_empty()) {
+        Some(rrule) => rrule,
+        None => return Ok((None, remind_at)),
+    };
+
+    let recurrence = Recurrence {
+        rrule,
+        timezone: timezone.filter(|tz| !tz.trim().is_empty()),
+        dtstart: remind_at,
+    };
+    let first = recurrence::next_occurrence(&recurrence, remind_at - 1)
+        .map_err(|e| format!("重复规则无效: {}", e))?
+        .ok_or_else(|| "重复规则没有任何提醒时间".to_string())?;
+    Ok((Some(recurrence), first))
+}
+
+#[tauri::command]
+pub async fn add_reminder(
+    note_id: i64,
+    remind_at: u64,
+    rrule: Option<String>,
+    timezone: Option<String>,
+    store: State<'_, StoreState>,
+) -> Result<i64, String> {
+    let (recurrence, reminder_time) = build_recurrence(remind_at as i64, rrule, timezone)?;
+    let reminder = NoteReminder {
+        id: None,
+        note_id,
+        reminder_time,
+        completed: false,
+        recurrence,
     };
     
-    let note = db::Note {
+    store.run(move |store| store.save_reminder(&reminder)).await
+        .map_err(|e| format!("添加提醒失败: {}", e))
+}
+
+// 修改提醒时间和重复规则，提醒会重新变为未触发状态
+#[tauri::command]
+pub async fn update_reminder(
+    id: i64,
+    note_id: i64,
+    remind_at: u64,
+    rrule: Option<String>,
+    timezone: Option<String>,
+    store: State<'_, StoreState>,
+) -> Result<bool, String> {
+    let (recurrence, reminder_time) = build_recurrence(remind_at as i64, rrule, timezone)?;
+    let reminder = NoteReminder {
         id: Some(id),
-        content,
-        screenshot_path,
-        created_at: existing_note.created_at,
-        updated_at: Some(now),
-        is_pinned,
-        color,
-        category_id,
-        deleted_at: existing_note.deleted_at,
+        note_id,
+        reminder_time,
+        completed: false,
+        recurrence,
     };
     
-    match db::save_note(&note) {
+    match store.run(move |store| store.save_reminder(&reminder)).await {
         Ok(_) => Ok(true),
-        Err(e) => Err(format!("更新便签失败: {}", e)),
+        Err(e) => Err(format!("更新提醒失败: {}", e)),
     }
 }
 
 #[tauri::command]
-pub fn get_note_by_id(id: i64) -> Result<Option<NoteResponse>, String> {
-    match db::get_note(id) {
-        Ok(Some(note)) => Ok(Some(NoteResponse {
-            id: note.id.unwrap(),
-            content: note.content,
-            screenshot_path: note.screenshot_path,
-            created_at: note.created_at as u64,
-            updated_at: note.updated_at.map(|ts| ts as u64),
-            is_pinned: note.is_pinned,
-            color: note.color,
-            category_id: note.category_id,
-            deleted_at: note.deleted_at.map(|ts| ts as u64),
-        })),
-        Ok(None) => Ok(None),
-        Err(e) => Err(format!("获取便签失败: {}", e)),
+pub async fn delete_reminder(id: i64, store: State<'_, StoreState>) -> Result<bool, String> {
+    match store.run(move |store| store.delete_reminder(id)).await {
+        Ok(_) => Ok(true),
+        Err(e) => Err(format!("删除提醒失败: {}", e)),
     }
 }
 
 #[tauri::command]
-pub fn get_notes_sorted(limit: Option<i64>, offset: Option<i64>, search: Option<String>) -> Result<Vec<NoteResponse>, String> {
-    let search_ref = search.as_deref();
-    
-    match db::get_notes_sorted(limit, offset, search_ref) {
-        Ok(notes) => {
-            let responses = notes.into_iter().map(|note| NoteResponse {
-                id: note.id.unwrap(),
-                content: note.content,
-                screenshot_path: note.screenshot_path,
-                created_at: note.created_at as u64,
-                updated_at: note.updated_at.map(|ts| ts as u64),
-                is_pinned: note.is_pinned,
-                color: note.color,
-                category_id: note.category_id,
+pub async fn get_reminders_by_note(note_id: i64, store: State<'_, StoreState>) -> Result<Vec<NoteReminderResponse>, String> {
+    match store.run(move |store| store.get_reminders_by_note(note_id)).await {
+        Ok(reminders) => Ok(reminders.into_iter().map(note_reminder_to_response).collect()),
+        Err(e) => Err(format!("获取提醒失败: {}", e)),
+    }
+}
+
+// 获取所有未触发的提醒
+#[tauri::command]
+pub async fn get_pending_reminders(limit: Option<i64>, store: State<'_, StoreState>) -> Result<Vec<ReminderResponse>, String> {
+    match store.run(move |store| store.get_upcoming_reminders(limit)).await {
+        Ok(reminders) => Ok(reminders.into_iter()
+            .map(|(reminder, note)| reminder_to_response(reminder, note))
+            .collect()),
+        Err(e) => Err(format!("获取提醒失败: {}", e)),
+    }
+}
+
+#[tauri::command]
+pub async fn mark_reminder_triggered(id: i64, note_id: i64, store: State<'_, StoreState>) -> Result<bool, String> {
+    store.run(move |store| {
+        let mut reminder = store.get_reminders_by_note(note_id)?
+            .into_iter()
+            .find(|r| r.id == Some(id))
+            .ok_or_else(|| IoError::new(ErrorKind::NotFound, "提醒不存在"))?;
+        
+        // 重复提醒改为下一次的时间
+        recurrence::advance(&mut reminder, chrono::Utc::now().timestamp());
+        store.save_reminder(&reminder)
+    }).await
+        .map(|_| true)
+        .map_err(|e| format!("更新提醒失败: {}", e))
+}
+
+// 列出即将到来的提醒，重复提醒展开为每一次，按时间排序
+#[tauri::command]
+pub async fn get_upcoming_occurrences(limit: Option<usize>, store: State<'_, StoreState>) -> Result<Vec<ReminderOccurrenceResponse>, String> {
+    let limit = limit.unwrap_or(20).min(recurrence::MAX_OCCURRENCES);
+    let reminders = store.run(move |store| store.get_upcoming_reminders(None)).await
+        .map_err(|e| format!("获取提醒失败: {}", e))?;
+
+    let mut occurrences = Vec::new();
+    for (reminder, note) in reminders {
+        let mut times = vec![reminder.reminder_time];
+        if let Some(recurrence) = &reminder.recurrence {
+            match recurrence::occurrences(recurrence, reminder.reminder_time, limit.saturating_sub(1)) {
+                Ok(more) => times.extend(more),
+                Err(e) => log::warn!("提醒 {:?} 的重复规则无效: {}", reminder.id, e),
+            }
+        }
+        for time in times {
+            occurrences.push(ReminderOccurrenceResponse {
+                reminder_id: reminder.id.unwrap_or(0),
+                note_id: reminder.note_id,
+                remind_at: time as u64,
+                note_content: note.content.clone(),
+                rrule: reminder.recurrence.as_ref().map(|r| r.rrule.clone()),
             });
-            Ok(responses.collect())
         }
-        Err(e) => Err(e),
+    }
+
+    occurrences.sort_by_key(|occurrence| occurrence.remind_at);
+    occurrences.truncate(limit);
+    Ok(occurrences)
+}
+
+// 预览重复规则接下来的提醒时间，用于编辑提醒时校验和展示
+#[tauri::command]
+pub async fn preview_reminder_occurrences(
+    remind_at: u64,
+    rrule: String,
+    timezone: Option<String>,
+    count: Option<usize>,
+) -> Result<Vec<u64>, String> {
+    let (recurrence, first) = build_recurrence(remind_at as i64, Some(rrule), timezone)?;
+    let mut times = vec![first];
+    if let Some(recurrence) = recurrence {
+        let more = recurrence::occurrences(&recurrence, first, count.unwrap_or(10).saturating_sub(1))
+            .map_err(|e| format!("重复规则无效: {}", e))?;
+        times.extend(more);
+    }
+    Ok(times.into_iter().map(|time| time as u64).collect())
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct TagResponse {
+    pub id: i64,
+    pub name: String,
+    pub note_count: i64,
+}
+
+impl From<db::Tag> for TagResponse {
+    fn from(tag: db::Tag) -> Self {
+        TagResponse {
+            id: tag.id,
+            name: tag.name,
+            note_count: tag.note_count,
+        }
+    }
+}
+
+// 标签相关命令
+#[tauri::command]
+pub async fn add_note_tag(note_id: i64, name: String, store: State<'_, StoreState>) -> Result<i64, String> {
+    store.run(move |store| store.add_tag_to_note(note_id, &name)).await
+        .map_err(|e| format!("添加标签失败: {}", e))
+}
+
+#[tauri::command]
+pub async fn remove_note_tag(note_id: i64, tag_id: i64, store: State<'_, StoreState>) -> Result<bool, String> {
+    store.run(move |store| store.remove_tag_from_note(note_id, tag_id)).await
+        .map(|_| true)
+        .map_err(|e| format!("移除标签失败: {}", e))
+}
+
+#[tauri::command]
+pub async fn get_note_tags(note_id: i64, store: State<'_, StoreState>) -> Result<Vec<TagResponse>, String> {
+    match store.run(move |store| store.get_note_tags(note_id)).await {
+        Ok(tags) => Ok(tags.into_iter().map(TagResponse::from).collect()),
+        Err(e) => Err(format!("获取便签标签失败: {}", e)),
+    }
+}
+
+#[tauri::command]
+pub async fn get_all_tags(store: State<'_, StoreState>) -> Result<Vec<TagResponse>, String> {
+    match store.run(|store| store.get_all_tags()).await {
+        Ok(tags) => Ok(tags.into_iter().map(TagResponse::from).collect()),
+        Err(e) => Err(format!("获取标签失败: {}", e)),
     }
 }
 
+// 重命名标签，返回重命名后的标签ID（与已有标签重名时为合并后的标签）
 #[tauri::command]
-pub async fn run_app() -> Result<bool, String> {
-    Ok(true)
-} 
\ No newline at end of file
+pub async fn rename_tag(id: i64, name: String, store: State<'_, StoreState>) -> Result<i64, String> {
+    store.run(move |store| store.rename_tag(id, &name)).await
+        .map_err(|e| format!("重命名标签失败: {}", e))
+}
+
+#[tauri::command]
+pub async fn merge_tags(source_ids: Vec<i64>, target_id: i64, store: State<'_, StoreState>) -> Result<bool, String> {
+    store.run(move |store| store.merge_tags(&source_ids, target_id)).await
+        .map(|_| true)
+        .map_err(|e| format!("合并标签失败: {}", e))
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct NoteRevisionResponse {
+    pub id: i64,
+    pub note_id: i64,
+    pub content: String,
+    pub created_at: u64,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct DiffLineResponse {
+    pub tag: String,  // "equal" | "insert" | "delete"
+    pub content: String,
+}
+
+// 获取便签的历史版本列表
+#[tauri::command]
+pub async fn get_note_revisions(note_id: i64, store: State<'_, StoreState>) -> Result<Vec<NoteRevisionResponse>, String> {
+    match store.run(move |store| store.get_note_revisions(note_id)).await {
+        Ok(revisions) => Ok(revisions.into_iter().map(|revision| NoteRevisionResponse {
+            id: revision.id,
+            note_id: revision.note_id,
+            content: revision.content,
+            created_at: revision.created_at as u64,
+        }).collect()),
+        Err(e) => Err(format!("获取历史版本失败: {}", e)),
+    }
+}
+
+// 比较两个历史版本，to_revision_id 为空时与便签当前内容比较
+#[tauri::command]
+pub async fn diff_note_revisions(
+    from_revision_id: i64,
+    to_revision_id: Option<i64>,
+    store: State<'_, StoreState>,
+) -> Result<Vec<DiffLineResponse>, String> {
+    let from = match store.run(move |store| store.get_note_revision(from_revision_id)).await {
+        Ok(Some(revision)) => revision,
+        Ok(None) => return Err("历史版本不存在".to_string()),
+        Err(e) => return Err(format!("获取历史版本失败: {}", e)),
+    };
+    
+    let note_id = from.note_id;
+    let to_content = match to_revision_id {
+        Some(id) => match store.run(move |store| store.get_note_revision(id)).await {
+            Ok(Some(revision)) if revision.note_id == note_id => revision.content,
+            Ok(Some(_)) => return Err("只能比较同一便签的历史版本".to_string()),
+            Ok(None) => return Err("历史版本不存在".to_string()),
+            Err(e) => return Err(format!("获取历史版本失败: {}", e)),
+        },
+        None => match store.run(move |store| store.get_note(note_id)).await {
+            Ok(Some(note)) => note.content,
+            Ok(None) => return Err("便签不存在".to_string()),
+            Err(e) => return Err(format!("获取便签失败: {}", e)),
+        },
+    };
+    
+    db::run_blocking(move || {
+        Ok(db::diff_lines(&from.content, &to_content)
+            .into_iter()
+            .map(|line| DiffLineResponse {
+                tag: match line.tag {
+                    db::DiffTag::Equal => "equal",
+                    db::DiffTag::Insert => "insert",
+                    db::DiffTag::Delete => "delete",
+                }.to_string(),
+                content: line.content,
+            })
+            .collect())
+    }).await
+        .map_err(|e| format!("比较历史版本失败: {}", e))
+}
+
+// 将历史版本恢复为便签当前内容
+#[tauri::command]
+pub async fn restore_note_revision(revision_id: i64, store: State<'_, StoreState>) -> Result<bool, String> {
+    store.run(move |store| store.restore_note_revision(revision_id)).await
+        .map(|_| true)
+        .map_err(|e| format!("恢复历史版本失败: {}", e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::memory_store::MemoryStore;
+    use tauri::test::{mock_app, MockRuntime};
+    use tauri::{App, Manager};
+    
+    fn app() -> App<MockRuntime> {
+        let app = mock_app();
+        app.manage(StoreState::new(MemoryStore::new()));
+        app
+    }
+    
+    fn note(app: &App<MockRuntime>, content: &str) -> i64 {
+        tauri::async_runtime::block_on(create_note(content.to_string(), None, None, None, None, app.state())).unwrap()
+    }
+    
+    #[test]
+    fn filters_notes_by_inline_and_manual_tags() {
+        let app = app();
+        let work = note(&app, "周计划 #工作");
+        let shopping = note(&app, "购物清单");
+        tauri::async_runtime::block_on(async {
+            let tags = get_all_tags(app.state()).await.unwrap();
+            assert_eq!(tags.len(), 1);
+            assert_eq!(tags[0].name, "工作");
+            let tag_id = tags[0].id;
+            
+            let notes = get_notes_sorted(None, None, None, None, Some(vec![tag_id]), app.state()).await.unwrap();
+            assert_eq!(notes.iter().map(|n| n.id).collect::<Vec<_>>(), vec![work]);
+            
+            add_note_tag(shopping, "工作".to_string(), app.state()).await.unwrap();
+            let notes = get_notes_sorted(None, None, None, None, Some(vec![tag_id]), app.state()).await.unwrap();
+            assert_eq!(notes.len(), 2);
+            
+            // 手动添加的标签不会因内容中没有 #标签 而被移除
+            update_note(shopping, "购物清单\n牛奶".to_string(), None, false, None, None, None, app.state()).await.unwrap();
+            assert_eq!(get_note_tags(shopping, app.state()).await.unwrap().len(), 1);
+            
+            remove_note_tag(shopping, tag_id, app.state()).await.unwrap();
+            let notes = get_notes_sorted(None, None, None, None, Some(vec![tag_id]), app.state()).await.unwrap();
+            assert_eq!(notes.len(), 1);
+        });
+    }
+    
+    #[test]
+    fn renaming_a_tag_rewrites_content_and_can_be_reverted() {
+        let app = app();
+        let id = note(&app, "周一开会 #会议");
+        tauri::async_runtime::block_on(async {
+            let tag_id = get_all_tags(app.state()).await.unwrap()[0].id;
+            rename_tag(tag_id, "例会".to_string(), app.state()).await.unwrap();
+            
+            let note = get_note_by_id(id, app.state()).await.unwrap().unwrap();
+            assert_eq!(note.content, "周一开会 #例会");
+            
+            let revisions = get_note_revisions(id, app.state()).await.unwrap();
+            assert_eq!(revisions.len(), 1);
+            assert_eq!(revisions[0].content, "周一开会 #会议");
+            
+            let diff = diff_note_revisions(revisions[0].id, None, app.state()).await.unwrap();
+            assert!(diff.iter().any(|line| line.tag == "insert" && line.content == "周一开会 #例会"));
+            
+            restore_note_revision(revisions[0].id, app.state()).await.unwrap();
+            let note = get_note_by_id(id, app.state()).await.unwrap().unwrap();
+            assert_eq!(note.content, "周一开会 #会议");
+        });
+    }
+    
+    #[test]
+    fn merging_tags_moves_notes_to_the_target() {
+        let app = app();
+        let first = note(&app, "#工作 周报");
+        let second = note(&app, "#公司 例会");
+        tauri::async_runtime::block_on(async {
+            let tags = get_all_tags(app.state()).await.unwrap();
+            let id_of = |name: &str| tags.iter().find(|t| t.name == name).unwrap().id;
+            merge_tags(vec![id_of("公司")], id_of("工作"), app.state()).await.unwrap();
+            
+            let tags = get_all_tags(app.state()).await.unwrap();
+            assert_eq!(tags.len(), 1);
+            assert_eq!(tags[0].note_count, 2);
+            let note = get_note_by_id(second, app.state()).await.unwrap().unwrap();
+            assert_eq!(note.content, "#工作 例会");
+            assert!(get_note_by_id(first, app.state()).await.unwrap().is_some());
+        });
+    }
+    
+    #[test]
+    fn search_highlights_terms_and_skips_trashed_notes() {
+        let app = app();
+        let kept = note(&app, "明天买牛奶");
+        let trashed = note(&app, "牛奶过期了");
+        tauri::async_runtime::block_on(async {
+            move_notes_to_trash(vec![trashed], app.state()).await.unwrap();
+            
+            let results = search_notes("牛奶".to_string(), None, None, app.state()).await.unwrap();
+            assert_eq!(results.len(), 1);
+            assert_eq!(results[0].note.id, kept);
+            assert!(results[0].snippet.contains("<mark>牛奶</mark>"));
+            
+            assert!(search_notes("  ".to_string(), None, None, app.state()).await.unwrap().is_empty());
+        });
+    }
+    
+    #[test]
+    fn attachments_stay_linked_until_removed() {
+        let app = app();
+        let id = note(&app, "附件");
+        tauri::async_runtime::block_on(async {
+            let attachment = app.state::<StoreState>()
+                .run(|store| store.add_attachment(b"image", "image/png", None)).await
+                .unwrap();
+            add_note_attachment(id, attachment.id, app.state()).await.unwrap();
+            let linked = app.state::<StoreState>().run(move |store| store.get_note_attachments(id)).await.unwrap();
+            assert_eq!(linked.len(), 1);
+            assert_eq!(linked[0].ref_count, 1);
+            
+            remove_note_attachment(id, attachment.id, app.state()).await.unwrap();
+            let linked = app.state::<StoreState>().run(move |store| store.get_note_attachments(id)).await.unwrap();
+            assert!(linked.is_empty());
+            // 新附件在宽限期内不会被回收
+            assert_eq!(collect_attachment_garbage(app.state()).await.unwrap(), 0);
+        });
+    }
+    
+    #[test]
+    fn rotating_the_key_of_an_unencrypted_store_returns_no_recovery_key() {
+        let app = app();
+        let recovery_key = tauri::async_runtime::block_on(rotate_encryption_key(None, app.state())).unwrap();
+        assert!(recovery_key.is_none());
+    }
+}
@@ -1,17 +1,58 @@
-use rusqlite::{params, Connection, Result};
-use std::path::PathBuf;
-use std::sync::Mutex;
+use rusqlite::{params, params_from_iter, Connection, OptionalExtension, Result, Row};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::ops::Deref;
+use std::sync::{Mutex, MutexGuard, PoisonError};
+use std::time::Duration;
+use std::sync::atomic::{AtomicBool, AtomicI64, AtomicU64, Ordering};
 use once_cell::sync::Lazy;
+use crate::attachments;
+use crate::auto_backup;
+use crate::checklist;
 use crate::key_manager;
+use crate::migrations;
 use std::io::{Error as IoError, ErrorKind};
 
-// 使用单例模式管理数据库连接
-static DB_CONNECTION: Lazy<Mutex<Option<Connection>>> = Lazy::new(|| Mutex::new(None));
+// 数据库连接池，WAL 模式下读操作可以与写操作并行
+static DB_POOL: Lazy<Mutex<Option<ConnectionPool>>> = Lazy::new(|| Mutex::new(None));
+
+// 连接池的代数，关闭后重新打开也不会与旧连接的代数重复
+static POOL_GENERATION: AtomicU64 = AtomicU64::new(0);
+
+// 数据库因应用锁定而关闭
+static DB_LOCKED: AtomicBool = AtomicBool::new(false);
+
+// 本次运行中已经执行过迁移，解锁后重新打开数据库时不再执行
+static DB_MIGRATED: AtomicBool = AtomicBool::new(false);
+
+// 连接池中最多保留的空闲连接数，超出的连接用完后直接关闭
+const MAX_IDLE_CONNECTIONS: usize = 4;
+
+// 写事务等待其他连接释放写锁的最长时间（毫秒）
+const BUSY_TIMEOUT_MS: u64 = 5000;
 
 // 数据库文件名
 const DB_FILENAME: &str = "stickynotes.db";
 
+// 每个便签保留的历史版本数量，由设置中的 history.max_revisions 更新
+pub const DEFAULT_REVISION_LIMIT: i64 = 50;
+static REVISION_LIMIT: AtomicI64 = AtomicI64::new(DEFAULT_REVISION_LIMIT);
+
+// 未被引用的附件在创建后至少保留的秒数，避免回收刚粘贴、尚未保存到便签的图片
+pub const ATTACHMENT_GC_GRACE_SECS: i64 = 24 * 60 * 60;
+
+// trigram 分词器只能索引至少三个字符的关键词，更短的词退回 LIKE 查询
+const FTS_MIN_TERM_CHARS: usize = 3;
+
+// 搜索摘要中命中词前后保留的字符数
+const SNIPPET_CONTEXT_CHARS: usize = 24;
+
+// 摘要高亮标记，使用私有区字符以便在转义HTML后替换为<mark>
+const MARK_START: char = '\u{E000}';
+const MARK_END: char = '\u{E001}';
+
 // 数据结构定义
+#[derive(Clone)]
 pub struct Note {
     pub id: Option<i64>,
     pub content: String,
@@ -22,78 +63,228 @@ pub struct Note {
     pub color: Option<String>,
     pub category_id: Option<i64>,
     pub deleted_at: Option<i64>,  // 软删除标记
+    pub position: i64,            // 手动排序位置，在同一分类内从小到大排列
+    pub note_type: NoteType,
+    pub content_format: ContentFormat,
+    pub archived_at: Option<i64>,  // 归档时间，归档的便签不在主列表中显示，但仍可搜索
+}
+
+// 便签类型
+#[derive(Clone, Copy, PartialEq, serde::Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum NoteType {
+    Text,       // 普通文本便签
+    Checklist,  // 清单便签，条目保存在 checklist_items 表中，content 作为标题
+}
+
+impl NoteType {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            NoteType::Text => "text",
+            NoteType::Checklist => "checklist",
+        }
+    }
+    
+    pub fn from_name(name: &str) -> Self {
+        match name {
+            "checklist" => NoteType::Checklist,
+            _ => NoteType::Text,
+        }
+    }
+}
+
+// 便签内容格式
+#[derive(Clone, Copy, PartialEq, serde::Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ContentFormat {
+    Plain,     // 纯文本
+    Markdown,
+}
+
+impl ContentFormat {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ContentFormat::Plain => "plain",
+            ContentFormat::Markdown => "markdown",
+        }
+    }
+    
+    pub fn from_name(name: &str) -> Self {
+        match name {
+            "markdown" => ContentFormat::Markdown,
+            _ => ContentFormat::Plain,
+        }
+    }
+}
+
+// 清单条目
+#[derive(Clone)]
+pub struct ChecklistItem {
+    pub id: i64,
+    pub note_id: i64,
+    pub content: String,
+    pub done: bool,
+    pub position: i64,         // 在清单中的顺序，从小到大排列
+    pub due_at: Option<i64>,   // 截止时间（Unix时间戳）
+    pub created_at: i64,
 }
 
+// 清单完成进度
+#[derive(Clone, Copy, Default)]
+pub struct ChecklistProgress {
+    pub done: i64,
+    pub total: i64,
+}
+
+// 便签浮动窗口状态，位置和尺寸均为物理像素
+#[derive(Clone)]
+pub struct NoteWindowState {
+    pub note_id: i64,
+    pub x: i32,
+    pub y: i32,
+    pub width: u32,
+    pub height: u32,               // 折叠时仍保存展开后的高度
+    pub monitor: Option<String>,   // 窗口所在显示器的名称
+    pub opacity: f64,              // 0.0 ~ 1.0，由前端应用到窗口内容上
+    pub always_on_top: bool,
+    pub collapsed: bool,           // 只显示标题栏
+    pub is_open: bool,             // 退出时仍打开的窗口会在下次启动时恢复
+}
+
+#[derive(Clone)]
 pub struct NoteReminder {
     pub id: Option<i64>,
     pub note_id: i64,
-    pub reminder_time: i64,  // Unix时间戳
-    pub completed: bool,
+    pub reminder_time: i64,  // Unix时间戳，重复提醒为下一次提醒的时间
+    pub completed: bool,     // 单次提醒已触发，或重复提醒已没有下一次
+    pub recurrence: Option<Recurrence>,
+}
+
+// 重复提醒的规则，按 timezone 中的当地时间计算每次提醒的时间
+#[derive(Clone)]
+pub struct Recurrence {
+    pub rrule: String,             // 如 FREQ=WEEKLY;BYDAY=MO,TU,WE,TH,FR
+    pub timezone: Option<String>,  // IANA 时区名称，None 表示系统时区
+    pub dtstart: i64,              // 第一次提醒的时间，决定每次提醒的当地时刻和间隔的起点
+}
+
+#[derive(Clone)]
+pub struct NoteRevision {
+    pub id: i64,
+    pub note_id: i64,
+    pub content: String,
+    pub created_at: i64,  // 该版本内容最后一次保存的时间
+}
+
+// 差异中的一行
+pub struct DiffLine {
+    pub tag: DiffTag,
+    pub content: String,
+}
+
+pub enum DiffTag {
+    Equal,
+    Insert,
+    Delete,
 }
 
+#[derive(Clone)]
+pub struct Attachment {
+    pub id: i64,
+    pub hash: String,      // 内容的 SHA-256
+    pub mime: String,
+    pub extension: String,
+    pub size: i64,         // 字节数
+    pub ref_count: i64,    // 引用该附件的便签数量
+    pub created_at: i64,
+}
+
+impl Attachment {
+    // 附件文件在磁盘上的位置
+    pub fn path(&self) -> Result<PathBuf, IoError> {
+        attachments::blob_path(&self.hash, &self.extension)
+    }
+    
+    // 便签内容中引用附件使用的 asset: URL
+    pub fn url(&self) -> Result<String, IoError> {
+        Ok(attachments::asset_url(&self.path()?))
+    }
+}
+
+#[derive(Clone)]
+pub struct Tag {
+    pub id: i64,
+    pub name: String,
+    pub note_count: i64,  // 使用该标签的未删除便签数量
+}
+
+// 内容中的 [[链接]]
+#[derive(Clone, PartialEq)]
+pub enum WikiLink {
+    Title(String),  // [[便签标题]]，按标题匹配，不区分大小写
+    Id(i64),        // [[#便签ID]]
+}
+
+// 便签之间的链接
+pub struct NoteLink {
+    pub source_id: i64,
+    pub source_title: String,
+    pub target_id: Option<i64>,  // 找不到目标便签时为 None
+    pub target_title: String,    // 链接中书写的文本
+    pub dangling: bool,          // 目标不存在或已移入回收站
+}
+
+#[derive(Clone)]
 pub struct Category {
     pub id: Option<i64>,
     pub name: String,
     pub color: Option<String>,
+    pub parent_id: Option<i64>,  // 父分类，None 表示顶级分类
+    pub position: i64,           // 同级分类中的手动排序位置，新建时自动追加到末尾
 }
 
-pub struct Database {
-    conn: Connection,
+// 分类树节点
+pub struct CategoryNode {
+    pub category: Category,
+    pub children: Vec<CategoryNode>,
 }
 
-impl Database {
-    pub fn new() -> Self {
-        let db_path = get_db_path().expect("无法获取数据库路径");
-        let conn = Connection::open(&db_path).expect("无法打开数据库");
-        
-        // 设置加密密钥
-        let key = key_manager::get_encryption_key().expect("无法获取加密密钥");
-        conn.execute_batch(&format!("PRAGMA key = '{}';", key))
-            .expect("无法设置数据库加密密钥");
-            
-        Database { conn }
-    }
+// 删除分类时对子分类的处理方式
+pub enum ChildCategoryAction {
+    Reparent,  // 子分类移动到被删除分类的父分类下
+    Delete,    // 连同所有子孙分类一起删除
+}
 
-    pub fn get_notes(&self) -> Result<Vec<Note>, IoError> {
-        let mut stmt = self.conn.prepare(
-            "SELECT id, content, screenshot_path, created_at, updated_at, is_pinned, color, category_id, deleted_at 
-             FROM notes 
-             WHERE deleted_at IS NULL 
-             ORDER BY is_pinned DESC, created_at DESC"
-        ).map_err(|e| IoError::new(ErrorKind::Other, format!("准备查询语句失败: {}", e)))?;
-        
-        let notes = stmt.query_map([], |row| {
-            Ok(Note {
-                id: Some(row.get(0)?),
-                content: row.get(1)?,
-                screenshot_path: row.get(2)?,
-                created_at: row.get(3)?,
-                updated_at: row.get(4)?,
-                is_pinned: row.get(5)?,
-                color: row.get(6)?,
-                category_id: row.get(7)?,
-                deleted_at: row.get(8)?,
-            })
-        }).map_err(|e| IoError::new(ErrorKind::Other, format!("查询便签失败: {}", e)))?;
-        
-        notes.collect::<Result<Vec<_>, _>>()
-            .map_err(|e| IoError::new(ErrorKind::Other, format!("处理查询结果失败: {}", e)))
-    }
+// 便签排序方式，置顶便签始终排在前面
+#[derive(serde::Deserialize)]
+pub enum NoteSortOption {
+    CreatedTimeDesc,   // 创建时间降序
+    CreatedTimeAsc,    // 创建时间升序
+    UpdatedTimeDesc,   // 修改时间降序
+    UpdatedTimeAsc,    // 修改时间升序
+    Manual,            // 手动拖拽排序
+}
 
-    pub fn add_note(&self, content: &str, screenshot_path: Option<&str>) -> Result<i64, IoError> {
-        let now = chrono::Utc::now().timestamp();
-        
-        match self.conn.execute(
-            "INSERT INTO notes (content, screenshot_path, created_at, is_pinned) 
-             VALUES (?1, ?2, ?3, 0)",
-            params![content, screenshot_path, now],
-        ) {
-            Ok(_) => Ok(self.conn.last_insert_rowid()),
-            Err(e) => Err(IoError::new(ErrorKind::Other, format!("创建便签失败: {}", e))),
+impl NoteSortOption {
+    pub fn order_by(&self) -> &'static str {
+        match self {
+            NoteSortOption::CreatedTimeDesc => "is_pinned DESC, created_at DESC",
+            NoteSortOption::CreatedTimeAsc => "is_pinned DESC, created_at ASC",
+            NoteSortOption::UpdatedTimeDesc => "is_pinned DESC, COALESCE(updated_at, created_at) DESC",
+            NoteSortOption::UpdatedTimeAsc => "is_pinned DESC, COALESCE(updated_at, created_at) ASC",
+            // 位置只在同一分类内有意义，不按分类筛选时各分类的便签分组排列
+            NoteSortOption::Manual => "is_pinned DESC, category_id ASC, position ASC, created_at DESC",
         }
     }
 }
 
+// 全文搜索结果
+pub struct SearchResult {
+    pub note: Note,
+    pub snippet: String,  // 已转义的HTML片段，命中部分用<mark>包裹
+    pub rank: f64,        // 相关度，越小越相关
+}
+
 // 获取数据库文件路径
 fn get_db_path() -> Result<PathBuf, IoError> {
     let mut path = match dirs::data_dir() {
@@ -111,601 +302,2548 @@ fn get_db_path() -> Result<PathBuf, IoError> {
     Ok(path)
 }
 
-// 初始化数据库连接
-pub fn init_db() -> Result<(), IoError> {
-    let mut db_instance = DB_CONNECTION.lock().unwrap();
+// 数据库文件是否已经存在，用于判断能否为它生成新的密钥
+pub fn database_exists() -> Result<bool, IoError> {
+    Ok(get_db_path()?.exists())
+}
+
+struct ConnectionPool {
+    path: PathBuf,
+    key: String,
+    generation: u64,  // 每次初始化递增，旧连接归还时直接丢弃
+    idle: Vec<Connection>,
+}
+
+// 从连接池借出的连接，离开作用域时自动归还
+struct PooledConnection {
+    conn: Option<Connection>,
+    generation: u64,
+}
+
+impl Deref for PooledConnection {
+    type Target = Connection;
     
-    // 如果连接已经存在，先关闭它
-    if db_instance.is_some() {
-        *db_instance = None;
+    fn deref(&self) -> &Connection {
+        self.conn.as_ref().expect("连接已归还")
+    }
+}
+
+impl Drop for PooledConnection {
+    fn drop(&mut self) {
+        if let (Some(conn), Ok(mut pool)) = (self.conn.take(), DB_POOL.lock()) {
+            // 出错提前返回时事务可能没有结束，先回滚，回滚失败则直接关闭连接，
+            // 否则下一个借用者会在这个事务里执行语句
+            if !conn.is_autocommit() {
+                conn.execute_batch("ROLLBACK").ok();
+                if !conn.is_autocommit() {
+                    return;
+                }
+            }
+            if let Some(pool) = pool.as_mut() {
+                if pool.generation == self.generation && pool.idle.len() < MAX_IDLE_CONNECTIONS {
+                    pool.idle.push(conn);
+                }
+            }
+        }
     }
+}
+
+// 锁损坏时返回错误而不是让应用崩溃
+fn lock_pool() -> Result<MutexGuard<'static, Option<ConnectionPool>>, IoError> {
+    DB_POOL.lock().map_err(|_: PoisonError<_>| IoError::new(ErrorKind::Other, "数据库连接池锁已损坏"))
+}
+
+// 打开一个新连接并设置加密密钥
+fn open_connection(path: &PathBuf, key: &str) -> Result<Connection, IoError> {
+    let conn = Connection::open(path)
+        .map_err(|e| IoError::new(ErrorKind::Other, format!("连接数据库失败: {}", e)))?;
     
-    // 获取数据库路径
-    let db_path = get_db_path()?;
+    conn.execute_batch(&format!("PRAGMA key = '{}';", key))
+        .map_err(|e| IoError::new(ErrorKind::Other, format!("数据库加密失败: {}", e)))?;
+    conn.busy_timeout(Duration::from_millis(BUSY_TIMEOUT_MS))
+        .map_err(|e| IoError::new(ErrorKind::Other, format!("设置数据库超时失败: {}", e)))?;
     
+    Ok(conn)
+}
+
+// 初始化数据库连接
+pub fn init_db() -> Result<(), IoError> {
     // 获取加密密钥
     let key = key_manager::get_encryption_key()?;
+    open_db(key)
+}
+
+// 使用给定的密钥打开数据库（启用主密码时由解锁流程提供密钥）
+pub fn open_db(key: String) -> Result<(), IoError> {
+    // 获取数据库路径
+    let db_path = get_db_path()?;
     
-    // 连接数据库
-    match Connection::open(&db_path) {
-        Ok(mut conn) => {
-            // 设置加密密钥
-            conn.execute_batch(&format!("PRAGMA key = '{}';", key))
-                .map_err(|e| IoError::new(ErrorKind::Other, format!("数据库加密失败: {}", e)))?;
-            
-            // 创建所需的表
-            conn.execute_batch(
-                "CREATE TABLE IF NOT EXISTS notes (
-                    id INTEGER PRIMARY KEY,
-                    content TEXT NOT NULL,
-                    screenshot_path TEXT,
-                    created_at INTEGER NOT NULL,
-                    updated_at INTEGER,
-                    is_pinned BOOLEAN NOT NULL DEFAULT 0,
-                    color TEXT,
-                    category_id INTEGER,
-                    deleted_at INTEGER,
-                    FOREIGN KEY (category_id) REFERENCES categories (id)
-                );
-                
-                CREATE TABLE IF NOT EXISTS note_reminders (
-                    id INTEGER PRIMARY KEY,
-                    note_id INTEGER NOT NULL,
-                    reminder_time INTEGER NOT NULL,
-                    completed BOOLEAN NOT NULL DEFAULT 0,
-                    FOREIGN KEY (note_id) REFERENCES notes (id)
-                );
-                
-                CREATE TABLE IF NOT EXISTS categories (
-                    id INTEGER PRIMARY KEY,
-                    name TEXT NOT NULL,
-                    color TEXT
-                );
-                
-                CREATE INDEX IF NOT EXISTS idx_notes_created_at ON notes (created_at);
-                CREATE INDEX IF NOT EXISTS idx_notes_is_pinned ON notes (is_pinned);
-                CREATE INDEX IF NOT EXISTS idx_notes_category_id ON notes (category_id);
-                CREATE INDEX IF NOT EXISTS idx_notes_deleted_at ON notes (deleted_at);
-                CREATE INDEX IF NOT EXISTS idx_reminders_time ON note_reminders (reminder_time);
-                CREATE INDEX IF NOT EXISTS idx_reminders_note_id ON note_reminders (note_id);"
-            )
-            .map_err(|e| IoError::new(ErrorKind::Other, format!("创建数据库表失败: {}", e)))?;
-            
-            // 保存连接
-            *db_instance = Some(conn);
-            Ok(())
-        },
-        Err(e) => Err(IoError::new(ErrorKind::Other, format!("连接数据库失败: {}", e))),
-    }
+    let mut conn = open_connection(&db_path, &key)?;
+    
+    // 按版本执行数据库迁移
+    migrations::migrate(&mut conn, Some(&db_path))?;
+    DB_MIGRATED.store(true, Ordering::SeqCst);
+    
+    // 启用 WAL 日志，读连接不会被写事务阻塞
+    enable_wal(&conn)?;
+    
+    install_pool(db_path, key, conn)
 }
 
-// 获取数据库连接
-fn get_db() -> Result<Connection, IoError> {
-    let db_instance = DB_CONNECTION.lock().unwrap();
-    match &*db_instance {
-        Some(conn) => Ok(conn.try_clone().map_err(|e| IoError::new(ErrorKind::Other, format!("克隆数据库连接失败: {}", e)))?),
-        None => Err(IoError::new(ErrorKind::NotFound, "数据库未初始化")),
+// 解锁后重新打开数据库，只校验密钥，不再执行迁移；本次运行中尚未打开过数据库时（以锁定状态启动）与 open_db 相同
+pub fn reopen_db(key: String) -> Result<(), IoError> {
+    if !DB_MIGRATED.load(Ordering::SeqCst) {
+        return open_db(key);
     }
+    
+    let db_path = get_db_path()?;
+    let conn = open_connection(&db_path, &key)?;
+    conn.query_row("SELECT COUNT(*) FROM sqlite_master", [], |row| row.get::<_, i64>(0))
+        .map_err(|e| IoError::new(ErrorKind::InvalidData, format!("无法打开数据库: {}", e)))?;
+    
+    install_pool(db_path, key, conn)
 }
 
-// ======= 笔记操作 =======
+// 替换连接池，之前借出的连接归还时会被丢弃
+fn install_pool(path: PathBuf, key: String, conn: Connection) -> Result<(), IoError> {
+    let mut pool = lock_pool()?;
+    let generation = POOL_GENERATION.fetch_add(1, Ordering::SeqCst) + 1;
+    *pool = Some(ConnectionPool {
+        path,
+        key,
+        generation,
+        idle: vec![conn],
+    });
+    DB_LOCKED.store(false, Ordering::SeqCst);
+    
+    Ok(())
+}
 
-// 创建或更新便签
-pub fn save_note(note: &Note) -> Result<i64, IoError> {
-    let conn = get_db()?;
+// 关闭数据库（应用锁定时调用），清空连接池并丢弃内存中的密钥，
+// 借出的连接归还时直接关闭，重新打开需要再次调用 init_db
+pub fn close_db() -> Result<(), IoError> {
+    let mut pool = lock_pool()?;
+    *pool = None;
+    DB_LOCKED.store(true, Ordering::SeqCst);
+    Ok(())
+}
 
-    // 如果note.id为None，则插入新记录；否则更新现有记录
-    if note.id.is_none() {
-        // 插入新便签
-        match conn.execute(
-            "INSERT INTO notes (content, screenshot_path, created_at, updated_at, is_pinned, color, category_id, deleted_at)
-             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)",
-            params![
-                note.content,
-                note.screenshot_path,
-                note.created_at,
-                note.updated_at,
-                note.is_pinned,
-                note.color,
-                note.category_id,
-                note.deleted_at
-            ],
-        ) {
-            Ok(_) => {
-                let id = conn.last_insert_rowid();
-                Ok(id)
-            },
-            Err(e) => Err(IoError::new(ErrorKind::Other, format!("创建便签失败: {}", e))),
-        }
-    } else {
-        // 更新现有便签
-        let id = note.id.unwrap();
-        match conn.execute(
-            "UPDATE notes SET 
-                content = ?1, 
-                screenshot_path = ?2,
-                updated_at = ?3,
-                is_pinned = ?4,
-                color = ?5,
-                category_id = ?6,
-                deleted_at = ?7
-             WHERE id = ?8",
-            params![
-                note.content,
-                note.screenshot_path,
-                note.updated_at,
-                note.is_pinned,
-                note.color,
-                note.category_id,
-                note.deleted_at,
-                id
-            ],
-        ) {
-            Ok(_) => Ok(id),
-            Err(e) => Err(IoError::new(ErrorKind::Other, format!("更新便签失败: {}", e))),
+// 从连接池获取数据库连接，没有空闲连接时新建一个
+fn get_db() -> Result<PooledConnection, IoError> {
+    let (path, key, generation) = {
+        let mut pool = lock_pool()?;
+        let pool = match pool.as_mut() {
+            Some(pool) => pool,
+            None if DB_LOCKED.load(Ordering::SeqCst) => {
+                return Err(IoError::new(ErrorKind::PermissionDenied, "应用已锁定"));
+            }
+            None => return Err(IoError::new(ErrorKind::NotFound, "数据库未初始化")),
+        };
+        
+        if let Some(conn) = pool.idle.pop() {
+            return Ok(PooledConnection { conn: Some(conn), generation: pool.generation });
         }
-    }
+        (pool.path.clone(), pool.key.clone(), pool.generation)
+    };
+    
+    // 在锁外打开连接，避免阻塞其他线程借用空闲连接
+    let conn = open_connection(&path, &key)?;
+    Ok(PooledConnection { conn: Some(conn), generation })
 }
 
-// 根据ID获取便签
-pub fn get_note(id: i64) -> Result<Option<Note>, IoError> {
-    let conn = get_db()?;
+// 在阻塞线程池中执行数据库操作，供异步命令调用，避免阻塞异步运行时
+pub async fn run_blocking<T, F>(f: F) -> Result<T, IoError>
+where
+    T: Send + 'static,
+    F: FnOnce() -> Result<T, IoError> + Send + 'static,
+{
+    tauri::async_runtime::spawn_blocking(f)
+        .await
+        .map_err(|e| IoError::new(ErrorKind::Other, format!("数据库任务异常终止: {}", e)))?
+}
+
+// ======= 密钥轮换 =======
+
+// 更换数据库加密密钥：备份数据库、PRAGMA rekey、保存新密钥、用新密钥重新打开验证，
+// 任一步失败都恢复旧密钥。成功后旧密钥不再保存，数据目录中的数据库备份和自动备份快照
+// 也改用新密钥加密。启用主密码时需要提供主密码，并返回新的恢复密钥
+pub fn rotate_encryption_key(password: Option<&str>) -> Result<Option<String>, IoError> {
+    // 轮换期间持有连接池锁，其他数据库操作等待轮换完成
+    let mut pool = lock_pool()?;
+    let (path, old_key) = match pool.as_mut() {
+        Some(pool) => {
+            pool.idle.clear();
+            (pool.path.clone(), pool.key.clone())
+        }
+        None => return Err(IoError::new(ErrorKind::NotFound, "数据库未初始化")),
+    };
     
-    let mut stmt = conn.prepare(
-        "SELECT id, content, screenshot_path, created_at, updated_at, is_pinned, color, category_id, deleted_at 
-         FROM notes 
-         WHERE id = ?1"
-    ).map_err(|e| IoError::new(ErrorKind::Other, format!("准备查询语句失败: {}", e)))?;
+    let conn = open_connection(&path, &old_key)?;
+    // rekey 需要独占数据库，退出 WAL 模式时若仍有其他连接在使用会直接失败
+    conn.query_row("PRAGMA journal_mode = DELETE", [], |row| row.get::<_, String>(0))
+        .map_err(|e| IoError::new(ErrorKind::Other, format!("退出WAL模式失败: {}", e)))?;
     
-    let note_result = stmt.query_row(params![id], |row| {
-        Ok(Note {
-            id: Some(row.get(0)?),
-            content: row.get(1)?,
-            screenshot_path: row.get(2)?,
-            created_at: row.get(3)?,
-            updated_at: row.get(4)?,
-            is_pinned: row.get(5)?,
-            color: row.get(6)?,
-            category_id: row.get(7)?,
-            deleted_at: row.get(8)?,
-        })
+    let prepared = backup_database_file(&path)
+        .and_then(|backup_path| Ok((backup_path, key_manager::backup_stored_key(&old_key)?)));
+    let (backup_path, stored_backup) = match prepared {
+        Ok(prepared) => prepared,
+        Err(e) => {
+            enable_wal(&conn)?;
+            return Err(e);
+        }
+    };
+    let new_key = key_manager::generate_encryption_key();
+    
+    let rotated = rekey(&conn, &new_key).and_then(|_| {
+        let recovery_key = key_manager::save_rotated_key(&new_key, password)?;
+        verify_key(&path, &new_key)?;
+        Ok(recovery_key)
     });
     
-    match note_result {
-        Ok(note) => Ok(Some(note)),
-        Err(rusqlite::Error::QueryReturnedNoRows) => Ok(None),
-        Err(e) => Err(IoError::new(ErrorKind::Other, format!("查询便签失败: {}", e))),
-    }
-}
-
-// 获取所有便签，按照是否置顶和创建时间排序
-pub fn get_notes_sorted(limit: Option<i64>, offset: Option<i64>, search: Option<&str>) -> Result<Vec<Note>, IoError> {
-    let conn = get_db()?;
+    let recovery_key = match rotated {
+        Ok(recovery_key) => recovery_key,
+        Err(e) => {
+            // 恢复旧密钥，数据库无法改回时用备份覆盖
+            if rekey(&conn, &old_key).and_then(|_| verify_key(&path, &old_key)).is_ok() {
+                enable_wal(&conn)?;
+            } else {
+                drop(conn);
+                std::fs::copy(&backup_path, &path)
+                    .map_err(|e| IoError::new(e.kind(), format!("从备份恢复数据库失败: {}", e)))?;
+            }
+            key_manager::restore_stored_key(stored_backup)?;
+            return Err(e);
+        }
+    };
     
-    let mut query = String::from(
-        "SELECT id, content, screenshot_path, created_at, updated_at, is_pinned, color, category_id, deleted_at 
-         FROM notes 
-         WHERE deleted_at IS NULL"
-    );
+    enable_wal(&conn)?;
     
-    // 添加搜索条件
-    if let Some(search_term) = search {
-        query.push_str(&format!(" AND content LIKE '%{}%'", search_term.replace('\'', "''")));
+    // 备份使用旧密钥加密，轮换成功后不再保留
+    if let Err(e) = std::fs::remove_file(&backup_path) {
+        log::warn!("删除轮换前的备份失败: {}", e);
     }
     
-    // 添加排序
-    query.push_str(" ORDER BY is_pinned DESC, created_at DESC");
+    // 换用新密钥的连接池，之前借出的连接归还时会被丢弃
+    *pool = Some(ConnectionPool {
+        path: path.clone(),
+        key: new_key.clone(),
+        generation: POOL_GENERATION.fetch_add(1, Ordering::SeqCst) + 1,
+        idle: vec![conn],
+    });
+    // 创建快照时需要借用连接，重新加密快照前先释放连接池锁
+    drop(pool);
     
-    // 添加分页
-    if let Some(limit_val) = limit {
-        query.push_str(&format!(" LIMIT {}", limit_val));
-        
-        if let Some(offset_val) = offset {
-            query.push_str(&format!(" OFFSET {}", offset_val));
-        }
+    rekey_database_backups(&path, &old_key, &new_key);
+    if let Err(e) = auto_backup::rekey_snapshots(&old_key, &new_key) {
+        log::warn!("重新加密自动备份快照失败: {}", e);
     }
     
-    let mut stmt = conn.prepare(&query)
-        .map_err(|e| IoError::new(ErrorKind::Other, format!("准备查询语句失败: {}", e)))?;
-    
-    let note_iter = stmt.query_map([], |row| {
-        Ok(Note {
-            id: Some(row.get(0)?),
-            content: row.get(1)?,
-            screenshot_path: row.get(2)?,
-            created_at: row.get(3)?,
-            updated_at: row.get(4)?,
-            is_pinned: row.get(5)?,
-            color: row.get(6)?,
-            category_id: row.get(7)?,
-            deleted_at: row.get(8)?,
-        })
-    }).map_err(|e| IoError::new(ErrorKind::Other, format!("执行查询失败: {}", e)))?;
+    Ok(recovery_key)
+}
+
+// 用新密钥重新加密数据目录中的数据库备份（迁移前、恢复前的 .bak 文件），
+// 失败的文件保持不变并记录警告
+fn rekey_database_backups(db_path: &Path, old_key: &str, new_key: &str) {
+    let dir = match db_path.parent() {
+        Some(dir) => dir,
+        None => return,
+    };
+    let entries = match std::fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(e) => {
+            log::warn!("读取数据目录失败: {}", e);
+            return;
+        }
+    };
     
-    let mut notes = Vec::new();
-    for note_result in note_iter {
-        match note_result {
-            Ok(note) => notes.push(note),
-            Err(e) => return Err(IoError::new(ErrorKind::Other, format!("处理查询结果失败: {}", e))),
+    let prefix = format!("{}.", DB_FILENAME);
+    for path in entries.filter_map(|entry| entry.ok()).map(|entry| entry.path()) {
+        let is_backup = path.file_name()
+            .and_then(|name| name.to_str())
+            .map_or(false, |name| name.starts_with(&prefix) && name.ends_with(".bak"));
+        if !is_backup {
+            continue;
         }
+        if let Err(e) = rekey_database_file(&path, old_key, new_key) {
+            log::warn!("重新加密数据库备份 {} 失败: {}", path.display(), e);
+        }
+    }
+}
+
+// 将用旧密钥加密的数据库文件改用新密钥加密，已经使用新密钥的文件不做修改
+pub fn rekey_database_file(path: &Path, old_key: &str, new_key: &str) -> Result<(), IoError> {
+    let path = path.to_path_buf();
+    if verify_key(&path, new_key).is_ok() {
+        return Ok(());
     }
     
-    Ok(notes)
+    let conn = open_connection(&path, old_key)?;
+    // 备份文件可能复制自 WAL 模式的数据库，rekey 前改回普通日志模式（同时校验旧密钥）
+    conn.query_row("PRAGMA journal_mode = DELETE", [], |row| row.get::<_, String>(0))
+        .map_err(|e| IoError::new(ErrorKind::InvalidData, format!("无法用旧密钥打开数据库: {}", e)))?;
+    rekey(&conn, new_key)?;
+    drop(conn);
+    verify_key(&path, new_key)
 }
 
-// 获取回收站中的便签
-pub fn get_trash_notes() -> Result<Vec<Note>, IoError> {
+fn enable_wal(conn: &Connection) -> Result<(), IoError> {
+    conn.query_row("PRAGMA journal_mode = WAL", [], |row| row.get::<_, String>(0))
+        .map_err(|e| IoError::new(ErrorKind::Other, format!("启用WAL模式失败: {}", e)))?;
+    Ok(())
+}
+
+fn rekey(conn: &Connection, key: &str) -> Result<(), IoError> {
+    conn.execute_batch(&format!("PRAGMA rekey = '{}';", key))
+        .map_err(|e| IoError::new(ErrorKind::Other, format!("更换数据库密钥失败: {}", e)))
+}
+
+// 用新连接确认密钥能够解密数据库
+fn verify_key(path: &PathBuf, key: &str) -> Result<(), IoError> {
+    let conn = open_connection(path, key)?;
+    conn.query_row("SELECT COUNT(*) FROM sqlite_master", [], |row| row.get::<_, i64>(0))
+        .map_err(|e| IoError::new(ErrorKind::InvalidData, format!("无法用新密钥打开数据库: {}", e)))?;
+    Ok(())
+}
+
+fn backup_database_file(path: &PathBuf) -> Result<PathBuf, IoError> {
+    let backup_path = path.with_file_name(format!(
+        "{}.rekey.{}.bak",
+        DB_FILENAME,
+        chrono::Local::now().format("%Y%m%d_%H%M%S"),
+    ));
+    std::fs::copy(path, &backup_path)
+        .map_err(|e| IoError::new(e.kind(), format!("备份数据库失败: {}", e)))?;
+    Ok(backup_path)
+}
+
+// ======= 备份操作 =======
+
+// 将数据库导出为未加密的副本，供加密备份打包使用
+pub fn export_plaintext(dest: &Path) -> Result<(), IoError> {
     let conn = get_db()?;
     
-    let mut stmt = conn.prepare(
-        "SELECT id, content, screenshot_path, created_at, updated_at, is_pinned, color, category_id, deleted_at 
-         FROM notes 
-         WHERE deleted_at IS NOT NULL
-         ORDER BY deleted_at DESC"
-    ).map_err(|e| IoError::new(ErrorKind::Other, format!("准备查询语句失败: {}", e)))?;
+    conn.execute_batch(&format!(
+        "ATTACH DATABASE '{}' AS plaintext KEY '';",
+        sql_quote_path(dest),
+    )).map_err(|e| IoError::new(ErrorKind::Other, format!("创建导出文件失败: {}", e)))?;
     
-    let note_iter = stmt.query_map([], |row| {
-        Ok(Note {
-            id: Some(row.get(0)?),
-            content: row.get(1)?,
-            screenshot_path: row.get(2)?,
-            created_at: row.get(3)?,
-            updated_at: row.get(4)?,
-            is_pinned: row.get(5)?,
-            color: row.get(6)?,
-            category_id: row.get(7)?,
-            deleted_at: row.get(8)?,
-        })
-    }).map_err(|e| IoError::new(ErrorKind::Other, format!("执行查询失败: {}", e)))?;
+    let exported = conn.query_row("SELECT sqlcipher_export('plaintext')", [], |_| Ok(()))
+        .map_err(|e| IoError::new(ErrorKind::Other, format!("导出数据库失败: {}", e)));
+    conn.execute_batch("DETACH DATABASE plaintext;").ok();
+    exported
+}
+
+// 将数据库导出为加密副本，供自动备份使用（不指定 KEY 时附加的数据库使用主库的密钥）
+pub fn export_encrypted(dest: &Path) -> Result<(), IoError> {
+    let conn = get_db()?;
     
-    let mut notes = Vec::new();
-    for note_result in note_iter {
-        match note_result {
-            Ok(note) => notes.push(note),
-            Err(e) => return Err(IoError::new(ErrorKind::Other, format!("处理查询结果失败: {}", e))),
-        }
-    }
+    conn.execute_batch(&format!(
+        "ATTACH DATABASE '{}' AS snapshot;",
+        sql_quote_path(dest),
+    )).map_err(|e| IoError::new(ErrorKind::Other, format!("创建导出文件失败: {}", e)))?;
     
-    Ok(notes)
+    let exported = conn.query_row("SELECT sqlcipher_export('snapshot')", [], |_| Ok(()))
+        .map_err(|e| IoError::new(ErrorKind::Other, format!("导出数据库失败: {}", e)));
+    conn.execute_batch("DETACH DATABASE snapshot;").ok();
+    exported
 }
 
-// 将便签移到回收站
-pub fn move_note_to_trash(id: i64, timestamp: i64) -> Result<(), IoError> {
+// 当前数据库的版本和内容数量，写入备份清单
+pub struct DatabaseStats {
+    pub schema_version: u32,
+    pub note_count: i64,  // 未删除的便签数量
+    pub category_count: i64,
+}
+
+pub fn get_database_stats() -> Result<DatabaseStats, IoError> {
     let conn = get_db()?;
+    let count = |sql: &str| conn.query_row(sql, [], |row| row.get::<_, i64>(0))
+        .map_err(|e| IoError::new(ErrorKind::Other, format!("统计数据库内容失败: {}", e)));
     
-    match conn.execute(
-        "UPDATE notes SET deleted_at = ?1 WHERE id = ?2",
-        params![timestamp, id],
-    ) {
-        Ok(_) => Ok(()),
-        Err(e) => Err(IoError::new(ErrorKind::Other, format!("移动便签到回收站失败: {}", e))),
+    Ok(DatabaseStats {
+        schema_version: migrations::current_version(&conn)?,
+        note_count: count("SELECT COUNT(*) FROM notes WHERE deleted_at IS NULL")?,
+        category_count: count("SELECT COUNT(*) FROM categories")?,
+    })
+}
+
+// 用未加密的数据库副本替换当前数据库：升级到当前版本、用当前密钥加密，
+// 原数据库保留为 .pre-restore 备份后再替换文件并重新打开
+pub fn replace_database(plain_path: &Path) -> Result<(), IoError> {
+    let backup_path = get_db_path()?.with_file_name(format!(
+        "{}.pre-restore.{}.bak",
+        DB_FILENAME,
+        chrono::Local::now().format("%Y%m%d_%H%M%S"),
+    ));
+    replace_database_keeping(plain_path, &backup_path)
+}
+
+// 替换数据库，原数据库复制到 backup_path
+fn replace_database_keeping(plain_path: &Path, backup_path: &Path) -> Result<(), IoError> {
+    let key = match lock_pool()?.as_ref() {
+        Some(pool) => pool.key.clone(),
+        None => return Err(IoError::new(ErrorKind::NotFound, "数据库未初始化")),
+    };
+    let db_path = get_db_path()?;
+    let staged_path = db_path.with_extension("db.restoring");
+    attachments::remove_blob(&staged_path)?;
+    
+    {
+        let mut plain = Connection::open(plain_path)
+            .map_err(|e| IoError::new(ErrorKind::Other, format!("打开备份数据库失败: {}", e)))?;
+        migrations::migrate(&mut plain, None)?;
+        
+        plain.execute_batch(&format!(
+            "ATTACH DATABASE '{}' AS encrypted KEY '{}';",
+            sql_quote_path(&staged_path),
+            key,
+        )).map_err(|e| IoError::new(ErrorKind::Other, format!("创建加密数据库失败: {}", e)))?;
+        plain.query_row("SELECT sqlcipher_export('encrypted')", [], |_| Ok(()))
+            .map_err(|e| IoError::new(ErrorKind::Other, format!("加密备份数据库失败: {}", e)))?;
+        plain.execute_batch("DETACH DATABASE encrypted;")
+            .map_err(|e| IoError::new(ErrorKind::Other, format!("加密备份数据库失败: {}", e)))?;
+    }
+    
+    {
+        // 替换期间持有连接池锁，并清空连接池
+        let mut pool = lock_pool()?;
+        let conn = open_connection(&db_path, &key)?;
+        conn.query_row("PRAGMA wal_checkpoint(TRUNCATE)", [], |_| Ok(()))
+            .map_err(|e| IoError::new(ErrorKind::Other, format!("写回WAL日志失败: {}", e)))?;
+        drop(conn);
+        *pool = None;
+        
+        let replaced = (|| -> Result<(), IoError> {
+            std::fs::copy(&db_path, backup_path)
+                .map_err(|e| IoError::new(e.kind(), format!("备份当前数据库失败: {}", e)))?;
+            for suffix in ["-wal", "-shm"] {
+                attachments::remove_blob(&PathBuf::from(format!("{}{}", db_path.display(), suffix)))?;
+            }
+            std::fs::rename(&staged_path, &db_path)
+                .map_err(|e| IoError::new(e.kind(), format!("替换数据库失败: {}", e)))
+        })();
+        
+        if let Err(e) = replaced {
+            // 替换失败时原数据库未被改动，重新打开后应用可以继续使用
+            drop(pool);
+            attachments::remove_blob(&staged_path).ok();
+            if let Err(reopen_error) = open_db(key) {
+                log::warn!("重新打开原数据库失败: {}", reopen_error);
+            }
+            return Err(e);
+        }
     }
+    
+    open_db(key)
 }
 
-// 从回收站恢复便签
-pub fn restore_note_from_trash(id: i64) -> Result<(), IoError> {
+// 按内容和创建时间查找便签，用于合并导入时跳过已存在的便签
+pub fn find_note_id(content: &str, created_at: i64) -> Result<Option<i64>, IoError> {
     let conn = get_db()?;
     
-    match conn.execute(
-        "UPDATE notes SET deleted_at = NULL WHERE id = ?1",
-        params![id],
-    ) {
-        Ok(_) => Ok(()),
-        Err(e) => Err(IoError::new(ErrorKind::Other, format!("从回收站恢复便签失败: {}", e))),
-    }
+    conn.query_row(
+        "SELECT id FROM notes WHERE content = ?1 AND created_at = ?2 AND deleted_at IS NULL LIMIT 1",
+        params![content, created_at],
+        |row| row.get(0),
+    )
+    .optional()
+    .map_err(|e| IoError::new(ErrorKind::Other, format!("查询便签失败: {}", e)))
 }
 
-// 永久删除便签
-pub fn delete_note_permanently(id: i64) -> Result<(), IoError> {
+// 在一个事务中保存导入的便签及其清单条目、手动标签和附件，任何一步失败时都不会留下不完整的便签；
+// checklist 为（内容, 是否完成），attachment_files 为（文件内容, MIME 类型）
+pub fn save_imported_note(
+    note: &Note,
+    checklist: &[(String, bool)],
+    tags: &[String],
+    attachment_files: &[(Vec<u8>, String)],
+) -> Result<i64, IoError> {
+    // 附件文件先写入存储，事务回滚后没有记录的文件会在附件回收时删除
+    let mut blobs = Vec::with_capacity(attachment_files.len());
+    for (bytes, mime) in attachment_files {
+        let (hash, _) = attachments::write_blob(bytes, attachments::extension_for_mime(mime))?;
+        blobs.push((hash, mime.as_str(), bytes.len() as i64));
+    }
+    
     let conn = get_db()?;
+    let now = chrono::Utc::now().timestamp();
     
-    // 开始事务
-    conn.execute("BEGIN TRANSACTION", [])
+    conn.execute("BEGIN IMMEDIATE TRANSACTION", [])
         .map_err(|e| IoError::new(ErrorKind::Other, format!("开始事务失败: {}", e)))?;
     
-    // 先删除与便签关联的提醒
-    match conn.execute(
-        "DELETE FROM note_reminders WHERE note_id = ?1",
-        params![id],
-    ) {
-        Ok(_) => {},
-        Err(e) => {
-            conn.execute("ROLLBACK", []).ok();
-            return Err(IoError::new(ErrorKind::Other, format!("删除便签提醒失败: {}", e)));
+    let result = (|| -> Result<i64, IoError> {
+        let id = write_note(&conn, note)?;
+        link_screenshot_attachment(&conn, id, note.screenshot_path.as_deref())?;
+        sync_inline_tags(&conn, id, &note.content)?;
+        sync_note_links(&conn, id, &note.content, None)?;
+        
+        for (position, (content, done)) in checklist.iter().enumerate() {
+            conn.execute(
+                "INSERT INTO checklist_items (note_id, content, done, position, due_at, created_at)
+                 VALUES (?1, ?2, ?3, ?4, NULL, ?5)",
+                params![id, content, done, position as i64, now],
+            ).map_err(|e| IoError::new(ErrorKind::Other, format!("添加清单条目失败: {}", e)))?;
         }
-    }
+        for name in tags {
+            let name = name.trim().trim_start_matches('#');
+            if !name.is_empty() {
+                insert_manual_tag(&conn, id, name)?;
+            }
+        }
+        for (hash, mime, size) in &blobs {
+            insert_attachment(&conn, hash, mime, *size, Some(id), now)?;
+        }
+        Ok(id)
+    })();
     
-    // 再删除便签本身
-    match conn.execute(
-        "DELETE FROM notes WHERE id = ?1",
-        params![id],
-    ) {
-        Ok(_) => {
-            // 提交事务
+    match result {
+        Ok(id) => {
             conn.execute("COMMIT", [])
                 .map_err(|e| IoError::new(ErrorKind::Other, format!("提交事务失败: {}", e)))?;
-            Ok(())
+            Ok(id)
         },
         Err(e) => {
             conn.execute("ROLLBACK", []).ok();
-            Err(IoError::new(ErrorKind::Other, format!("永久删除便签失败: {}", e)))
+            Err(e)
         }
     }
 }
 
-// 清理回收站（删除30天前的便签）
-pub fn cleanup_trash(days: i64) -> Result<i32, IoError> {
+// 将路径转为 SQL 字符串字面量的内容
+fn sql_quote_path(path: &Path) -> String {
+    path.to_string_lossy().replace('\'', "''")
+}
+
+// ======= 笔记操作 =======
+
+// 创建或更新便签
+pub fn save_note(note: &Note) -> Result<i64, IoError> {
     let conn = get_db()?;
     
-    // 计算30天前的时间戳
-    let now = chrono::Utc::now().timestamp();
-    let threshold = now - (days * 24 * 60 * 60); // days天的秒数
-    
-    // 获取要删除的便签ID列表
-    let mut stmt = conn.prepare(
-        "SELECT id FROM notes WHERE deleted_at IS NOT NULL AND deleted_at < ?1"
-    ).map_err(|e| IoError::new(ErrorKind::Other, format!("准备查询语句失败: {}", e)))?;
-    
-    let note_ids: Result<Vec<i64>, _> = stmt.query_map(params![threshold], |row| row.get(0))
-        .map_err(|e| IoError::new(ErrorKind::Other, format!("执行查询失败: {}", e)))?
-        .collect();
-    
-    let note_ids = match note_ids {
-        Ok(ids) => ids,
-        Err(e) => return Err(IoError::new(ErrorKind::Other, format!("收集便签ID失败: {}", e))),
-    };
-    
-    let mut deleted_count = 0;
-    
     // 开始事务
-    conn.execute("BEGIN TRANSACTION", [])
+    conn.execute("BEGIN IMMEDIATE TRANSACTION", [])
         .map_err(|e| IoError::new(ErrorKind::Other, format!("开始事务失败: {}", e)))?;
     
-    // 逐个删除便签及其关联的提醒
-    for id in note_ids {
-        // 先删除提醒
-        match conn.execute(
-            "DELETE FROM note_reminders WHERE note_id = ?1",
-            params![id],
-        ) {
-            Ok(_) => {},
-            Err(e) => {
-                conn.execute("ROLLBACK", []).ok();
-                return Err(IoError::new(ErrorKind::Other, format!("删除便签提醒失败: {}", e)));
-            }
-        }
-        
-        // 再删除便签
-        match conn.execute(
-            "DELETE FROM notes WHERE id = ?1",
-            params![id],
-        ) {
-            Ok(_) => deleted_count += 1,
-            Err(e) => {
-                conn.execute("ROLLBACK", []).ok();
-                return Err(IoError::new(ErrorKind::Other, format!("删除便签失败: {}", e)));
-            }
-        }
-    }
-    
-    // 提交事务
-    conn.execute("COMMIT", [])
-        .map_err(|e| IoError::new(ErrorKind::Other, format!("提交事务失败: {}", e)))?;
-    
-    Ok(deleted_count)
-}
-
-// 切换便签置顶状态
-pub fn toggle_note_pin(id: i64) -> Result<bool, IoError> {
-    let conn = get_db()?;
-    
-    // 首先获取当前的置顶状态
-    let mut stmt = conn.prepare("SELECT is_pinned FROM notes WHERE id = ?1")
-        .map_err(|e| IoError::new(ErrorKind::Other, format!("准备查询语句失败: {}", e)))?;
+    let result = note_old_title(&conn, note.id).and_then(|old_title| {
+        let id = write_note(&conn, note)?;
+        link_screenshot_attachment(&conn, id, note.screenshot_path.as_deref())?;
+        // 同步内容中的 #标签和 [[链接]]
+        sync_inline_tags(&conn, id, &note.content)?;
+        sync_note_links(&conn, id, &note.content, old_title.as_deref())?;
+        Ok(id)
+    });
     
-    let is_pinned = match stmt.query_row(params![id], |row| row.get::<_, bool>(0)) {
-        Ok(value) => value,
-        Err(rusqlite::Error::QueryReturnedNoRows) => {
-            return Err(IoError::new(ErrorKind::NotFound, "便签不存在"));
+    match result {
+        Ok(id) => {
+            // 提交事务
+            conn.execute("COMMIT", [])
+                .map_err(|e| IoError::new(ErrorKind::Other, format!("提交事务失败: {}", e)))?;
+            Ok(id)
         },
         Err(e) => {
-            return Err(IoError::new(ErrorKind::Other, format!("查询便签置顶状态失败: {}", e)));
+            conn.execute("ROLLBACK", []).ok();
+            Err(e)
         }
-    };
-    
-    // 更新为相反的状态
-    let new_status = !is_pinned;
-    match conn.execute(
-        "UPDATE notes SET is_pinned = ?1 WHERE id = ?2",
-        params![new_status, id],
-    ) {
-        Ok(_) => Ok(new_status),
-        Err(e) => Err(IoError::new(ErrorKind::Other, format!("更新便签置顶状态失败: {}", e))),
     }
 }
 
-// ======= 提醒操作 =======
-
-// 保存便签提醒
-pub fn save_reminder(reminder: &NoteReminder) -> Result<i64, IoError> {
-    let conn = get_db()?;
+// 更新前便签的标题，新便签返回 None
+fn note_old_title(conn: &Connection, id: Option<i64>) -> Result<Option<String>, IoError> {
+    let id = match id {
+        Some(id) => id,
+        None => return Ok(None),
+    };
     
-    if reminder.id.is_none() {
-        // 插入新提醒
+    conn.query_row("SELECT content FROM notes WHERE id = ?1", params![id], |row| row.get::<_, String>(0))
+        .optional()
+        .map(|content| content.map(|c| note_title(&c).to_string()))
+        .map_err(|e| IoError::new(ErrorKind::Other, format!("查询便签失败: {}", e)))
+}
+
+// 写入便签记录，需在调用方的事务中执行
+fn write_note(conn: &Connection, note: &Note) -> Result<i64, IoError> {
+    // 如果note.id为None，则插入新记录；否则更新现有记录
+    if note.id.is_none() {
+        // 插入新便签，手动排序时排在所在分类的最前面
         match conn.execute(
-            "INSERT INTO note_reminders (note_id, reminder_time, completed)
-             VALUES (?1, ?2, ?3)",
+            "INSERT INTO notes (content, screenshot_path, created_at, updated_at, is_pinned, color, category_id, deleted_at, position, note_type, content_format, archived_at)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8,
+                     (SELECT COALESCE(MIN(position), 0) - 1 FROM notes WHERE category_id IS ?7), ?9, ?10, ?11)",
             params![
-                reminder.note_id,
-                reminder.reminder_time,
-                reminder.completed
+                note.content,
+                note.screenshot_path,
+                note.created_at,
+                note.updated_at,
+                note.is_pinned,
+                note.color,
+                note.category_id,
+                note.deleted_at,
+                note.note_type.as_str(),
+                note.content_format.as_str(),
+                note.archived_at
             ],
         ) {
             Ok(_) => {
                 let id = conn.last_insert_rowid();
                 Ok(id)
             },
-            Err(e) => Err(IoError::new(ErrorKind::Other, format!("创建提醒失败: {}", e))),
+            Err(e) => Err(IoError::new(ErrorKind::Other, format!("创建便签失败: {}", e))),
         }
     } else {
-        // 更新现有提醒
-        let id = reminder.id.unwrap();
+        // 更新现有便签
+        let id = note.id.unwrap();
+        
+        // 内容有变化时先保存旧版本
+        record_revision(conn, id, &note.content)?;
+        
         match conn.execute(
-            "UPDATE note_reminders SET 
-                note_id = ?1, 
-                reminder_time = ?2,
-                completed = ?3
-             WHERE id = ?4",
+            "UPDATE notes SET 
+                content = ?1, 
+                screenshot_path = ?2,
+                updated_at = ?3,
+                is_pinned = ?4,
+                color = ?5,
+                category_id = ?6,
+                deleted_at = ?7,
+                note_type = ?8,
+                content_format = ?9,
+                archived_at = ?10
+             WHERE id = ?11",
             params![
-                reminder.note_id,
-                reminder.reminder_time,
-                reminder.completed,
+                note.content,
+                note.screenshot_path,
+                note.updated_at,
+                note.is_pinned,
+                note.color,
+                note.category_id,
+                note.deleted_at,
+                note.note_type.as_str(),
+                note.content_format.as_str(),
+                note.archived_at,
                 id
             ],
         ) {
+            Ok(0) => Err(IoError::new(ErrorKind::NotFound, "便签不存在")),
             Ok(_) => Ok(id),
-            Err(e) => Err(IoError::new(ErrorKind::Other, format!("更新提醒失败: {}", e))),
+            Err(e) => Err(IoError::new(ErrorKind::Other, format!("更新便签失败: {}", e))),
         }
     }
 }
 
-// 获取便签的所有提醒
-pub fn get_reminders_by_note(note_id: i64) -> Result<Vec<NoteReminder>, IoError> {
-    let conn = get_db()?;
+// 如果新内容与当前内容不同，将当前内容记录为历史版本并清理超出保留数量的旧版本
+// 需在调用方的事务中执行
+fn record_revision(conn: &Connection, note_id: i64, new_content: &str) -> Result<(), IoError> {
+    let limit = revision_limit();
+    if limit <= 0 {
+        return Ok(());
+    }
     
-    let mut stmt = conn.prepare(
-        "SELECT id, note_id, reminder_time, completed 
-         FROM note_reminders 
-         WHERE note_id = ?1
-         ORDER BY reminder_time ASC"
-    ).map_err(|e| IoError::new(ErrorKind::Other, format!("准备查询语句失败: {}", e)))?;
+    conn.execute(
+        "INSERT INTO note_revisions (note_id, content, created_at)
+         SELECT id, content, COALESCE(updated_at, created_at)
+         FROM notes
+         WHERE id = ?1 AND content != ?2",
+        params![note_id, new_content],
+    ).map_err(|e| IoError::new(ErrorKind::Other, format!("保存历史版本失败: {}", e)))?;
     
-    let reminder_iter = stmt.query_map(params![note_id], |row| {
-        Ok(NoteReminder {
-            id: Some(row.get(0)?),
-            note_id: row.get(1)?,
-            reminder_time: row.get(2)?,
-            completed: row.get(3)?,
-        })
-    }).map_err(|e| IoError::new(ErrorKind::Other, format!("执行查询失败: {}", e)))?;
+    conn.execute(
+        "DELETE FROM note_revisions
+         WHERE note_id = ?1 AND id NOT IN (
+             SELECT id FROM note_revisions WHERE note_id = ?1 ORDER BY id DESC LIMIT ?2
+         )",
+        params![note_id, limit],
+    ).map_err(|e| IoError::new(ErrorKind::Other, format!("清理历史版本失败: {}", e)))?;
     
-    let mut reminders = Vec::new();
-    for reminder_result in reminder_iter {
-        match reminder_result {
-            Ok(reminder) => reminders.push(reminder),
-            Err(e) => return Err(IoError::new(ErrorKind::Other, format!("处理查询结果失败: {}", e))),
-        }
-    }
+    Ok(())
+}
+
+// 自动改写便签内容（标签重命名、链接标题变化），与保存便签一样记录历史版本并更新修改时间，
+// 改写可能改变便签标题，同样更新指向它的链接，需在调用方的事务中执行
+fn rewrite_note_content(conn: &Connection, note_id: i64, content: &str) -> Result<(), IoError> {
+    let old_title = note_old_title(conn, Some(note_id))?;
+    record_revision(conn, note_id, content)?;
     
-    Ok(reminders)
+    conn.execute(
+        "UPDATE notes SET content = ?1, updated_at = ?2 WHERE id = ?3 AND content != ?1",
+        params![content, chrono::Utc::now().timestamp(), note_id],
+    ).map_err(|e| IoError::new(ErrorKind::Other, format!("更新便签内容失败: {}", e)))?;
+    
+    sync_note_links(conn, note_id, content, old_title.as_deref())
 }
 
-// 删除便签提醒
-pub fn delete_reminder(id: i64) -> Result<(), IoError> {
+// 根据ID获取便签
+pub fn get_note(id: i64) -> Result<Option<Note>, IoError> {
     let conn = get_db()?;
     
-    match conn.execute(
-        "DELETE FROM note_reminders WHERE id = ?1",
-        params![id],
-    ) {
-        Ok(_) => Ok(()),
-        Err(e) => Err(IoError::new(ErrorKind::Other, format!("删除提醒失败: {}", e))),
+    let mut stmt = conn.prepare(
+        "SELECT id, content, screenshot_path, created_at, updated_at, is_pinned, color, category_id, deleted_at, position, note_type, content_format, archived_at
+         FROM notes 
+         WHERE id = ?1"
+    ).map_err(|e| IoError::new(ErrorKind::Other, format!("准备查询语句失败: {}", e)))?;
+    
+    let note_result = stmt.query_row(params![id], |row| note_from_row(row, 0));
+    
+    match note_result {
+        Ok(note) => Ok(Some(note)),
+        Err(rusqlite::Error::QueryReturnedNoRows) => Ok(None),
+        Err(e) => Err(IoError::new(ErrorKind::Other, format!("查询便签失败: {}", e))),
     }
 }
 
-// 获取即将到期的提醒
-pub fn get_upcoming_reminders(limit: Option<i64>) -> Result<Vec<(NoteReminder, Note)>, IoError> {
+// 获取所有便签，置顶便签在前，其余按 sort 排序
+// tag_ids 不为空时只返回同时带有所有这些标签的便签
+pub fn get_notes_sorted(
+    sort: &NoteSortOption,
+    limit: Option<i64>,
+    offset: Option<i64>,
+    search: Option<&str>,
+    tag_ids: &[i64],
+) -> Result<Vec<Note>, IoError> {
     let conn = get_db()?;
     
     let mut query = String::from(
-        "SELECT r.id, r.note_id, r.reminder_time, r.completed, 
-                n.id, n.content, n.screenshot_path, n.created_at, n.updated_at, n.is_pinned, n.color, n.category_id, n.deleted_at 
-         FROM note_reminders r
-         JOIN notes n ON r.note_id = n.id
-         WHERE r.completed = 0 AND n.deleted_at IS NULL
-         ORDER BY r.reminder_time ASC"
+        "SELECT id, content, screenshot_path, created_at, updated_at, is_pinned, color, category_id, deleted_at, position, note_type, content_format, archived_at
+         FROM notes 
+         WHERE deleted_at IS NULL AND archived_at IS NULL"
     );
     
-    // 添加限制
+    // 添加搜索条件
+    let mut query_params: Vec<Box<dyn rusqlite::ToSql>> = Vec::new();
+    if let Some(search_term) = search.filter(|t| !t.trim().is_empty()) {
+        match build_fts_query(search_term) {
+            Some(fts_query) => {
+                query.push_str(" AND id IN (SELECT rowid FROM notes_fts WHERE notes_fts MATCH ?)");
+                query_params.push(Box::new(fts_query));
+            }
+            None => {
+                for term in search_term.split_whitespace() {
+                    query.push_str(" AND content LIKE ? ESCAPE '\\'");
+                    query_params.push(Box::new(like_pattern(term)));
+                }
+            }
+        }
+    }
+    
+    // 添加标签筛选
+    for tag_id in tag_ids {
+        query.push_str(" AND id IN (SELECT note_id FROM note_tags WHERE tag_id = ?)");
+        query_params.push(Box::new(*tag_id));
+    }
+    
+    // 添加排序
+    query.push_str(" ORDER BY ");
+    query.push_str(sort.order_by());
+    
+    // 添加分页
     if let Some(limit_val) = limit {
         query.push_str(&format!(" LIMIT {}", limit_val));
+        
+        if let Some(offset_val) = offset {
+            query.push_str(&format!(" OFFSET {}", offset_val));
+        }
     }
     
     let mut stmt = conn.prepare(&query)
         .map_err(|e| IoError::new(ErrorKind::Other, format!("准备查询语句失败: {}", e)))?;
     
-    let result_iter = stmt.query_map([], |row| {
-        let reminder = NoteReminder {
-            id: Some(row.get(0)?),
-            note_id: row.get(1)?,
-            reminder_time: row.get(2)?,
-            completed: row.get(3)?,
-        };
-        
-        let note = Note {
-            id: Some(row.get(4)?),
-            content: row.get(5)?,
-            screenshot_path: row.get(6)?,
-            created_at: row.get(7)?,
-            updated_at: row.get(8)?,
-            is_pinned: row.get(9)?,
-            color: row.get(10)?,
-            category_id: row.get(11)?,
-            deleted_at: row.get(12)?,
-        };
-        
-        Ok((reminder, note))
-    }).map_err(|e| IoError::new(ErrorKind::Other, format!("执行查询失败: {}", e)))?;
+    let note_iter = stmt.query_map(params_from_iter(query_params.iter()), |row| note_from_row(row, 0))
+        .map_err(|e| IoError::new(ErrorKind::Other, format!("执行查询失败: {}", e)))?;
     
-    let mut results = Vec::new();
-    for result in result_iter {
-        match result {
-            Ok(pair) => results.push(pair),
+    let mut notes = Vec::new();
+    for note_result in note_iter {
+        match note_result {
+            Ok(note) => notes.push(note),
             Err(e) => return Err(IoError::new(ErrorKind::Other, format!("处理查询结果失败: {}", e))),
         }
     }
     
+    Ok(notes)
+}
+
+// 全文搜索便签，结果按相关度排序并附带高亮摘要
+pub fn search_notes(search: &str, category_id: Option<i64>, limit: Option<i64>) -> Result<Vec<SearchResult>, IoError> {
+    let conn = get_db()?;
+    let terms: Vec<&str> = search.split_whitespace().collect();
+    
+    if terms.is_empty() {
+        return Ok(Vec::new());
+    }
+    
+    // LIMIT -1 表示不限制数量
+    let limit = limit.unwrap_or(-1);
+    let mut results = Vec::new();
+    
+    match build_fts_query(search) {
+        Some(fts_query) => {
+            let mut stmt = conn.prepare(
+                "SELECT n.id, n.content, n.screenshot_path, n.created_at, n.updated_at, n.is_pinned, n.color, n.category_id, n.deleted_at, n.position, n.note_type, n.content_format, n.archived_at,
+                        snippet(notes_fts, 0, char(57344), char(57345), '…', 32), bm25(notes_fts)
+                 FROM notes_fts
+                 JOIN notes n ON n.id = notes_fts.rowid
+                 WHERE notes_fts MATCH ?1
+                   AND n.deleted_at IS NULL
+                   AND (?2 IS NULL OR n.category_id = ?2)
+                 ORDER BY bm25(notes_fts)
+                 LIMIT ?3"
+            ).map_err(|e| IoError::new(ErrorKind::Other, format!("准备查询语句失败: {}", e)))?;
+            
+            let result_iter = stmt.query_map(params![fts_query, category_id, limit], |row| {
+                Ok(SearchResult {
+                    note: note_from_row(row, 0)?,
+                    snippet: render_snippet(&row.get::<_, String>(13)?),
+                    rank: row.get(14)?,
+                })
+            }).map_err(|e| IoError::new(ErrorKind::Other, format!("执行查询失败: {}", e)))?;
+            
+            for result in result_iter {
+                match result {
+                    Ok(result) => results.push(result),
+                    Err(e) => return Err(IoError::new(ErrorKind::Other, format!("处理查询结果失败: {}", e))),
+                }
+            }
+        }
+        None => {
+            // 关键词过短，无法使用索引，逐条匹配后按时间排序
+            let mut query = String::from(
+                "SELECT id, content, screenshot_path, created_at, updated_at, is_pinned, color, category_id, deleted_at, position, note_type, content_format, archived_at
+                 FROM notes
+                 WHERE deleted_at IS NULL AND (?1 IS NULL OR category_id = ?1)"
+            );
+            for i in 0..terms.len() {
+                query.push_str(&format!(" AND content LIKE ?{} ESCAPE '\\'", i + 3));
+            }
+            query.push_str(" ORDER BY is_pinned DESC, COALESCE(updated_at, created_at) DESC LIMIT ?2");
+            
+            let mut stmt = conn.prepare(&query)
+                .map_err(|e| IoError::new(ErrorKind::Other, format!("准备查询语句失败: {}", e)))?;
+            
+            let mut query_params: Vec<Box<dyn rusqlite::ToSql>> = vec![Box::new(category_id), Box::new(limit)];
+            for term in &terms {
+                query_params.push(Box::new(like_pattern(term)));
+            }
+            
+            let note_iter = stmt.query_map(params_from_iter(query_params.iter()), |row| note_from_row(row, 0))
+                .map_err(|e| IoError::new(ErrorKind::Other, format!("执行查询失败: {}", e)))?;
+            
+            for note_result in note_iter {
+                match note_result {
+                    Ok(note) => {
+                        let snippet = search_snippet(&note.content, &terms);
+                        results.push(SearchResult { note, snippet, rank: 0.0 });
+                    }
+                    Err(e) => return Err(IoError::new(ErrorKind::Other, format!("处理查询结果失败: {}", e))),
+                }
+            }
+        }
+    }
+    
     Ok(results)
 }
 
+// 从查询结果的指定列开始读取一条便签
+pub fn note_from_row(row: &Row, start: usize) -> Result<Note> {
+    Ok(Note {
+        id: Some(row.get(start)?),
+        content: row.get(start + 1)?,
+        screenshot_path: row.get(start + 2)?,
+        created_at: row.get(start + 3)?,
+        updated_at: row.get(start + 4)?,
+        is_pinned: row.get(start + 5)?,
+        color: row.get(start + 6)?,
+        category_id: row.get(start + 7)?,
+        deleted_at: row.get(start + 8)?,
+        position: row.get(start + 9)?,
+        note_type: NoteType::from_name(&row.get::<_, String>(start + 10)?),
+        content_format: ContentFormat::from_name(&row.get::<_, String>(start + 11)?),
+        archived_at: row.get(start + 12)?,
+    })
+}
+
+// 将用户输入转换为FTS5查询，每个关键词作为短语并以AND连接
+// 任一关键词短于trigram长度时返回None
+fn build_fts_query(search: &str) -> Option<String> {
+    let terms: Vec<&str> = search.split_whitespace().collect();
+    if terms.is_empty() || terms.iter().any(|t| t.chars().count() < FTS_MIN_TERM_CHARS) {
+        return None;
+    }
+    
+    Some(terms.iter()
+        .map(|t| format!("\"{}\"", t.replace('"', "\"\"")))
+        .collect::<Vec<_>>()
+        .join(" "))
+}
+
+// 与上面的 SQL 搜索相同的匹配规则，供不使用数据库的存储调用：所有关键词都能使用 FTS 时
+// 与 trigram 分词器一样忽略大小写，否则与 LIKE 一样只忽略 ASCII 字母的大小写
+pub fn search_matches(content: &str, search: &str) -> bool {
+    let terms = search.split_whitespace();
+    if build_fts_query(search).is_some() {
+        let content = content.to_lowercase();
+        terms.map(str::to_lowercase).all(|term| content.contains(&term))
+    } else {
+        let content = content.to_ascii_lowercase();
+        terms.map(str::to_ascii_lowercase).all(|term| content.contains(&term))
+    }
+}
+
+// 生成LIKE匹配模式，转义通配符
+fn like_pattern(term: &str) -> String {
+    let escaped = term
+        .replace('\\', "\\\\")
+        .replace('%', "\\%")
+        .replace('_', "\\_");
+    format!("%{}%", escaped)
+}
+
+// 在内容中标记关键词并截取首个命中位置附近的文字，用于无法使用FTS的搜索
+fn mark_terms(content: &str, terms: &[&str]) -> String {
+    let chars: Vec<char> = content.chars().collect();
+    let lower: Vec<char> = chars.iter().map(|c| c.to_lowercase().next().unwrap_or(*c)).collect();
+    
+    // 标记所有命中的字符
+    let mut marked = vec![false; chars.len()];
+    for term in terms {
+        let needle: Vec<char> = term.chars().map(|c| c.to_lowercase().next().unwrap_or(c)).collect();
+        if needle.is_empty() || needle.len() > lower.len() {
+            continue;
+        }
+        for start in 0..=(lower.len() - needle.len()) {
+            if lower[start..start + needle.len()] == needle[..] {
+                marked[start..start + needle.len()].iter_mut().for_each(|m| *m = true);
+            }
+        }
+    }
+    
+    let first = marked.iter().position(|m| *m).unwrap_or(0);
+    let begin = first.saturating_sub(SNIPPET_CONTEXT_CHARS);
+    let end = std::cmp::min(chars.len(), first + SNIPPET_CONTEXT_CHARS * 2);
+    
+    let mut snippet = String::new();
+    if begin > 0 {
+        snippet.push('…');
+    }
+    for i in begin..end {
+        if marked[i] && (i == begin || !marked[i - 1]) {
+            snippet.push(MARK_START);
+        }
+        snippet.push(chars[i]);
+        if marked[i] && (i + 1 == end || !marked[i + 1]) {
+            snippet.push(MARK_END);
+        }
+    }
+    if end < chars.len() {
+        snippet.push('…');
+    }
+    
+    snippet
+}
+
+// 无法使用FTS时的搜索摘要：标记关键词并转义为HTML
+pub fn search_snippet(content: &str, terms: &[&str]) -> String {
+    render_snippet(&mark_terms(content, terms))
+}
+
+// 转义摘要中的HTML并将高亮标记替换为<mark>
+fn render_snippet(raw: &str) -> String {
+    let mut html = String::with_capacity(raw.len());
+    for c in raw.chars() {
+        match c {
+            MARK_START => html.push_str("<mark>"),
+            MARK_END => html.push_str("</mark>"),
+            '&' => html.push_str("&amp;"),
+            '<' => html.push_str("&lt;"),
+            '>' => html.push_str("&gt;"),
+            '"' => html.push_str("&quot;"),
+            '\'' => html.push_str("&#39;"),
+            _ => html.push(c),
+        }
+    }
+    html
+}
+
+// 获取回收站中的便签
+pub fn get_trash_notes() -> Result<Vec<Note>, IoError> {
+    let conn = get_db()?;
+    
+    let mut stmt = conn.prepare(
+        "SELECT id, content, screenshot_path, created_at, updated_at, is_pinned, color, category_id, deleted_at, position, note_type, content_format, archived_at
+         FROM notes 
+         WHERE deleted_at IS NOT NULL
+         ORDER BY deleted_at DESC"
+    ).map_err(|e| IoError::new(ErrorKind::Other, format!("准备查询语句失败: {}", e)))?;
+    
+    let note_iter = stmt.query_map([], |row| note_from_row(row, 0))
+        .map_err(|e| IoError::new(ErrorKind::Other, format!("执行查询失败: {}", e)))?;
+    
+    let mut notes = Vec::new();
+    for note_result in note_iter {
+        match note_result {
+            Ok(note) => notes.push(note),
+            Err(e) => return Err(IoError::new(ErrorKind::Other, format!("处理查询结果失败: {}", e))),
+        }
+    }
+    
+    Ok(notes)
+}
+
+// 将便签移到回收站
+pub fn move_note_to_trash(id: i64, timestamp: i64) -> Result<(), IoError> {
+    let conn = get_db()?;
+    
+    match conn.execute(
+        "UPDATE notes SET deleted_at = ?1 WHERE id = ?2",
+        params![timestamp, id],
+    ) {
+        Ok(0) => Err(IoError::new(ErrorKind::NotFound, "便签不存在")),
+        Ok(_) => Ok(()),
+        Err(e) => Err(IoError::new(ErrorKind::Other, format!("移动便签到回收站失败: {}", e))),
+    }
+}
+
+// 从回收站恢复便签
+pub fn restore_note_from_trash(id: i64) -> Result<(), IoError> {
+    let conn = get_db()?;
+    
+    match conn.execute(
+        "UPDATE notes SET deleted_at = NULL WHERE id = ?1",
+        params![id],
+    ) {
+        Ok(0) => Err(IoError::new(ErrorKind::NotFound, "便签不存在")),
+        Ok(_) => Ok(()),
+        Err(e) => Err(IoError::new(ErrorKind::Other, format!("从回收站恢复便签失败: {}", e))),
+    }
+}
+
+// 永久删除便签
+pub fn delete_note_permanently(id: i64) -> Result<(), IoError> {
+    let conn = get_db()?;
+    
+    // 开始事务
+    conn.execute("BEGIN IMMEDIATE TRANSACTION", [])
+        .map_err(|e| IoError::new(ErrorKind::Other, format!("开始事务失败: {}", e)))?;
+    
+    match delete_note_rows(&conn, id) {
+        Ok(_) => {
+            // 提交事务
+            conn.execute("COMMIT", [])
+                .map_err(|e| IoError::new(ErrorKind::Other, format!("提交事务失败: {}", e)))?;
+            Ok(())
+        },
+        Err(e) => {
+            conn.execute("ROLLBACK", []).ok();
+            Err(e)
+        }
+    }
+}
+
+// 删除便签及所有关联数据，需在调用方的事务中执行
+fn delete_note_rows(conn: &Connection, id: i64) -> Result<(), IoError> {
+    // 先删除与便签关联的数据
+    let dependents = [
+        ("DELETE FROM note_reminders WHERE note_id = ?1", "删除便签提醒失败"),
+        ("DELETE FROM note_revisions WHERE note_id = ?1", "删除便签历史版本失败"),
+        ("DELETE FROM note_tags WHERE note_id = ?1", "删除便签标签失败"),
+        ("DELETE FROM note_attachments WHERE note_id = ?1", "删除便签附件失败"),
+        ("DELETE FROM checklist_items WHERE note_id = ?1", "删除清单条目失败"),
+        ("DELETE FROM note_windows WHERE note_id = ?1", "删除便签窗口状态失败"),
+        ("DELETE FROM note_links WHERE source_id = ?1", "删除便签链接失败"),
+        ("UPDATE note_links SET target_id = NULL WHERE target_id = ?1", "更新便签链接失败"),
+    ];
+    for (sql, message) in dependents {
+        conn.execute(sql, params![id])
+            .map_err(|e| IoError::new(ErrorKind::Other, format!("{}: {}", message, e)))?;
+    }
+    
+    // 再删除便签本身
+    conn.execute("DELETE FROM notes WHERE id = ?1", params![id])
+        .map_err(|e| IoError::new(ErrorKind::Other, format!("永久删除便签失败: {}", e)))?;
+    
+    Ok(())
+}
+
+// 清理回收站（删除30天前的便签）
+pub fn cleanup_trash(days: i64) -> Result<i32, IoError> {
+    let conn = get_db()?;
+    
+    // 计算30天前的时间戳
+    let now = chrono::Utc::now().timestamp();
+    let threshold = now - (days * 24 * 60 * 60); // days天的秒数
+    
+    // 获取要删除的便签ID列表，归档的便签即使在回收站中也不自动清理
+    let mut stmt = conn.prepare(
+        "SELECT id FROM notes WHERE deleted_at IS NOT NULL AND deleted_at < ?1 AND archived_at IS NULL"
+    ).map_err(|e| IoError::new(ErrorKind::Other, format!("准备查询语句失败: {}", e)))?;
+    
+    let note_ids: Result<Vec<i64>, _> = stmt.query_map(params![threshold], |row| row.get(0))
+        .map_err(|e| IoError::new(ErrorKind::Other, format!("执行查询失败: {}", e)))?
+        .collect();
+    
+    let note_ids = match note_ids {
+        Ok(ids) => ids,
+        Err(e) => return Err(IoError::new(ErrorKind::Other, format!("收集便签ID失败: {}", e))),
+    };
+    
+    let mut deleted_count = 0;
+    
+    // 开始事务
+    conn.execute("BEGIN IMMEDIATE TRANSACTION", [])
+        .map_err(|e| IoError::new(ErrorKind::Other, format!("开始事务失败: {}", e)))?;
+    
+    // 逐个删除便签及其关联的数据
+    for id in note_ids {
+        match delete_note_rows(&conn, id) {
+            Ok(_) => deleted_count += 1,
+            Err(e) => {
+                conn.execute("ROLLBACK", []).ok();
+                return Err(e);
+            }
+        }
+    }
+    
+    // 提交事务
+    conn.execute("COMMIT", [])
+        .map_err(|e| IoError::new(ErrorKind::Other, format!("提交事务失败: {}", e)))?;
+    
+    Ok(deleted_count)
+}
+
+// 切换便签置顶状态
+pub fn toggle_note_pin(id: i64) -> Result<bool, IoError> {
+    let conn = get_db()?;
+    
+    // 首先获取当前的置顶状态
+    let mut stmt = conn.prepare("SELECT is_pinned FROM notes WHERE id = ?1")
+        .map_err(|e| IoError::new(ErrorKind::Other, format!("准备查询语句失败: {}", e)))?;
+    
+    let is_pinned = match stmt.query_row(params![id], |row| row.get::<_, bool>(0)) {
+        Ok(value) => value,
+        Err(rusqlite::Error::QueryReturnedNoRows) => {
+            return Err(IoError::new(ErrorKind::NotFound, "便签不存在"));
+        },
+        Err(e) => {
+            return Err(IoError::new(ErrorKind::Other, format!("查询便签置顶状态失败: {}", e)));
+        }
+    };
+    
+    // 更新为相反的状态
+    let new_status = !is_pinned;
+    match conn.execute(
+        "UPDATE notes SET is_pinned = ?1 WHERE id = ?2",
+        params![new_status, id],
+    ) {
+        Ok(_) => Ok(new_status),
+        Err(e) => Err(IoError::new(ErrorKind::Other, format!("更新便签置顶状态失败: {}", e))),
+    }
+}
+
+// ======= 归档操作 =======
+
+// 归档便签，归档的便签不会被回收站清理；一条语句完成，不会只归档其中一部分
+pub fn archive_notes(ids: &[i64], timestamp: i64) -> Result<(), IoError> {
+    if ids.is_empty() {
+        return Ok(());
+    }
+    
+    let conn = get_db()?;
+    let placeholders = vec!["?"; ids.len()].join(", ");
+    let mut query_params: Vec<&dyn rusqlite::ToSql> = vec![&timestamp];
+    query_params.extend(ids.iter().map(|id| id as &dyn rusqlite::ToSql));
+    
+    match conn.execute(
+        &format!("UPDATE notes SET archived_at = ? WHERE id IN ({}) AND archived_at IS NULL", placeholders),
+        params_from_iter(query_params),
+    ) {
+        Ok(_) => Ok(()),
+        Err(e) => Err(IoError::new(ErrorKind::Other, format!("归档便签失败: {}", e))),
+    }
+}
+
+pub fn unarchive_notes(ids: &[i64]) -> Result<(), IoError> {
+    if ids.is_empty() {
+        return Ok(());
+    }
+    
+    let conn = get_db()?;
+    let placeholders = vec!["?"; ids.len()].join(", ");
+    
+    match conn.execute(
+        &format!("UPDATE notes SET archived_at = NULL WHERE id IN ({})", placeholders),
+        params_from_iter(ids.iter()),
+    ) {
+        Ok(_) => Ok(()),
+        Err(e) => Err(IoError::new(ErrorKind::Other, format!("取消归档失败: {}", e))),
+    }
+}
+
+// 获取已归档（且未删除）的便签，最近归档的在前
+pub fn get_archived_notes() -> Result<Vec<Note>, IoError> {
+    let conn = get_db()?;
+    
+    let mut stmt = conn.prepare(
+        "SELECT id, content, screenshot_path, created_at, updated_at, is_pinned, color, category_id, deleted_at, position, note_type, content_format, archived_at
+         FROM notes
+         WHERE archived_at IS NOT NULL AND deleted_at IS NULL
+         ORDER BY archived_at DESC"
+    ).map_err(|e| IoError::new(ErrorKind::Other, format!("准备查询语句失败: {}", e)))?;
+    
+    let notes = stmt.query_map([], |row| note_from_row(row, 0))
+        .map_err(|e| IoError::new(ErrorKind::Other, format!("查询归档便签失败: {}", e)))?;
+    
+    notes.collect::<Result<Vec<_>, _>>()
+        .map_err(|e| IoError::new(ErrorKind::Other, format!("处理查询结果失败: {}", e)))
+}
+
+// ======= 便签窗口操作 =======
+
+fn note_window_from_row(row: &Row) -> Result<NoteWindowState> {
+    Ok(NoteWindowState {
+        note_id: row.get(0)?,
+        x: row.get(1)?,
+        y: row.get(2)?,
+        width: row.get(3)?,
+        height: row.get(4)?,
+        monitor: row.get(5)?,
+        opacity: row.get(6)?,
+        always_on_top: row.get(7)?,
+        collapsed: row.get(8)?,
+        is_open: row.get(9)?,
+    })
+}
+
+pub fn get_note_window_state(note_id: i64) -> Result<Option<NoteWindowState>, IoError> {
+    let conn = get_db()?;
+    
+    conn.query_row(
+        "SELECT note_id, x, y, width, height, monitor, opacity, always_on_top, collapsed, is_open
+         FROM note_windows WHERE note_id = ?1",
+        params![note_id],
+        note_window_from_row,
+    )
+    .optional()
+    .map_err(|e| IoError::new(ErrorKind::Other, format!("获取便签窗口状态失败: {}", e)))
+}
+
+// 保存窗口状态，不存在时插入
+pub fn save_note_window_state(state: &NoteWindowState) -> Result<(), IoError> {
+    let conn = get_db()?;
+    let now = chrono::Utc::now().timestamp();
+    
+    match conn.execute(
+        "INSERT INTO note_windows (note_id, x, y, width, height, monitor, opacity, always_on_top, collapsed, is_open, updated_at)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11)
+         ON CONFLICT (note_id) DO UPDATE SET
+             x = excluded.x, y = excluded.y, width = excluded.width, height = excluded.height,
+             monitor = excluded.monitor, opacity = excluded.opacity, always_on_top = excluded.always_on_top,
+             collapsed = excluded.collapsed, is_open = excluded.is_open, updated_at = excluded.updated_at",
+        params![
+            state.note_id,
+            state.x,
+            state.y,
+            state.width,
+            state.height,
+            state.monitor,
+            state.opacity,
+            state.always_on_top,
+            state.collapsed,
+            state.is_open,
+            now,
+        ],
+    ) {
+        Ok(_) => Ok(()),
+        Err(e) => Err(IoError::new(ErrorKind::Other, format!("保存便签窗口状态失败: {}", e))),
+    }
+}
+
+// 只更新位置、尺寸和显示器，折叠状态下保留展开时的高度
+pub fn update_note_window_geometry(state: &NoteWindowState) -> Result<(), IoError> {
+    let conn = get_db()?;
+    let now = chrono::Utc::now().timestamp();
+    
+    match conn.execute(
+        "UPDATE note_windows SET x = ?1, y = ?2, width = ?3,
+             height = CASE WHEN collapsed THEN height ELSE ?4 END,
+             monitor = ?5, updated_at = ?6
+         WHERE note_id = ?7",
+        params![state.x, state.y, state.width, state.height, state.monitor, now, state.note_id],
+    ) {
+        Ok(_) => Ok(()),
+        Err(e) => Err(IoError::new(ErrorKind::Other, format!("保存便签窗口位置失败: {}", e))),
+    }
+}
+
+pub fn set_note_window_open(note_id: i64, is_open: bool) -> Result<(), IoError> {
+    let conn = get_db()?;
+    
+    match conn.execute(
+        "UPDATE note_windows SET is_open = ?1 WHERE note_id = ?2",
+        params![is_open, note_id],
+    ) {
+        Ok(_) => Ok(()),
+        Err(e) => Err(IoError::new(ErrorKind::Other, format!("更新便签窗口状态失败: {}", e))),
+    }
+}
+
+// 上次退出时仍打开的窗口，已删除或已归档的便签不再恢复
+pub fn get_open_note_windows() -> Result<Vec<NoteWindowState>, IoError> {
+    let conn = get_db()?;
+    
+    let mut stmt = conn.prepare(
+        "SELECT w.note_id, w.x, w.y, w.width, w.height, w.monitor, w.opacity, w.always_on_top, w.collapsed, w.is_open
+         FROM note_windows w
+         JOIN notes n ON n.id = w.note_id
+         WHERE w.is_open = 1 AND n.deleted_at IS NULL AND n.archived_at IS NULL
+         ORDER BY w.updated_at ASC"
+    ).map_err(|e| IoError::new(ErrorKind::Other, format!("准备查询语句失败: {}", e)))?;
+    
+    let windows = stmt.query_map([], note_window_from_row)
+        .map_err(|e| IoError::new(ErrorKind::Other, format!("查询便签窗口失败: {}", e)))?;
+    
+    windows.collect::<Result<Vec<_>, _>>()
+        .map_err(|e| IoError::new(ErrorKind::Other, format!("处理查询结果失败: {}", e)))
+}
+
+// ======= 清单操作 =======
+
+fn checklist_item_from_row(row: &Row) -> Result<ChecklistItem> {
+    Ok(ChecklistItem {
+        id: row.get(0)?,
+        note_id: row.get(1)?,
+        content: row.get(2)?,
+        done: row.get(3)?,
+        position: row.get(4)?,
+        due_at: row.get(5)?,
+        created_at: row.get(6)?,
+    })
+}
+
+// 获取清单的所有条目，按顺序排列
+pub fn get_checklist_items(note_id: i64) -> Result<Vec<ChecklistItem>, IoError> {
+    let conn = get_db()?;
+    
+    let mut stmt = conn.prepare(
+        "SELECT id, note_id, content, done, position, due_at, created_at
+         FROM checklist_items
+         WHERE note_id = ?1
+         ORDER BY position ASC, id ASC"
+    ).map_err(|e| IoError::new(ErrorKind::Other, format!("准备查询语句失败: {}", e)))?;
+    
+    let items = stmt.query_map(params![note_id], checklist_item_from_row)
+        .map_err(|e| IoError::new(ErrorKind::Other, format!("执行查询失败: {}", e)))?;
+    
+    items.collect::<Result<Vec<_>, _>>()
+        .map_err(|e| IoError::new(ErrorKind::Other, format!("处理查询结果失败: {}", e)))
+}
+
+// 在清单末尾添加条目，返回条目ID
+pub fn add_checklist_item(note_id: i64, content: &str, due_at: Option<i64>) -> Result<i64, IoError> {
+    let conn = get_db()?;
+    let now = chrono::Utc::now().timestamp();
+    
+    conn.execute(
+        "INSERT INTO checklist_items (note_id, content, done, position, due_at, created_at)
+         VALUES (?1, ?2, 0, (SELECT COALESCE(MAX(position), -1) + 1 FROM checklist_items WHERE note_id = ?1), ?3, ?4)",
+        params![note_id, content, due_at, now],
+    ).map_err(|e| IoError::new(ErrorKind::Other, format!("添加清单条目失败: {}", e)))?;
+    
+    Ok(conn.last_insert_rowid())
+}
+
+// 修改条目内容和截止时间
+pub fn update_checklist_item(id: i64, content: &str, due_at: Option<i64>) -> Result<(), IoError> {
+    let conn = get_db()?;
+    
+    match conn.execute(
+        "UPDATE checklist_items SET content = ?1, due_at = ?2 WHERE id = ?3",
+        params![content, due_at, id],
+    ) {
+        Ok(0) => Err(IoError::new(ErrorKind::NotFound, "清单条目不存在")),
+        Ok(_) => Ok(()),
+        Err(e) => Err(IoError::new(ErrorKind::Other, format!("更新清单条目失败: {}", e))),
+    }
+}
+
+// 切换条目的完成状态，返回新的状态
+pub fn toggle_checklist_item(id: i64) -> Result<bool, IoError> {
+    let conn = get_db()?;
+    
+    conn.execute("UPDATE checklist_items SET done = NOT done WHERE id = ?1", params![id])
+        .map_err(|e| IoError::new(ErrorKind::Other, format!("更新清单条目失败: {}", e)))?;
+    
+    conn.query_row("SELECT done FROM checklist_items WHERE id = ?1", params![id], |row| row.get(0))
+        .optional()
+        .map_err(|e| IoError::new(ErrorKind::Other, format!("查询清单条目失败: {}", e)))?
+        .ok_or_else(|| IoError::new(ErrorKind::NotFound, "清单条目不存在"))
+}
+
+// 按列表顺序写入条目位置，不属于该清单的条目会被忽略
+pub fn reorder_checklist_items(note_id: i64, item_ids: &[i64]) -> Result<(), IoError> {
+    let conn = get_db()?;
+    
+    conn.execute("BEGIN IMMEDIATE TRANSACTION", [])
+        .map_err(|e| IoError::new(ErrorKind::Other, format!("开始事务失败: {}", e)))?;
+    
+    for (position, id) in item_ids.iter().enumerate() {
+        if let Err(e) = conn.execute(
+            "UPDATE checklist_items SET position = ?1 WHERE id = ?2 AND note_id = ?3",
+            params![position as i64, id, note_id],
+        ) {
+            conn.execute("ROLLBACK", []).ok();
+            return Err(IoError::new(ErrorKind::Other, format!("更新条目顺序失败: {}", e)));
+        }
+    }
+    
+    conn.execute("COMMIT", [])
+        .map_err(|e| IoError::new(ErrorKind::Other, format!("提交事务失败: {}", e)))?;
+    
+    Ok(())
+}
+
+pub fn delete_checklist_item(id: i64) -> Result<(), IoError> {
+    let conn = get_db()?;
+    
+    match conn.execute("DELETE FROM checklist_items WHERE id = ?1", params![id]) {
+        Ok(_) => Ok(()),
+        Err(e) => Err(IoError::new(ErrorKind::Other, format!("删除清单条目失败: {}", e))),
+    }
+}
+
+// 获取多个清单的完成进度，没有条目的清单不在结果中
+pub fn get_checklist_progress(note_ids: &[i64]) -> Result<HashMap<i64, ChecklistProgress>, IoError> {
+    let mut progress = HashMap::new();
+    if note_ids.is_empty() {
+        return Ok(progress);
+    }
+    
+    let conn = get_db()?;
+    let placeholders = vec!["?"; note_ids.len()].join(", ");
+    let mut stmt = conn.prepare(&format!(
+        "SELECT note_id, SUM(done), COUNT(*) FROM checklist_items
+         WHERE note_id IN ({})
+         GROUP BY note_id",
+        placeholders
+    )).map_err(|e| IoError::new(ErrorKind::Other, format!("准备查询语句失败: {}", e)))?;
+    
+    let rows = stmt.query_map(params_from_iter(note_ids.iter()), |row| {
+        Ok((row.get::<_, i64>(0)?, ChecklistProgress { done: row.get(1)?, total: row.get(2)? }))
+    }).map_err(|e| IoError::new(ErrorKind::Other, format!("执行查询失败: {}", e)))?;
+    
+    for row in rows {
+        let (note_id, note_progress) = row
+            .map_err(|e| IoError::new(ErrorKind::Other, format!("处理查询结果失败: {}", e)))?;
+        progress.insert(note_id, note_progress);
+    }
+    
+    Ok(progress)
+}
+
+// 将文本便签转换为清单，任务列表行（- [ ] / - [x]）变为条目，其余内容保留，
+// 修改内容和添加条目在同一个事务中完成
+pub fn convert_to_checklist(note_id: i64) -> Result<(), IoError> {
+    let conn = get_db()?;
+    
+    conn.execute("BEGIN IMMEDIATE TRANSACTION", [])
+        .map_err(|e| IoError::new(ErrorKind::Other, format!("开始事务失败: {}", e)))?;
+    
+    let result = (|| -> Result<(), IoError> {
+        let (content, note_type) = conn.query_row(
+            "SELECT content, note_type FROM notes WHERE id = ?1",
+            params![note_id],
+            |row| Ok((row.get::<_, String>(0)?, NoteType::from_name(&row.get::<_, String>(1)?))),
+        )
+        .optional()
+        .map_err(|e| IoError::new(ErrorKind::Other, format!("查询便签失败: {}", e)))?
+        .ok_or_else(|| IoError::new(ErrorKind::NotFound, "便签不存在"))?;
+        if note_type == NoteType::Checklist {
+            return Ok(());
+        }
+        
+        let (remaining, items) = checklist::split_checklist_content(&content);
+        record_revision(&conn, note_id, &remaining)?;
+        conn.execute(
+            "UPDATE notes SET content = ?1, note_type = ?2, updated_at = ?3 WHERE id = ?4",
+            params![remaining, NoteType::Checklist.as_str(), chrono::Utc::now().timestamp(), note_id],
+        ).map_err(|e| IoError::new(ErrorKind::Other, format!("更新便签失败: {}", e)))?;
+        sync_inline_tags(&conn, note_id, &remaining)?;
+        sync_note_links(&conn, note_id, &remaining, Some(note_title(&content)))?;
+        
+        let now = chrono::Utc::now().timestamp();
+        for (position, (item, done)) in items.iter().enumerate() {
+            conn.execute(
+                "INSERT INTO checklist_items (note_id, content, done, position, due_at, created_at)
+                 VALUES (?1, ?2, ?3, ?4, NULL, ?5)",
+                params![note_id, item, done, position as i64, now],
+            ).map_err(|e| IoError::new(ErrorKind::Other, format!("添加清单条目失败: {}", e)))?;
+        }
+        Ok(())
+    })();
+    
+    match result {
+        Ok(()) => {
+            conn.execute("COMMIT", [])
+                .map_err(|e| IoError::new(ErrorKind::Other, format!("提交事务失败: {}", e)))?;
+            Ok(())
+        },
+        Err(e) => {
+            conn.execute("ROLLBACK", []).ok();
+            Err(e)
+        }
+    }
+}
+
+// ======= 历史版本操作 =======
+
+// 设置每个便签保留的历史版本数量，0 表示不记录历史
+pub fn set_revision_limit(limit: i64) {
+    REVISION_LIMIT.store(limit.max(0), Ordering::Relaxed);
+}
+
+pub fn revision_limit() -> i64 {
+    REVISION_LIMIT.load(Ordering::Relaxed)
+}
+
+// 获取便签的历史版本，最新的在前
+pub fn get_note_revisions(note_id: i64) -> Result<Vec<NoteRevision>, IoError> {
+    let conn = get_db()?;
+    
+    let mut stmt = conn.prepare(
+        "SELECT id, note_id, content, created_at
+         FROM note_revisions
+         WHERE note_id = ?1
+         ORDER BY id DESC"
+    ).map_err(|e| IoError::new(ErrorKind::Other, format!("准备查询语句失败: {}", e)))?;
+    
+    let revision_iter = stmt.query_map(params![note_id], |row| {
+        Ok(NoteRevision {
+            id: row.get(0)?,
+            note_id: row.get(1)?,
+            content: row.get(2)?,
+            created_at: row.get(3)?,
+        })
+    }).map_err(|e| IoError::new(ErrorKind::Other, format!("执行查询失败: {}", e)))?;
+    
+    let mut revisions = Vec::new();
+    for revision_result in revision_iter {
+        match revision_result {
+            Ok(revision) => revisions.push(revision),
+            Err(e) => return Err(IoError::new(ErrorKind::Other, format!("处理查询结果失败: {}", e))),
+        }
+    }
+    
+    Ok(revisions)
+}
+
+// 根据ID获取历史版本
+pub fn get_note_revision(id: i64) -> Result<Option<NoteRevision>, IoError> {
+    let conn = get_db()?;
+    
+    let revision_result = conn.query_row(
+        "SELECT id, note_id, content, created_at FROM note_revisions WHERE id = ?1",
+        params![id],
+        |row| {
+            Ok(NoteRevision {
+                id: row.get(0)?,
+                note_id: row.get(1)?,
+                content: row.get(2)?,
+                created_at: row.get(3)?,
+            })
+        },
+    );
+    
+    match revision_result {
+        Ok(revision) => Ok(Some(revision)),
+        Err(rusqlite::Error::QueryReturnedNoRows) => Ok(None),
+        Err(e) => Err(IoError::new(ErrorKind::Other, format!("查询历史版本失败: {}", e))),
+    }
+}
+
+// 比较两段文本，返回逐行差异
+pub fn diff_lines(old: &str, new: &str) -> Vec<DiffLine> {
+    similar::TextDiff::from_lines(old, new)
+        .iter_all_changes()
+        .map(|change| DiffLine {
+            tag: match change.tag() {
+                similar::ChangeTag::Equal => DiffTag::Equal,
+                similar::ChangeTag::Insert => DiffTag::Insert,
+                similar::ChangeTag::Delete => DiffTag::Delete,
+            },
+            content: change.value().trim_end_matches('\n').to_string(),
+        })
+        .collect()
+}
+
+// 将历史版本恢复为便签的当前内容，恢复前的内容会作为新的历史版本保留
+pub fn restore_note_revision(revision_id: i64) -> Result<i64, IoError> {
+    let revision = get_note_revision(revision_id)?
+        .ok_or_else(|| IoError::new(ErrorKind::NotFound, "历史版本不存在"))?;
+    let mut note = get_note(revision.note_id)?
+        .ok_or_else(|| IoError::new(ErrorKind::NotFound, "便签不存在"))?;
+    
+    note.content = revision.content;
+    note.updated_at = Some(chrono::Utc::now().timestamp());
+    save_note(&note)
+}
+
+// ======= 提醒操作 =======
+
+// 保存便签提醒
+pub fn save_reminder(reminder: &NoteReminder) -> Result<i64, IoError> {
+    let conn = get_db()?;
+    let rrule = reminder.recurrence.as_ref().map(|r| r.rrule.as_str());
+    let timezone = reminder.recurrence.as_ref().and_then(|r| r.timezone.as_deref());
+    let dtstart = reminder.recurrence.as_ref().map(|r| r.dtstart);
+    
+    if reminder.id.is_none() {
+        // 插入新提醒
+        match conn.execute(
+            "INSERT INTO note_reminders (note_id, reminder_time, completed, rrule, timezone, dtstart)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+            params![
+                reminder.note_id,
+                reminder.reminder_time,
+                reminder.completed,
+                rrule,
+                timezone,
+                dtstart
+            ],
+        ) {
+            Ok(_) => {
+                let id = conn.last_insert_rowid();
+                Ok(id)
+            },
+            Err(e) => Err(IoError::new(ErrorKind::Other, format!("创建提醒失败: {}", e))),
+        }
+    } else {
+        // 更新现有提醒
+        let id = reminder.id.unwrap();
+        match conn.execute(
+            "UPDATE note_reminders SET 
+                note_id = ?1, 
+                reminder_time = ?2,
+                completed = ?3,
+                rrule = ?4,
+                timezone = ?5,
+                dtstart = ?6
+             WHERE id = ?7",
+            params![
+                reminder.note_id,
+                reminder.reminder_time,
+                reminder.completed,
+                rrule,
+                timezone,
+                dtstart,
+                id
+            ],
+        ) {
+            Ok(_) => Ok(id),
+            Err(e) => Err(IoError::new(ErrorKind::Other, format!("更新提醒失败: {}", e))),
+        }
+    }
+}
+
+// 读取提醒的前 7 列：id, note_id, reminder_time, completed, rrule, timezone, dtstart
+fn reminder_from_row(row: &Row) -> Result<NoteReminder> {
+    let reminder_time: i64 = row.get(2)?;
+    let recurrence = match row.get::<_, Option<String>>(4)? {
+        Some(rrule) => Some(Recurrence {
+            rrule,
+            timezone: row.get(5)?,
+            dtstart: row.get::<_, Option<i64>>(6)?.unwrap_or(reminder_time),
+        }),
+        None => None,
+    };
+    
+    Ok(NoteReminder {
+        id: Some(row.get(0)?),
+        note_id: row.get(1)?,
+        reminder_time,
+        completed: row.get(3)?,
+        recurrence,
+    })
+}
+
+// 获取便签的所有提醒
+pub fn get_reminders_by_note(note_id: i64) -> Result<Vec<NoteReminder>, IoError> {
+    let conn = get_db()?;
+    
+    let mut stmt = conn.prepare(
+        "SELECT id, note_id, reminder_time, completed, rrule, timezone, dtstart
+         FROM note_reminders 
+         WHERE note_id = ?1
+         ORDER BY reminder_time ASC"
+    ).map_err(|e| IoError::new(ErrorKind::Other, format!("准备查询语句失败: {}", e)))?;
+    
+    let reminder_iter = stmt.query_map(params![note_id], reminder_from_row).map_err(|e| IoError::new(ErrorKind::Other, format!("执行查询失败: {}", e)))?;
+    
+    let mut reminders = Vec::new();
+    for reminder_result in reminder_iter {
+        match reminder_result {
+            Ok(reminder) => reminders.push(reminder),
+            Err(e) => return Err(IoError::new(ErrorKind::Other, format!("处理查询结果失败: {}", e))),
+        }
+    }
+    
+    Ok(reminders)
+}
+
+// 删除便签提醒
+pub fn delete_reminder(id: i64) -> Result<(), IoError> {
+    let conn = get_db()?;
+    
+    match conn.execute(
+        "DELETE FROM note_reminders WHERE id = ?1",
+        params![id],
+    ) {
+        Ok(_) => Ok(()),
+        Err(e) => Err(IoError::new(ErrorKind::Other, format!("删除提醒失败: {}", e))),
+    }
+}
+
+// 获取即将到期的提醒
+pub fn get_upcoming_reminders(limit: Option<i64>) -> Result<Vec<(NoteReminder, Note)>, IoError> {
+    let conn = get_db()?;
+    
+    let mut query = String::from(
+        "SELECT r.id, r.note_id, r.reminder_time, r.completed, r.rrule, r.timezone, r.dtstart,
+                n.id, n.content, n.screenshot_path, n.created_at, n.updated_at, n.is_pinned, n.color, n.category_id, n.deleted_at, n.position, n.note_type, n.content_format, n.archived_at
+         FROM note_reminders r
+         JOIN notes n ON r.note_id = n.id
+         WHERE r.completed = 0 AND n.deleted_at IS NULL
+         ORDER BY r.reminder_time ASC"
+    );
+    
+    // 添加限制
+    if let Some(limit_val) = limit {
+        query.push_str(&format!(" LIMIT {}", limit_val));
+    }
+    
+    let mut stmt = conn.prepare(&query)
+        .map_err(|e| IoError::new(ErrorKind::Other, format!("准备查询语句失败: {}", e)))?;
+    
+    let result_iter = stmt.query_map([], |row| {
+        let reminder = reminder_from_row(row)?;
+        let note = note_from_row(row, 7)?;
+        
+        Ok((reminder, note))
+    }).map_err(|e| IoError::new(ErrorKind::Other, format!("执行查询失败: {}", e)))?;
+    
+    let mut results = Vec::new();
+    for result in result_iter {
+        match result {
+            Ok(pair) => results.push(pair),
+            Err(e) => return Err(IoError::new(ErrorKind::Other, format!("处理查询结果失败: {}", e))),
+        }
+    }
+    
+    Ok(results)
+}
+
+// ======= 附件操作 =======
+
+const ATTACHMENT_COLUMNS: &str = "id, hash, mime, extension, size, ref_count, created_at";
+
+fn attachment_from_row(row: &Row) -> Result<Attachment> {
+    Ok(Attachment {
+        id: row.get(0)?,
+        hash: row.get(1)?,
+        mime: row.get(2)?,
+        extension: row.get(3)?,
+        size: row.get(4)?,
+        ref_count: row.get(5)?,
+        created_at: row.get(6)?,
+    })
+}
+
+// 保存附件内容，相同内容只存储一份；note_id 不为空时同时关联到便签
+// 已有相同内容时刷新创建时间，让重新粘贴的附件重新获得回收宽限期
+pub fn add_attachment(bytes: &[u8], mime: &str, note_id: Option<i64>) -> Result<Attachment, IoError> {
+    let conn = get_db()?;
+    
+    let extension = attachments::extension_for_mime(mime);
+    let (hash, _) = attachments::write_blob(bytes, extension)?;
+    let now = chrono::Utc::now().timestamp();
+    
+    // 开始事务
+    conn.execute("BEGIN IMMEDIATE TRANSACTION", [])
+        .map_err(|e| IoError::new(ErrorKind::Other, format!("开始事务失败: {}", e)))?;
+    
+    let result = insert_attachment(&conn, &hash, mime, bytes.len() as i64, note_id, now);
+    
+    match result {
+        Ok(attachment) => {
+            // 提交事务
+            conn.execute("COMMIT", [])
+                .map_err(|e| IoError::new(ErrorKind::Other, format!("提交事务失败: {}", e)))?;
+            Ok(attachment)
+        },
+        Err(e) => {
+            conn.execute("ROLLBACK", []).ok();
+            Err(e)
+        }
+    }
+}
+
+// 写入已保存到附件存储中的文件的记录，需在调用方的事务中执行
+fn insert_attachment(conn: &Connection, hash: &str, mime: &str, size: i64, note_id: Option<i64>, now: i64) -> Result<Attachment, IoError> {
+    conn.execute(
+        "INSERT INTO attachments (hash, mime, extension, size, created_at) VALUES (?1, ?2, ?3, ?4, ?5)
+         ON CONFLICT (hash) DO UPDATE SET created_at = excluded.created_at",
+        params![hash, mime, attachments::extension_for_mime(mime), size, now],
+    ).map_err(|e| IoError::new(ErrorKind::Other, format!("保存附件失败: {}", e)))?;
+    
+    let attachment_id: i64 = conn.query_row("SELECT id FROM attachments WHERE hash = ?1", params![hash], |row| row.get(0))
+        .map_err(|e| IoError::new(ErrorKind::Other, format!("查询附件失败: {}", e)))?;
+    
+    if let Some(note_id) = note_id {
+        conn.execute(
+            "INSERT OR IGNORE INTO note_attachments (note_id, attachment_id, created_at) VALUES (?1, ?2, ?3)",
+            params![note_id, attachment_id, now],
+        ).map_err(|e| IoError::new(ErrorKind::Other, format!("关联附件失败: {}", e)))?;
+    }
+    
+    query_attachment(conn, attachment_id)?
+        .ok_or_else(|| IoError::new(ErrorKind::NotFound, "附件不存在"))
+}
+
+// 将附件关联到便签
+pub fn attach_to_note(note_id: i64, attachment_id: i64) -> Result<(), IoError> {
+    let conn = get_db()?;
+    
+    match conn.execute(
+        "INSERT OR IGNORE INTO note_attachments (note_id, attachment_id, created_at) VALUES (?1, ?2, ?3)",
+        params![note_id, attachment_id, chrono::Utc::now().timestamp()],
+    ) {
+        Ok(_) => Ok(()),
+        Err(e) => Err(IoError::new(ErrorKind::Other, format!("关联附件失败: {}", e))),
+    }
+}
+
+// 取消附件与便签的关联，文件在垃圾回收时删除
+pub fn detach_from_note(note_id: i64, attachment_id: i64) -> Result<(), IoError> {
+    let conn = get_db()?;
+    
+    match conn.execute(
+        "DELETE FROM note_attachments WHERE note_id = ?1 AND attachment_id = ?2",
+        params![note_id, attachment_id],
+    ) {
+        Ok(_) => Ok(()),
+        Err(e) => Err(IoError::new(ErrorKind::Other, format!("移除附件失败: {}", e))),
+    }
+}
+
+// 截图保存在附件存储中，将其关联到便签，避免被附件回收删除；需在调用方的事务中执行
+fn link_screenshot_attachment(conn: &Connection, note_id: i64, screenshot_path: Option<&str>) -> Result<(), IoError> {
+    let hash = match screenshot_path.and_then(|path| attachments::hash_from_blob_path(Path::new(path))) {
+        Some(hash) => hash,
+        None => return Ok(()),
+    };
+    
+    conn.execute(
+        "INSERT OR IGNORE INTO note_attachments (note_id, attachment_id, created_at)
+         SELECT ?1, id, ?2 FROM attachments WHERE hash = ?3",
+        params![note_id, chrono::Utc::now().timestamp(), hash],
+    ).map_err(|e| IoError::new(ErrorKind::Other, format!("关联截图附件失败: {}", e)))?;
+    
+    Ok(())
+}
+
+// 根据ID获取附件
+pub fn get_attachment(id: i64) -> Result<Option<Attachment>, IoError> {
+    let conn = get_db()?;
+    query_attachment(&conn, id)
+}
+
+fn query_attachment(conn: &Connection, id: i64) -> Result<Option<Attachment>, IoError> {
+    let attachment_result = conn.query_row(
+        &format!("SELECT {} FROM attachments WHERE id = ?1", ATTACHMENT_COLUMNS),
+        params![id],
+        attachment_from_row,
+    );
+    
+    match attachment_result {
+        Ok(attachment) => Ok(Some(attachment)),
+        Err(rusqlite::Error::QueryReturnedNoRows) => Ok(None),
+        Err(e) => Err(IoError::new(ErrorKind::Other, format!("查询附件失败: {}", e))),
+    }
+}
+
+// 获取便签的所有附件，按添加顺序排列
+pub fn get_note_attachments(note_id: i64) -> Result<Vec<Attachment>, IoError> {
+    let conn = get_db()?;
+    
+    let mut stmt = conn.prepare(
+        "SELECT a.id, a.hash, a.mime, a.extension, a.size, a.ref_count, a.created_at
+         FROM attachments a
+         JOIN note_attachments na ON na.attachment_id = a.id
+         WHERE na.note_id = ?1
+         ORDER BY na.created_at ASC, a.id ASC"
+    ).map_err(|e| IoError::new(ErrorKind::Other, format!("准备查询语句失败: {}", e)))?;
+    
+    let attachment_iter = stmt.query_map(params![note_id], attachment_from_row)
+        .map_err(|e| IoError::new(ErrorKind::Other, format!("执行查询失败: {}", e)))?;
+    
+    let mut attachments = Vec::new();
+    for attachment_result in attachment_iter {
+        match attachment_result {
+            Ok(attachment) => attachments.push(attachment),
+            Err(e) => return Err(IoError::new(ErrorKind::Other, format!("处理查询结果失败: {}", e))),
+        }
+    }
+    
+    Ok(attachments)
+}
+
+// 回收没有任何便签引用的附件，返回删除的文件数量
+// 同时清理存储目录中没有数据库记录的文件
+pub fn collect_attachment_garbage() -> Result<usize, IoError> {
+    let conn = get_db()?;
+    let threshold = chrono::Utc::now().timestamp() - ATTACHMENT_GC_GRACE_SECS;
+    
+    let mut stmt = conn.prepare(&format!(
+        "SELECT {} FROM attachments WHERE ref_count <= 0 AND created_at < ?1",
+        ATTACHMENT_COLUMNS
+    )).map_err(|e| IoError::new(ErrorKind::Other, format!("准备查询语句失败: {}", e)))?;
+    
+    let unreferenced = stmt.query_map(params![threshold], attachment_from_row)
+        .map_err(|e| IoError::new(ErrorKind::Other, format!("执行查询失败: {}", e)))?
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| IoError::new(ErrorKind::Other, format!("处理查询结果失败: {}", e)))?;
+    
+    let mut removed = 0;
+    for attachment in unreferenced {
+        // 先删除记录再删除文件，删除文件失败时只会留下孤立文件，下次回收时清理
+        conn.execute(
+            "DELETE FROM attachments WHERE id = ?1 AND ref_count <= 0",
+            params![attachment.id],
+        ).map_err(|e| IoError::new(ErrorKind::Other, format!("删除附件记录失败: {}", e)))?;
+        attachments::remove_blob(&attachment.path()?)?;
+        removed += 1;
+    }
+    
+    // 清理没有数据库记录的孤立文件
+    let mut stmt = conn.prepare("SELECT hash FROM attachments")
+        .map_err(|e| IoError::new(ErrorKind::Other, format!("准备查询语句失败: {}", e)))?;
+    let known: std::collections::HashSet<String> = stmt.query_map([], |row| row.get(0))
+        .map_err(|e| IoError::new(ErrorKind::Other, format!("执行查询失败: {}", e)))?
+        .collect::<Result<_, _>>()
+        .map_err(|e| IoError::new(ErrorKind::Other, format!("处理查询结果失败: {}", e)))?;
+    
+    for path in attachments::list_blobs()? {
+        let hash = path.file_stem().map(|s| s.to_string_lossy().to_string()).unwrap_or_default();
+        let is_recent = std::fs::metadata(&path)
+            .and_then(|m| m.modified())
+            .map(|modified| chrono::DateTime::<chrono::Utc>::from(modified).timestamp() >= threshold)
+            .unwrap_or(true);
+        
+        if !known.contains(&hash) && !is_recent {
+            attachments::remove_blob(&path)?;
+            removed += 1;
+        }
+    }
+    
+    Ok(removed)
+}
+
+// ======= 标签操作 =======
+
+// 查找内容中的 #标签，返回标签名（不含#）的字节范围
+// # 必须位于开头或空白之后，标签名由字母、数字（含中文）、_ 和 - 组成
+fn hashtag_spans(content: &str) -> Vec<(usize, usize)> {
+    let mut spans = Vec::new();
+    let mut prev: Option<char> = None;
+    let mut chars = content.char_indices().peekable();
+    
+    while let Some((i, c)) = chars.next() {
+        if c == '#' && prev.map_or(true, char::is_whitespace) {
+            let start = i + c.len_utf8();
+            let mut end = start;
+            while let Some(&(j, next)) = chars.peek() {
+                if next.is_alphanumeric() || next == '_' || next == '-' {
+                    end = j + next.len_utf8();
+                    prev = Some(next);
+                    chars.next();
+                } else {
+                    break;
+                }
+            }
+            if end > start {
+                spans.push((start, end));
+                continue;
+            }
+        }
+        prev = Some(c);
+    }
+    
+    spans
+}
+
+// 解析内容中的 #标签，按首次出现顺序去重（不区分大小写）
+pub fn parse_hashtags(content: &str) -> Vec<String> {
+    let mut tags: Vec<String> = Vec::new();
+    for (start, end) in hashtag_spans(content) {
+        let name = &content[start..end];
+        if !tags.iter().any(|t| t.to_lowercase() == name.to_lowercase()) {
+            tags.push(name.to_string());
+        }
+    }
+    tags
+}
+
+// 将内容中的 #old 替换为 #new（不区分大小写）
+pub fn replace_hashtag(content: &str, old: &str, new: &str) -> String {
+    let mut result = String::with_capacity(content.len());
+    let mut last = 0;
+    for (start, end) in hashtag_spans(content) {
+        if content[start..end].to_lowercase() == old.to_lowercase() {
+            result.push_str(&content[last..start]);
+            result.push_str(new);
+            last = end;
+        }
+    }
+    result.push_str(&content[last..]);
+    result
+}
+
+// 获取标签ID，不存在时创建
+fn ensure_tag(conn: &Connection, name: &str) -> Result<i64, IoError> {
+    conn.execute(
+        "INSERT OR IGNORE INTO tags (name, created_at) VALUES (?1, ?2)",
+        params![name, chrono::Utc::now().timestamp()],
+    ).map_err(|e| IoError::new(ErrorKind::Other, format!("创建标签失败: {}", e)))?;
+    
+    conn.query_row("SELECT id FROM tags WHERE name = ?1", params![name], |row| row.get(0))
+        .map_err(|e| IoError::new(ErrorKind::Other, format!("查询标签失败: {}", e)))
+}
+
+// 根据内容中的 #标签 更新便签的内联标签，手动添加的标签出现在内容中时改为内联来源，
+// 其余手动添加的标签不受影响，需在调用方的事务中执行
+fn sync_inline_tags(conn: &Connection, note_id: i64, content: &str) -> Result<(), IoError> {
+    let mut tag_ids = Vec::new();
+    for name in parse_hashtags(content) {
+        let tag_id = ensure_tag(conn, &name)?;
+        conn.execute(
+            "INSERT INTO note_tags (note_id, tag_id, source) VALUES (?1, ?2, 'inline')
+             ON CONFLICT (note_id, tag_id) DO UPDATE SET source = 'inline'",
+            params![note_id, tag_id],
+        ).map_err(|e| IoError::new(ErrorKind::Other, format!("添加便签标签失败: {}", e)))?;
+        tag_ids.push(tag_id);
+    }
+    
+    // 移除内容中已不存在的内联标签
+    let mut stmt = conn.prepare("SELECT tag_id FROM note_tags WHERE note_id = ?1 AND source = 'inline'")
+        .map_err(|e| IoError::new(ErrorKind::Other, format!("准备查询语句失败: {}", e)))?;
+    let existing: Vec<i64> = stmt.query_map(params![note_id], |row| row.get(0))
+        .map_err(|e| IoError::new(ErrorKind::Other, format!("执行查询失败: {}", e)))?
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| IoError::new(ErrorKind::Other, format!("处理查询结果失败: {}", e)))?;
+    
+    for tag_id in existing.into_iter().filter(|id| !tag_ids.contains(id)) {
+        conn.execute(
+            "DELETE FROM note_tags WHERE note_id = ?1 AND tag_id = ?2",
+            params![note_id, tag_id],
+        ).map_err(|e| IoError::new(ErrorKind::Other, format!("移除便签标签失败: {}", e)))?;
+    }
+    
+    Ok(())
+}
+
+// 为便签手动添加标签，返回标签ID
+// 内容中已有该 #标签 时保持内联来源，重命名、合并标签时仍会改写内容
+pub fn add_tag_to_note(note_id: i64, name: &str) -> Result<i64, IoError> {
+    let name = name.trim().trim_start_matches('#');
+    if name.is_empty() {
+        return Err(IoError::new(ErrorKind::InvalidInput, "标签名不能为空"));
+    }
+    
+    let conn = get_db()?;
+    insert_manual_tag(&conn, note_id, name)
+}
+
+// 添加手动标签，已有该标签时保持原来的来源
+fn insert_manual_tag(conn: &Connection, note_id: i64, name: &str) -> Result<i64, IoError> {
+    let tag_id = ensure_tag(conn, name)?;
+    
+    match conn.execute(
+        "INSERT INTO note_tags (note_id, tag_id, source) VALUES (?1, ?2, 'manual')
+         ON CONFLICT (note_id, tag_id) DO NOTHING",
+        params![note_id, tag_id],
+    ) {
+        Ok(_) => Ok(tag_id),
+        Err(e) => Err(IoError::new(ErrorKind::Other, format!("添加便签标签失败: {}", e))),
+    }
+}
+
+// 移除便签上的标签
+// 内容中仍包含该 #标签 时，下次保存会重新添加
+pub fn remove_tag_from_note(note_id: i64, tag_id: i64) -> Result<(), IoError> {
+    let conn = get_db()?;
+    
+    match conn.execute(
+        "DELETE FROM note_tags WHERE note_id = ?1 AND tag_id = ?2",
+        params![note_id, tag_id],
+    ) {
+        Ok(_) => Ok(()),
+        Err(e) => Err(IoError::new(ErrorKind::Other, format!("移除便签标签失败: {}", e))),
+    }
+}
+
+// 获取所有标签及使用次数
+pub fn get_all_tags() -> Result<Vec<Tag>, IoError> {
+    query_tags(
+        "SELECT t.id, t.name, COUNT(n.id)
+         FROM tags t
+         LEFT JOIN note_tags nt ON nt.tag_id = t.id
+         LEFT JOIN notes n ON n.id = nt.note_id AND n.deleted_at IS NULL
+         GROUP BY t.id
+         ORDER BY t.name ASC",
+        None,
+    )
+}
+
+// 获取便签的所有标签
+pub fn get_note_tags(note_id: i64) -> Result<Vec<Tag>, IoError> {
+    query_tags(
+        "SELECT t.id, t.name,
+                (SELECT COUNT(*) FROM note_tags c JOIN notes n ON n.id = c.note_id
+                 WHERE c.tag_id = t.id AND n.deleted_at IS NULL)
+         FROM tags t
+         JOIN note_tags nt ON nt.tag_id = t.id
+         WHERE nt.note_id = ?1
+         ORDER BY t.name ASC",
+        Some(note_id),
+    )
+}
+
+fn query_tags(sql: &str, note_id: Option<i64>) -> Result<Vec<Tag>, IoError> {
+    let conn = get_db()?;
+    
+    let mut stmt = conn.prepare(sql)
+        .map_err(|e| IoError::new(ErrorKind::Other, format!("准备查询语句失败: {}", e)))?;
+    
+    let map_tag = |row: &Row| -> Result<Tag> {
+        Ok(Tag {
+            id: row.get(0)?,
+            name: row.get(1)?,
+            note_count: row.get(2)?,
+        })
+    };
+    let tag_iter = match note_id {
+        Some(id) => stmt.query_map(params![id], map_tag),
+        None => stmt.query_map([], map_tag),
+    }.map_err(|e| IoError::new(ErrorKind::Other, format!("执行查询失败: {}", e)))?;
+    
+    let mut tags = Vec::new();
+    for tag_result in tag_iter {
+        match tag_result {
+            Ok(tag) => tags.push(tag),
+            Err(e) => return Err(IoError::new(ErrorKind::Other, format!("处理查询结果失败: {}", e))),
+        }
+    }
+    
+    Ok(tags)
+}
+
+// 重命名标签，新名称已被其他标签使用时合并到该标签
+// 便签内容中的 #旧名称 会同步替换
+pub fn rename_tag(id: i64, new_name: &str) -> Result<i64, IoError> {
+    let new_name = new_name.trim().trim_start_matches('#');
+    if new_name.is_empty() {
+        return Err(IoError::new(ErrorKind::InvalidInput, "标签名不能为空"));
+    }
+    
+    let conn = get_db()?;
+    if !tag_exists(&conn, id)? {
+        return Err(IoError::new(ErrorKind::NotFound, "标签不存在"));
+    }
+    
+    let existing: Option<i64> = match conn.query_row(
+        "SELECT id FROM tags WHERE name = ?1 AND id != ?2",
+        params![new_name, id],
+        |row| row.get(0),
+    ) {
+        Ok(existing_id) => Some(existing_id),
+        Err(rusqlite::Error::QueryReturnedNoRows) => None,
+        Err(e) => return Err(IoError::new(ErrorKind::Other, format!("查询标签失败: {}", e))),
+    };
+    
+    if let Some(target_id) = existing {
+        merge_tags(&[id], target_id)?;
+        return Ok(target_id);
+    }
+    
+    // 开始事务
+    conn.execute("BEGIN IMMEDIATE TRANSACTION", [])
+        .map_err(|e| IoError::new(ErrorKind::Other, format!("开始事务失败: {}", e)))?;
+    
+    let result = rewrite_hashtags(&conn, id, new_name).and_then(|_| {
+        conn.execute("UPDATE tags SET name = ?1 WHERE id = ?2", params![new_name, id])
+            .map_err(|e| IoError::new(ErrorKind::Other, format!("重命名标签失败: {}", e)))
+    });
+    
+    match result {
+        Ok(_) => {
+            // 提交事务
+            conn.execute("COMMIT", [])
+                .map_err(|e| IoError::new(ErrorKind::Other, format!("提交事务失败: {}", e)))?;
+            Ok(id)
+        },
+        Err(e) => {
+            conn.execute("ROLLBACK", []).ok();
+            Err(e)
+        }
+    }
+}
+
+// 将多个标签合并到目标标签并删除原标签，任一标签不存在时不做任何修改
+pub fn merge_tags(source_ids: &[i64], target_id: i64) -> Result<(), IoError> {
+    let conn = get_db()?;
+    
+    let target_name: String = conn.query_row("SELECT name FROM tags WHERE id = ?1", params![target_id], |row| row.get(0))
+        .map_err(|e| match e {
+            rusqlite::Error::QueryReturnedNoRows => IoError::new(ErrorKind::NotFound, "目标标签不存在"),
+            e => IoError::new(ErrorKind::Other, format!("查询标签失败: {}", e)),
+        })?;
+    
+    // 开始事务
+    conn.execute("BEGIN IMMEDIATE TRANSACTION", [])
+        .map_err(|e| IoError::new(ErrorKind::Other, format!("开始事务失败: {}", e)))?;
+    
+    for &source_id in source_ids {
+        match tag_exists(&conn, source_id) {
+            Ok(true) => {},
+            Ok(false) => {
+                conn.execute("ROLLBACK", []).ok();
+                return Err(IoError::new(ErrorKind::NotFound, "标签不存在"));
+            },
+            Err(e) => {
+                conn.execute("ROLLBACK", []).ok();
+                return Err(e);
+            },
+        }
+    }
+    
+    for &source_id in source_ids.iter().filter(|&&id| id != target_id) {
+        let result = rewrite_hashtags(&conn, source_id, &target_name)
+            // 两个标签都在便签上时，任一为内联来源则合并后也是内联来源
+            .and_then(|_| conn.execute(
+                "INSERT INTO note_tags (note_id, tag_id, source)
+                 SELECT note_id, ?2, source FROM note_tags WHERE tag_id = ?1 AND true
+                 ON CONFLICT (note_id, tag_id) DO UPDATE SET
+                     source = CASE WHEN excluded.source = 'inline' THEN 'inline' ELSE note_tags.source END",
+                params![source_id, target_id],
+            ).map_err(|e| IoError::new(ErrorKind::Other, format!("合并标签失败: {}", e))))
+            .and_then(|_| conn.execute_batch(&format!(
+                "DELETE FROM note_tags WHERE tag_id = {id};
+                 DELETE FROM tags WHERE id = {id};",
+                id = source_id,
+            )).map_err(|e| IoError::new(ErrorKind::Other, format!("删除标签失败: {}", e))));
+        
+        if let Err(e) = result {
+            conn.execute("ROLLBACK", []).ok();
+            return Err(e);
+        }
+    }
+    
+    // 提交事务
+    conn.execute("COMMIT", [])
+        .map_err(|e| IoError::new(ErrorKind::Other, format!("提交事务失败: {}", e)))?;
+    
+    Ok(())
+}
+
+fn tag_exists(conn: &Connection, id: i64) -> Result<bool, IoError> {
+    conn.query_row("SELECT EXISTS(SELECT 1 FROM tags WHERE id = ?1)", params![id], |row| row.get(0))
+        .map_err(|e| IoError::new(ErrorKind::Other, format!("查询标签失败: {}", e)))
+}
+
+// 将所有内容中包含该 #标签 的便签改为新名称，不论便签上的标签来源，需在调用方的事务中执行
+fn rewrite_hashtags(conn: &Connection, tag_id: i64, new_name: &str) -> Result<(), IoError> {
+    let old_name: String = conn.query_row("SELECT name FROM tags WHERE id = ?1", params![tag_id], |row| row.get(0))
+        .map_err(|e| IoError::new(ErrorKind::Other, format!("查询标签失败: {}", e)))?;
+    
+    // 标签名比较不区分大小写，LIKE 只能忽略 ASCII 大小写，这里只按 # 粗筛
+    let mut stmt = conn.prepare("SELECT id, content FROM notes WHERE instr(content, '#') > 0")
+        .map_err(|e| IoError::new(ErrorKind::Other, format!("准备查询语句失败: {}", e)))?;
+    
+    let notes: Vec<(i64, String)> = stmt.query_map([], |row| Ok((row.get(0)?, row.get(1)?)))
+        .map_err(|e| IoError::new(ErrorKind::Other, format!("执行查询失败: {}", e)))?
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| IoError::new(ErrorKind::Other, format!("处理查询结果失败: {}", e)))?;
+    
+    for (note_id, content) in notes {
+        let rewritten = replace_hashtag(&content, &old_name, new_name);
+        if rewritten != content {
+            rewrite_note_content(conn, note_id, &rewritten)?;
+        }
+    }
+    
+    Ok(())
+}
+
+// ======= 链接操作 =======
+
+// 便签标题（第一行去掉 Markdown 标题符号）的 SQL 表达式，需与 note_title 保持一致
+const NOTE_TITLE_SQL: &str = "trim(ltrim(substr(content, 1, CASE WHEN instr(content, char(10)) > 0 THEN instr(content, char(10)) - 1 ELSE length(content) END), '#'), ' ' || char(9) || char(13))";
+
+// 便签标题：内容的第一行，去掉开头的 # 和首尾空白
+pub fn note_title(content: &str) -> &str {
+    content.split('\n').next().unwrap_or("")
+        .trim_start_matches('#')
+        .trim_matches(|c| c == ' ' || c == '\t' || c == '\r')
+}
+
+// 查找内容中所有 [[...]] 的内部文本位置（字节偏移），链接不能跨行
+fn wiki_link_spans(content: &str) -> Vec<(usize, usize)> {
+    let mut spans = Vec::new();
+    let mut rest = 0;
+    
+    while let Some(open) = content[rest..].find("[[") {
+        let start = rest + open + 2;
+        let inner_len = content[start..].find(|c| c == ']' || c == '[' || c == '\n');
+        match inner_len {
+            Some(len) if content[start + len..].starts_with("]]") => {
+                if !content[start..start + len].trim().is_empty() {
+                    spans.push((start, start + len));
+                }
+                rest = start + len + 2;
+            }
+            Some(len) => rest = start + len,
+            None => break,
+        }
+    }
+    
+    spans
+}
+
+// 解析内容中的链接，按出现顺序去重
+pub fn parse_wiki_links(content: &str) -> Vec<WikiLink> {
+    let mut links: Vec<WikiLink> = Vec::new();
+    for (start, end) in wiki_link_spans(content) {
+        let text = content[start..end].trim();
+        let link = match text.strip_prefix('#').and_then(|id| id.parse::<i64>().ok()) {
+            Some(id) => WikiLink::Id(id),
+            None => WikiLink::Title(text.to_string()),
+        };
+        let duplicate = links.iter().any(|l| match (l, &link) {
+            (WikiLink::Title(a), WikiLink::Title(b)) => a.to_lowercase() == b.to_lowercase(),
+            (a, b) => a == b,
+        });
+        if !duplicate {
+            links.push(link);
+        }
+    }
+    links
+}
+
+// 将内容中的 [[old]] 替换为 [[new]]（不区分大小写）
+pub fn replace_wiki_link(content: &str, old: &str, new: &str) -> String {
+    let mut result = String::with_capacity(content.len());
+    let mut last = 0;
+    for (start, end) in wiki_link_spans(content) {
+        if content[start..end].trim().to_lowercase() == old.to_lowercase() {
+            result.push_str(&content[last..start]);
+            result.push_str(new);
+            last = end;
+        }
+    }
+    result.push_str(&content[last..]);
+    result
+}
+
+// 重新写入便签的链接，并解析每个链接的目标便签，需在调用方的事务中执行
+pub fn write_note_links(conn: &Connection, note_id: i64, content: &str) -> Result<()> {
+    conn.execute("DELETE FROM note_links WHERE source_id = ?1", params![note_id])?;
+    
+    for (position, link) in parse_wiki_links(content).into_iter().enumerate() {
+        let (target_id, target_title): (Option<i64>, String) = match link {
+            WikiLink::Id(id) => {
+                let exists: bool = conn.query_row(
+                    "SELECT EXISTS(SELECT 1 FROM notes WHERE id = ?1)", params![id], |row| row.get(0))?;
+                (if exists { Some(id) } else { None }, format!("#{}", id))
+            }
+            WikiLink::Title(title) => {
+                let target_id = conn.query_row(
+                    &format!(
+                        "SELECT id FROM notes WHERE deleted_at IS NULL AND {} = ?1 COLLATE NOCASE
+                         ORDER BY created_at ASC LIMIT 1",
+                        NOTE_TITLE_SQL
+                    ),
+                    params![title],
+                    |row| row.get(0),
+                ).optional()?;
+                (target_id, title)
+            }
+        };
+        
+        conn.execute(
+            "INSERT INTO note_links (source_id, target_id, target_title, position) VALUES (?1, ?2, ?3, ?4)",
+            params![note_id, target_id, target_title, position as i64],
+        )?;
+    }
+    
+    Ok(())
+}
+
+// 同步便签的链接：重写自身的链接，标题变化时更新其他便签中指向它的链接，
+// 并让之前找不到目标、标题与之相同的链接指向它
+fn sync_note_links(conn: &Connection, note_id: i64, content: &str, old_title: Option<&str>) -> Result<(), IoError> {
+    write_note_links(conn, note_id, content)
+        .map_err(|e| IoError::new(ErrorKind::Other, format!("更新便签链接失败: {}", e)))?;
+    
+    let new_title = note_title(content);
+    
+    if let Some(old_title) = old_title.filter(|t| !t.is_empty() && *t != new_title) {
+        let mut stmt = conn.prepare(
+            "SELECT DISTINCT n.id, n.content FROM notes n
+             JOIN note_links l ON l.source_id = n.id
+             WHERE l.target_id = ?1 AND l.target_title = ?2 COLLATE NOCASE AND n.id != ?1"
+        ).map_err(|e| IoError::new(ErrorKind::Other, format!("准备查询语句失败: {}", e)))?;
+        
+        let sources: Vec<(i64, String)> = stmt.query_map(params![note_id, old_title], |row| Ok((row.get(0)?, row.get(1)?)))
+            .map_err(|e| IoError::new(ErrorKind::Other, format!("执行查询失败: {}", e)))?
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|e| IoError::new(ErrorKind::Other, format!("处理查询结果失败: {}", e)))?;
+        
+        if !new_title.is_empty() {
+            for (source_id, source_content) in sources {
+                rewrite_note_content(conn, source_id, &replace_wiki_link(&source_content, old_title, new_title))?;
+            }
+            
+            conn.execute(
+                "UPDATE note_links SET target_title = ?3
+                 WHERE target_id = ?1 AND target_title = ?2 COLLATE NOCASE",
+                params![note_id, old_title, new_title],
+            ).map_err(|e| IoError::new(ErrorKind::Other, format!("更新便签链接失败: {}", e)))?;
+        }
+    }
+    
+    if !new_title.is_empty() {
+        conn.execute(
+            "UPDATE note_links SET target_id = ?1
+             WHERE target_id IS NULL AND target_title = ?2 COLLATE NOCASE",
+            params![note_id, new_title],
+        ).map_err(|e| IoError::new(ErrorKind::Other, format!("更新便签链接失败: {}", e)))?;
+    }
+    
+    Ok(())
+}
+
+const NOTE_LINK_SELECT: &str =
+    "SELECT l.source_id, s.content, l.target_id, l.target_title, t.id IS NULL OR t.deleted_at IS NOT NULL
+     FROM note_links l
+     JOIN notes s ON s.id = l.source_id
+     LEFT JOIN notes t ON t.id = l.target_id";
+
+fn query_note_links(sql: &str, note_id: i64) -> Result<Vec<NoteLink>, IoError> {
+    let conn = get_db()?;
+    
+    let mut stmt = conn.prepare(sql)
+        .map_err(|e| IoError::new(ErrorKind::Other, format!("准备查询语句失败: {}", e)))?;
+    
+    let links = stmt.query_map(params![note_id], |row| {
+        let source_content: String = row.get(1)?;
+        Ok(NoteLink {
+            source_id: row.get(0)?,
+            source_title: note_title(&source_content).to_string(),
+            target_id: row.get(2)?,
+            target_title: row.get(3)?,
+            dangling: row.get(4)?,
+        })
+    }).map_err(|e| IoError::new(ErrorKind::Other, format!("执行查询失败: {}", e)))?;
+    
+    links.collect::<Result<Vec<_>, _>>()
+        .map_err(|e| IoError::new(ErrorKind::Other, format!("处理查询结果失败: {}", e)))
+}
+
+// 获取便签中的链接，按在内容中出现的顺序
+pub fn get_outgoing_links(note_id: i64) -> Result<Vec<NoteLink>, IoError> {
+    query_note_links(&format!("{} WHERE l.source_id = ?1 ORDER BY l.position", NOTE_LINK_SELECT), note_id)
+}
+
+// 获取链接到该便签的未删除便签，每个来源便签只返回一次
+pub fn get_backlinks(note_id: i64) -> Result<Vec<NoteLink>, IoError> {
+    query_note_links(
+        &format!(
+            "{} WHERE l.target_id = ?1 AND s.deleted_at IS NULL
+             GROUP BY l.source_id
+             ORDER BY COALESCE(s.updated_at, s.created_at) DESC",
+            NOTE_LINK_SELECT
+        ),
+        note_id,
+    )
+}
+
 // ======= 分类操作 =======
 
-// 保存分类
+// 子孙分类查询（包含自身），使用 UNION 去重以防数据中存在环
+const CATEGORY_SUBTREE_SQL: &str =
+    "WITH RECURSIVE subtree(id) AS (
+         SELECT ?1
+         UNION
+         SELECT c.id FROM categories c JOIN subtree s ON c.parent_id = s.id
+     )";
+
+// 保存分类，父分类的检查与写入在同一事务中执行
 pub fn save_category(category: &Category) -> Result<i64, IoError> {
     let conn = get_db()?;
     
-    if category.id.is_none() {
-        // 插入新分类
-        match conn.execute(
-            "INSERT INTO categories (name, color)
-             VALUES (?1, ?2)",
-            params![
-                category.name,
-                category.color
-            ],
-        ) {
-            Ok(_) => {
-                let id = conn.last_insert_rowid();
-                Ok(id)
+    // 开始事务
+    conn.execute("BEGIN IMMEDIATE TRANSACTION", [])
+        .map_err(|e| IoError::new(ErrorKind::Other, format!("开始事务失败: {}", e)))?;
+    
+    let result = (|| -> Result<i64, IoError> {
+        match category.id {
+            None => {
+                check_category_parent(&conn, None, category.parent_id)?;
+                
+                // 插入新分类，排在同级分类的最后
+                conn.execute(
+                    "INSERT INTO categories (name, color, parent_id, position)
+                     VALUES (?1, ?2, ?3,
+                             (SELECT COALESCE(MAX(position), -1) + 1 FROM categories WHERE parent_id IS ?3))",
+                    params![
+                        category.name,
+                        category.color,
+                        category.parent_id
+                    ],
+                ).map_err(|e| IoError::new(ErrorKind::Other, format!("创建分类失败: {}", e)))?;
+                
+                Ok(conn.last_insert_rowid())
+            },
+            Some(id) => {
+                // 更新现有分类
+                check_category_parent(&conn, Some(id), category.parent_id)?;
+                
+                match conn.execute(
+                    "UPDATE categories SET 
+                        name = ?1, 
+                        color = ?2,
+                        parent_id = ?3
+                     WHERE id = ?4",
+                    params![
+                        category.name,
+                        category.color,
+                        category.parent_id,
+                        id
+                    ],
+                ) {
+                    Ok(0) => Err(IoError::new(ErrorKind::NotFound, "分类不存在")),
+                    Ok(_) => Ok(id),
+                    Err(e) => Err(IoError::new(ErrorKind::Other, format!("更新分类失败: {}", e))),
+                }
             },
-            Err(e) => Err(IoError::new(ErrorKind::Other, format!("创建分类失败: {}", e))),
         }
-    } else {
-        // 更新现有分类
-        let id = category.id.unwrap();
-        match conn.execute(
-            "UPDATE categories SET 
-                name = ?1, 
-                color = ?2
-             WHERE id = ?3",
-            params![
-                category.name,
-                category.color,
-                id
-            ],
-        ) {
-            Ok(_) => Ok(id),
-            Err(e) => Err(IoError::new(ErrorKind::Other, format!("更新分类失败: {}", e))),
+    })();
+    
+    match result {
+        Ok(id) => {
+            // 提交事务
+            conn.execute("COMMIT", [])
+                .map_err(|e| IoError::new(ErrorKind::Other, format!("提交事务失败: {}", e)))?;
+            Ok(id)
+        },
+        Err(e) => {
+            conn.execute("ROLLBACK", []).ok();
+            Err(e)
         }
     }
 }
@@ -715,9 +2853,9 @@ pub fn get_all_categories() -> Result<Vec<Category>, IoError> {
     let conn = get_db()?;
     
     let mut stmt = conn.prepare(
-        "SELECT id, name, color
+        "SELECT id, name, color, parent_id, position
          FROM categories
-         ORDER BY name ASC"
+         ORDER BY position ASC, name ASC"
     ).map_err(|e| IoError::new(ErrorKind::Other, format!("准备查询语句失败: {}", e)))?;
     
     let category_iter = stmt.query_map([], |row| {
@@ -725,6 +2863,8 @@ pub fn get_all_categories() -> Result<Vec<Category>, IoError> {
             id: Some(row.get(0)?),
             name: row.get(1)?,
             color: row.get(2)?,
+            parent_id: row.get(3)?,
+            position: row.get(4)?,
         })
     }).map_err(|e| IoError::new(ErrorKind::Other, format!("执行查询失败: {}", e)))?;
     
@@ -739,31 +2879,251 @@ pub fn get_all_categories() -> Result<Vec<Category>, IoError> {
     Ok(categories)
 }
 
-// 删除分类
-pub fn delete_category(id: i64) -> Result<(), IoError> {
+// 获取完整的分类树，同级分类保持 get_all_categories 的顺序
+pub fn get_category_tree() -> Result<Vec<CategoryNode>, IoError> {
+    Ok(build_category_tree(get_all_categories()?))
+}
+
+// 将按顺序排列的分类列表组装成树，父分类不存在的分类作为顶级分类返回
+pub fn build_category_tree(categories: Vec<Category>) -> Vec<CategoryNode> {
+    let ids: Vec<i64> = categories.iter().filter_map(|c| c.id).collect();
+    
+    let mut children_of: std::collections::HashMap<Option<i64>, Vec<Category>> = std::collections::HashMap::new();
+    for category in categories {
+        let parent = category.parent_id.filter(|p| ids.contains(p) && Some(*p) != category.id);
+        children_of.entry(parent).or_default().push(category);
+    }
+    
+    fn build(parent: Option<i64>, children_of: &mut std::collections::HashMap<Option<i64>, Vec<Category>>) -> Vec<CategoryNode> {
+        let categories = children_of.remove(&parent).unwrap_or_default();
+        categories.into_iter().map(|category| {
+            let children = build(category.id, children_of);
+            CategoryNode { category, children }
+        }).collect()
+    }
+    
+    build(None, &mut children_of)
+}
+
+// 获取分类及其所有子孙分类的ID
+fn category_subtree_ids(conn: &Connection, id: i64) -> Result<Vec<i64>, IoError> {
+    let mut stmt = conn.prepare(&format!("{} SELECT id FROM subtree", CATEGORY_SUBTREE_SQL))
+        .map_err(|e| IoError::new(ErrorKind::Other, format!("准备查询语句失败: {}", e)))?;
+    
+    let ids = stmt.query_map(params![id], |row| row.get(0))
+        .map_err(|e| IoError::new(ErrorKind::Other, format!("执行查询失败: {}", e)))?
+        .collect::<Result<Vec<i64>, _>>()
+        .map_err(|e| IoError::new(ErrorKind::Other, format!("处理查询结果失败: {}", e)))?;
+    
+    Ok(ids)
+}
+
+// 检查新的父分类是否合法：父分类必须存在，且不能是自身或自身的子孙分类
+// id 为 None 表示新建分类，需在调用方的事务中执行
+fn check_category_parent(conn: &Connection, id: Option<i64>, parent_id: Option<i64>) -> Result<(), IoError> {
+    let parent_id = match parent_id {
+        Some(parent_id) => parent_id,
+        None => return Ok(()),
+    };
+    
+    let exists: bool = conn.query_row(
+        "SELECT EXISTS(SELECT 1 FROM categories WHERE id = ?1)",
+        params![parent_id],
+        |row| row.get(0),
+    ).map_err(|e| IoError::new(ErrorKind::Other, format!("查询分类失败: {}", e)))?;
+    if !exists {
+        return Err(IoError::new(ErrorKind::NotFound, "父分类不存在"));
+    }
+    
+    if let Some(id) = id {
+        if category_subtree_ids(conn, id)?.contains(&parent_id) {
+            return Err(IoError::new(ErrorKind::InvalidInput, "不能将分类移动到自身或其子分类下"));
+        }
+    }
+    Ok(())
+}
+
+// 移动分类（连同其子树）到新的父分类下，parent_id 为 None 时移动到顶级
+// 检查与更新在同一事务中执行，避免并发移动形成环
+pub fn move_category(id: i64, parent_id: Option<i64>) -> Result<(), IoError> {
     let conn = get_db()?;
     
     // 开始事务
-    conn.execute("BEGIN TRANSACTION", [])
+    conn.execute("BEGIN IMMEDIATE TRANSACTION", [])
         .map_err(|e| IoError::new(ErrorKind::Other, format!("开始事务失败: {}", e)))?;
     
-    // 先将该分类下的便签的分类ID设为NULL
-    match conn.execute(
-        "UPDATE notes SET category_id = NULL WHERE category_id = ?1",
-        params![id],
-    ) {
-        Ok(_) => {},
+    let result = (|| -> Result<(), IoError> {
+        check_category_parent(&conn, Some(id), parent_id)?;
+        
+        match conn.execute(
+            "UPDATE categories SET parent_id = ?1 WHERE id = ?2",
+            params![parent_id, id],
+        ) {
+            Ok(0) => Err(IoError::new(ErrorKind::NotFound, "分类不存在")),
+            Ok(_) => Ok(()),
+            Err(e) => Err(IoError::new(ErrorKind::Other, format!("移动分类失败: {}", e))),
+        }
+    })();
+    
+    match result {
+        Ok(_) => {
+            // 提交事务
+            conn.execute("COMMIT", [])
+                .map_err(|e| IoError::new(ErrorKind::Other, format!("提交事务失败: {}", e)))?;
+            Ok(())
+        },
         Err(e) => {
             conn.execute("ROLLBACK", []).ok();
-            return Err(IoError::new(ErrorKind::Other, format!("更新便签分类失败: {}", e)));
+            Err(e)
         }
     }
+}
+
+// 获取分类下的便签，include_descendants 为 true 时包含所有子孙分类中的便签
+// 置顶便签在前，其余按 sort 排序
+pub fn get_notes_in_category(category_id: i64, include_descendants: bool, sort: &NoteSortOption) -> Result<Vec<Note>, IoError> {
+    let conn = get_db()?;
     
-    // 再删除分类
-    match conn.execute(
-        "DELETE FROM categories WHERE id = ?1",
-        params![id],
-    ) {
+    let query = if include_descendants {
+        format!(
+            "{} SELECT id, content, screenshot_path, created_at, updated_at, is_pinned, color, category_id, deleted_at, position, note_type, content_format, archived_at
+             FROM notes
+             WHERE deleted_at IS NULL AND archived_at IS NULL AND category_id IN (SELECT id FROM subtree)
+             ORDER BY {}",
+            CATEGORY_SUBTREE_SQL,
+            sort.order_by()
+        )
+    } else {
+        format!(
+            "SELECT id, content, screenshot_path, created_at, updated_at, is_pinned, color, category_id, deleted_at, position, note_type, content_format, archived_at
+             FROM notes
+             WHERE deleted_at IS NULL AND archived_at IS NULL AND category_id = ?1
+             ORDER BY {}",
+            sort.order_by()
+        )
+    };
+    
+    let mut stmt = conn.prepare(&query)
+        .map_err(|e| IoError::new(ErrorKind::Other, format!("准备查询语句失败: {}", e)))?;
+    
+    let note_iter = stmt.query_map(params![category_id], |row| note_from_row(row, 0))
+        .map_err(|e| IoError::new(ErrorKind::Other, format!("执行查询失败: {}", e)))?;
+    
+    let mut notes = Vec::new();
+    for note_result in note_iter {
+        match note_result {
+            Ok(note) => notes.push(note),
+            Err(e) => return Err(IoError::new(ErrorKind::Other, format!("处理查询结果失败: {}", e))),
+        }
+    }
+    
+    Ok(notes)
+}
+
+// 将分类移动到同级分类中的指定位置，其他同级分类依次顺延
+pub fn update_category_order(id: i64, new_order: i32) -> Result<(), IoError> {
+    let conn = get_db()?;
+    
+    let mut stmt = conn.prepare(
+        "SELECT id FROM categories
+         WHERE parent_id IS (SELECT parent_id FROM categories WHERE id = ?1)
+         ORDER BY position ASC, name ASC"
+    ).map_err(|e| IoError::new(ErrorKind::Other, format!("准备查询语句失败: {}", e)))?;
+    
+    let mut sibling_ids = stmt.query_map(params![id], |row| row.get::<_, i64>(0))
+        .map_err(|e| IoError::new(ErrorKind::Other, format!("执行查询失败: {}", e)))?
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| IoError::new(ErrorKind::Other, format!("处理查询结果失败: {}", e)))?;
+    
+    if !sibling_ids.contains(&id) {
+        return Err(IoError::new(ErrorKind::NotFound, "分类不存在"));
+    }
+    
+    sibling_ids.retain(|&sibling_id| sibling_id != id);
+    let index = (new_order.max(0) as usize).min(sibling_ids.len());
+    sibling_ids.insert(index, id);
+    
+    save_manual_order(&sibling_ids, &[])
+}
+
+// 批量保存拖拽排序结果，两个列表分别为按新顺序排列的分类ID和便签ID
+// 位置按列表下标写入，只需传入发生变化的同级分类或同一分类内的便签
+pub fn save_manual_order(category_ids: &[i64], note_ids: &[i64]) -> Result<(), IoError> {
+    let conn = get_db()?;
+    
+    // 开始事务
+    conn.execute("BEGIN IMMEDIATE TRANSACTION", [])
+        .map_err(|e| IoError::new(ErrorKind::Other, format!("开始事务失败: {}", e)))?;
+    
+    let updates = category_ids.iter()
+        .enumerate()
+        .map(|(position, id)| ("UPDATE categories SET position = ?1 WHERE id = ?2", position, id, "更新分类排序失败"))
+        .chain(note_ids.iter()
+            .enumerate()
+            .map(|(position, id)| ("UPDATE notes SET position = ?1 WHERE id = ?2", position, id, "更新便签排序失败")));
+    
+    for (sql, position, id, message) in updates {
+        if let Err(e) = conn.execute(sql, params![position as i64, id]) {
+            conn.execute("ROLLBACK", []).ok();
+            return Err(IoError::new(ErrorKind::Other, format!("{}: {}", message, e)));
+        }
+    }
+    
+    // 提交事务
+    conn.execute("COMMIT", [])
+        .map_err(|e| IoError::new(ErrorKind::Other, format!("提交事务失败: {}", e)))?;
+    
+    Ok(())
+}
+
+// 删除分类，子分类按 children 指定的方式处理
+// 被删除分类下的便签都变为未分类，子树的查询与删除在同一事务中执行
+pub fn delete_category(id: i64, children: ChildCategoryAction) -> Result<(), IoError> {
+    let conn = get_db()?;
+    
+    // 开始事务
+    conn.execute("BEGIN IMMEDIATE TRANSACTION", [])
+        .map_err(|e| IoError::new(ErrorKind::Other, format!("开始事务失败: {}", e)))?;
+    
+    let result = (|| -> Result<(), IoError> {
+        // 确定要删除的分类，子孙分类排在父分类之前，避免违反外键约束
+        let removed_ids = match children {
+            ChildCategoryAction::Reparent => vec![id],
+            ChildCategoryAction::Delete => {
+                let mut ids = category_subtree_ids(&conn, id)?;
+                ids.reverse();
+                ids
+            },
+        };
+        
+        // 子分类移到被删除分类的父分类下
+        if let ChildCategoryAction::Reparent = children {
+            conn.execute(
+                "UPDATE categories
+                 SET parent_id = (SELECT parent_id FROM categories WHERE id = ?1)
+                 WHERE parent_id = ?1",
+                params![id],
+            ).map_err(|e| IoError::new(ErrorKind::Other, format!("移动子分类失败: {}", e)))?;
+        }
+        
+        for removed_id in &removed_ids {
+            // 先将该分类下的便签的分类ID设为NULL
+            conn.execute(
+                "UPDATE notes SET category_id = NULL WHERE category_id = ?1",
+                params![removed_id],
+            ).map_err(|e| IoError::new(ErrorKind::Other, format!("更新便签分类失败: {}", e)))?;
+            
+            // 再删除分类
+            conn.execute(
+                "DELETE FROM categories WHERE id = ?1",
+                params![removed_id],
+            ).map_err(|e| IoError::new(ErrorKind::Other, format!("删除分类失败: {}", e)))?;
+        }
+        
+        Ok(())
+    })();
+    
+    match result {
         Ok(_) => {
             // 提交事务
             conn.execute("COMMIT", [])
@@ -772,7 +3132,7 @@ pub fn delete_category(id: i64) -> Result<(), IoError> {
         },
         Err(e) => {
             conn.execute("ROLLBACK", []).ok();
-            Err(IoError::new(ErrorKind::Other, format!("删除分类失败: {}", e)))
+            Err(e)
         }
     }
 }
@@ -784,5 +3144,526 @@ pub fn init_database() -> Result<(), IoError> {
 
 // 获取所有便签的包装函数
 pub fn get_notes() -> Result<Vec<Note>, IoError> {
-    get_notes_sorted(None, None, None)
-} 
\ No newline at end of file
+    get_notes_sorted(&NoteSortOption::CreatedTimeDesc, None, None, None, &[])
+} 
+// 测试使用临时数据目录中的数据库，连接池是全局的，同一时间只允许一个测试打开数据库
+#[cfg(test)]
+pub mod test_support {
+    use super::*;
+    
+    static TEST_DB_LOCK: Lazy<Mutex<()>> = Lazy::new(|| Mutex::new(()));
+    
+    pub struct TestDb {
+        pub dir: tempfile::TempDir,
+        _guard: MutexGuard<'static, ()>,
+    }
+    
+    impl Drop for TestDb {
+        fn drop(&mut self) {
+            close_db().ok();
+        }
+    }
+    
+    // 在新的临时数据目录中创建数据库，返回值离开作用域时关闭数据库
+    pub fn open_test_db() -> TestDb {
+        let guard = TEST_DB_LOCK.lock().unwrap_or_else(PoisonError::into_inner);
+        let dir = tempfile::tempdir().unwrap();
+        // dirs::data_dir 在 Linux 上使用 XDG_DATA_HOME
+        std::env::set_var("XDG_DATA_HOME", dir.path());
+        open_db("test-key".to_string()).unwrap();
+        TestDb { dir, _guard: guard }
+    }
+    
+    pub fn text_note(content: &str) -> Note {
+        Note {
+            id: None,
+            content: content.to_string(),
+            screenshot_path: None,
+            created_at: 1_700_000_000,
+            updated_at: None,
+            is_pinned: false,
+            color: None,
+            category_id: None,
+            deleted_at: None,
+            position: 0,
+            note_type: NoteType::Text,
+            content_format: ContentFormat::Plain,
+            archived_at: None,
+        }
+    }
+    
+    // 让所有附件超过回收宽限期
+    pub fn expire_attachment_grace_period() {
+        get_db().unwrap().execute("UPDATE attachments SET created_at = 0", []).unwrap();
+    }
+    
+    // 直接执行 SQL，例如用 TEMP 触发器模拟写入失败；单线程测试中连接池总是借出同一个连接
+    pub fn execute_sql(sql: &str) {
+        get_db().unwrap().execute_batch(sql).unwrap();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::test_support::*;
+    
+    #[test]
+    fn screenshot_note_survives_attachment_gc() {
+        let _db = open_test_db();
+        let screenshot = add_attachment(b"screenshot", "image/png", None).unwrap();
+        let unused = add_attachment(b"unused", "image/png", None).unwrap();
+        
+        let mut note = text_note("截图");
+        note.screenshot_path = Some(screenshot.path().unwrap().to_string_lossy().to_string());
+        let note_id = save_note(&note).unwrap();
+        
+        expire_attachment_grace_period();
+        collect_attachment_garbage().unwrap();
+        
+        assert!(get_attachment(screenshot.id).unwrap().is_some());
+        assert!(screenshot.path().unwrap().exists());
+        assert_eq!(get_note_attachments(note_id).unwrap().len(), 1);
+        
+        // 没有便签引用的附件仍会被回收
+        assert!(get_attachment(unused.id).unwrap().is_none());
+        assert!(!unused.path().unwrap().exists());
+    }
+    
+    #[test]
+    fn convert_to_checklist_moves_task_lines_into_items() {
+        let _db = open_test_db();
+        let note_id = save_note(&text_note("购物\n- [ ] 牛奶\n- [x] 面包\n备注")).unwrap();
+        
+        convert_to_checklist(note_id).unwrap();
+        
+        let note = get_note(note_id).unwrap().unwrap();
+        assert!(note.note_type == NoteType::Checklist);
+        assert_eq!(note.content, "购物\n备注");
+        let items: Vec<(String, bool)> = get_checklist_items(note_id).unwrap()
+            .into_iter()
+            .map(|item| (item.content, item.done))
+            .collect();
+        assert_eq!(items, vec![("牛奶".to_string(), false), ("面包".to_string(), true)]);
+    }
+    
+    #[test]
+    fn failed_checklist_conversion_leaves_note_unchanged() {
+        let _db = open_test_db();
+        let note_id = save_note(&text_note("购物\n- [ ] 牛奶")).unwrap();
+        get_db().unwrap().execute_batch(
+            "CREATE TEMP TRIGGER fail_checklist BEFORE INSERT ON checklist_items
+             BEGIN SELECT RAISE(ABORT, 'fail'); END;"
+        ).unwrap();
+        
+        assert!(convert_to_checklist(note_id).is_err());
+        
+        let note = get_note(note_id).unwrap().unwrap();
+        assert!(note.note_type == NoteType::Text);
+        assert_eq!(note.content, "购物\n- [ ] 牛奶");
+    }
+    
+    #[test]
+    fn archiving_several_notes_is_all_or_nothing() {
+        let _db = open_test_db();
+        let first = save_note(&text_note("第一条")).unwrap();
+        let second = save_note(&text_note("第二条")).unwrap();
+        get_db().unwrap().execute_batch(&format!(
+            "CREATE TEMP TRIGGER fail_archive BEFORE UPDATE OF archived_at ON notes WHEN NEW.id = {}
+             BEGIN SELECT RAISE(ABORT, 'fail'); END;",
+            second
+        )).unwrap();
+        
+        assert!(archive_notes(&[first, second], 1_700_000_000).is_err());
+        assert!(get_archived_notes().unwrap().is_empty());
+        
+        get_db().unwrap().execute_batch("DROP TRIGGER fail_archive").unwrap();
+        archive_notes(&[first, second], 1_700_000_000).unwrap();
+        assert_eq!(get_archived_notes().unwrap().len(), 2);
+        unarchive_notes(&[first, second]).unwrap();
+        assert!(get_archived_notes().unwrap().is_empty());
+    }
+    
+    #[test]
+    fn reopening_after_lock_does_not_run_migrations() {
+        let _db = open_test_db();
+        let note_id = save_note(&text_note("锁定前")).unwrap();
+        close_db().unwrap();
+        assert!(matches!(get_note(note_id), Err(e) if e.kind() == ErrorKind::PermissionDenied));
+        
+        // 迁移会拒绝版本更高的数据库，重新打开时不应执行迁移
+        get_db_path().and_then(|path| open_connection(&path, "test-key")).unwrap()
+            .execute("INSERT INTO schema_version (version, applied_at) VALUES (9999, 0)", [])
+            .unwrap();
+        reopen_db("test-key".to_string()).unwrap();
+        
+        assert_eq!(get_note(note_id).unwrap().unwrap().content, "锁定前");
+    }
+    
+    #[test]
+    fn database_backups_are_rekeyed_after_rotation() {
+        let _db = open_test_db();
+        let note_id = save_note(&text_note("备份中的便签")).unwrap();
+        get_db().unwrap().query_row("PRAGMA wal_checkpoint(TRUNCATE)", [], |_| Ok(())).unwrap();
+        let db_path = get_db_path().unwrap();
+        let backup_path = db_path.with_file_name(format!("{}.pre-restore.20240101_000000.bak", DB_FILENAME));
+        std::fs::copy(&db_path, &backup_path).unwrap();
+        
+        rekey_database_backups(&db_path, "test-key", "new-key");
+        assert!(verify_key(&backup_path, "test-key").is_err());
+        let conn = open_connection(&backup_path, "new-key").unwrap();
+        let content: String = conn.query_row("SELECT content FROM notes WHERE id = ?1", [note_id], |row| row.get(0)).unwrap();
+        assert_eq!(content, "备份中的便签");
+        drop(conn);
+        
+        // 已经使用新密钥的文件保持不变
+        rekey_database_file(&backup_path, "test-key", "new-key").unwrap();
+        assert!(verify_key(&backup_path, "new-key").is_ok());
+    }
+    
+    #[test]
+    fn failed_database_replacement_reopens_original() {
+        let db = open_test_db();
+        let note_id = save_note(&text_note("原数据库")).unwrap();
+        let plain_path = db.dir.path().join("plain.db");
+        export_plaintext(&plain_path).unwrap();
+        
+        // 备份文件所在的目录不存在，替换前的备份一定失败
+        let backup_path = db.dir.path().join("missing").join("pre-restore.bak");
+        let err = replace_database_keeping(&plain_path, &backup_path).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::NotFound);
+        assert!(err.to_string().contains("备份当前数据库失败"));
+        assert!(!get_db_path().unwrap().with_extension("db.restoring").exists());
+        assert_eq!(get_note(note_id).unwrap().unwrap().content, "原数据库");
+    }
+    
+    #[test]
+    fn unfinished_transaction_is_rolled_back_before_reuse() {
+        let _db = open_test_db();
+        {
+            let conn = get_db().unwrap();
+            conn.execute("BEGIN IMMEDIATE TRANSACTION", []).unwrap();
+            conn.execute("INSERT INTO categories (name) VALUES ('未提交')", []).unwrap();
+            // 模拟出错后没有提交或回滚就返回
+        }
+        
+        let conn = get_db().unwrap();
+        assert!(conn.is_autocommit());
+        let count: i64 = conn.query_row("SELECT COUNT(*) FROM categories WHERE name = '未提交'", [], |row| row.get(0)).unwrap();
+        assert_eq!(count, 0);
+    }
+    
+    #[test]
+    fn screenshot_outside_attachment_store_is_not_linked() {
+        let db = open_test_db();
+        let mut note = text_note("旧截图");
+        note.screenshot_path = Some(db.dir.path().join("legacy.png").to_string_lossy().to_string());
+        let note_id = save_note(&note).unwrap();
+        
+        assert!(get_note_attachments(note_id).unwrap().is_empty());
+    }
+    
+    fn search_ids(search: &str) -> Vec<i64> {
+        let mut ids: Vec<i64> = search_notes(search, None, None).unwrap()
+            .into_iter()
+            .filter_map(|result| result.note.id)
+            .collect();
+        ids.sort();
+        ids
+    }
+    
+    #[test]
+    fn trigram_search_matches_chinese_without_word_breaks() {
+        let _db = open_test_db();
+        let meeting = save_note(&text_note("周一下午开会讨论季度预算")).unwrap();
+        let report = save_note(&text_note("周二提交季度报告")).unwrap();
+        
+        assert_eq!(search_ids("开会讨论"), vec![meeting]);
+        assert_eq!(search_ids("季度"), vec![meeting, report]);
+        // 多个关键词需要同时命中
+        assert_eq!(search_ids("季度预算 周一"), vec![meeting]);
+        assert!(search_ids("年度预算").is_empty());
+        
+        let results = search_notes("讨论季度", None, None).unwrap();
+        assert!(results[0].snippet.contains("<mark>讨论季度</mark>"));
+        
+        let notes = get_notes_sorted(&NoteSortOption::CreatedTimeDesc, None, None, Some("提交季度"), &[]).unwrap();
+        assert_eq!(notes.len(), 1);
+        assert_eq!(notes[0].id, Some(report));
+    }
+    
+    #[test]
+    fn short_terms_fall_back_to_like_matching() {
+        let _db = open_test_db();
+        let percent = save_note(&text_note("进度完成 50% 了")).unwrap();
+        let plain = save_note(&text_note("进度完成 500 项")).unwrap();
+        let english = save_note(&text_note("Go to the gym")).unwrap();
+        
+        assert!(build_fts_query("会").is_none());
+        assert!(build_fts_query("进度 完成").is_none());
+        assert_eq!(build_fts_query("进度条 完成度").as_deref(), Some("\"进度条\" \"完成度\""));
+        
+        // 通配符按字面匹配
+        assert_eq!(search_ids("0%"), vec![percent]);
+        assert_eq!(search_ids("进度"), vec![percent, plain]);
+        // 短关键词不区分大小写
+        assert_eq!(search_ids("go GY"), vec![english]);
+        
+        let results = search_notes("50", None, None).unwrap();
+        assert_eq!(results.len(), 2);
+        assert!(results.iter().all(|r| r.snippet.contains("<mark>50</mark>")));
+        
+        let notes = get_notes_sorted(&NoteSortOption::CreatedTimeDesc, None, None, Some("% 了"), &[]).unwrap();
+        assert_eq!(notes.len(), 1);
+        assert_eq!(notes[0].id, Some(percent));
+    }
+    
+    #[test]
+    fn search_excludes_trashed_notes_and_follows_edits() {
+        let _db = open_test_db();
+        let kept = save_note(&text_note("旧的会议纪要")).unwrap();
+        let trashed = save_note(&text_note("废弃的会议纪要")).unwrap();
+        move_note_to_trash(trashed, 1_700_000_000).unwrap();
+        
+        assert_eq!(search_ids("会议纪要"), vec![kept]);
+        
+        let mut note = get_note(kept).unwrap().unwrap();
+        note.content = "新的工作计划".to_string();
+        save_note(&note).unwrap();
+        assert!(search_ids("会议纪要").is_empty());
+        assert_eq!(search_ids("工作计划"), vec![kept]);
+    }
+    
+    fn tag_id(name: &str) -> i64 {
+        get_all_tags().unwrap().into_iter().find(|t| t.name == name).unwrap().id
+    }
+    
+    fn tag_source(note_id: i64, tag_id: i64) -> String {
+        get_db().unwrap().query_row(
+            "SELECT source FROM note_tags WHERE note_id = ?1 AND tag_id = ?2",
+            params![note_id, tag_id],
+            |row| row.get(0),
+        ).unwrap()
+    }
+    
+    #[test]
+    fn manual_tag_becomes_inline_when_typed_into_content() {
+        let _db = open_test_db();
+        let note_id = save_note(&text_note("计划")).unwrap();
+        let work = add_tag_to_note(note_id, "work").unwrap();
+        assert_eq!(tag_source(note_id, work), "manual");
+        
+        let mut note = get_note(note_id).unwrap().unwrap();
+        note.content = "计划 #work".to_string();
+        save_note(&note).unwrap();
+        assert_eq!(tag_source(note_id, work), "inline");
+        
+        // 手动添加内容中已有的标签不改变来源
+        add_tag_to_note(note_id, "#work").unwrap();
+        assert_eq!(tag_source(note_id, work), "inline");
+    }
+    
+    #[test]
+    fn renaming_tag_rewrites_content_regardless_of_source() {
+        let _db = open_test_db();
+        let note_id = save_note(&text_note("计划 #Work 和 #workshop")).unwrap();
+        let work = tag_id("Work");
+        // 旧数据中内容里的标签可能记录为手动来源
+        get_db().unwrap().execute("UPDATE note_tags SET source = 'manual'", []).unwrap();
+        
+        rename_tag(work, "job").unwrap();
+        
+        assert_eq!(get_note(note_id).unwrap().unwrap().content, "计划 #job 和 #workshop");
+        assert_eq!(get_note_tags(note_id).unwrap().iter().map(|t| t.name.as_str()).collect::<Vec<_>>(), vec!["job", "workshop"]);
+    }
+    
+    #[test]
+    fn merging_tags_keeps_inline_source() {
+        let _db = open_test_db();
+        let note_id = save_note(&text_note("待办 #todo")).unwrap();
+        let todo = tag_id("todo");
+        let task = add_tag_to_note(note_id, "task").unwrap();
+        let other = save_note(&text_note("只有 #todo")).unwrap();
+        
+        merge_tags(&[todo], task).unwrap();
+        
+        assert_eq!(get_note(note_id).unwrap().unwrap().content, "待办 #task");
+        assert_eq!(tag_source(note_id, task), "inline");
+        assert_eq!(get_note(other).unwrap().unwrap().content, "只有 #task");
+        assert_eq!(tag_source(other, task), "inline");
+        assert!(get_all_tags().unwrap().iter().all(|t| t.id != todo));
+    }
+    
+    fn category(name: &str, parent_id: Option<i64>) -> i64 {
+        save_category(&Category { id: None, name: name.to_string(), color: None, parent_id, position: 0 }).unwrap()
+    }
+    
+    fn parent_of(id: i64) -> Option<i64> {
+        get_all_categories().unwrap().into_iter().find(|c| c.id == Some(id)).unwrap().parent_id
+    }
+    
+    #[test]
+    fn category_cannot_move_into_its_subtree_or_missing_parent() {
+        let _db = open_test_db();
+        let work = category("工作", None);
+        let project = category("项目", Some(work));
+        let archive = category("归档", Some(project));
+        
+        let err = move_category(work, Some(archive)).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::InvalidInput);
+        assert_eq!(move_category(work, Some(work)).unwrap_err().kind(), ErrorKind::InvalidInput);
+        let err = save_category(&Category { id: Some(work), name: "工作".to_string(), color: None, parent_id: Some(project), position: 0 }).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::InvalidInput);
+        assert_eq!(parent_of(work), None);
+        
+        assert_eq!(move_category(project, Some(9999)).unwrap_err().kind(), ErrorKind::NotFound);
+        let err = save_category(&Category { id: None, name: "孤儿".to_string(), color: None, parent_id: Some(9999), position: 0 }).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::NotFound);
+        assert_eq!(get_all_categories().unwrap().len(), 3);
+        
+        // 整棵子树随分类一起移动
+        move_category(project, None).unwrap();
+        assert_eq!(parent_of(project), None);
+        assert_eq!(parent_of(archive), Some(project));
+        let mut moved: Vec<i64> = get_category_tree().unwrap().into_iter()
+            .filter_map(|node| node.category.id)
+            .collect();
+        moved.sort();
+        assert_eq!(moved, vec![work, project]);
+    }
+    
+    #[test]
+    fn deleting_category_reparents_or_removes_subtree() {
+        let _db = open_test_db();
+        let work = category("工作", None);
+        let project = category("项目", Some(work));
+        let archive = category("归档", Some(project));
+        let mut note = text_note("项目便签");
+        note.category_id = Some(project);
+        let note_id = save_note(&note).unwrap();
+        
+        delete_category(project, ChildCategoryAction::Reparent).unwrap();
+        assert_eq!(parent_of(archive), Some(work));
+        assert_eq!(get_note(note_id).unwrap().unwrap().category_id, None);
+        
+        let mut note = text_note("归档便签");
+        note.category_id = Some(archive);
+        let archived_id = save_note(&note).unwrap();
+        let other = category("生活", None);
+        
+        delete_category(work, ChildCategoryAction::Delete).unwrap();
+        let remaining: Vec<Option<i64>> = get_all_categories().unwrap().into_iter().map(|c| c.id).collect();
+        assert_eq!(remaining, vec![Some(other)]);
+        assert_eq!(get_note(archived_id).unwrap().unwrap().category_id, None);
+    }
+    
+    #[test]
+    fn category_notes_follow_manual_order() {
+        let _db = open_test_db();
+        let work = category("工作", None);
+        let project = category("项目", Some(work));
+        let in_category = |content: &str, category_id: i64, created_at: i64| {
+            let mut note = text_note(content);
+            note.category_id = Some(category_id);
+            note.created_at = created_at;
+            save_note(&note).unwrap()
+        };
+        let first = in_category("第一", work, 1_700_000_001);
+        let second = in_category("第二", work, 1_700_000_002);
+        let third = in_category("第三", work, 1_700_000_003);
+        let nested = in_category("子分类", project, 1_700_000_004);
+        
+        save_manual_order(&[], &[second, third, first]).unwrap();
+        toggle_note_pin(first).unwrap();
+        
+        let ids = |notes: Vec<Note>| notes.into_iter().filter_map(|n| n.id).collect::<Vec<_>>();
+        assert_eq!(ids(get_notes_in_category(work, false, &NoteSortOption::Manual).unwrap()), vec![first, second, third]);
+        assert_eq!(ids(get_notes_in_category(work, false, &NoteSortOption::CreatedTimeDesc).unwrap()), vec![first, third, second]);
+        // 包含子分类时各分类的便签分组排列
+        assert_eq!(ids(get_notes_in_category(work, true, &NoteSortOption::Manual).unwrap()), vec![first, second, third, nested]);
+    }
+    
+    #[test]
+    fn re_adding_attachment_renews_grace_period() {
+        let _db = open_test_db();
+        let note_id = save_note(&text_note("粘贴图片")).unwrap();
+        let first = add_attachment(b"pasted", "image/png", None).unwrap();
+        expire_attachment_grace_period();
+        
+        // 再次粘贴相同内容，尚未关联到便签时不应被回收
+        let again = add_attachment(b"pasted", "image/png", None).unwrap();
+        assert_eq!(again.id, first.id);
+        assert_eq!(collect_attachment_garbage().unwrap(), 0);
+        assert!(first.path().unwrap().exists());
+        
+        let linked = add_attachment(b"pasted", "image/png", Some(note_id)).unwrap();
+        assert_eq!(linked.ref_count, 1);
+        assert_eq!(get_note_attachments(note_id).unwrap().len(), 1);
+    }
+    
+    #[test]
+    fn failed_attachment_link_does_not_keep_record() {
+        let _db = open_test_db();
+        let note_id = save_note(&text_note("附件")).unwrap();
+        get_db().unwrap().execute_batch(
+            "CREATE TEMP TRIGGER fail_link BEFORE INSERT ON note_attachments
+             BEGIN SELECT RAISE(ABORT, 'fail'); END;"
+        ).unwrap();
+        
+        assert!(add_attachment(b"orphan", "image/png", Some(note_id)).is_err());
+        let count: i64 = get_db().unwrap().query_row("SELECT COUNT(*) FROM attachments", [], |row| row.get(0)).unwrap();
+        assert_eq!(count, 0);
+    }
+    
+    #[test]
+    fn wiki_links_are_parsed_and_replaced_case_insensitively() {
+        let content = "见 [[周计划]]、[[#12]] 和 [[ 周计划 ]]，[[未闭合\n[[]] [[Week Plan]]";
+        let links = parse_wiki_links(content);
+        assert!(links == vec![
+            WikiLink::Title("周计划".to_string()),
+            WikiLink::Id(12),
+            WikiLink::Title("Week Plan".to_string()),
+        ]);
+        
+        assert_eq!(replace_wiki_link("[[week plan]] 和 [[Week Planner]]", "Week Plan", "周报"), "[[周报]] 和 [[Week Planner]]");
+        assert_eq!(note_title("## 标题 \n正文"), "标题");
+    }
+    
+    #[test]
+    fn renaming_linked_note_rewrites_title_links() {
+        let _db = open_test_db();
+        let target = save_note(&text_note("周计划\n内容")).unwrap();
+        let by_title = save_note(&text_note("汇总\n见 [[周计划]]")).unwrap();
+        let by_id = save_note(&text_note(&format!("按编号 [[#{}]]", target))).unwrap();
+        
+        let mut note = get_note(target).unwrap().unwrap();
+        note.content = "周报\n内容".to_string();
+        save_note(&note).unwrap();
+        
+        assert_eq!(get_note(by_title).unwrap().unwrap().content, "汇总\n见 [[周报]]");
+        assert_eq!(get_note(by_id).unwrap().unwrap().content, format!("按编号 [[#{}]]", target));
+        let mut backlinks: Vec<i64> = get_backlinks(target).unwrap().into_iter().map(|l| l.source_id).collect();
+        backlinks.sort();
+        assert_eq!(backlinks, vec![by_title, by_id]);
+        // 历史版本保留改写前的内容
+        assert_eq!(get_note_revisions(by_title).unwrap()[0].content, "汇总\n见 [[周计划]]");
+    }
+    
+    #[test]
+    fn trash_cleanup_never_purges_archived_notes() {
+        let _db = open_test_db();
+        let expired = save_note(&text_note("过期")).unwrap();
+        let archived = save_note(&text_note("已归档")).unwrap();
+        let recent = save_note(&text_note("刚删除")).unwrap();
+        archive_notes(&[archived], 1_600_000_000).unwrap();
+        move_note_to_trash(expired, 1_600_000_000).unwrap();
+        move_note_to_trash(archived, 1_600_000_000).unwrap();
+        move_note_to_trash(recent, chrono::Utc::now().timestamp()).unwrap();
+        
+        assert_eq!(cleanup_trash(30).unwrap(), 1);
+        
+        assert!(get_note(expired).unwrap().is_none());
+        assert!(get_note(archived).unwrap().is_some());
+        assert!(get_note(recent).unwrap().is_some());
+    }
+}
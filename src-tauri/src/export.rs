@@ -0,0 +1,674 @@
+use std::collections::{HashMap, HashSet};
+use std::io::{Error as IoError, ErrorKind};
+use std::path::{Path, PathBuf};
+use chrono::TimeZone;
+use serde::{Deserialize, Serialize};
+use crate::attachments;
+use crate::db::{self, Attachment, Category, ChecklistItem, ContentFormat, Note, NoteReminder, NoteSortOption, NoteType};
+use crate::render;
+
+// 附件复制到导出目录下的 attachments/，便签中以相对路径引用
+const ATTACHMENTS_DIR: &str = "attachments";
+const JSON_FILENAME: &str = "notes.json";
+const HTML_FILENAME: &str = "notes.html";
+
+// JSON 导出格式的版本，结构变化时递增
+const JSON_SCHEMA_VERSION: u32 = 1;
+
+// 文件名中便签标题的最大字符数
+const MAX_TITLE_CHARS: usize = 60;
+
+// 导出范围
+#[derive(Deserialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum ExportScope {
+    Notes { note_ids: Vec<i64> },                            // 选中的便签
+    Category { category_id: i64, include_descendants: bool }, // 分类（可包含子分类）中的便签
+    All,                                                     // 所有未删除的便签，包括已归档的
+}
+
+#[derive(Deserialize, Clone, Copy)]
+#[serde(rename_all = "lowercase")]
+pub enum ExportFormat {
+    Markdown,  // 每个便签一个 .md 文件，带 YAML front matter
+    Json,      // 单个 notes.json
+    Html,      // 单个可独立打开的 notes.html
+}
+
+#[derive(Serialize)]
+pub struct ExportSummary {
+    pub note_count: usize,
+    pub attachment_count: usize,
+    pub path: String,  // Markdown 为导出目录，JSON/HTML 为生成的文件
+}
+
+// ======= JSON 导出格式 =======
+//
+// {
+//   "format": "stickynotes-export",
+//   "schema_version": 1,
+//   "exported_at": "2024-01-01T12:00:00+08:00",
+//   "notes": [ExportedNote, ...]
+// }
+//
+// 时间均为带时区的 RFC 3339 字符串，可选字段为 null
+
+#[derive(Serialize)]
+struct JsonExport<'a> {
+    format: &'static str,
+    schema_version: u32,
+    exported_at: String,
+    notes: &'a [ExportedNote],
+}
+
+#[derive(Serialize)]
+struct ExportedNote {
+    id: i64,
+    title: String,
+    content: String,
+    content_format: &'static str,    // plain / markdown
+    note_type: &'static str,         // text / checklist
+    #[serde(skip)]
+    format: ContentFormat,
+    #[serde(skip)]
+    is_checklist: bool,
+    category: Option<String>,        // 分类路径，如 "工作 / 项目"
+    color: Option<String>,
+    pinned: bool,
+    archived: bool,
+    created_at: String,
+    updated_at: Option<String>,
+    tags: Vec<String>,
+    checklist: Vec<ExportedChecklistItem>,
+    reminders: Vec<ExportedReminder>,
+    attachments: Vec<ExportedAttachment>,
+}
+
+#[derive(Serialize)]
+struct ExportedChecklistItem {
+    content: String,
+    done: bool,
+    due_at: Option<String>,
+}
+
+#[derive(Serialize)]
+struct ExportedReminder {
+    time: String,              // 重复提醒为下一次提醒的时间
+    completed: bool,
+    rrule: Option<String>,     // 重复规则（RRULE），单次提醒为 null
+    timezone: Option<String>,  // 重复提醒使用的时区，null 表示系统时区
+}
+
+#[derive(Serialize)]
+struct ExportedAttachment {
+    path: String,  // 相对于导出目录的路径
+    mime: String,
+    size: i64,
+}
+
+// ======= 导出 =======
+
+pub fn write_export(scope: &ExportScope, format: ExportFormat, dest_dir: &Path) -> Result<ExportSummary, IoError> {
+    let notes = collect_notes(scope)?;
+    std::fs::create_dir_all(dest_dir)?;
+
+    let categories: HashMap<i64, Category> = db::get_all_categories()?
+        .into_iter()
+        .filter_map(|category| category.id.map(|id| (id, category)))
+        .collect();
+
+    let mut copied = HashSet::new();
+    let mut exported = Vec::with_capacity(notes.len());
+    for note in notes {
+        let note_id = note.id.unwrap_or_default();
+        let attachments = db::get_note_attachments(note_id)?;
+        for attachment in &attachments {
+            if copied.insert(attachment.hash.clone()) {
+                copy_attachment(attachment, dest_dir)?;
+            }
+        }
+
+        let mut content = relink_attachments(&note.content, &attachments);
+        let mut exported_attachments: Vec<ExportedAttachment> = attachments.iter().map(|attachment| ExportedAttachment {
+            path: attachment_relative_path(attachment),
+            mime: attachment.mime.clone(),
+            size: attachment.size,
+        }).collect();
+        if let Some((source, screenshot)) = export_legacy_screenshot(&note, &attachments, dest_dir, &mut copied)? {
+            content = content.replace(&source, &screenshot.path);
+            exported_attachments.push(screenshot);
+        }
+
+        exported.push(ExportedNote {
+            id: note_id,
+            title: db::note_title(&note.content).to_string(),
+            content,
+            content_format: note.content_format.as_str(),
+            note_type: note.note_type.as_str(),
+            format: note.content_format,
+            is_checklist: note.note_type == NoteType::Checklist,
+            category: note.category_id.and_then(|id| category_path(id, &categories)),
+            color: note.color.clone(),
+            pinned: note.is_pinned,
+            archived: note.archived_at.is_some(),
+            created_at: format_time(note.created_at),
+            updated_at: note.updated_at.map(format_time),
+            tags: db::get_note_tags(note_id)?.into_iter().map(|tag| tag.name).collect(),
+            checklist: db::get_checklist_items(note_id)?.into_iter().map(export_checklist_item).collect(),
+            reminders: db::get_reminders_by_note(note_id)?.into_iter().map(export_reminder).collect(),
+            attachments: exported_attachments,
+        });
+    }
+
+    let path = match format {
+        ExportFormat::Markdown => {
+            write_markdown(&exported, dest_dir)?;
+            dest_dir.to_path_buf()
+        }
+        ExportFormat::Json => write_json(&exported, dest_dir)?,
+        ExportFormat::Html => write_html(&exported, dest_dir)?,
+    };
+
+    Ok(ExportSummary {
+        note_count: exported.len(),
+        attachment_count: copied.len(),
+        path: path.to_string_lossy().to_string(),
+    })
+}
+
+fn collect_notes(scope: &ExportScope) -> Result<Vec<Note>, IoError> {
+    match scope {
+        ExportScope::Notes { note_ids } => {
+            let mut notes = Vec::with_capacity(note_ids.len());
+            for id in note_ids {
+                match db::get_note(*id)? {
+                    Some(note) if note.deleted_at.is_none() => notes.push(note),
+                    _ => return Err(IoError::new(ErrorKind::NotFound, format!("便签 {} 不存在", id))),
+                }
+            }
+            Ok(notes)
+        }
+        ExportScope::Category { category_id, include_descendants } => {
+            db::get_notes_in_category(*category_id, *include_descendants, &NoteSortOption::CreatedTimeDesc)
+        }
+        ExportScope::All => {
+            let mut notes = db::get_notes()?;
+            notes.extend(db::get_archived_notes()?);
+            Ok(notes)
+        }
+    }
+}
+
+// 分类的完整路径，父分类在前
+fn category_path(id: i64, categories: &HashMap<i64, Category>) -> Option<String> {
+    let mut names = Vec::new();
+    let mut current = categories.get(&id);
+    while let Some(category) = current {
+        // 分类树中出现环时停止
+        if names.len() > categories.len() {
+            break;
+        }
+        names.push(category.name.as_str());
+        current = category.parent_id.and_then(|parent_id| categories.get(&parent_id));
+    }
+    if names.is_empty() {
+        return None;
+    }
+    names.reverse();
+    Some(names.join(" / "))
+}
+
+fn export_checklist_item(item: ChecklistItem) -> ExportedChecklistItem {
+    ExportedChecklistItem {
+        content: item.content,
+        done: item.done,
+        due_at: item.due_at.map(format_time),
+    }
+}
+
+fn export_reminder(reminder: NoteReminder) -> ExportedReminder {
+    ExportedReminder {
+        time: format_time(reminder.reminder_time),
+        completed: reminder.completed,
+        rrule: reminder.recurrence.as_ref().map(|r| r.rrule.clone()),
+        timezone: reminder.recurrence.and_then(|r| r.timezone),
+    }
+}
+
+fn format_time(timestamp: i64) -> String {
+    match chrono::Local.timestamp_opt(timestamp, 0).single() {
+        Some(time) => time.to_rfc3339(),
+        None => timestamp.to_string(),
+    }
+}
+
+// ======= 附件 =======
+
+fn attachment_relative_path(attachment: &Attachment) -> String {
+    format!("{}/{}.{}", ATTACHMENTS_DIR, attachment.hash, attachment.extension)
+}
+
+fn copy_attachment(attachment: &Attachment, dest_dir: &Path) -> Result<(), IoError> {
+    let target = dest_dir.join(attachment_relative_path(attachment));
+    if target.exists() {
+        return Ok(());
+    }
+    if let Some(parent) = target.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    std::fs::copy(attachment.path()?, &target)
+        .map_err(|e| IoError::new(e.kind(), format!("复制附件 {} 失败: {}", attachment.hash, e)))?;
+    Ok(())
+}
+
+// 旧版本的截图只记录在便签的 screenshot_path 中，没有关联为附件。
+// 复制到导出目录的附件目录中，返回原路径和导出的附件信息
+fn export_legacy_screenshot(
+    note: &Note,
+    linked: &[Attachment],
+    dest_dir: &Path,
+    copied: &mut HashSet<String>,
+) -> Result<Option<(String, ExportedAttachment)>, IoError> {
+    let source = match note.screenshot_path.as_deref().filter(|path| !path.is_empty()) {
+        Some(source) => source,
+        None => return Ok(None),
+    };
+    // 已关联的截图随附件一起导出
+    let stored_hash = attachments::hash_from_blob_path(Path::new(source));
+    if stored_hash.map_or(false, |hash| linked.iter().any(|attachment| attachment.hash == hash)) {
+        return Ok(None);
+    }
+
+    let bytes = match std::fs::read(source) {
+        Ok(bytes) => bytes,
+        Err(e) if e.kind() == ErrorKind::NotFound => {
+            log::warn!("便签 {} 的截图 {} 不存在，跳过", note.id.unwrap_or_default(), source);
+            return Ok(None);
+        }
+        Err(e) => return Err(IoError::new(e.kind(), format!("读取截图 {} 失败: {}", source, e))),
+    };
+    let mime = attachments::mime_for_path(Path::new(source));
+    let hash = attachments::hash_bytes(&bytes);
+    let path = format!("{}/{}.{}", ATTACHMENTS_DIR, hash, attachments::extension_for_mime(mime));
+
+    if copied.insert(hash) {
+        let target = dest_dir.join(&path);
+        if let Some(parent) = target.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        std::fs::write(&target, &bytes)?;
+    }
+
+    Ok(Some((source.to_string(), ExportedAttachment {
+        path,
+        mime: mime.to_string(),
+        size: bytes.len() as i64,
+    })))
+}
+
+// 将内容中附件的 asset: 链接和本地绝对路径替换为导出目录中的相对路径
+fn relink_attachments(content: &str, attachments: &[Attachment]) -> String {
+    let mut content = content.to_string();
+    for attachment in attachments {
+        if let Ok(path) = attachment.path() {
+            let relative = attachment_relative_path(attachment);
+            for reference in [attachments::asset_url(&path), path.to_string_lossy().to_string()] {
+                if content.contains(&reference) {
+                    content = content.replace(&reference, &relative);
+                }
+            }
+        }
+    }
+    content
+}
+
+fn is_image(attachment: &ExportedAttachment) -> bool {
+    attachment.mime.starts_with("image/")
+}
+
+// ======= Markdown =======
+
+// 每个便签写成 <标题>-<ID>.md，ID 保证文件名不重复
+fn write_markdown(notes: &[ExportedNote], dest_dir: &Path) -> Result<(), IoError> {
+    for note in notes {
+        let mut text = front_matter(note);
+        text.push_str(&note.content);
+        if !note.content.ends_with('\n') {
+            text.push('\n');
+        }
+
+        if note.is_checklist && !note.checklist.is_empty() {
+            text.push('\n');
+            for item in &note.checklist {
+                text.push_str(&format!("- [{}] {}\n", if item.done { "x" } else { " " }, item.content));
+            }
+        }
+
+        // 内容中没有引用的附件附在末尾
+        let unreferenced: Vec<&ExportedAttachment> = note.attachments.iter()
+            .filter(|attachment| !note.content.contains(&attachment.path))
+            .collect();
+        if !unreferenced.is_empty() {
+            text.push('\n');
+            for attachment in unreferenced {
+                let prefix = if is_image(attachment) { "!" } else { "" };
+                text.push_str(&format!("{}[{}]({})\n", prefix, attachment.mime, attachment.path));
+            }
+        }
+
+        let file_name = format!("{}-{}.md", file_name_title(&note.title), note.id);
+        std::fs::write(dest_dir.join(file_name), text)?;
+    }
+    Ok(())
+}
+
+// YAML front matter，字符串统一用 JSON 字符串（也是合法的 YAML 双引号字符串）
+fn front_matter(note: &ExportedNote) -> String {
+    let quote = |value: &str| serde_json::to_string(value).unwrap_or_else(|_| "\"\"".to_string());
+    let mut yaml = String::from("---\n");
+
+    yaml.push_str(&format!("id: {}\n", note.id));
+    yaml.push_str(&format!("title: {}\n", quote(&note.title)));
+    if let Some(category) = &note.category {
+        yaml.push_str(&format!("category: {}\n", quote(category)));
+    }
+    if let Some(color) = &note.color {
+        yaml.push_str(&format!("color: {}\n", quote(color)));
+    }
+    yaml.push_str(&format!("pinned: {}\n", note.pinned));
+    if note.archived {
+        yaml.push_str("archived: true\n");
+    }
+    yaml.push_str(&format!("type: {}\n", note.note_type));
+    yaml.push_str(&format!("format: {}\n", note.content_format));
+    yaml.push_str(&format!("created: {}\n", quote(&note.created_at)));
+    if let Some(updated_at) = &note.updated_at {
+        yaml.push_str(&format!("updated: {}\n", quote(updated_at)));
+    }
+    if !note.tags.is_empty() {
+        yaml.push_str("tags:\n");
+        for tag in &note.tags {
+            yaml.push_str(&format!("  - {}\n", quote(tag)));
+        }
+    }
+    if !note.reminders.is_empty() {
+        yaml.push_str("reminders:\n");
+        for reminder in &note.reminders {
+            yaml.push_str(&format!("  - time: {}\n    completed: {}\n", quote(&reminder.time), reminder.completed));
+            if let Some(rrule) = &reminder.rrule {
+                yaml.push_str(&format!("    rrule: {}\n", quote(rrule)));
+            }
+        }
+    }
+    if !note.attachments.is_empty() {
+        yaml.push_str("attachments:\n");
+        for attachment in &note.attachments {
+            yaml.push_str(&format!("  - {}\n", quote(&attachment.path)));
+        }
+    }
+
+    yaml.push_str("---\n\n");
+    yaml
+}
+
+// 去掉文件名中不允许的字符，标题为空时使用 "untitled"
+fn file_name_title(title: &str) -> String {
+    let cleaned: String = title.chars()
+        .map(|c| if c.is_control() || "\\/:*?\"<>|".contains(c) { '_' } else { c })
+        .take(MAX_TITLE_CHARS)
+        .collect();
+    let cleaned = cleaned.trim().trim_matches('.');
+    if cleaned.is_empty() {
+        "untitled".to_string()
+    } else {
+        cleaned.to_string()
+    }
+}
+
+// ======= JSON =======
+
+fn write_json(notes: &[ExportedNote], dest_dir: &Path) -> Result<PathBuf, IoError> {
+    let export = JsonExport {
+        format: "stickynotes-export",
+        schema_version: JSON_SCHEMA_VERSION,
+        exported_at: chrono::Local::now().to_rfc3339(),
+        notes,
+    };
+    let json = serde_json::to_string_pretty(&export)
+        .map_err(|e| IoError::new(ErrorKind::Other, format!("序列化便签失败: {}", e)))?;
+
+    let path = dest_dir.join(JSON_FILENAME);
+    std::fs::write(&path, json)?;
+    Ok(path)
+}
+
+// ======= HTML =======
+
+const HTML_STYLE: &str = "
+body { font-family: -apple-system, 'Segoe UI', 'Microsoft YaHei', sans-serif; max-width: 860px; margin: 2em auto; padding: 0 1em; color: #222; }
+article { border: 1px solid #ddd; border-radius: 6px; padding: 1em 1.2em; margin-bottom: 1.5em; }
+article h2 { margin-top: 0; font-size: 1.2em; }
+.meta { color: #777; font-size: 0.85em; margin-bottom: 0.8em; }
+.tag { background: #eee; border-radius: 3px; padding: 0 0.4em; margin-right: 0.3em; }
+img { max-width: 100%; }
+ul.checklist { list-style: none; padding-left: 0.2em; }
+";
+
+fn write_html(notes: &[ExportedNote], dest_dir: &Path) -> Result<PathBuf, IoError> {
+    let mut html = String::from("<!DOCTYPE html>\n<html lang=\"zh-CN\">\n<head>\n<meta charset=\"utf-8\">\n<title>便签导出</title>\n");
+    html.push_str(&format!("<style>{}</style>\n</head>\n<body>\n", HTML_STYLE));
+    html.push_str(&format!(
+        "<h1>便签导出</h1>\n<p class=\"meta\">{} 个便签，导出于 {}</p>\n",
+        notes.len(),
+        render::escape_html(&chrono::Local::now().to_rfc3339()),
+    ));
+
+    for note in notes {
+        let border = note.color.as_deref()
+            .filter(|color| color.chars().all(|c| c.is_ascii_alphanumeric() || c == '#'))
+            .map(|color| format!(" style=\"border-left: 6px solid {}\"", color))
+            .unwrap_or_default();
+        html.push_str(&format!("<article id=\"note-{}\"{}>\n", note.id, border));
+        html.push_str(&format!("<h2>{}{}</h2>\n", if note.pinned { "📌 " } else { "" }, render::escape_html(&note.title)));
+
+        let mut meta = vec![format!("创建于 {}", note.created_at)];
+        if let Some(updated_at) = &note.updated_at {
+            meta.push(format!("更新于 {}", updated_at));
+        }
+        if let Some(category) = &note.category {
+            meta.push(format!("分类：{}", category));
+        }
+        if note.archived {
+            meta.push("已归档".to_string());
+        }
+        for reminder in &note.reminders {
+            let repeat = reminder.rrule.as_ref().map(|rrule| format!("（重复：{}）", rrule)).unwrap_or_default();
+            meta.push(format!("提醒：{}{}{}", reminder.time, repeat, if reminder.completed { "（已完成）" } else { "" }));
+        }
+        html.push_str(&format!("<div class=\"meta\">{}", render::escape_html(&meta.join(" · "))));
+        for tag in &note.tags {
+            html.push_str(&format!(" <span class=\"tag\">#{}</span>", render::escape_html(tag)));
+        }
+        html.push_str("</div>\n");
+
+        html.push_str(&render::render_html(&note.content, note.format));
+
+        if note.is_checklist && !note.checklist.is_empty() {
+            html.push_str("<ul class=\"checklist\">\n");
+            for item in &note.checklist {
+                html.push_str(&format!(
+                    "<li><input type=\"checkbox\" disabled{}> {}</li>\n",
+                    if item.done { " checked" } else { "" },
+                    render::escape_html(&item.content),
+                ));
+            }
+            html.push_str("</ul>\n");
+        }
+
+        for attachment in note.attachments.iter().filter(|a| !note.content.contains(&a.path)) {
+            let path = render::escape_html(&attachment.path);
+            if is_image(attachment) {
+                html.push_str(&format!("<p><img src=\"{}\" alt=\"\"></p>\n", path));
+            } else {
+                html.push_str(&format!("<p><a href=\"{}\">{}</a></p>\n", path, path));
+            }
+        }
+
+        html.push_str("</article>\n");
+    }
+
+    html.push_str("</body>\n</html>\n");
+    let path = dest_dir.join(HTML_FILENAME);
+    std::fs::write(&path, html)?;
+    Ok(path)
+}
+
+// ======= 命令 =======
+
+#[tauri::command]
+pub async fn export_notes(scope: ExportScope, format: ExportFormat, path: String) -> Result<ExportSummary, String> {
+    db::run_blocking(move || write_export(&scope, format, Path::new(&path))).await
+        .map_err(|e| format!("导出失败: {}", e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::test_support::{open_test_db, text_note};
+
+    fn exported_note(title: &str) -> ExportedNote {
+        ExportedNote {
+            id: 7,
+            title: title.to_string(),
+            content: String::new(),
+            content_format: "plain",
+            note_type: "text",
+            format: ContentFormat::Plain,
+            is_checklist: false,
+            category: None,
+            color: None,
+            pinned: false,
+            archived: false,
+            created_at: "2024-01-01T12:00:00+08:00".to_string(),
+            updated_at: None,
+            tags: Vec::new(),
+            checklist: Vec::new(),
+            reminders: Vec::new(),
+            attachments: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn front_matter_omits_missing_fields() {
+        assert_eq!(front_matter(&exported_note("购物清单")), concat!(
+            "---\n",
+            "id: 7\n",
+            "title: \"购物清单\"\n",
+            "pinned: false\n",
+            "type: text\n",
+            "format: plain\n",
+            "created: \"2024-01-01T12:00:00+08:00\"\n",
+            "---\n\n",
+        ));
+    }
+
+    #[test]
+    fn front_matter_quotes_strings_and_lists() {
+        let mut note = exported_note("引号 \"标题\": #1");
+        note.category = Some("工作 / 项目".to_string());
+        note.color = Some("#ffeb3b".to_string());
+        note.pinned = true;
+        note.archived = true;
+        note.updated_at = Some("2024-01-02T08:00:00+08:00".to_string());
+        note.tags = vec!["待办".to_string(), "a: b".to_string()];
+        note.reminders = vec![
+            ExportedReminder {
+                time: "2024-01-03T09:00:00+08:00".to_string(),
+                completed: false,
+                rrule: Some("FREQ=WEEKLY;BYDAY=MO".to_string()),
+                timezone: None,
+            },
+            ExportedReminder {
+                time: "2024-01-01T09:00:00+08:00".to_string(),
+                completed: true,
+                rrule: None,
+                timezone: None,
+            },
+        ];
+        note.attachments = vec![ExportedAttachment {
+            path: "attachments/abc.png".to_string(),
+            mime: "image/png".to_string(),
+            size: 3,
+        }];
+
+        assert_eq!(front_matter(&note), concat!(
+            "---\n",
+            "id: 7\n",
+            "title: \"引号 \\\"标题\\\": #1\"\n",
+            "category: \"工作 / 项目\"\n",
+            "color: \"#ffeb3b\"\n",
+            "pinned: true\n",
+            "archived: true\n",
+            "type: text\n",
+            "format: plain\n",
+            "created: \"2024-01-01T12:00:00+08:00\"\n",
+            "updated: \"2024-01-02T08:00:00+08:00\"\n",
+            "tags:\n",
+            "  - \"待办\"\n",
+            "  - \"a: b\"\n",
+            "reminders:\n",
+            "  - time: \"2024-01-03T09:00:00+08:00\"\n",
+            "    completed: false\n",
+            "    rrule: \"FREQ=WEEKLY;BYDAY=MO\"\n",
+            "  - time: \"2024-01-01T09:00:00+08:00\"\n",
+            "    completed: true\n",
+            "attachments:\n",
+            "  - \"attachments/abc.png\"\n",
+            "---\n\n",
+        ));
+    }
+
+    #[test]
+    fn file_names_drop_reserved_characters() {
+        assert_eq!(file_name_title("a/b: c?"), "a_b_ c_");
+        assert_eq!(file_name_title("  ..  "), "untitled");
+        assert_eq!(file_name_title(&"长".repeat(100)).chars().count(), MAX_TITLE_CHARS);
+    }
+
+    #[test]
+    fn attachment_links_are_rewritten_to_relative_paths() {
+        let _db = open_test_db();
+        let attachment = db::add_attachment(b"png", "image/png", None).unwrap();
+        let path = attachment.path().unwrap();
+        let content = format!("![图]({})\n{}", attachments::asset_url(&path), path.display());
+
+        let relative = format!("attachments/{}.png", attachment.hash);
+        assert_eq!(relink_attachments(&content, &[attachment]), format!("![图]({})\n{}", relative, relative));
+    }
+
+    #[test]
+    fn markdown_export_copies_and_relinks_attachments() {
+        let db = open_test_db();
+        let note_id = db::save_note(&text_note("报告")).unwrap();
+        let referenced = db::add_attachment(b"image", "image/png", Some(note_id)).unwrap();
+        let unreferenced = db::add_attachment(b"document", "application/pdf", Some(note_id)).unwrap();
+        let mut note = db::get_note(note_id).unwrap().unwrap();
+        note.content = format!("报告\n![图]({})", attachments::asset_url(&referenced.path().unwrap()));
+        db::save_note(&note).unwrap();
+
+        let dest = db.dir.path().join("export");
+        let summary = write_export(&ExportScope::Notes { note_ids: vec![note_id] }, ExportFormat::Markdown, &dest).unwrap();
+        assert_eq!(summary.note_count, 1);
+        assert_eq!(summary.attachment_count, 2);
+
+        let image = attachment_relative_path(&referenced);
+        let document = attachment_relative_path(&unreferenced);
+        assert_eq!(std::fs::read(dest.join(&image)).unwrap(), b"image");
+        assert_eq!(std::fs::read(dest.join(&document)).unwrap(), b"document");
+
+        let text = std::fs::read_to_string(dest.join(format!("报告-{}.md", note_id))).unwrap();
+        assert!(text.contains(&format!("报告\n![图]({})\n", image)));
+        assert!(!text.contains("asset://"));
+        assert!(text.ends_with(&format!("\n[application/pdf]({})\n", document)));
+    }
+}
@@ -0,0 +1,1194 @@
+use std::collections::HashMap;
+use std::io::{Error as IoError, ErrorKind};
+use std::path::{Path, PathBuf};
+use chrono::TimeZone;
+use md5::{Digest, Md5};
+use quick_xml::escape::unescape_with;
+use quick_xml::events::Event;
+use quick_xml::Reader;
+use rusqlite::{Connection, OpenFlags};
+use serde::{Deserialize, Serialize};
+use tauri::{AppHandle, Manager};
+use crate::attachments;
+use crate::db::{self, Category, ContentFormat, Note, NoteType};
+
+#[derive(Serialize, Default)]
+pub struct ImportSummary {
+    pub imported: usize,        // 预览时为将要导入的数量
+    pub skipped: usize,         // 空便签、已删除的便签和已经导入过的便签
+    pub attachments: usize,
+    pub warnings: Vec<String>,  // 无法读取的文件、缺失的附件等
+}
+
+// 保存一条导入的便签，内容为空或已存在相同内容和创建时间的便签时跳过；
+// 返回新便签ID，跳过或预览（dry_run）时返回 None
+fn import_note(note: Note, dry_run: bool, summary: &mut ImportSummary) -> Result<Option<i64>, IoError> {
+    if will_skip(&note)? {
+        summary.skipped += 1;
+        return Ok(None);
+    }
+
+    summary.imported += 1;
+    if dry_run {
+        return Ok(None);
+    }
+    db::save_note(&note).map(Some)
+}
+
+// 内容为空或已有相同内容和创建时间的便签时跳过导入，没有标题的清单内容为空，但不是空便签
+fn will_skip(note: &Note) -> Result<bool, IoError> {
+    let is_empty = note.content.trim().is_empty() && note.note_type != NoteType::Checklist;
+    Ok(is_empty || db::find_note_id(&note.content, note.created_at)?.is_some())
+}
+
+// 按名称索引的顶级分类，导入时用于将标签、笔记本等映射到分类
+fn top_level_categories() -> Result<HashMap<String, i64>, IoError> {
+    Ok(db::get_all_categories()?
+        .into_iter()
+        .filter(|category| category.parent_id.is_none())
+        .filter_map(|category| category.id.map(|id| (category.name, id)))
+        .collect())
+}
+
+// 查找同名的顶级分类，没有时新建（预览时不新建，返回 None）
+fn category_by_name(name: String, categories: &mut HashMap<String, i64>, dry_run: bool) -> Result<Option<i64>, IoError> {
+    if let Some(id) = categories.get(&name) {
+        return Ok(Some(*id));
+    }
+    if dry_run {
+        return Ok(None);
+    }
+
+    let id = db::save_category(&Category {
+        id: None,
+        name: name.clone(),
+        color: None,
+        parent_id: None,
+        position: 0,
+    })?;
+    categories.insert(name, id);
+    Ok(Some(id))
+}
+
+// 导入便签的默认字段
+fn new_note(content: String, created_at: i64, updated_at: Option<i64>, category_id: Option<i64>) -> Note {
+    Note {
+        id: None,
+        content,
+        screenshot_path: None,
+        created_at,
+        updated_at,
+        is_pinned: false,
+        color: None,
+        category_id,
+        deleted_at: None,
+        position: 0,
+        note_type: NoteType::Text,
+        content_format: ContentFormat::Plain,
+        archived_at: None,
+    }
+}
+
+// ======= Microsoft Sticky Notes =======
+
+// .NET 的 DateTime.Ticks（自 0001-01-01 起的 100 纳秒数）与 Unix 时间戳的换算
+const TICKS_PER_SECOND: i64 = 10_000_000;
+const TICKS_UNIX_EPOCH: i64 = 621_355_968_000_000_000;
+
+// 读取 Windows 便笺（Sticky Notes 3.x 及以上）的 plum.sqlite，文件以只读方式打开
+pub fn import_sticky_notes(path: &Path, category_id: Option<i64>) -> Result<ImportSummary, IoError> {
+    let conn = Connection::open_with_flags(path, OpenFlags::SQLITE_OPEN_READ_ONLY)
+        .map_err(|e| IoError::new(ErrorKind::Other, format!("打开 plum.sqlite 失败: {}", e)))?;
+
+    let rows = {
+        let mut stmt = conn.prepare("SELECT Text, Theme, CreatedAt, UpdatedAt, DeletedAt FROM Note")
+            .map_err(|e| IoError::new(ErrorKind::InvalidData, format!("不是有效的便笺数据库: {}", e)))?;
+        let rows = stmt.query_map([], |row| Ok((
+                row.get::<_, Option<String>>(0)?,
+                row.get::<_, Option<String>>(1)?,
+                row.get::<_, Option<i64>>(2)?,
+                row.get::<_, Option<i64>>(3)?,
+                row.get::<_, Option<i64>>(4)?,
+            )))
+            .map_err(|e| IoError::new(ErrorKind::Other, format!("读取便笺失败: {}", e)))?
+            .collect::<rusqlite::Result<Vec<_>>>()
+            .map_err(|e| IoError::new(ErrorKind::Other, format!("读取便笺失败: {}", e)))?;
+        rows
+    };
+
+    let now = chrono::Utc::now().timestamp();
+    let mut summary = ImportSummary::default();
+    for (text, theme, created_at, updated_at, deleted_at) in rows {
+        if deleted_at.is_some() {
+            summary.skipped += 1;
+            continue;
+        }
+
+        let created_at = created_at.and_then(ticks_to_unix).unwrap_or(now);
+        let mut note = new_note(
+            sticky_text_to_content(text.as_deref().unwrap_or("")),
+            created_at,
+            updated_at.and_then(ticks_to_unix),
+            category_id,
+        );
+        note.color = theme.as_deref().and_then(sticky_theme_color).map(String::from);
+        import_note(note, false, &mut summary)?;
+    }
+
+    Ok(summary)
+}
+
+fn ticks_to_unix(ticks: i64) -> Option<i64> {
+    (ticks > TICKS_UNIX_EPOCH).then(|| (ticks - TICKS_UNIX_EPOCH) / TICKS_PER_SECOND)
+}
+
+// 便笺的每个段落以 "\id=<GUID> " 开头，段落之间用换行分隔；
+// 去掉段落标记，并还原转义的反斜杠
+fn sticky_text_to_content(text: &str) -> String {
+    text.lines()
+        .map(|line| {
+            let line = match line.strip_prefix("\\id=") {
+                Some(rest) => rest.split_once(' ').map_or("", |(_, text)| text),
+                None => line,
+            };
+            line.replace("\\\\", "\\")
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+        .trim_end()
+        .to_string()
+}
+
+// 便笺主题色对应的便签颜色，与编辑器中的颜色选项一致；炭黑没有对应颜色，使用默认颜色
+fn sticky_theme_color(theme: &str) -> Option<&'static str> {
+    match theme {
+        "Yellow" => Some("#FFF9C4"),
+        "Pink" => Some("#F8BBD0"),
+        "Blue" => Some("#BBDEFB"),
+        "Green" => Some("#C8E6C9"),
+        "Purple" => Some("#E1BEE7"),
+        "Gray" => Some("#FFFFFF"),
+        _ => None,
+    }
+}
+
+// ======= Google Keep =======
+
+// Takeout 中 Keep 的每个便签是一个 JSON 文件，附件图片与 JSON 放在同一目录；
+// Takeout 不导出提醒的时间，Keep 的提醒无法导入，JSON 中带有提醒相关字段的便签会给出警告
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct KeepNote {
+    #[serde(default)]
+    title: String,
+    #[serde(default)]
+    text_content: String,
+    #[serde(default)]
+    list_content: Vec<KeepListItem>,
+    #[serde(default)]
+    labels: Vec<KeepLabel>,
+    #[serde(default)]
+    attachments: Vec<KeepAttachment>,
+    color: Option<String>,
+    #[serde(default)]
+    is_pinned: bool,
+    #[serde(default)]
+    is_archived: bool,
+    #[serde(default)]
+    is_trashed: bool,
+    created_timestamp_usec: Option<i64>,
+    user_edited_timestamp_usec: Option<i64>,
+    #[serde(flatten)]
+    other: HashMap<String, serde_json::Value>,
+}
+
+impl KeepNote {
+    fn has_reminder(&self) -> bool {
+        self.other.iter().any(|(key, value)| key.to_ascii_lowercase().contains("reminder") && !value.is_null())
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct KeepListItem {
+    #[serde(default)]
+    text: String,
+    #[serde(default)]
+    is_checked: bool,
+}
+
+#[derive(Deserialize)]
+struct KeepLabel {
+    name: String,
+}
+
+#[derive(Deserialize)]
+struct KeepAttachment {
+    #[serde(rename = "filePath")]
+    file_path: String,
+    mimetype: Option<String>,
+}
+
+// Keep 标签的导入方式
+#[derive(Deserialize, Clone, Copy)]
+#[serde(rename_all = "lowercase")]
+pub enum LabelMapping {
+    Tags,        // 所有标签都作为便签标签
+    Categories,  // 第一个标签作为分类（按名称匹配顶级分类，没有时新建），其余作为便签标签
+}
+
+// 导入 Takeout 中的 Keep 便签；path 可以是 Takeout 目录或其中的 Keep 目录，
+// dry_run 时只统计将要导入的内容，不写入数据库
+pub fn import_google_keep(path: &Path, labels: LabelMapping, dry_run: bool) -> Result<ImportSummary, IoError> {
+    let dir = [path.join("Takeout").join("Keep"), path.join("Keep"), path.to_path_buf()]
+        .into_iter()
+        .find(|dir| dir.is_dir())
+        .ok_or_else(|| IoError::new(ErrorKind::NotFound, "找不到 Keep 导出目录"))?;
+
+    let mut files: Vec<PathBuf> = std::fs::read_dir(&dir)?
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.path())
+        .filter(|path| path.extension().map_or(false, |ext| ext.eq_ignore_ascii_case("json")))
+        .collect();
+    files.sort();
+
+    let mut categories = top_level_categories()?;
+
+    let now = chrono::Utc::now().timestamp();
+    let mut summary = ImportSummary::default();
+    for file in files {
+        let keep_note: KeepNote = match std::fs::read_to_string(&file).map_err(|e| e.to_string())
+            .and_then(|json| serde_json::from_str(&json).map_err(|e| e.to_string()))
+        {
+            Ok(note) => note,
+            Err(e) => {
+                summary.warnings.push(format!("{}: {}", file.display(), e));
+                continue;
+            }
+        };
+        if keep_note.is_trashed {
+            summary.skipped += 1;
+            continue;
+        }
+
+        let mut label_names: Vec<String> = keep_note.labels.iter().map(|label| label.name.clone()).collect();
+        let category_id = match labels {
+            LabelMapping::Categories if !label_names.is_empty() => {
+                let name = label_names.remove(0);
+                category_by_name(name, &mut categories, dry_run)?
+            }
+            _ => None,
+        };
+
+        let created_at = keep_note.created_timestamp_usec.map(|usec| usec / 1_000_000).unwrap_or(now);
+        let updated_at = keep_note.user_edited_timestamp_usec.map(|usec| usec / 1_000_000);
+        let is_checklist = !keep_note.list_content.is_empty();
+        let content = match (keep_note.title.trim(), keep_note.text_content.trim()) {
+            ("", text) => text.to_string(),
+            (title, "") => title.to_string(),
+            (title, text) => format!("{}\n\n{}", title, text),
+        };
+
+        let mut note = new_note(content, created_at, updated_at, category_id);
+        note.is_pinned = keep_note.is_pinned;
+        note.color = keep_note.color.as_deref().and_then(keep_color).map(String::from);
+        note.archived_at = keep_note.is_archived.then(|| updated_at.unwrap_or(created_at));
+        if is_checklist {
+            // 清单的条目单独保存，便签内容只有标题
+            note.note_type = NoteType::Checklist;
+        }
+        if keep_note.has_reminder() {
+            summary.warnings.push(format!("{}: Keep 的提醒无法导入", file.display()));
+        }
+
+        let attachment_files: Vec<(PathBuf, String)> = keep_note.attachments.iter()
+            .filter_map(|attachment| {
+                let found = keep_attachment_path(&dir, &attachment.file_path);
+                if found.is_none() {
+                    summary.warnings.push(format!("找不到附件: {}", attachment.file_path));
+                }
+                found.map(|path| {
+                    let mime = attachment.mimetype.clone()
+                        .unwrap_or_else(|| attachments::mime_for_path(&path).to_string());
+                    (path, mime)
+                })
+            })
+            .collect();
+
+        if will_skip(&note)? {
+            summary.skipped += 1;
+            continue;
+        }
+        summary.imported += 1;
+        summary.attachments += attachment_files.len();
+        if dry_run {
+            continue;
+        }
+
+        // 便签与清单条目、标签、附件在同一个事务中保存，导入中断时不会留下缺少条目的便签
+        let checklist: Vec<(String, bool)> = keep_note.list_content.iter()
+            .map(|item| (item.text.clone(), item.is_checked))
+            .collect();
+        let files = attachment_files.into_iter()
+            .map(|(path, mime)| Ok((std::fs::read(&path)?, mime)))
+            .collect::<Result<Vec<_>, IoError>>()?;
+        db::save_imported_note(&note, &checklist, &label_names, &files)?;
+    }
+
+    Ok(summary)
+}
+
+// Takeout 中附件的实际扩展名有时与 JSON 中记录的不同（如 .jpeg 与 .jpg）
+fn keep_attachment_path(dir: &Path, file_path: &str) -> Option<PathBuf> {
+    let path = dir.join(file_path);
+    if path.is_file() {
+        return Some(path);
+    }
+    ["jpg", "jpeg", "png", "gif", "webp"].iter()
+        .map(|ext| path.with_extension(ext))
+        .find(|candidate| candidate.is_file())
+}
+
+// Keep 的颜色对应的便签颜色，与编辑器中的颜色选项一致，默认色和灰色使用默认颜色
+fn keep_color(color: &str) -> Option<&'static str> {
+    match color {
+        "YELLOW" => Some("#FFF9C4"),
+        "RED" | "PINK" => Some("#F8BBD0"),
+        "BLUE" | "TEAL" | "CERULEAN" => Some("#BBDEFB"),
+        "GREEN" => Some("#C8E6C9"),
+        "PURPLE" => Some("#E1BEE7"),
+        "ORANGE" | "BROWN" => Some("#FFE0B2"),
+        _ => None,
+    }
+}
+
+// ======= Evernote ENEX =======
+
+// ENEX 中的日期格式（UTC），如 20130730T205204Z
+const ENEX_DATE_FORMAT: &str = "%Y%m%dT%H%M%SZ";
+
+// ENEX 便签的分类方式
+#[derive(Deserialize, Clone, Copy)]
+#[serde(rename_all = "lowercase")]
+pub enum EnexCategoryMapping {
+    None,      // 不设置分类，标签都作为便签标签
+    Notebook,  // 以笔记本名称作为分类（Evernote 导出的文件名就是笔记本名称）
+    Tag,       // 第一个标签作为分类，其余作为便签标签
+}
+
+// 解析中的一条 ENEX 便签
+#[derive(Default)]
+struct EnexNote {
+    title: String,
+    content: String,  // ENML
+    created: String,
+    updated: String,
+    tags: Vec<String>,
+    resources: Vec<EnexResource>,
+}
+
+#[derive(Default)]
+struct EnexResource {
+    data: String,  // base64
+    mime: String,
+    file_name: String,
+}
+
+// 流式读取 ENEX 文件，每读完一条便签就写入数据库，内存中最多保留一条便签及其资源
+pub fn import_enex(path: &Path, mapping: EnexCategoryMapping) -> Result<ImportSummary, IoError> {
+    let file = std::fs::File::open(path)?;
+    let mut reader = Reader::from_reader(std::io::BufReader::new(file));
+    reader.trim_text(true);
+
+    let notebook = path.file_stem().map(|stem| stem.to_string_lossy().to_string());
+    let mut categories = top_level_categories()?;
+    let mut summary = ImportSummary::default();
+
+    let mut buf = Vec::new();
+    let mut path_stack: Vec<Vec<u8>> = Vec::new();
+    let mut note: Option<EnexNote> = None;
+    let mut resource: Option<EnexResource> = None;
+    loop {
+        let event = reader.read_event_into(&mut buf)
+            .map_err(|e| IoError::new(ErrorKind::InvalidData, format!("ENEX 文件格式错误（位置 {}）: {}", reader.buffer_position(), e)))?;
+        match event {
+            Event::Start(e) => {
+                let name = e.name().as_ref().to_vec();
+                match name.as_slice() {
+                    b"note" => note = Some(EnexNote::default()),
+                    b"resource" => resource = Some(EnexResource::default()),
+                    _ => {}
+                }
+                path_stack.push(name);
+            }
+            Event::End(e) => {
+                path_stack.pop();
+                match e.name().as_ref() {
+                    b"resource" => {
+                        if let (Some(note), Some(resource)) = (note.as_mut(), resource.take()) {
+                            note.resources.push(resource);
+                        }
+                    }
+                    b"note" => {
+                        if let Some(finished) = note.take() {
+                            let category_name = match mapping {
+                                EnexCategoryMapping::None => None,
+                                EnexCategoryMapping::Notebook => notebook.clone(),
+                                EnexCategoryMapping::Tag => finished.tags.first().cloned(),
+                            };
+                            save_enex_note(finished, category_name, &mut categories, mapping, &mut summary)?;
+                        }
+                    }
+                    _ => {}
+                }
+            }
+            Event::Text(e) => {
+                let text = e.unescape()
+                    .map_err(|e| IoError::new(ErrorKind::InvalidData, format!("ENEX 文本无效: {}", e)))?;
+                append_enex_field(&path_stack, &text, note.as_mut(), resource.as_mut());
+            }
+            Event::CData(e) => {
+                let text = String::from_utf8_lossy(&e);
+                append_enex_field(&path_stack, &text, note.as_mut(), resource.as_mut());
+            }
+            Event::Eof => break,
+            _ => {}
+        }
+        buf.clear();
+    }
+
+    Ok(summary)
+}
+
+// 将文本追加到当前元素对应的字段
+fn append_enex_field(path: &[Vec<u8>], text: &str, note: Option<&mut EnexNote>, resource: Option<&mut EnexResource>) {
+    let current = path.last().map(|name| name.as_slice());
+    let parent = path.len().checked_sub(2).map(|i| path[i].as_slice());
+
+    if let Some(resource) = resource {
+        match current {
+            Some(b"data") => resource.data.push_str(text),
+            Some(b"mime") => resource.mime.push_str(text),
+            Some(b"file-name") => resource.file_name.push_str(text),
+            _ => {}
+        }
+        return;
+    }
+
+    // 只处理 <note> 的直接子元素，跳过 note-attributes 等嵌套元素中的同名字段
+    let note = match (note, parent) {
+        (Some(note), Some(b"note")) => note,
+        _ => return,
+    };
+    match current {
+        Some(b"title") => note.title.push_str(text),
+        Some(b"content") => note.content.push_str(text),
+        Some(b"created") => note.created.push_str(text),
+        Some(b"updated") => note.updated.push_str(text),
+        Some(b"tag") => note.tags.push(text.to_string()),
+        _ => {}
+    }
+}
+
+fn save_enex_note(
+    enex: EnexNote,
+    category_name: Option<String>,
+    categories: &mut HashMap<String, i64>,
+    mapping: EnexCategoryMapping,
+    summary: &mut ImportSummary,
+) -> Result<(), IoError> {
+    // 先保存资源，ENML 中的 <en-media> 按资源内容的 MD5 引用资源；
+    // 便签被跳过时资源没有引用，会在清理附件时删除
+    let mut media = HashMap::new();
+    let mut attachment_ids = Vec::new();
+    for resource in &enex.resources {
+        let encoded: String = resource.data.chars().filter(|c| !c.is_whitespace()).collect();
+        let bytes = match base64::decode(&encoded) {
+            Ok(bytes) => bytes,
+            Err(e) => {
+                summary.warnings.push(format!("{}: 资源 {} 解码失败: {}", enex.title, resource.file_name, e));
+                continue;
+            }
+        };
+        let mime = if resource.mime.is_empty() { "application/octet-stream" } else { resource.mime.as_str() };
+        let attachment = db::add_attachment(&bytes, mime, None)?;
+        media.insert(format!("{:x}", Md5::digest(&bytes)), (attachment.url()?, mime.to_string(), resource.file_name.clone()));
+        attachment_ids.push(attachment.id);
+    }
+
+    let mut problems = Vec::new();
+    let body = enml_to_markdown(&enex.content, &media, &mut problems);
+    summary.warnings.extend(problems.into_iter().map(|problem| format!("{}: {}", enex.title, problem)));
+    let title = enex.title.trim();
+    let content = if title.is_empty() { body } else { format!("# {}\n\n{}", title, body) };
+
+    let now = chrono::Utc::now().timestamp();
+    let created_at = parse_enex_date(&enex.created).unwrap_or(now);
+    let mut note = new_note(content.trim_end().to_string(), created_at, parse_enex_date(&enex.updated), None);
+    note.content_format = ContentFormat::Markdown;
+    // 确认便签会被导入后再创建分类，被跳过的便签不会留下空分类
+    if let Some(name) = category_name {
+        if !will_skip(&note)? {
+            note.category_id = category_by_name(name, categories, false)?;
+        }
+    }
+
+    let note_id = match import_note(note, false, summary)? {
+        Some(id) => id,
+        None => return Ok(()),
+    };
+    for attachment_id in attachment_ids {
+        db::attach_to_note(note_id, attachment_id)?;
+        summary.attachments += 1;
+    }
+    let tags = match mapping {
+        EnexCategoryMapping::Tag => enex.tags.get(1..).unwrap_or_default(),
+        _ => &enex.tags[..],
+    };
+    for tag in tags {
+        db::add_tag_to_note(note_id, tag)?;
+    }
+    Ok(())
+}
+
+fn parse_enex_date(value: &str) -> Option<i64> {
+    chrono::NaiveDateTime::parse_from_str(value.trim(), ENEX_DATE_FORMAT).ok()
+        .map(|time| chrono::Utc.from_utc_datetime(&time).timestamp())
+}
+
+// 将 ENML（XHTML 子集）转换为 Markdown；<en-media> 按 MD5 替换为附件的 asset: 链接，图片显示为图片。
+// 无法识别的实体和解析错误记录到 warnings 中，解析出错时保留出错位置之前已转换的内容
+fn enml_to_markdown(enml: &str, media: &HashMap<String, (String, String, String)>, warnings: &mut Vec<String>) -> String {
+    let mut reader = Reader::from_str(enml);
+    reader.expand_empty_elements(true);
+
+    let mut out = String::new();
+    let mut lists: Vec<Option<usize>> = Vec::new();  // None 为无序列表，Some 为有序列表的下一个序号
+    let mut links: Vec<String> = Vec::new();
+    let mut in_pre = false;
+    let mut unknown_entities: Vec<String> = Vec::new();
+
+    loop {
+        let event = match reader.read_event() {
+            Ok(event) => event,
+            Err(e) => {
+                warnings.push(format!("内容格式错误（位置 {}），只导入了之前的部分: {}", reader.buffer_position(), e));
+                break;
+            }
+        };
+        match event {
+            Event::Start(e) => {
+                let attribute = |name: &str| e.try_get_attribute(name).ok().flatten()
+                    .and_then(|attr| attr.unescape_value_with(html_entity).ok().map(|value| value.to_string()));
+                match e.local_name().as_ref() {
+                    b"div" | b"p" | b"blockquote" | b"table" | b"tr" => ensure_line_break(&mut out),
+                    b"br" => out.push('\n'),
+                    b"hr" => {
+                        ensure_line_break(&mut out);
+                        out.push_str("---\n");
+                    }
+                    level @ (b"h1" | b"h2" | b"h3" | b"h4" | b"h5" | b"h6") => {
+                        ensure_line_break(&mut out);
+                        out.push_str(&"#".repeat((level[1] - b'0') as usize));
+                        out.push(' ');
+                    }
+                    b"b" | b"strong" => out.push_str("**"),
+                    b"i" | b"em" => out.push('*'),
+                    b"s" | b"strike" | b"del" => out.push_str("~~"),
+                    b"code" if !in_pre => out.push('`'),
+                    b"pre" => {
+                        ensure_line_break(&mut out);
+                        out.push_str("```\n");
+                        in_pre = true;
+                    }
+                    b"a" => {
+                        links.push(attribute("href").unwrap_or_default());
+                        out.push('[');
+                    }
+                    b"ul" => lists.push(None),
+                    b"ol" => lists.push(Some(1)),
+                    b"li" => {
+                        ensure_line_break(&mut out);
+                        out.push_str(&"  ".repeat(lists.len().saturating_sub(1)));
+                        match lists.last_mut() {
+                            Some(Some(number)) => {
+                                out.push_str(&format!("{}. ", number));
+                                *number += 1;
+                            }
+                            _ => out.push_str("- "),
+                        }
+                    }
+                    b"td" | b"th" => out.push_str(" | "),
+                    b"en-todo" => {
+                        let checked = attribute("checked").map_or(false, |value| value == "true");
+                        out.push_str(if checked { "- [x] " } else { "- [ ] " });
+                    }
+                    b"en-media" => {
+                        let hash = attribute("hash").unwrap_or_default();
+                        if let Some((path, mime, file_name)) = media.get(&hash) {
+                            let prefix = if mime.starts_with("image/") { "!" } else { "" };
+                            out.push_str(&format!("{}[{}](<{}>)", prefix, file_name, path));
+                        }
+                    }
+                    _ => {}
+                }
+            }
+            Event::End(e) => match e.local_name().as_ref() {
+                b"div" | b"p" | b"blockquote" | b"table" | b"tr" | b"h1" | b"h2" | b"h3" | b"h4" | b"h5" | b"h6" => {
+                    ensure_line_break(&mut out)
+                }
+                b"b" | b"strong" => out.push_str("**"),
+                b"i" | b"em" => out.push('*'),
+                b"s" | b"strike" | b"del" => out.push_str("~~"),
+                b"code" if !in_pre => out.push('`'),
+                b"pre" => {
+                    ensure_line_break(&mut out);
+                    out.push_str("```\n");
+                    in_pre = false;
+                }
+                b"a" => {
+                    let href = links.pop().unwrap_or_default();
+                    out.push_str(&format!("]({})", href));
+                }
+                b"ul" | b"ol" => {
+                    lists.pop();
+                    ensure_line_break(&mut out);
+                }
+                _ => {}
+            },
+            Event::Text(e) => {
+                let text = unescape_enml(&String::from_utf8_lossy(&e), &mut unknown_entities);
+                if in_pre {
+                    out.push_str(&text);
+                } else {
+                    // 标签之间的换行和缩进只是源码格式，不是内容
+                    let text = text.replace(['\n', '\r', '\t'], " ");
+                    if !(text.trim().is_empty() && (out.is_empty() || out.ends_with('\n'))) {
+                        out.push_str(&text.replace('\u{a0}', " "));
+                    }
+                }
+            }
+            Event::Eof => break,
+            _ => {}
+        }
+    }
+
+    if !unknown_entities.is_empty() {
+        unknown_entities.sort();
+        unknown_entities.dedup();
+        let names: Vec<String> = unknown_entities.iter().map(|name| format!("&{};", name)).collect();
+        warnings.push(format!("无法识别的实体 {}，已原样保留", names.join(" ")));
+    }
+
+    // 合并连续的空行
+    let mut markdown = String::with_capacity(out.len());
+    let mut blank_lines = 0;
+    for line in out.lines() {
+        if line.trim().is_empty() {
+            blank_lines += 1;
+            if blank_lines > 1 {
+                continue;
+            }
+        } else {
+            blank_lines = 0;
+        }
+        markdown.push_str(line.trim_end());
+        markdown.push('\n');
+    }
+    markdown.trim().to_string()
+}
+
+fn ensure_line_break(out: &mut String) {
+    if !out.is_empty() && !out.ends_with('\n') {
+        out.push('\n');
+    }
+}
+
+// 解码 ENML 文本中的实体，除 XML 的预定义实体和字符引用外还允许 HTML 的命名实体；
+// 无法识别的实体原样保留，名称记录到 unknown 中
+fn unescape_enml(raw: &str, unknown: &mut Vec<String>) -> String {
+    // 解析器按名称回调，原样保留时需要返回借用自调用方的字符串
+    let literals: Vec<String> = raw.split('&').skip(1)
+        .filter_map(|rest| rest.split_once(';'))
+        .map(|(name, _)| format!("&{};", name))
+        .collect();
+    let resolved = unescape_with(raw, |name| html_entity(name).or_else(|| {
+        unknown.push(name.to_string());
+        literals.iter().find(|literal| literal[1..literal.len() - 1] == *name).map(String::as_str)
+    }));
+    // 单独的 & 或无效的字符引用：整段保留原文
+    resolved.map(|text| text.into_owned()).unwrap_or_else(|_| raw.to_string())
+}
+
+// ENML 中常见的 HTML 命名实体
+fn html_entity(name: &str) -> Option<&'static str> {
+    let value = match name {
+        "nbsp" => "\u{a0}",
+        "ensp" => "\u{2002}",
+        "emsp" => "\u{2003}",
+        "thinsp" => "\u{2009}",
+        "shy" => "\u{ad}",
+        "zwnj" => "\u{200c}",
+        "zwj" => "\u{200d}",
+        "ndash" => "–",
+        "mdash" => "—",
+        "hellip" => "…",
+        "lsquo" => "‘",
+        "rsquo" => "’",
+        "sbquo" => "‚",
+        "ldquo" => "“",
+        "rdquo" => "”",
+        "bdquo" => "„",
+        "laquo" => "«",
+        "raquo" => "»",
+        "lsaquo" => "‹",
+        "rsaquo" => "›",
+        "bull" => "•",
+        "middot" => "·",
+        "dagger" => "†",
+        "Dagger" => "‡",
+        "permil" => "‰",
+        "prime" => "′",
+        "Prime" => "″",
+        "iexcl" => "¡",
+        "iquest" => "¿",
+        "cent" => "¢",
+        "pound" => "£",
+        "curren" => "¤",
+        "yen" => "¥",
+        "euro" => "€",
+        "brvbar" => "¦",
+        "sect" => "§",
+        "uml" => "¨",
+        "copy" => "©",
+        "ordf" => "ª",
+        "ordm" => "º",
+        "not" => "¬",
+        "reg" => "®",
+        "trade" => "™",
+        "macr" => "¯",
+        "deg" => "°",
+        "plusmn" => "±",
+        "sup1" => "¹",
+        "sup2" => "²",
+        "sup3" => "³",
+        "acute" => "´",
+        "micro" => "µ",
+        "para" => "¶",
+        "cedil" => "¸",
+        "frac14" => "¼",
+        "frac12" => "½",
+        "frac34" => "¾",
+        "times" => "×",
+        "divide" => "÷",
+        "minus" => "−",
+        "le" => "≤",
+        "ge" => "≥",
+        "ne" => "≠",
+        "asymp" => "≈",
+        "infin" => "∞",
+        "larr" => "←",
+        "uarr" => "↑",
+        "rarr" => "→",
+        "darr" => "↓",
+        "harr" => "↔",
+        "lArr" => "⇐",
+        "rArr" => "⇒",
+        "hArr" => "⇔",
+        "Agrave" => "À",
+        "Aacute" => "Á",
+        "Acirc" => "Â",
+        "Atilde" => "Ã",
+        "Auml" => "Ä",
+        "Aring" => "Å",
+        "AElig" => "Æ",
+        "Ccedil" => "Ç",
+        "Egrave" => "È",
+        "Eacute" => "É",
+        "Ecirc" => "Ê",
+        "Euml" => "Ë",
+        "Igrave" => "Ì",
+        "Iacute" => "Í",
+        "Icirc" => "Î",
+        "Iuml" => "Ï",
+        "ETH" => "Ð",
+        "Ntilde" => "Ñ",
+        "Ograve" => "Ò",
+        "Oacute" => "Ó",
+        "Ocirc" => "Ô",
+        "Otilde" => "Õ",
+        "Ouml" => "Ö",
+        "Oslash" => "Ø",
+        "OElig" => "Œ",
+        "Scaron" => "Š",
+        "Ugrave" => "Ù",
+        "Uacute" => "Ú",
+        "Ucirc" => "Û",
+        "Uuml" => "Ü",
+        "Yacute" => "Ý",
+        "Yuml" => "Ÿ",
+        "THORN" => "Þ",
+        "szlig" => "ß",
+        "agrave" => "à",
+        "aacute" => "á",
+        "acirc" => "â",
+        "atilde" => "ã",
+        "auml" => "ä",
+        "aring" => "å",
+        "aelig" => "æ",
+        "ccedil" => "ç",
+        "egrave" => "è",
+        "eacute" => "é",
+        "ecirc" => "ê",
+        "euml" => "ë",
+        "igrave" => "ì",
+        "iacute" => "í",
+        "icirc" => "î",
+        "iuml" => "ï",
+        "eth" => "ð",
+        "ntilde" => "ñ",
+        "ograve" => "ò",
+        "oacute" => "ó",
+        "ocirc" => "ô",
+        "otilde" => "õ",
+        "ouml" => "ö",
+        "oslash" => "ø",
+        "oelig" => "œ",
+        "scaron" => "š",
+        "ugrave" => "ù",
+        "uacute" => "ú",
+        "ucirc" => "û",
+        "uuml" => "ü",
+        "yacute" => "ý",
+        "thorn" => "þ",
+        "yuml" => "ÿ",
+        _ => return None,
+    };
+    Some(value)
+}
+
+// ======= 命令 =======
+
+#[tauri::command]
+pub async fn import_microsoft_sticky_notes(path: String, category_id: Option<i64>, app: AppHandle) -> Result<ImportSummary, String> {
+    let summary = db::run_blocking(move || import_sticky_notes(Path::new(&path), category_id)).await
+        .map_err(|e| format!("导入失败: {}", e))?;
+    // 通知所有窗口重新加载便签列表
+    let _ = app.emit_all("notes-imported", summary.imported);
+    Ok(summary)
+}
+
+// dry_run 为 true 时只返回预览统计，不写入数据
+#[tauri::command]
+pub async fn import_google_keep_takeout(
+    path: String,
+    labels: LabelMapping,
+    dry_run: bool,
+    app: AppHandle,
+) -> Result<ImportSummary, String> {
+    let summary = db::run_blocking(move || import_google_keep(Path::new(&path), labels, dry_run)).await
+        .map_err(|e| format!("导入失败: {}", e))?;
+    if !dry_run {
+        let _ = app.emit_all("notes-imported", summary.imported);
+    }
+    Ok(summary)
+}
+
+#[tauri::command]
+pub async fn import_evernote_enex(path: String, mapping: EnexCategoryMapping, app: AppHandle) -> Result<ImportSummary, String> {
+    let summary = db::run_blocking(move || import_enex(Path::new(&path), mapping)).await
+        .map_err(|e| format!("导入失败: {}", e))?;
+    let _ = app.emit_all("notes-imported", summary.imported);
+    Ok(summary)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::test_support::{execute_sql, open_test_db};
+
+    #[test]
+    fn sticky_paragraph_markers_are_removed() {
+        let text = "\\id=2c1a3e9b-47a4-4a3f-9a9f-1f5d0c3a5e71 第一段\n\
+                    \\id=8f0e2d1c-aaaa-bbbb-cccc-000000000001 路径 C:\\\\Users\\\\me\n\
+                    \\id=8f0e2d1c-aaaa-bbbb-cccc-000000000002\n\
+                    没有标记的行\n\n";
+        assert_eq!(sticky_text_to_content(text), "第一段\n路径 C:\\Users\\me\n\n没有标记的行");
+    }
+
+    #[test]
+    fn sticky_text_without_markers_is_kept() {
+        assert_eq!(sticky_text_to_content("纯文本\n第二行"), "纯文本\n第二行");
+        assert_eq!(sticky_text_to_content(""), "");
+    }
+
+    #[test]
+    fn sticky_ticks_convert_to_unix_seconds() {
+        assert_eq!(ticks_to_unix(TICKS_UNIX_EPOCH + 1_700_000_000 * TICKS_PER_SECOND), Some(1_700_000_000));
+        assert_eq!(ticks_to_unix(0), None);
+    }
+
+    #[test]
+    fn sticky_notes_database_is_imported() {
+        let db = open_test_db();
+        let plum = db.dir.path().join("plum.sqlite");
+        {
+            let conn = Connection::open(&plum).unwrap();
+            conn.execute_batch(
+                "CREATE TABLE Note (Text TEXT, Theme TEXT, CreatedAt INTEGER, UpdatedAt INTEGER, DeletedAt INTEGER);"
+            ).unwrap();
+            let created = TICKS_UNIX_EPOCH + 1_700_000_000 * TICKS_PER_SECOND;
+            let mut insert = conn.prepare("INSERT INTO Note VALUES (?1, ?2, ?3, ?4, ?5)").unwrap();
+            insert.execute(rusqlite::params!["\\id=a 便笺", "Pink", created, created + TICKS_PER_SECOND, None::<i64>]).unwrap();
+            insert.execute(rusqlite::params!["\\id=b 已删除", "Yellow", created, None::<i64>, created]).unwrap();
+            insert.execute(rusqlite::params!["\\id=c ", "Charcoal", created, None::<i64>, None::<i64>]).unwrap();
+        }
+
+        let summary = import_sticky_notes(&plum, None).unwrap();
+        assert_eq!(summary.imported, 1);
+        assert_eq!(summary.skipped, 2);
+
+        let note_id = db::find_note_id("便笺", 1_700_000_000).unwrap().unwrap();
+        let note = db::get_note(note_id).unwrap().unwrap();
+        assert_eq!(note.updated_at, Some(1_700_000_001));
+        assert_eq!(note.color.as_deref(), Some("#F8BBD0"));
+
+        // 再次导入时跳过已导入的便签
+        let summary = import_sticky_notes(&plum, None).unwrap();
+        assert_eq!(summary.imported, 0);
+        assert_eq!(summary.skipped, 3);
+    }
+
+    // 在 Takeout/Keep 目录中写入便签 JSON
+    fn keep_dir(notes: &[(&str, serde_json::Value)]) -> tempfile::TempDir {
+        let root = tempfile::tempdir().unwrap();
+        let dir = root.path().join("Takeout").join("Keep");
+        std::fs::create_dir_all(&dir).unwrap();
+        for (name, json) in notes {
+            std::fs::write(dir.join(name), json.to_string()).unwrap();
+        }
+        root
+    }
+
+    fn keep_checklist() -> serde_json::Value {
+        serde_json::json!({
+            "listContent": [
+                { "text": "牛奶", "isChecked": true },
+                { "text": "面包", "isChecked": false }
+            ],
+            "createdTimestampUsec": 1_700_000_000_000_000i64
+        })
+    }
+
+    #[test]
+    fn keep_notes_are_imported_with_labels_and_attachments() {
+        let _db = open_test_db();
+        let root = keep_dir(&[
+            ("会议.json", serde_json::json!({
+                "title": "会议",
+                "textContent": "讨论预算",
+                "labels": [{ "name": "工作" }, { "name": "紧急" }],
+                "attachments": [{ "filePath": "photo.jpg", "mimetype": "image/jpeg" }],
+                "color": "BLUE",
+                "isPinned": true,
+                "isArchived": true,
+                "reminders": [{ "time": "2024-01-01T09:00:00Z" }],
+                "createdTimestampUsec": 1_700_000_000_000_000i64,
+                "userEditedTimestampUsec": 1_700_000_100_000_000i64
+            })),
+            ("已删除.json", serde_json::json!({ "textContent": "已删除", "isTrashed": true })),
+            ("损坏.json", serde_json::json!("不是便签")),
+        ]);
+        // 实际文件的扩展名与 JSON 中记录的不同
+        std::fs::write(root.path().join("Takeout/Keep/photo.jpeg"), b"jpeg").unwrap();
+
+        let summary = import_google_keep(root.path(), LabelMapping::Categories, false).unwrap();
+        assert_eq!(summary.imported, 1);
+        assert_eq!(summary.skipped, 1);
+        assert_eq!(summary.attachments, 1);
+        assert_eq!(summary.warnings.len(), 2);
+        assert!(summary.warnings.iter().any(|w| w.contains("会议.json") && w.contains("提醒")));
+        assert!(summary.warnings.iter().any(|w| w.contains("损坏.json")));
+
+        let note_id = db::find_note_id("会议\n\n讨论预算", 1_700_000_000).unwrap().unwrap();
+        let note = db::get_note(note_id).unwrap().unwrap();
+        assert!(note.is_pinned);
+        assert_eq!(note.color.as_deref(), Some("#BBDEFB"));
+        assert_eq!(note.updated_at, Some(1_700_000_100));
+        assert_eq!(note.archived_at, Some(1_700_000_100));
+
+        let category = db::get_all_categories().unwrap().into_iter().find(|c| c.name == "工作").unwrap();
+        assert_eq!(note.category_id, category.id);
+        let tags: Vec<String> = db::get_note_tags(note_id).unwrap().into_iter().map(|tag| tag.name).collect();
+        assert_eq!(tags, ["紧急"]);
+        let attachments = db::get_note_attachments(note_id).unwrap();
+        assert_eq!(attachments.len(), 1);
+        assert_eq!(std::fs::read(attachments[0].path().unwrap()).unwrap(), b"jpeg");
+    }
+
+    #[test]
+    fn keep_checklist_without_title_keeps_items_out_of_content() {
+        let _db = open_test_db();
+        let root = keep_dir(&[("清单.json", keep_checklist())]);
+
+        let summary = import_google_keep(root.path(), LabelMapping::Tags, false).unwrap();
+        assert_eq!(summary.imported, 1);
+
+        let note_id = db::find_note_id("", 1_700_000_000).unwrap().unwrap();
+        let note = db::get_note(note_id).unwrap().unwrap();
+        assert_eq!(note.content, "");
+        assert!(note.note_type == NoteType::Checklist);
+        let items: Vec<(String, bool)> = db::get_checklist_items(note_id).unwrap()
+            .into_iter()
+            .map(|item| (item.content, item.done))
+            .collect();
+        assert_eq!(items, [("牛奶".to_string(), true), ("面包".to_string(), false)]);
+
+        // 再次导入时跳过
+        let summary = import_google_keep(root.path(), LabelMapping::Tags, false).unwrap();
+        assert_eq!((summary.imported, summary.skipped), (0, 1));
+    }
+
+    #[test]
+    fn keep_dry_run_writes_nothing() {
+        let _db = open_test_db();
+        let mut labelled = keep_checklist();
+        labelled["title"] = "购物".into();
+        labelled["labels"] = serde_json::json!([{ "name": "家务" }]);
+        let root = keep_dir(&[("购物.json", labelled)]);
+
+        let summary = import_google_keep(root.path(), LabelMapping::Categories, true).unwrap();
+        assert_eq!(summary.imported, 1);
+        assert!(db::get_notes().unwrap().is_empty());
+        assert!(db::get_all_categories().unwrap().is_empty());
+    }
+
+    #[test]
+    fn failed_keep_note_is_not_left_half_imported() {
+        let _db = open_test_db();
+        let root = keep_dir(&[("清单.json", keep_checklist())]);
+        execute_sql(
+            "CREATE TEMP TRIGGER fail_items BEFORE INSERT ON checklist_items
+             BEGIN SELECT RAISE(ABORT, '写入失败'); END;"
+        );
+
+        assert!(import_google_keep(root.path(), LabelMapping::Tags, false).is_err());
+        assert!(db::get_notes().unwrap().is_empty());
+
+        execute_sql("DROP TRIGGER fail_items;");
+        let summary = import_google_keep(root.path(), LabelMapping::Tags, false).unwrap();
+        assert_eq!(summary.imported, 1);
+        let note_id = db::find_note_id("", 1_700_000_000).unwrap().unwrap();
+        assert_eq!(db::get_checklist_items(note_id).unwrap().len(), 2);
+    }
+
+    fn enml(body: &str) -> String {
+        format!(
+            "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\
+             <!DOCTYPE en-note SYSTEM \"http://xml.evernote.com/pub/enml2.dtd\">\
+             <en-note>{}</en-note>",
+            body,
+        )
+    }
+
+    #[test]
+    fn enml_html_entities_are_decoded() {
+        let mut warnings = Vec::new();
+        let markdown = enml_to_markdown(
+            &enml("<div>a&nbsp;&mdash; b &amp; &ldquo;caf&eacute;&rdquo; &#8364;5</div><div>下一行</div>"),
+            &HashMap::new(),
+            &mut warnings,
+        );
+        assert_eq!(markdown, "a — b & “café” €5\n下一行");
+        assert!(warnings.is_empty());
+    }
+
+    #[test]
+    fn enml_unknown_entities_are_kept_with_a_warning() {
+        let mut warnings = Vec::new();
+        let markdown = enml_to_markdown(&enml("<div>&foo; &amp; &bar; &foo;</div>"), &HashMap::new(), &mut warnings);
+        assert_eq!(markdown, "&foo; & &bar; &foo;");
+        assert_eq!(warnings, ["无法识别的实体 &bar; &foo;，已原样保留"]);
+    }
+
+    #[test]
+    fn enml_parse_error_keeps_converted_part_with_a_warning() {
+        let mut warnings = Vec::new();
+        let markdown = enml_to_markdown(&enml("<div>完整</div><div>未闭合</p></div>"), &HashMap::new(), &mut warnings);
+        assert!(markdown.starts_with("完整"));
+        assert_eq!(warnings.len(), 1);
+        assert!(warnings[0].contains("只导入了之前的部分"));
+    }
+
+    #[test]
+    fn enml_formatting_and_media_are_converted() {
+        let media = HashMap::from([(
+            "0123abcd".to_string(),
+            ("asset://localhost/a.png".to_string(), "image/png".to_string(), "a.png".to_string()),
+        )]);
+        let mut warnings = Vec::new();
+        let markdown = enml_to_markdown(&enml(concat!(
+            "<h2>标题</h2>",
+            "<div><b>粗</b> <i>斜</i> <a href=\"https://example.com/?a=1&amp;b=2\">链接</a></div>",
+            "<ul><li>一</li><li>二</li></ul>",
+            "<div><en-todo checked=\"true\"/>完成</div>",
+            "<en-media type=\"image/png\" hash=\"0123abcd\"/>",
+        )), &media, &mut warnings);
+        assert_eq!(markdown, concat!(
+            "## 标题\n",
+            "**粗** *斜* [链接](https://example.com/?a=1&b=2)\n",
+            "- 一\n",
+            "- 二\n",
+            "- [x] 完成\n",
+            "![a.png](<asset://localhost/a.png>)",
+        ));
+    }
+
+    #[test]
+    fn enex_file_is_imported_with_resources_and_warnings() {
+        let db = open_test_db();
+        let image = b"png-bytes";
+        let hash = format!("{:x}", Md5::digest(image));
+        let content = enml(&format!("<div>价格&nbsp;&euro;5 &hearts;</div><en-media type=\"image/png\" hash=\"{}\"/>", hash));
+        let enex = format!(
+            "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n\
+             <en-export>\n\
+               <note>\n\
+                 <title>旅行</title>\n\
+                 <content><![CDATA[{}]]></content>\n\
+                 <created>20130730T205204Z</created>\n\
+                 <updated>20130731T080000Z</updated>\n\
+                 <tag>计划</tag>\n\
+                 <tag>2013</tag>\n\
+                 <note-attributes><author>someone</author></note-attributes>\n\
+                 <resource>\n\
+                   <data encoding=\"base64\">{}</data>\n\
+                   <mime>image/png</mime>\n\
+                   <resource-attributes><file-name>photo.png</file-name></resource-attributes>\n\
+                 </resource>\n\
+               </note>\n\
+             </en-export>\n",
+            content,
+            base64::encode(image),
+        );
+        let path = db.dir.path().join("笔记本.enex");
+        std::fs::write(&path, enex).unwrap();
+
+        let summary = import_enex(&path, EnexCategoryMapping::Tag).unwrap();
+        assert_eq!((summary.imported, summary.attachments), (1, 1));
+        assert_eq!(summary.warnings, ["旅行: 无法识别的实体 &hearts;，已原样保留"]);
+
+        let note = db::get_notes().unwrap().remove(0);
+        let attachment = db::get_note_attachments(note.id.unwrap()).unwrap().remove(0);
+        assert_eq!(note.content, format!(
+            "# 旅行\n\n价格 €5 &hearts;\n![photo.png](<{}>)",
+            attachment.url().unwrap(),
+        ));
+        assert_eq!(note.created_at, 1_375_217_524);
+        assert_eq!(note.updated_at, Some(1_375_257_600));
+        let category = db::get_all_categories().unwrap().into_iter().find(|c| c.name == "计划").unwrap();
+        assert_eq!(note.category_id, category.id);
+        let tags: Vec<String> = db::get_note_tags(note.id.unwrap()).unwrap().into_iter().map(|tag| tag.name).collect();
+        assert_eq!(tags, ["2013"]);
+    }
+}
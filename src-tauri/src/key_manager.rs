@@ -1,12 +1,48 @@
+use aes_gcm::aead::{rand_core::RngCore, Aead, AeadCore, KeyInit, OsRng};
+use aes_gcm::{Aes256Gcm, Key, Nonce};
+use argon2::{Algorithm, Argon2, Params, Version};
 use rand::{distributions::Alphanumeric, Rng};
+use serde::{Deserialize, Serialize};
 use std::io::{Error, ErrorKind};
+use std::path::{Path, PathBuf};
+#[cfg(not(windows))]
+use std::collections::HashMap;
+#[cfg(not(windows))]
+use secret_service::{blocking::SecretService, EncryptionType};
+use crate::db;
 
 // 应用的唯一标识符，用于在凭据管理器中存储密钥
 const APP_ID: &str = "com.stickynotes.app";
 const KEY_LENGTH: usize = 32; // 256位密钥
 
-#[cfg(windows)]
+// 主密码模式下保存加密后数据库密钥的文件
+const VAULT_FILENAME: &str = "key_vault.json";
+
+// Argon2id 参数：64 MiB 内存、3 轮迭代，在普通电脑上约需半秒
+#[cfg(not(test))]
+const KDF_MEMORY_KIB: u32 = 64 * 1024;
+#[cfg(not(test))]
+const KDF_ITERATIONS: u32 = 3;
+// 测试使用最小参数，未优化构建下每次派生也不会耗时数秒
+#[cfg(test)]
+const KDF_MEMORY_KIB: u32 = 64;
+#[cfg(test)]
+const KDF_ITERATIONS: u32 = 1;
+const KDF_PARALLELISM: u32 = 1;
+
+const SALT_LENGTH: usize = 16;
+const RECOVERY_KEY_BYTES: usize = 16;  // 打印为 8 组、每组 4 个十六进制字符
+
+// 获取数据库加密密钥，启用主密码后需要通过 unlock_with_password 获取
 pub fn get_encryption_key() -> Result<String, Error> {
+    if master_password_enabled()? {
+        return Err(Error::new(ErrorKind::PermissionDenied, "已启用主密码，需要输入主密码解锁"));
+    }
+    read_or_create_stored_key()
+}
+
+#[cfg(windows)]
+fn read_or_create_stored_key() -> Result<String, Error> {
     use std::ffi::OsStr;
     use std::iter::once;
     use std::os::windows::ffi::OsStrExt;
@@ -50,8 +86,8 @@ pub fn get_encryption_key() -> Result<String, Error> {
             CredFree(credential_ptr as *mut _);
         }
 
-        // 如果凭据不存在或读取失败，生成新密钥
-        let key = generate_random_key();
+        // 如果凭据不存在或读取失败，生成新密钥（已有数据库时报错）
+        let key = create_new_key()?;
         
         // 将密钥存储到凭据管理器
         let mut cred = CREDENTIALW {
@@ -80,6 +116,47 @@ pub fn get_encryption_key() -> Result<String, Error> {
     }
 }
 
+// 将密钥写入凭据管理器，已有的凭据会被覆盖
+#[cfg(windows)]
+fn write_stored_key(key: &str) -> Result<(), Error> {
+    use std::ffi::OsStr;
+    use std::iter::once;
+    use std::os::windows::ffi::OsStrExt;
+    use windows::core::PWSTR;
+    use windows::Win32::Security::Credentials::{
+        CredWriteW, CREDENTIALW, CRED_PERSIST_LOCAL_MACHINE, CRED_TYPE_GENERIC,
+    };
+
+    let mut target_name: Vec<u16> = OsStr::new(APP_ID).encode_wide().chain(once(0)).collect();
+    let mut blob = key.as_bytes().to_vec();
+
+    unsafe {
+        let mut cred = CREDENTIALW {
+            Flags: 0,
+            Type: CRED_TYPE_GENERIC,
+            TargetName: PWSTR::from_raw(target_name.as_mut_ptr()),
+            Comment: PWSTR::null(),
+            LastWritten: windows::Win32::Foundation::FILETIME::default(),
+            CredentialBlobSize: blob.len() as u32,
+            CredentialBlob: blob.as_mut_ptr(),
+            Persist: CRED_PERSIST_LOCAL_MACHINE,
+            AttributeCount: 0,
+            Attributes: std::ptr::null_mut(),
+            TargetAlias: PWSTR::null(),
+            UserName: PWSTR::null(),
+        };
+
+        if CredWriteW(&mut cred, 0).is_ok() {
+            Ok(())
+        } else {
+            Err(Error::new(
+                ErrorKind::Other,
+                "无法将加密密钥写入凭据管理器",
+            ))
+        }
+    }
+}
+
 #[cfg(windows)]
 pub fn delete_encryption_key() -> Result<(), Error> {
     use std::ffi::OsStr;
@@ -104,37 +181,47 @@ pub fn delete_encryption_key() -> Result<(), Error> {
     }
 }
 
-// 针对非Windows平台的简化实现（仅用于开发测试）
+// 非 Windows 平台通过 Secret Service（gnome-keyring、KWallet 等）保存密钥，
+// 连接使用 DBUS_SESSION_BUS_ADDRESS 指定的会话总线，可以在 dbus-run-session 中
+// 启动 gnome-keyring-daemon 进行测试。没有可用的密钥环时才退回到权限为 0600 的密钥文件
 #[cfg(not(windows))]
-pub fn get_encryption_key() -> Result<String, Error> {
-    use std::fs::File;
-    use std::io::{Read, Write};
-    use std::path::PathBuf;
-    
-    let key_path = get_key_path()?;
-
-    // 尝试读取现有密钥
-    if let Ok(mut file) = File::open(&key_path) {
-        let mut key = String::new();
-        file.read_to_string(&mut key)?;
-        if !key.is_empty() {
-            return Ok(key);
+fn read_or_create_stored_key() -> Result<String, Error> {
+    let service = match SecretService::connect(EncryptionType::Dh) {
+        Ok(service) => service,
+        Err(e) => {
+            log::warn!("无法连接 Secret Service，使用本地密钥文件: {}", e);
+            return read_or_create_key_file();
         }
+    };
+
+    if let Some(key) = keyring_read(&service)? {
+        return Ok(key);
     }
 
-    // 生成新密钥并保存
-    let key = generate_random_key();
-    if let Some(parent) = key_path.parent() {
-        std::fs::create_dir_all(parent)?;
+    // 将旧版本保存在文件中的密钥迁移到密钥环，确认写入成功后删除文件
+    let key_path = get_key_path()?;
+    if let Some(key) = read_key_file(&key_path)? {
+        keyring_write(&service, &key)?;
+        if keyring_read(&service)?.as_deref() == Some(key.as_str()) {
+            std::fs::remove_file(&key_path)?;
+        }
+        return Ok(key);
     }
-    let mut file = File::create(&key_path)?;
-    file.write_all(key.as_bytes())?;
-    
+
+    let key = create_new_key()?;
+    keyring_write(&service, &key)?;
     Ok(key)
 }
 
 #[cfg(not(windows))]
 pub fn delete_encryption_key() -> Result<(), Error> {
+    if let Ok(service) = SecretService::connect(EncryptionType::Dh) {
+        let items = service.search_items(keyring_attributes()).map_err(keyring_error)?;
+        for item in items.unlocked.iter().chain(items.locked.iter()) {
+            item.delete().map_err(keyring_error)?;
+        }
+    }
+
     let key_path = get_key_path()?;
     if key_path.exists() {
         std::fs::remove_file(key_path)?;
@@ -142,17 +229,142 @@ pub fn delete_encryption_key() -> Result<(), Error> {
     Ok(())
 }
 
+// 密钥环条目的查找属性
+#[cfg(not(windows))]
+fn keyring_attributes() -> HashMap<&'static str, &'static str> {
+    HashMap::from([("application", APP_ID), ("type", "database-key")])
+}
+
+#[cfg(not(windows))]
+fn keyring_error(e: secret_service::Error) -> Error {
+    Error::new(ErrorKind::Other, format!("访问密钥环失败: {}", e))
+}
+
+#[cfg(not(windows))]
+fn keyring_read(service: &SecretService) -> Result<Option<String>, Error> {
+    let items = service.search_items(keyring_attributes()).map_err(keyring_error)?;
+    let item = match items.unlocked.first().or_else(|| items.locked.first()) {
+        Some(item) => item,
+        None => return Ok(None),
+    };
+    // 密钥环被锁定时会弹出系统的解锁提示
+    item.ensure_unlocked().map_err(keyring_error)?;
+
+    let secret = item.get_secret().map_err(keyring_error)?;
+    let key = String::from_utf8(secret)
+        .map_err(|_| Error::new(ErrorKind::InvalidData, "密钥环中的密钥已损坏"))?;
+    Ok(Some(key).filter(|key| !key.is_empty()))
+}
+
+#[cfg(not(windows))]
+fn keyring_write(service: &SecretService, key: &str) -> Result<(), Error> {
+    let collection = service.get_any_collection().map_err(keyring_error)?;
+    collection.ensure_unlocked().map_err(keyring_error)?;
+    collection.create_item(
+        "StickyNotes 数据库密钥",
+        keyring_attributes(),
+        key.as_bytes(),
+        true,  // 替换已有条目
+        "text/plain",
+    ).map_err(keyring_error)?;
+    Ok(())
+}
+
+#[cfg(not(windows))]
+fn read_key_file(key_path: &Path) -> Result<Option<String>, Error> {
+    match std::fs::read_to_string(key_path) {
+        Ok(key) if !key.is_empty() => Ok(Some(key)),
+        Ok(_) => Ok(None),
+        Err(e) if e.kind() == ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(e),
+    }
+}
+
+// 没有密钥环时的后备方案，文件只允许当前用户读写
+#[cfg(not(windows))]
+fn read_or_create_key_file() -> Result<String, Error> {
+    use std::fs::Permissions;
+    use std::os::unix::fs::PermissionsExt;
+    
+    let key_path = get_key_path()?;
+
+    // 尝试读取现有密钥，并修正旧版本创建的文件权限
+    if let Some(key) = read_key_file(&key_path)? {
+        std::fs::set_permissions(&key_path, Permissions::from_mode(0o600))?;
+        return Ok(key);
+    }
+
+    // 生成新密钥并保存
+    let key = create_new_key()?;
+    write_key_file(&key_path, &key)?;
+    
+    Ok(key)
+}
+
+#[cfg(not(windows))]
+fn write_key_file(key_path: &Path, key: &str) -> Result<(), Error> {
+    if let Some(parent) = key_path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    write_private_file(key_path, key)
+}
+
+// 写入只允许当前用户读写的文件，已有文件的权限也会被修正
+fn write_private_file(path: &Path, content: &str) -> Result<(), Error> {
+    use std::fs::OpenOptions;
+    use std::io::Write;
+
+    let mut options = OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+    let mut file = options.open(path)?;
+    #[cfg(unix)]
+    {
+        use std::fs::Permissions;
+        use std::os::unix::fs::PermissionsExt;
+        file.set_permissions(Permissions::from_mode(0o600))?;
+    }
+    file.write_all(content.as_bytes())
+}
+
+// 保存到密钥环，没有密钥环时保存到密钥文件
+#[cfg(not(windows))]
+fn write_stored_key(key: &str) -> Result<(), Error> {
+    match SecretService::connect(EncryptionType::Dh) {
+        Ok(service) => keyring_write(&service, key),
+        Err(_) => write_key_file(&get_key_path()?, key),
+    }
+}
+
 #[cfg(not(windows))]
 fn get_key_path() -> Result<PathBuf, Error> {
+    let mut path = get_config_dir()?;
+    path.push("encryption_key");
+    Ok(path)
+}
+
+fn get_config_dir() -> Result<PathBuf, Error> {
     let mut path = match dirs::config_dir() {
         Some(config_dir) => config_dir,
         None => return Err(Error::new(ErrorKind::NotFound, "无法找到配置目录")),
     };
     path.push("stickynotes");
-    path.push("encryption_key");
     Ok(path)
 }
 
+// 首次启动时生成数据库密钥。已有数据库却找不到密钥时（密钥环被重置、无法连接等）
+// 不能生成新密钥，否则原数据库再也打不开，新密钥还会覆盖掉之后可能恢复的旧密钥
+fn create_new_key() -> Result<String, Error> {
+    if db::database_exists()? {
+        return Err(Error::new(ErrorKind::NotFound, "找不到已有数据库的加密密钥，请检查系统密钥环"));
+    }
+    Ok(generate_random_key())
+}
+
 // 生成随机加密密钥
 fn generate_random_key() -> String {
     rand::thread_rng()
@@ -160,4 +372,440 @@ fn generate_random_key() -> String {
         .take(KEY_LENGTH)
         .map(char::from)
         .collect()
-} 
\ No newline at end of file
+} 
+
+// ======= 主密码模式 =======
+//
+// 数据库密钥仍是随机生成的，只是不再明文保存：主密码经 Argon2id 派生出包装密钥，
+// 用 AES-256-GCM 加密数据库密钥后写入 key_vault.json。恢复密钥另外包装一份，
+// 忘记主密码时可用它重设。修改主密码只需重新包装密钥，不用重新加密数据库。
+
+// 密钥库格式版本：版本 1 的两个包装共用一组 Argon2 参数，版本 2 起每个包装各自保存
+const VAULT_VERSION: u32 = 2;
+
+#[derive(Serialize, Deserialize)]
+struct KeyVault {
+    version: u32,
+    // 仅版本 1 使用，读取时会填入各个包装中
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    kdf: Option<KdfParams>,
+    password_slot: WrappedKey,  // 主密码包装的数据库密钥
+    recovery_slot: WrappedKey,  // 恢复密钥包装的数据库密钥
+}
+
+// Argon2id 参数，随密文一起保存，以后调整默认值也能解开旧数据
+#[derive(Serialize, Deserialize, Clone, Copy)]
+pub struct KdfParams {
+    pub memory_kib: u32,
+    pub iterations: u32,
+    pub parallelism: u32,
+}
+
+// 除 kdf 外的字段均为 base64 编码
+#[derive(Serialize, Deserialize)]
+struct WrappedKey {
+    // 修改主密码时只重新包装主密码这一份，两份包装的参数可能不同
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    kdf: Option<KdfParams>,
+    salt: String,
+    nonce: String,
+    ciphertext: String,
+}
+
+impl KdfParams {
+    pub fn current() -> Self {
+        KdfParams {
+            memory_kib: KDF_MEMORY_KIB,
+            iterations: KDF_ITERATIONS,
+            parallelism: KDF_PARALLELISM,
+        }
+    }
+}
+
+fn vault_path() -> Result<PathBuf, Error> {
+    let mut path = get_config_dir()?;
+    path.push(VAULT_FILENAME);
+    Ok(path)
+}
+
+pub fn master_password_enabled() -> Result<bool, Error> {
+    Ok(vault_path()?.exists())
+}
+
+// 启用主密码：包装当前的数据库密钥并删除明文保存的密钥，返回需要用户妥善保存的恢复密钥
+pub fn enable_master_password(password: &str) -> Result<String, Error> {
+    if master_password_enabled()? {
+        return Err(Error::new(ErrorKind::AlreadyExists, "已启用主密码"));
+    }
+
+    let key = read_or_create_stored_key()?;
+    let recovery_key = generate_recovery_key();
+    let vault = KeyVault {
+        version: VAULT_VERSION,
+        kdf: None,
+        password_slot: wrap_key(&key, password.as_bytes())?,
+        recovery_slot: wrap_key(&key, normalize_recovery_key(&recovery_key).as_bytes())?,
+    };
+    write_vault(&vault)?;
+
+    // 确认能解开后再删除原来的密钥，避免数据库无法再打开
+    if unwrap_key(&vault.password_slot, password.as_bytes())? != key {
+        std::fs::remove_file(vault_path()?)?;
+        return Err(Error::new(ErrorKind::Other, "主密码校验失败"));
+    }
+    delete_encryption_key().or_else(|e| if e.kind() == ErrorKind::NotFound { Ok(()) } else { Err(e) })?;
+
+    Ok(recovery_key)
+}
+
+// 用主密码解开数据库密钥
+pub fn unlock_with_password(password: &str) -> Result<String, Error> {
+    let vault = read_vault()?;
+    unwrap_key(&vault.password_slot, password.as_bytes())
+}
+
+// 修改主密码，只重新包装密钥，恢复密钥的包装及其参数保持不变
+pub fn change_master_password(current: &str, new_password: &str) -> Result<(), Error> {
+    let mut vault = read_vault()?;
+    let key = unwrap_key(&vault.password_slot, current.as_bytes())?;
+    vault.password_slot = wrap_key(&key, new_password.as_bytes())?;
+    write_vault(&vault)
+}
+
+// 忘记主密码时，用恢复密钥设置新的主密码
+pub fn reset_master_password(recovery_key: &str, new_password: &str) -> Result<(), Error> {
+    let mut vault = read_vault()?;
+    let normalized = normalize_recovery_key(recovery_key);
+    let key = unwrap_key(&vault.recovery_slot, normalized.as_bytes())
+        .map_err(|_| Error::new(ErrorKind::PermissionDenied, "恢复密钥错误"))?;
+    // 顺便用当前参数重新包装恢复密钥
+    vault.recovery_slot = wrap_key(&key, normalized.as_bytes())?;
+    vault.password_slot = wrap_key(&key, new_password.as_bytes())?;
+    write_vault(&vault)
+}
+
+fn read_vault() -> Result<KeyVault, Error> {
+    let content = std::fs::read_to_string(vault_path()?)
+        .map_err(|e| Error::new(e.kind(), format!("读取密钥库失败: {}", e)))?;
+    let mut vault: KeyVault = serde_json::from_str(&content)
+        .map_err(|e| Error::new(ErrorKind::InvalidData, format!("密钥库已损坏: {}", e)))?;
+
+    // 旧版本的共用参数转到各个包装中，下次写入时即为新格式
+    if let Some(kdf) = vault.kdf.take() {
+        vault.password_slot.kdf.get_or_insert(kdf);
+        vault.recovery_slot.kdf.get_or_insert(kdf);
+    }
+    vault.version = VAULT_VERSION;
+    Ok(vault)
+}
+
+fn write_vault(vault: &KeyVault) -> Result<(), Error> {
+    let content = serde_json::to_string_pretty(vault)
+        .map_err(|e| Error::new(ErrorKind::Other, format!("序列化密钥库失败: {}", e)))?;
+    write_vault_file(&content)
+}
+
+// 先写入临时文件再替换，避免写到一半时密钥库损坏
+fn write_vault_file(content: &str) -> Result<(), Error> {
+    let path = vault_path()?;
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    let temp_path = path.with_extension("json.tmp");
+    write_private_file(&temp_path, content)?;
+    std::fs::rename(temp_path, path)
+}
+
+// 由密码派生 256 位密钥，也用于加密备份文件
+pub fn derive_wrapping_key(secret: &[u8], salt: &[u8], kdf: KdfParams) -> Result<[u8; 32], Error> {
+    let params = Params::new(kdf.memory_kib, kdf.iterations, kdf.parallelism, Some(32))
+        .map_err(|e| Error::new(ErrorKind::InvalidData, format!("密钥派生参数无效: {}", e)))?;
+    let mut output = [0u8; 32];
+    Argon2::new(Algorithm::Argon2id, Version::V0x13, params)
+        .hash_password_into(secret, salt, &mut output)
+        .map_err(|e| Error::new(ErrorKind::Other, format!("密钥派生失败: {}", e)))?;
+    Ok(output)
+}
+
+// 使用当前的 Argon2 参数包装，参数随包装一起保存
+fn wrap_key(key: &str, secret: &[u8]) -> Result<WrappedKey, Error> {
+    let kdf = KdfParams::current();
+    let mut salt = [0u8; SALT_LENGTH];
+    OsRng.fill_bytes(&mut salt);
+    let wrapping_key = derive_wrapping_key(secret, &salt, kdf)?;
+
+    let cipher = Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(&wrapping_key));
+    let nonce = Aes256Gcm::generate_nonce(&mut OsRng);
+    let ciphertext = cipher.encrypt(&nonce, key.as_bytes())
+        .map_err(|_| Error::new(ErrorKind::Other, "加密数据库密钥失败"))?;
+
+    Ok(WrappedKey {
+        kdf: Some(kdf),
+        salt: base64::encode(salt),
+        nonce: base64::encode(nonce),
+        ciphertext: base64::encode(ciphertext),
+    })
+}
+
+fn unwrap_key(wrapped: &WrappedKey, secret: &[u8]) -> Result<String, Error> {
+    let kdf = wrapped.kdf
+        .ok_or_else(|| Error::new(ErrorKind::InvalidData, "密钥库已损坏: 缺少密钥派生参数"))?;
+    let decode = |value: &str| base64::decode(value)
+        .map_err(|e| Error::new(ErrorKind::InvalidData, format!("密钥库已损坏: {}", e)));
+    let salt = decode(&wrapped.salt)?;
+    let nonce = decode(&wrapped.nonce)?;
+    let ciphertext = decode(&wrapped.ciphertext)?;
+    if nonce.len() != 12 {
+        return Err(Error::new(ErrorKind::InvalidData, "密钥库已损坏: nonce 长度错误"));
+    }
+
+    let wrapping_key = derive_wrapping_key(secret, &salt, kdf)?;
+    let cipher = Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(&wrapping_key));
+    // 认证失败说明密码错误（或文件被篡改）
+    let key = cipher.decrypt(Nonce::from_slice(&nonce), ciphertext.as_ref())
+        .map_err(|_| Error::new(ErrorKind::PermissionDenied, "主密码错误"))?;
+    String::from_utf8(key).map_err(|_| Error::new(ErrorKind::InvalidData, "密钥库已损坏"))
+}
+
+// 生成形如 1A2B-3C4D-... 的恢复密钥，便于打印和手动输入
+fn generate_recovery_key() -> String {
+    let mut bytes = [0u8; RECOVERY_KEY_BYTES];
+    OsRng.fill_bytes(&mut bytes);
+    let hex: String = bytes.iter().map(|b| format!("{:02X}", b)).collect();
+    hex.as_bytes()
+        .chunks(4)
+        .map(|chunk| String::from_utf8_lossy(chunk).into_owned())
+        .collect::<Vec<_>>()
+        .join("-")
+}
+
+// 输入时忽略分隔符、空白和大小写
+fn normalize_recovery_key(recovery_key: &str) -> String {
+    recovery_key.chars()
+        .filter(char::is_ascii_alphanumeric)
+        .map(|c| c.to_ascii_uppercase())
+        .collect()
+}
+
+// ======= 密钥轮换 =======
+
+// 轮换前的密钥存储状态，轮换失败时用于恢复
+pub enum StoredKeyBackup {
+    Plain(String),  // 凭据管理器、密钥环或密钥文件中的旧密钥
+    Vault(String),  // 启用主密码时旧密钥库文件的内容
+}
+
+pub fn generate_encryption_key() -> String {
+    generate_random_key()
+}
+
+pub fn backup_stored_key(current_key: &str) -> Result<StoredKeyBackup, Error> {
+    if master_password_enabled()? {
+        Ok(StoredKeyBackup::Vault(std::fs::read_to_string(vault_path()?)?))
+    } else {
+        Ok(StoredKeyBackup::Plain(current_key.to_string()))
+    }
+}
+
+pub fn restore_stored_key(backup: StoredKeyBackup) -> Result<(), Error> {
+    match backup {
+        StoredKeyBackup::Plain(key) => write_stored_key(&key),
+        StoredKeyBackup::Vault(content) => write_vault_file(&content),
+    }
+}
+
+// 保存轮换后的新密钥。启用主密码时用主密码重新包装，
+// 旧的恢复密钥无法再解开新密钥，因此同时生成并返回新的恢复密钥
+pub fn save_rotated_key(new_key: &str, password: Option<&str>) -> Result<Option<String>, Error> {
+    if !master_password_enabled()? {
+        write_stored_key(new_key)?;
+        return Ok(None);
+    }
+
+    let password = password
+        .ok_or_else(|| Error::new(ErrorKind::PermissionDenied, "已启用主密码，需要输入主密码"))?;
+    let mut vault = read_vault()?;
+    unwrap_key(&vault.password_slot, password.as_bytes())?;
+
+    let recovery_key = generate_recovery_key();
+    vault.password_slot = wrap_key(new_key, password.as_bytes())?;
+    vault.recovery_slot = wrap_key(new_key, normalize_recovery_key(&recovery_key).as_bytes())?;
+    write_vault(&vault)?;
+    Ok(Some(recovery_key))
+}
+
+#[cfg(all(test, not(windows)))]
+pub mod test_support {
+    use super::*;
+    use std::ffi::OsString;
+
+    // 测试期间替换环境变量，离开作用域时恢复
+    pub struct EnvVar {
+        name: &'static str,
+        previous: Option<OsString>,
+    }
+
+    impl EnvVar {
+        fn set(name: &'static str, value: impl Into<OsString>) -> Self {
+            let previous = std::env::var_os(name);
+            std::env::set_var(name, value.into());
+            EnvVar { name, previous }
+        }
+    }
+
+    impl Drop for EnvVar {
+        fn drop(&mut self) {
+            match &self.previous {
+                Some(value) => std::env::set_var(self.name, value),
+                None => std::env::remove_var(self.name),
+            }
+        }
+    }
+
+    // dirs::config_dir 在 Linux 上使用 XDG_CONFIG_HOME
+    pub fn temp_config_dir() -> (tempfile::TempDir, EnvVar) {
+        let dir = tempfile::tempdir().unwrap();
+        let var = EnvVar::set("XDG_CONFIG_HOME", dir.path());
+        (dir, var)
+    }
+
+    // 指向不存在的会话总线，强制使用密钥文件
+    pub fn without_secret_service() -> EnvVar {
+        EnvVar::set("DBUS_SESSION_BUS_ADDRESS", "unix:path=/nonexistent/bus")
+    }
+
+    // 在不使用密钥环的情况下保存数据库密钥
+    pub fn store_key_file(key: &str) {
+        write_key_file(&get_key_path().unwrap(), key).unwrap();
+    }
+}
+
+#[cfg(all(test, not(windows)))]
+mod tests {
+    use super::*;
+    use super::test_support::*;
+    use crate::db::test_support::open_test_db;
+
+    #[test]
+    fn existing_database_without_key_is_an_error() {
+        let _db = open_test_db();
+        let (_config, _config_var) = temp_config_dir();
+        let _bus = without_secret_service();
+
+        let err = get_encryption_key().unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::NotFound);
+        assert!(!get_key_path().unwrap().exists());
+    }
+
+    #[test]
+    fn key_file_is_used_without_secret_service() {
+        let _db = open_test_db();
+        let (_config, _config_var) = temp_config_dir();
+        let _bus = without_secret_service();
+        store_key_file("stored-key");
+
+        assert_eq!(get_encryption_key().unwrap(), "stored-key");
+    }
+
+    #[test]
+    fn master_password_wraps_the_stored_key() {
+        let _db = open_test_db();
+        let (_config, _config_var) = temp_config_dir();
+        let _bus = without_secret_service();
+        store_key_file("stored-key");
+
+        let recovery_key = enable_master_password("master-pw").unwrap();
+        assert!(master_password_enabled().unwrap());
+        // 明文密钥在包装后删除，之后只能用主密码取得
+        assert!(!get_key_path().unwrap().exists());
+        assert_eq!(get_encryption_key().unwrap_err().kind(), ErrorKind::PermissionDenied);
+        assert_eq!(unlock_with_password("master-pw").unwrap(), "stored-key");
+        assert_eq!(unlock_with_password("wrong").unwrap_err().kind(), ErrorKind::PermissionDenied);
+        assert_eq!(normalize_recovery_key(&recovery_key).len(), RECOVERY_KEY_BYTES * 2);
+
+        assert_eq!(enable_master_password("again").unwrap_err().kind(), ErrorKind::AlreadyExists);
+    }
+
+    #[test]
+    fn changing_master_password_keeps_recovery_key() {
+        let _db = open_test_db();
+        let (_config, _config_var) = temp_config_dir();
+        let _bus = without_secret_service();
+        store_key_file("stored-key");
+        let recovery_key = enable_master_password("old-pw").unwrap();
+
+        assert_eq!(change_master_password("wrong", "new-pw").unwrap_err().kind(), ErrorKind::PermissionDenied);
+        change_master_password("old-pw", "new-pw").unwrap();
+        assert_eq!(unlock_with_password("old-pw").unwrap_err().kind(), ErrorKind::PermissionDenied);
+        assert_eq!(unlock_with_password("new-pw").unwrap(), "stored-key");
+
+        // 恢复密钥输入时不区分大小写，也不要求分隔符
+        let typed = recovery_key.replace('-', " ").to_lowercase();
+        reset_master_password(&typed, "reset-pw").unwrap();
+        assert_eq!(unlock_with_password("new-pw").unwrap_err().kind(), ErrorKind::PermissionDenied);
+        assert_eq!(unlock_with_password("reset-pw").unwrap(), "stored-key");
+    }
+
+    #[test]
+    fn wrong_recovery_key_is_rejected() {
+        let _db = open_test_db();
+        let (_config, _config_var) = temp_config_dir();
+        let _bus = without_secret_service();
+        store_key_file("stored-key");
+        enable_master_password("master-pw").unwrap();
+
+        let err = reset_master_password("0000-0000-0000-0000-0000-0000-0000-0000", "new-pw").unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::PermissionDenied);
+        assert_eq!(unlock_with_password("master-pw").unwrap(), "stored-key");
+    }
+
+    #[test]
+    fn version_one_vault_is_still_readable() {
+        let _db = open_test_db();
+        let (_config, _config_var) = temp_config_dir();
+        let _bus = without_secret_service();
+        store_key_file("stored-key");
+        enable_master_password("master-pw").unwrap();
+
+        // 旧版本把 kdf 参数保存在密钥库顶层
+        let mut vault: serde_json::Value = serde_json::from_str(&std::fs::read_to_string(vault_path().unwrap()).unwrap()).unwrap();
+        let kdf = vault["password_slot"]["kdf"].take();
+        vault["recovery_slot"].as_object_mut().unwrap().remove("kdf");
+        vault["password_slot"].as_object_mut().unwrap().remove("kdf");
+        vault["kdf"] = kdf;
+        vault["version"] = 1.into();
+        write_vault_file(&vault.to_string()).unwrap();
+
+        assert_eq!(unlock_with_password("master-pw").unwrap(), "stored-key");
+        change_master_password("master-pw", "new-pw").unwrap();
+        let vault: serde_json::Value = serde_json::from_str(&std::fs::read_to_string(vault_path().unwrap()).unwrap()).unwrap();
+        assert_eq!(vault["version"], VAULT_VERSION);
+        assert!(vault.get("kdf").is_none());
+        assert!(vault["recovery_slot"]["kdf"].is_object());
+    }
+
+    // 需要会话总线上有已解锁的密钥环，且会删除其中本应用的条目，只应在临时会话中运行：
+    //   dbus-run-session -- sh -c 'echo -n test | gnome-keyring-daemon --unlock --components=secrets \
+    //       && cargo test --features secret-service-tests keyring'
+    #[cfg(feature = "secret-service-tests")]
+    #[test]
+    fn keyring_migrates_key_file_and_keeps_missing_key_an_error() {
+        let _db = open_test_db();
+        let (_config, _config_var) = temp_config_dir();
+        let service = SecretService::connect(EncryptionType::Dh).unwrap();
+        delete_encryption_key().unwrap();
+
+        // 旧版本的密钥文件迁移到密钥环后删除
+        let key_path = get_key_path().unwrap();
+        write_key_file(&key_path, "legacy-key").unwrap();
+        assert_eq!(get_encryption_key().unwrap(), "legacy-key");
+        assert!(!key_path.exists());
+        assert_eq!(keyring_read(&service).unwrap().as_deref(), Some("legacy-key"));
+        assert_eq!(get_encryption_key().unwrap(), "legacy-key");
+
+        // 密钥环中的条目丢失后不会为已有数据库生成新密钥
+        delete_encryption_key().unwrap();
+        assert_eq!(get_encryption_key().unwrap_err().kind(), ErrorKind::NotFound);
+        assert_eq!(keyring_read(&service).unwrap(), None);
+    }
+}
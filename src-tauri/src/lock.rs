@@ -0,0 +1,343 @@
+use std::io::{Error as IoError, ErrorKind};
+use std::path::PathBuf;
+use std::sync::{Mutex, MutexGuard, PoisonError};
+use std::time::{Duration, Instant};
+use argon2::password_hash::{rand_core::OsRng, PasswordHash, PasswordHasher, PasswordVerifier, SaltString};
+use argon2::Argon2;
+use once_cell::sync::Lazy;
+use tauri::{AppHandle, Manager, Runtime};
+use crate::db;
+use crate::key_manager;
+use crate::note_window;
+use crate::settings::Settings;
+
+// 检查是否需要自动锁定的间隔（秒）
+const CHECK_INTERVAL_SECS: u64 = 15;
+
+// 解锁密码的最小长度
+const MIN_PASSPHRASE_CHARS: usize = 4;
+
+struct LockState {
+    locked: bool,
+    last_activity: Instant,  // 任意窗口中最近一次用户操作的时间
+}
+
+static LOCK_STATE: Lazy<Mutex<LockState>> = Lazy::new(|| Mutex::new(LockState {
+    locked: false,
+    last_activity: Instant::now(),
+}));
+
+// 锁状态只有两个简单字段，锁损坏时继续使用其中的数据
+fn state() -> MutexGuard<'static, LockState> {
+    LOCK_STATE.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+// 记录用户操作，重新开始计算空闲时间
+pub fn record_activity() {
+    state().last_activity = Instant::now();
+}
+
+pub fn is_locked() -> bool {
+    state().locked
+}
+
+// 启用主密码时应用以锁定状态启动，输入主密码后才能打开数据库
+pub fn lock_on_startup() -> Result<(), IoError> {
+    state().locked = true;
+    db::close_db()
+}
+
+// 锁定应用：关闭数据库连接并通知所有窗口隐藏便签内容
+pub fn lock<R: Runtime>(app: &AppHandle<R>) -> Result<(), IoError> {
+    if lock_database()? {
+        let _ = app.emit_all("app-locked", ());
+    }
+    Ok(())
+}
+
+// 校验密码后重新打开数据库并通知所有窗口
+pub fn unlock<R: Runtime>(app: &AppHandle<R>, passphrase: &str) -> Result<(), IoError> {
+    if unlock_database(passphrase)? {
+        let _ = app.emit_all("app-unlocked", ());
+    }
+    Ok(())
+}
+
+// 进入锁定状态并关闭数据库，已经锁定时返回 false
+fn lock_database() -> Result<bool, IoError> {
+    if !can_lock()? {
+        return Err(IoError::new(ErrorKind::NotFound, "请先设置解锁密码"));
+    }
+
+    {
+        let mut state = state();
+        if state.locked {
+            return Ok(false);
+        }
+        state.locked = true;
+    }
+
+    db::close_db()?;
+    Ok(true)
+}
+
+// 启用主密码时用主密码解开数据库密钥，否则校验解锁密码，未锁定时返回 false
+// 只重新打开连接池，迁移已在启动时完成；以锁定状态启动时无法在启动阶段回收附件，改为解锁后回收
+fn unlock_database(passphrase: &str) -> Result<bool, IoError> {
+    if !is_locked() {
+        return Ok(false);
+    }
+    if key_manager::master_password_enabled()? {
+        db::reopen_db(key_manager::unlock_with_password(passphrase)?)?;
+    } else if verify_passphrase(passphrase)? {
+        db::reopen_db(key_manager::get_encryption_key()?)?;
+    } else {
+        return Err(IoError::new(ErrorKind::PermissionDenied, "密码错误"));
+    }
+
+    if let Err(e) = db::collect_attachment_garbage() {
+        log::warn!("清理附件失败: {}", e);
+    }
+
+    let mut state = state();
+    state.locked = false;
+    state.last_activity = Instant::now();
+    Ok(true)
+}
+
+// 启动自动锁定服务，空闲时间超过设置中的 lock_timeout（分钟）后锁定
+pub fn start_auto_lock_service<R: Runtime>(app: AppHandle<R>) {
+    std::thread::spawn(move || loop {
+        std::thread::sleep(Duration::from_secs(CHECK_INTERVAL_SECS));
+
+        let security = Settings::load().unwrap_or_default().security;
+        if !security.auto_lock || security.lock_timeout == 0 {
+            continue;
+        }
+        // 没有设置解锁密码也没有启用主密码时无法锁定
+        match can_lock() {
+            Ok(true) => {}
+            Ok(false) => continue,
+            Err(e) => {
+                log::warn!("检查解锁密码失败: {}", e);
+                continue;
+            }
+        }
+
+        let idle = {
+            let state = state();
+            if state.locked {
+                continue;
+            }
+            state.last_activity.elapsed()
+        };
+        if idle >= Duration::from_secs(security.lock_timeout as u64 * 60) {
+            if let Err(e) = lock(&app) {
+                log::warn!("自动锁定失败: {}", e);
+            }
+        }
+    });
+}
+
+// ======= 解锁密码 =======
+
+// 密码以 Argon2 哈希（PHC 格式）保存在配置目录中
+fn passphrase_path() -> Result<PathBuf, IoError> {
+    let mut path = match dirs::config_dir() {
+        Some(config_dir) => config_dir,
+        None => return Err(IoError::new(ErrorKind::NotFound, "无法找到配置目录")),
+    };
+    path.push("stickynotes");
+    path.push("lock_passphrase");
+    Ok(path)
+}
+
+fn has_passphrase() -> Result<bool, IoError> {
+    Ok(passphrase_path()?.exists())
+}
+
+// 只有能够解锁时才允许锁定
+fn can_lock() -> Result<bool, IoError> {
+    Ok(key_manager::master_password_enabled()? || has_passphrase()?)
+}
+
+fn verify_passphrase(passphrase: &str) -> Result<bool, IoError> {
+    let stored = std::fs::read_to_string(passphrase_path()?)?;
+    let hash = PasswordHash::new(stored.trim())
+        .map_err(|e| IoError::new(ErrorKind::InvalidData, format!("解锁密码文件已损坏: {}", e)))?;
+    Ok(Argon2::default().verify_password(passphrase.as_bytes(), &hash).is_ok())
+}
+
+fn save_passphrase(passphrase: &str) -> Result<(), IoError> {
+    let salt = SaltString::generate(&mut OsRng);
+    let hash = Argon2::default()
+        .hash_password(passphrase.as_bytes(), &salt)
+        .map_err(|e| IoError::new(ErrorKind::Other, format!("计算密码哈希失败: {}", e)))?;
+
+    let path = passphrase_path()?;
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    std::fs::write(path, hash.to_string())
+}
+
+// ======= 命令 =======
+
+#[tauri::command]
+pub async fn lock_app(app: AppHandle) -> Result<bool, String> {
+    db::run_blocking(move || lock(&app)).await
+        .map(|_| true)
+        .map_err(|e| format!("锁定失败: {}", e))
+}
+
+#[tauri::command]
+pub async fn unlock_app(passphrase: String, app: AppHandle) -> Result<bool, String> {
+    db::run_blocking(move || {
+        unlock(&app, &passphrase)?;
+        // 以锁定状态启动时数据库尚未打开，上次打开的便签窗口在首次解锁后恢复
+        note_window::restore_open_windows(&app);
+        Ok(())
+    }).await
+        .map(|_| true)
+        .map_err(|e| format!("解锁失败: {}", e))
+}
+
+#[tauri::command]
+pub async fn get_lock_status() -> Result<bool, String> {
+    Ok(is_locked())
+}
+
+// 前端在键盘、鼠标操作时调用（自行节流），用于计算空闲时间
+#[tauri::command]
+pub async fn report_activity() -> Result<(), String> {
+    record_activity();
+    Ok(())
+}
+
+// 设置或修改解锁密码，已有密码时需要提供当前密码
+#[tauri::command]
+pub async fn set_lock_passphrase(current: Option<String>, new_passphrase: String) -> Result<bool, String> {
+    if new_passphrase.chars().count() < MIN_PASSPHRASE_CHARS {
+        return Err(format!("密码至少需要 {} 个字符", MIN_PASSPHRASE_CHARS));
+    }
+
+    db::run_blocking(move || {
+        if has_passphrase()? && !verify_passphrase(current.as_deref().unwrap_or(""))? {
+            return Err(IoError::new(ErrorKind::PermissionDenied, "当前密码错误"));
+        }
+        save_passphrase(&new_passphrase)
+    }).await
+        .map(|_| true)
+        .map_err(|e| format!("设置解锁密码失败: {}", e))
+}
+
+// ======= 主密码 =======
+
+// 启用主密码，返回恢复密钥（只显示这一次）
+#[tauri::command]
+pub async fn enable_master_password(password: String) -> Result<String, String> {
+    if password.chars().count() < MIN_PASSPHRASE_CHARS {
+        return Err(format!("密码至少需要 {} 个字符", MIN_PASSPHRASE_CHARS));
+    }
+    if is_locked() {
+        return Err("应用已锁定".to_string());
+    }
+
+    db::run_blocking(move || key_manager::enable_master_password(&password)).await
+        .map_err(|e| format!("启用主密码失败: {}", e))
+}
+
+#[tauri::command]
+pub async fn change_master_password(current: String, new_password: String) -> Result<bool, String> {
+    if new_password.chars().count() < MIN_PASSPHRASE_CHARS {
+        return Err(format!("密码至少需要 {} 个字符", MIN_PASSPHRASE_CHARS));
+    }
+
+    db::run_blocking(move || key_manager::change_master_password(&current, &new_password)).await
+        .map(|_| true)
+        .map_err(|e| format!("修改主密码失败: {}", e))
+}
+
+// 忘记主密码时用恢复密钥重设，之后用新密码解锁
+#[tauri::command]
+pub async fn reset_master_password(recovery_key: String, new_password: String) -> Result<bool, String> {
+    if new_password.chars().count() < MIN_PASSPHRASE_CHARS {
+        return Err(format!("密码至少需要 {} 个字符", MIN_PASSPHRASE_CHARS));
+    }
+
+    db::run_blocking(move || key_manager::reset_master_password(&recovery_key, &new_password)).await
+        .map(|_| true)
+        .map_err(|e| format!("重设主密码失败: {}", e))
+}
+
+#[tauri::command]
+pub async fn is_master_password_enabled() -> Result<bool, String> {
+    key_manager::master_password_enabled().map_err(|e| e.to_string())
+}
+
+#[cfg(all(test, not(windows)))]
+mod tests {
+    use super::*;
+    use crate::db::test_support::{open_test_db, text_note};
+    use crate::key_manager::test_support::*;
+
+    // 锁状态是全局的，测试前恢复为未锁定
+    fn reset_lock_state() {
+        state().locked = false;
+    }
+
+    #[test]
+    fn locking_requires_a_way_to_unlock() {
+        let _db = open_test_db();
+        let (_config, _config_var) = temp_config_dir();
+        reset_lock_state();
+
+        assert_eq!(lock_database().unwrap_err().kind(), ErrorKind::NotFound);
+        assert!(!is_locked());
+        assert!(db::get_note(1).is_ok());
+    }
+
+    #[test]
+    fn passphrase_unlocks_and_reopens_database() {
+        let _db = open_test_db();
+        let (_config, _config_var) = temp_config_dir();
+        let _bus = without_secret_service();
+        store_key_file("test-key");
+        reset_lock_state();
+        save_passphrase("1234").unwrap();
+        let id = db::save_note(&text_note("锁定前")).unwrap();
+
+        assert!(lock_database().unwrap());
+        assert!(is_locked());
+        assert!(matches!(db::get_note(id), Err(e) if e.kind() == ErrorKind::PermissionDenied));
+        // 重复锁定不会再次通知窗口
+        assert!(!lock_database().unwrap());
+
+        assert_eq!(unlock_database("4321").unwrap_err().kind(), ErrorKind::PermissionDenied);
+        assert!(is_locked());
+
+        assert!(unlock_database("1234").unwrap());
+        assert!(!is_locked());
+        assert_eq!(db::get_note(id).unwrap().unwrap().content, "锁定前");
+        assert!(!unlock_database("1234").unwrap());
+    }
+
+    #[test]
+    fn master_password_unlocks_instead_of_passphrase() {
+        let _db = open_test_db();
+        let (_config, _config_var) = temp_config_dir();
+        let _bus = without_secret_service();
+        store_key_file("test-key");
+        reset_lock_state();
+        save_passphrase("1234").unwrap();
+        key_manager::enable_master_password("master-pw").unwrap();
+        let id = db::save_note(&text_note("主密码")).unwrap();
+
+        assert!(lock_database().unwrap());
+        assert_eq!(unlock_database("1234").unwrap_err().kind(), ErrorKind::PermissionDenied);
+        assert!(is_locked());
+
+        assert!(unlock_database("master-pw").unwrap());
+        assert_eq!(db::get_note(id).unwrap().unwrap().content, "主密码");
+    }
+}
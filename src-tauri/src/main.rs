@@ -5,20 +5,35 @@
 )]
 
 mod key_manager;
+mod attachments;
 mod db;
+mod checklist;
+mod migrations;
+mod store;
+#[cfg(test)] // 内存存储，仅供测试命令逻辑使用
+mod memory_store;
+mod render;
 mod tray;
+mod note_window;
 mod shortcut;
 mod commands;
 mod screenshot;
 mod settings;
 mod reminder;
+mod recurrence;
 mod logger;
+mod lock;
+mod backup;
+mod auto_backup;
+mod export;
+mod importer;
 
 use std::sync::Mutex;
 use once_cell::sync::Lazy;
 use tauri::{AppHandle, Manager, CustomMenuItem, SystemTray, SystemTrayEvent, SystemTrayMenu, SystemTrayMenuItem};
 use window_shadows::set_shadow;
 use key_manager::KeyManager;
+use store::{SqliteStore, StoreState};
 
 static DB_CONNECTION: Lazy<Mutex<Option<rusqlite::Connection>>> = Lazy::new(|| Mutex::new(None));
 
@@ -42,6 +57,32 @@ fn main() {
                 eprintln!("日志系统初始化失败: {}", e);
             }
             
+            // 初始化数据库并注册存储，启用主密码时以锁定状态启动，解锁后再打开数据库
+            if key_manager::master_password_enabled()? {
+                lock::lock_on_startup()?;
+            } else {
+                db::init_db()?;
+                // 清理不再被引用的附件
+                if let Err(e) = db::collect_attachment_garbage() {
+                    log::warn!("清理附件失败: {}", e);
+                }
+            }
+            
+            // 应用历史版本保留数量设置
+            match settings::Settings::load() {
+                Ok(settings) => db::set_revision_limit(settings.history.max_revisions as i64),
+                Err(e) => log::warn!("读取设置失败: {}", e),
+            }
+            app.manage(StoreState::new(SqliteStore));
+            reminder::start_reminder_service(app.handle());
+            lock::start_auto_lock_service(app.handle());
+            auto_backup::start_auto_backup_service(app.handle());
+            
+            // 恢复上次退出时打开的便签窗口，锁定启动时在解锁后恢复
+            if !lock::is_locked() {
+                note_window::restore_open_windows(&app.handle());
+            }
+            
             // 创建系统托盘
             let tray = tray::create_tray();
             app.manage(tray);
@@ -61,6 +102,12 @@ fn main() {
             Ok(())
         })
         .system_tray(tray)
+        .on_window_event(|event| {
+            // 任意窗口获得焦点都视为用户操作，重新计算自动锁定的空闲时间
+            if let tauri::WindowEvent::Focused(true) = event.event() {
+                lock::record_activity();
+            }
+        })
         .on_system_tray_event(tray::handle_tray_event)
         .invoke_handler(tauri::generate_handler![
             run_app,
@@ -70,28 +117,110 @@ fn main() {
             shortcut::check_shortcut,
             shortcut::update_shortcut_command,
             // 便签管理命令
-            commands::add_note,
+            commands::create_note,
             commands::update_note,
             commands::get_note_by_id,
-            commands::get_notes,
-            commands::delete_note,
-            commands::toggle_pin_note,
-            commands::change_note_color,
-            commands::move_note_to_category,
+            commands::get_notes_sorted,
+            commands::toggle_note_pin,
+            commands::update_note_color,
+            commands::update_note_category,
+            commands::search_notes,
+            commands::render_content,
+            commands::render_note_html,
+            commands::open_link,
+            // 锁定相关命令
+            lock::lock_app,
+            lock::unlock_app,
+            lock::get_lock_status,
+            lock::report_activity,
+            lock::set_lock_passphrase,
+            lock::enable_master_password,
+            lock::change_master_password,
+            lock::reset_master_password,
+            lock::is_master_password_enabled,
+            commands::rotate_encryption_key,
+            // 备份相关命令
+            backup::create_backup_archive,
+            backup::inspect_backup_archive,
+            backup::restore_backup_archive,
+            auto_backup::get_backup_status,
+            auto_backup::run_backup_now,
+            // 导出相关命令
+            export::export_notes,
+            // 导入相关命令
+            importer::import_microsoft_sticky_notes,
+            importer::import_google_keep_takeout,
+            importer::import_evernote_enex,
+            // 便签窗口相关命令
+            note_window::create_note_window,
+            note_window::open_note_window,
+            note_window::close_note_window,
+            note_window::get_note_window_state,
+            note_window::update_note_window_options,
+            // 归档相关命令
+            commands::archive_notes,
+            commands::unarchive_notes,
+            commands::get_archived_notes,
+            // 清单相关命令
+            commands::get_checklist_items,
+            commands::add_checklist_item,
+            commands::update_checklist_item,
+            commands::toggle_checklist_item,
+            commands::reorder_checklist_items,
+            commands::delete_checklist_item,
+            commands::convert_note_to_checklist,
+            // 链接相关命令
+            commands::get_note_links,
+            commands::get_note_backlinks,
+            // 回收站相关命令
+            commands::move_notes_to_trash,
+            commands::restore_notes_from_trash,
+            commands::permanently_delete_notes,
+            commands::get_trash_notes,
+            commands::cleanup_trash,
+            // 标签相关命令
+            commands::add_note_tag,
+            commands::remove_note_tag,
+            commands::get_note_tags,
+            commands::get_all_tags,
+            commands::rename_tag,
+            commands::merge_tags,
+            // 历史版本相关命令
+            commands::get_note_revisions,
+            commands::diff_note_revisions,
+            commands::restore_note_revision,
             // 分类管理命令
             commands::create_category,
             commands::update_category,
             commands::get_all_categories,
             commands::delete_category,
+            commands::get_category_tree,
+            commands::move_category,
+            commands::get_notes_in_category,
+            commands::update_category_order,
+            commands::save_manual_order,
+            // 附件相关命令
+            commands::save_pasted_image,
+            commands::handle_file_drop,
+            commands::get_note_attachments,
+            commands::add_note_attachment,
+            commands::remove_note_attachment,
+            commands::collect_attachment_garbage,
             // 提醒相关命令
-            reminder::add_reminder,
-            reminder::update_reminder,
-            reminder::get_reminders_by_note,
-            reminder::get_all_reminders,
-            reminder::delete_reminder,
+            commands::add_reminder,
+            commands::update_reminder,
+            commands::get_reminders_by_note,
+            commands::get_pending_reminders,
+            commands::mark_reminder_triggered,
+            commands::get_upcoming_occurrences,
+            commands::preview_reminder_occurrences,
+            commands::delete_reminder,
             // 截图相关命令
             screenshot::save_screenshot_data,
             screenshot::cancel_screenshot,
+            // 设置相关命令
+            settings::get_settings,
+            settings::update_settings,
             // 日志相关命令
             logger::log_message
         ])
@@ -0,0 +1,1146 @@
+use crate::attachments;
+use crate::checklist;
+use crate::db::{self, Attachment, Category, ChecklistItem, ChecklistProgress, ChildCategoryAction, Note, NoteLink, NoteReminder, NoteRevision, NoteSortOption, NoteType, SearchResult, Tag, WikiLink};
+use crate::store::NoteStore;
+use std::cmp::Ordering;
+use std::collections::{BTreeMap, HashMap};
+use std::io::{Error as IoError, ErrorKind};
+use std::path::Path;
+use std::sync::{Mutex, MutexGuard};
+
+// 内存存储，行为与 SqliteStore 保持一致，不读写任何文件
+// 附件只保存记录不保存内容，没有加密
+#[derive(Default)]
+pub struct MemoryStore {
+    data: Mutex<MemoryData>,
+}
+
+#[derive(Default)]
+struct MemoryData {
+    notes: BTreeMap<i64, Note>,
+    categories: BTreeMap<i64, Category>,
+    reminders: BTreeMap<i64, NoteReminder>,
+    checklist_items: BTreeMap<i64, ChecklistItem>,
+    tags: BTreeMap<i64, String>,
+    note_tags: BTreeMap<(i64, i64), TagSource>,  // (便签ID, 标签ID)
+    revisions: BTreeMap<i64, NoteRevision>,
+    attachments: BTreeMap<i64, Attachment>,
+    note_attachments: Vec<(i64, i64)>,           // (便签ID, 附件ID)，按关联顺序
+    links: Vec<StoredLink>,                      // 按来源便签中出现的顺序
+    last_id: i64,
+}
+
+// 与 note_links 相同，保存便签时解析目标便签
+struct StoredLink {
+    source_id: i64,
+    target_id: Option<i64>,
+    target_title: String,
+}
+
+// 便签标签的来源，与 note_tags.source 相同
+#[derive(Clone, Copy, PartialEq)]
+enum TagSource {
+    Inline,  // 内容中的 #标签
+    Manual,  // 手动添加
+}
+
+impl MemoryData {
+    fn next_id(&mut self) -> i64 {
+        self.last_id += 1;
+        self.last_id
+    }
+
+    // 删除便签及其提醒、清单条目、标签、历史版本和附件关联
+    fn remove_note(&mut self, id: i64) {
+        self.reminders.retain(|_, r| r.note_id != id);
+        self.checklist_items.retain(|_, item| item.note_id != id);
+        self.note_tags.retain(|&(note_id, _), _| note_id != id);
+        self.revisions.retain(|_, revision| revision.note_id != id);
+        self.note_attachments.retain(|&(note_id, _)| note_id != id);
+        self.links.retain(|link| link.source_id != id);
+        for link in self.links.iter_mut().filter(|link| link.target_id == Some(id)) {
+            link.target_id = None;
+        }
+        self.notes.remove(&id);
+    }
+
+    // 分类及其所有子孙分类的ID（包含自身）
+    fn category_subtree_ids(&self, id: i64) -> Vec<i64> {
+        let mut ids = vec![id];
+        let mut i = 0;
+        while i < ids.len() {
+            let parent = ids[i];
+            for category in self.categories.values() {
+                if let Some(child_id) = category.id {
+                    if category.parent_id == Some(parent) && !ids.contains(&child_id) {
+                        ids.push(child_id);
+                    }
+                }
+            }
+            i += 1;
+        }
+        ids
+    }
+
+    // 重新写入便签的链接，并解析每个链接的目标便签，与 db::write_note_links 一致
+    fn write_note_links(&mut self, note_id: i64, content: &str) {
+        self.links.retain(|link| link.source_id != note_id);
+        for link in db::parse_wiki_links(content) {
+            let (target_id, target_title) = match link {
+                WikiLink::Id(id) => (Some(id).filter(|id| self.notes.contains_key(id)), format!("#{}", id)),
+                WikiLink::Title(title) => {
+                    let lower = title.to_lowercase();
+                    let target_id = self.notes.values()
+                        .filter(|n| n.deleted_at.is_none() && db::note_title(&n.content).to_lowercase() == lower)
+                        .min_by_key(|n| n.created_at)
+                        .and_then(|n| n.id);
+                    (target_id, title)
+                }
+            };
+            self.links.push(StoredLink { source_id: note_id, target_id, target_title });
+        }
+    }
+
+    // 同步便签的链接：重写自身的链接，标题变化时更新其他便签中指向它的链接，
+    // 并让之前找不到目标、标题与之相同的链接指向它，与 db::sync_note_links 一致
+    fn sync_note_links(&mut self, note_id: i64, content: &str, old_title: Option<&str>) {
+        self.write_note_links(note_id, content);
+        let new_title = db::note_title(content).to_string();
+
+        if let Some(old_title) = old_title.filter(|t| !t.is_empty() && *t != new_title) {
+            let old_lower = old_title.to_lowercase();
+            let points_here = |link: &StoredLink| link.target_id == Some(note_id) && link.target_title.to_lowercase() == old_lower;
+
+            if !new_title.is_empty() {
+                let mut sources: Vec<i64> = self.links.iter()
+                    .filter(|link| points_here(link) && link.source_id != note_id)
+                    .map(|link| link.source_id)
+                    .collect();
+                sources.sort();
+                sources.dedup();
+                for source_id in sources {
+                    if let Some(source) = self.notes.get(&source_id) {
+                        let content = db::replace_wiki_link(&source.content, old_title, &new_title);
+                        self.rewrite_note_content(source_id, &content);
+                    }
+                }
+
+                for link in self.links.iter_mut().filter(|link| points_here(link)) {
+                    link.target_title = new_title.clone();
+                }
+            }
+        }
+
+        if !new_title.is_empty() {
+            let new_lower = new_title.to_lowercase();
+            for link in self.links.iter_mut().filter(|link| link.target_id.is_none() && link.target_title.to_lowercase() == new_lower) {
+                link.target_id = Some(note_id);
+            }
+        }
+    }
+
+    fn note_link(&self, link: &StoredLink) -> Option<NoteLink> {
+        let source = self.notes.get(&link.source_id)?;
+        let target = link.target_id.and_then(|id| self.notes.get(&id));
+        Some(NoteLink {
+            source_id: link.source_id,
+            source_title: db::note_title(&source.content).to_string(),
+            target_id: link.target_id,
+            target_title: link.target_title.clone(),
+            dangling: target.map_or(true, |n| n.deleted_at.is_some()),
+        })
+    }
+
+    // 创建或更新便签，标题变化时改写按旧标题链接到该便签的其他便签
+    fn save_note(&mut self, note: &Note) -> Result<i64, IoError> {
+        match note.id {
+            None => {
+                let id = self.next_id();
+                // 手动排序时新便签排在所在分类的最前面
+                let position = self.notes.values()
+                    .filter(|n| n.category_id == note.category_id)
+                    .map(|n| n.position)
+                    .min()
+                    .unwrap_or(0) - 1;
+                let mut new_note = note.clone();
+                new_note.id = Some(id);
+                new_note.position = position;
+                self.notes.insert(id, new_note);
+                self.link_screenshot_attachment(id, note.screenshot_path.as_deref());
+                self.sync_inline_tags(id, &note.content);
+                self.sync_note_links(id, &note.content, None);
+                Ok(id)
+            }
+            Some(id) => {
+                let old_title = match self.notes.get(&id) {
+                    Some(existing) => db::note_title(&existing.content).to_string(),
+                    None => return Err(IoError::new(ErrorKind::NotFound, "便签不存在")),
+                };
+
+                self.record_revision(id, &note.content);
+                if let Some(existing) = self.notes.get_mut(&id) {
+                    let position = existing.position;
+                    let created_at = existing.created_at;
+                    *existing = note.clone();
+                    existing.position = position;
+                    existing.created_at = created_at;
+                }
+
+                self.link_screenshot_attachment(id, note.screenshot_path.as_deref());
+                self.sync_inline_tags(id, &note.content);
+                self.sync_note_links(id, &note.content, Some(&old_title));
+                Ok(id)
+            }
+        }
+    }
+
+    // 内容有变化时保存旧版本，并清理超出保留数量的旧版本
+    fn record_revision(&mut self, note_id: i64, new_content: &str) {
+        let limit = db::revision_limit();
+        if limit <= 0 {
+            return;
+        }
+
+        let (content, created_at) = match self.notes.get(&note_id) {
+            Some(note) if note.content != new_content => (note.content.clone(), note.updated_at.unwrap_or(note.created_at)),
+            _ => return,
+        };
+        let id = self.next_id();
+        self.revisions.insert(id, NoteRevision { id, note_id, content, created_at });
+
+        let ids: Vec<i64> = self.revisions.values()
+            .filter(|revision| revision.note_id == note_id)
+            .map(|revision| revision.id)
+            .collect();
+        for id in &ids[..ids.len().saturating_sub(limit as usize)] {
+            self.revisions.remove(id);
+        }
+    }
+
+    // 自动改写便签内容（标签重命名、链接标题变化），与保存便签一样记录历史版本并更新修改时间，
+    // 改写可能改变便签标题，同样更新指向它的链接
+    fn rewrite_note_content(&mut self, note_id: i64, content: &str) {
+        let old_title = match self.notes.get(&note_id) {
+            Some(note) => db::note_title(&note.content).to_string(),
+            None => return,
+        };
+        self.record_revision(note_id, content);
+        if let Some(note) = self.notes.get_mut(&note_id).filter(|note| note.content != content) {
+            note.content = content.to_string();
+            note.updated_at = Some(chrono::Utc::now().timestamp());
+        }
+        self.sync_note_links(note_id, content, Some(&old_title));
+    }
+
+    // 按名称查找标签，不区分大小写
+    fn find_tag(&self, name: &str) -> Option<i64> {
+        let name = name.to_lowercase();
+        self.tags.iter().find(|(_, tag)| tag.to_lowercase() == name).map(|(id, _)| *id)
+    }
+
+    // 获取标签ID，不存在时创建
+    fn ensure_tag(&mut self, name: &str) -> i64 {
+        match self.find_tag(name) {
+            Some(id) => id,
+            None => {
+                let id = self.next_id();
+                self.tags.insert(id, name.to_string());
+                id
+            }
+        }
+    }
+
+    // 根据内容中的 #标签 更新便签的内联标签，手动添加的标签出现在内容中时改为内联来源，
+    // 其余手动添加的标签不受影响
+    fn sync_inline_tags(&mut self, note_id: i64, content: &str) {
+        let tag_ids: Vec<i64> = db::parse_hashtags(content).iter().map(|name| self.ensure_tag(name)).collect();
+        for &tag_id in &tag_ids {
+            self.note_tags.insert((note_id, tag_id), TagSource::Inline);
+        }
+        self.note_tags.retain(|&(id, tag_id), source| {
+            id != note_id || *source == TagSource::Manual || tag_ids.contains(&tag_id)
+        });
+    }
+
+    // 标签及使用该标签的未删除便签数量
+    fn tag_with_count(&self, tag_id: i64) -> Option<Tag> {
+        let name = self.tags.get(&tag_id)?;
+        let note_count = self.note_tags.keys()
+            .filter(|&&(note_id, id)| id == tag_id && self.notes.get(&note_id).map_or(false, |n| n.deleted_at.is_none()))
+            .count();
+        Some(Tag { id: tag_id, name: name.clone(), note_count: note_count as i64 })
+    }
+
+    // 按名称排列的标签
+    fn sorted_tags(&self, tag_ids: impl Iterator<Item = i64>) -> Vec<Tag> {
+        let mut tags: Vec<Tag> = tag_ids.filter_map(|id| self.tag_with_count(id)).collect();
+        tags.sort_by_key(|tag| tag.name.to_lowercase());
+        tags
+    }
+
+    // 将所有内容中包含该 #标签 的便签改为新名称，不论便签上的标签来源
+    fn rewrite_hashtags(&mut self, tag_id: i64, new_name: &str) {
+        let old_name = match self.tags.get(&tag_id) {
+            Some(name) => name.clone(),
+            None => return,
+        };
+        let rewritten: Vec<(i64, String)> = self.notes.iter()
+            .map(|(&id, note)| (id, db::replace_hashtag(&note.content, &old_name, new_name)))
+            .filter(|(id, content)| self.notes[id].content != *content)
+            .collect();
+
+        for (note_id, content) in rewritten {
+            self.rewrite_note_content(note_id, &content);
+        }
+    }
+
+    // 将多个标签合并到目标标签并删除原标签，任一标签不存在时不做任何修改
+    fn merge_tags(&mut self, source_ids: &[i64], target_id: i64) -> Result<(), IoError> {
+        let target_name = self.tags.get(&target_id).cloned()
+            .ok_or_else(|| IoError::new(ErrorKind::NotFound, "目标标签不存在"))?;
+        if source_ids.iter().any(|id| !self.tags.contains_key(id)) {
+            return Err(IoError::new(ErrorKind::NotFound, "标签不存在"));
+        }
+
+        for &source_id in source_ids.iter().filter(|&&id| id != target_id) {
+            self.rewrite_hashtags(source_id, &target_name);
+            let moved: Vec<(i64, TagSource)> = self.note_tags.iter()
+                .filter(|(&(_, id), _)| id == source_id)
+                .map(|(&(note_id, _), source)| (note_id, *source))
+                .collect();
+            // 两个标签都在便签上时，任一为内联来源则合并后也是内联来源
+            for (note_id, source) in moved {
+                let entry = self.note_tags.entry((note_id, target_id)).or_insert(source);
+                if source == TagSource::Inline {
+                    *entry = TagSource::Inline;
+                }
+            }
+            self.note_tags.retain(|&(_, id), _| id != source_id);
+            self.tags.remove(&source_id);
+        }
+        Ok(())
+    }
+
+    // 附件及引用它的便签数量
+    fn attachment_with_count(&self, attachment_id: i64) -> Option<Attachment> {
+        let mut attachment = self.attachments.get(&attachment_id)?.clone();
+        attachment.ref_count = self.note_attachments.iter().filter(|&&(_, id)| id == attachment_id).count() as i64;
+        Some(attachment)
+    }
+
+    fn attach_to_note(&mut self, note_id: i64, attachment_id: i64) {
+        if !self.note_attachments.contains(&(note_id, attachment_id)) {
+            self.note_attachments.push((note_id, attachment_id));
+        }
+    }
+
+    // 截图保存在附件存储中时关联到便签，与 SqliteStore 一致
+    fn link_screenshot_attachment(&mut self, note_id: i64, screenshot_path: Option<&str>) {
+        let hash = match screenshot_path.and_then(|path| attachments::hash_from_blob_path(Path::new(path))) {
+            Some(hash) => hash,
+            None => return,
+        };
+        let attachment_id = self.attachments.values()
+            .find(|attachment| attachment.hash == hash)
+            .map(|attachment| attachment.id);
+        if let Some(attachment_id) = attachment_id {
+            self.attach_to_note(note_id, attachment_id);
+        }
+    }
+
+    // 在清单末尾添加条目，返回条目ID
+    fn add_checklist_item(&mut self, note_id: i64, content: &str, done: bool, due_at: Option<i64>) -> i64 {
+        let id = self.next_id();
+        let position = self.checklist_items.values()
+            .filter(|item| item.note_id == note_id)
+            .map(|item| item.position)
+            .max()
+            .unwrap_or(-1) + 1;
+
+        self.checklist_items.insert(id, ChecklistItem {
+            id,
+            note_id,
+            content: content.to_string(),
+            done,
+            position,
+            due_at,
+            created_at: chrono::Utc::now().timestamp(),
+        });
+        id
+    }
+
+    // id 为 None 表示新建分类
+    fn check_category_parent(&self, id: Option<i64>, parent_id: Option<i64>) -> Result<(), IoError> {
+        let parent_id = match parent_id {
+            Some(parent_id) => parent_id,
+            None => return Ok(()),
+        };
+        if !self.categories.contains_key(&parent_id) {
+            return Err(IoError::new(ErrorKind::NotFound, "父分类不存在"));
+        }
+        if let Some(id) = id {
+            if self.category_subtree_ids(id).contains(&parent_id) {
+                return Err(IoError::new(ErrorKind::InvalidInput, "不能将分类移动到自身或其子分类下"));
+            }
+        }
+        Ok(())
+    }
+}
+
+impl MemoryStore {
+    pub fn new() -> Self {
+        MemoryStore::default()
+    }
+
+    fn lock(&self) -> Result<MutexGuard<'_, MemoryData>, IoError> {
+        self.data.lock()
+            .map_err(|_| IoError::new(ErrorKind::Other, "内存存储锁已损坏"))
+    }
+}
+
+// 与 NoteSortOption::order_by 相同的排序规则
+fn compare_notes(sort: &NoteSortOption, a: &Note, b: &Note) -> Ordering {
+    let updated = |note: &Note| note.updated_at.unwrap_or(note.created_at);
+
+    b.is_pinned.cmp(&a.is_pinned).then_with(|| match sort {
+        NoteSortOption::CreatedTimeDesc => b.created_at.cmp(&a.created_at),
+        NoteSortOption::CreatedTimeAsc => a.created_at.cmp(&b.created_at),
+        NoteSortOption::UpdatedTimeDesc => updated(b).cmp(&updated(a)),
+        NoteSortOption::UpdatedTimeAsc => updated(a).cmp(&updated(b)),
+        NoteSortOption::Manual => a.category_id.cmp(&b.category_id)
+            .then_with(|| a.position.cmp(&b.position))
+            .then_with(|| b.created_at.cmp(&a.created_at)),
+    })
+}
+
+impl NoteStore for MemoryStore {
+    fn save_note(&self, note: &Note) -> Result<i64, IoError> {
+        self.lock()?.save_note(note)
+    }
+
+    fn get_note(&self, id: i64) -> Result<Option<Note>, IoError> {
+        Ok(self.lock()?.notes.get(&id).cloned())
+    }
+
+    fn get_notes_sorted(
+        &self,
+        sort: &NoteSortOption,
+        limit: Option<i64>,
+        offset: Option<i64>,
+        search: Option<&str>,
+        tag_ids: &[i64],
+    ) -> Result<Vec<Note>, IoError> {
+        let data = self.lock()?;
+
+        let mut notes: Vec<Note> = data.notes.values()
+            .filter(|n| n.deleted_at.is_none() && n.archived_at.is_none())
+            .filter(|n| search.map_or(true, |search| db::search_matches(&n.content, search)))
+            // 与 SqliteStore 一致，只返回同时带有所有这些标签的便签
+            .filter(|n| tag_ids.iter().all(|tag_id| n.id.map_or(false, |id| data.note_tags.contains_key(&(id, *tag_id)))))
+            .cloned()
+            .collect();
+        notes.sort_by(|a, b| compare_notes(sort, a, b));
+
+        // 与 SQL 一致，只有指定 limit 时 offset 才生效
+        if let Some(limit) = limit {
+            let offset = offset.unwrap_or(0).max(0) as usize;
+            notes = notes.into_iter().skip(offset).take(limit.max(0) as usize).collect();
+        }
+
+        Ok(notes)
+    }
+
+    fn toggle_note_pin(&self, id: i64) -> Result<bool, IoError> {
+        let mut data = self.lock()?;
+        match data.notes.get_mut(&id) {
+            Some(note) => {
+                note.is_pinned = !note.is_pinned;
+                Ok(note.is_pinned)
+            }
+            None => Err(IoError::new(ErrorKind::NotFound, "便签不存在")),
+        }
+    }
+
+    fn archive_notes(&self, ids: &[i64], timestamp: i64) -> Result<(), IoError> {
+        let mut data = self.lock()?;
+        for id in ids {
+            if let Some(note) = data.notes.get_mut(id) {
+                note.archived_at.get_or_insert(timestamp);
+            }
+        }
+        Ok(())
+    }
+
+    fn unarchive_notes(&self, ids: &[i64]) -> Result<(), IoError> {
+        let mut data = self.lock()?;
+        for id in ids {
+            if let Some(note) = data.notes.get_mut(id) {
+                note.archived_at = None;
+            }
+        }
+        Ok(())
+    }
+
+    fn get_archived_notes(&self) -> Result<Vec<Note>, IoError> {
+        let data = self.lock()?;
+        let mut notes: Vec<Note> = data.notes.values()
+            .filter(|n| n.archived_at.is_some() && n.deleted_at.is_none())
+            .cloned()
+            .collect();
+        notes.sort_by(|a, b| b.archived_at.cmp(&a.archived_at));
+        Ok(notes)
+    }
+
+    fn get_checklist_items(&self, note_id: i64) -> Result<Vec<ChecklistItem>, IoError> {
+        let data = self.lock()?;
+        let mut items: Vec<ChecklistItem> = data.checklist_items.values()
+            .filter(|item| item.note_id == note_id)
+            .cloned()
+            .collect();
+        items.sort_by_key(|item| (item.position, item.id));
+        Ok(items)
+    }
+
+    fn add_checklist_item(&self, note_id: i64, content: &str, due_at: Option<i64>) -> Result<i64, IoError> {
+        Ok(self.lock()?.add_checklist_item(note_id, content, false, due_at))
+    }
+
+    fn update_checklist_item(&self, id: i64, content: &str, due_at: Option<i64>) -> Result<(), IoError> {
+        match self.lock()?.checklist_items.get_mut(&id) {
+            Some(item) => {
+                item.content = content.to_string();
+                item.due_at = due_at;
+                Ok(())
+            }
+            None => Err(IoError::new(ErrorKind::NotFound, "清单条目不存在")),
+        }
+    }
+
+    fn toggle_checklist_item(&self, id: i64) -> Result<bool, IoError> {
+        match self.lock()?.checklist_items.get_mut(&id) {
+            Some(item) => {
+                item.done = !item.done;
+                Ok(item.done)
+            }
+            None => Err(IoError::new(ErrorKind::NotFound, "清单条目不存在")),
+        }
+    }
+
+    fn reorder_checklist_items(&self, note_id: i64, item_ids: &[i64]) -> Result<(), IoError> {
+        let mut data = self.lock()?;
+        for (position, id) in item_ids.iter().enumerate() {
+            if let Some(item) = data.checklist_items.get_mut(id).filter(|item| item.note_id == note_id) {
+                item.position = position as i64;
+            }
+        }
+        Ok(())
+    }
+
+    fn delete_checklist_item(&self, id: i64) -> Result<(), IoError> {
+        self.lock()?.checklist_items.remove(&id);
+        Ok(())
+    }
+
+    fn get_checklist_progress(&self, note_ids: &[i64]) -> Result<HashMap<i64, ChecklistProgress>, IoError> {
+        let data = self.lock()?;
+        let mut progress: HashMap<i64, ChecklistProgress> = HashMap::new();
+        for item in data.checklist_items.values().filter(|item| note_ids.contains(&item.note_id)) {
+            let entry = progress.entry(item.note_id).or_default();
+            entry.total += 1;
+            if item.done {
+                entry.done += 1;
+            }
+        }
+        Ok(progress)
+    }
+
+    fn convert_to_checklist(&self, note_id: i64) -> Result<(), IoError> {
+        let mut data = self.lock()?;
+        let mut note = data.notes.get(&note_id).cloned()
+            .ok_or_else(|| IoError::new(ErrorKind::NotFound, "便签不存在"))?;
+        if note.note_type == NoteType::Checklist {
+            return Ok(());
+        }
+
+        let (remaining, items) = checklist::split_checklist_content(&note.content);
+        note.content = remaining;
+        note.note_type = NoteType::Checklist;
+        note.updated_at = Some(chrono::Utc::now().timestamp());
+        data.save_note(&note)?;
+
+        for (content, done) in items {
+            data.add_checklist_item(note_id, &content, done, None);
+        }
+        Ok(())
+    }
+
+    fn get_outgoing_links(&self, note_id: i64) -> Result<Vec<NoteLink>, IoError> {
+        let data = self.lock()?;
+        Ok(data.links.iter()
+            .filter(|link| link.source_id == note_id)
+            .filter_map(|link| data.note_link(link))
+            .collect())
+    }
+
+    fn get_backlinks(&self, note_id: i64) -> Result<Vec<NoteLink>, IoError> {
+        let data = self.lock()?;
+        // 每个来源便签只返回一次
+        let mut links: Vec<NoteLink> = Vec::new();
+        for link in data.links.iter().filter(|link| link.target_id == Some(note_id)) {
+            if links.iter().all(|l| l.source_id != link.source_id) {
+                links.extend(data.note_link(link));
+            }
+        }
+        links.retain(|l| data.notes.get(&l.source_id).map_or(false, |n| n.deleted_at.is_none()));
+        let updated = |id: i64| data.notes.get(&id).map(|n| n.updated_at.unwrap_or(n.created_at));
+        links.sort_by_key(|l| std::cmp::Reverse(updated(l.source_id)));
+        Ok(links)
+    }
+
+    fn move_note_to_trash(&self, id: i64, timestamp: i64) -> Result<(), IoError> {
+        match self.lock()?.notes.get_mut(&id) {
+            Some(note) => {
+                note.deleted_at = Some(timestamp);
+                Ok(())
+            }
+            None => Err(IoError::new(ErrorKind::NotFound, "便签不存在")),
+        }
+    }
+
+    fn restore_note_from_trash(&self, id: i64) -> Result<(), IoError> {
+        match self.lock()?.notes.get_mut(&id) {
+            Some(note) => {
+                note.deleted_at = None;
+                Ok(())
+            }
+            None => Err(IoError::new(ErrorKind::NotFound, "便签不存在")),
+        }
+    }
+
+    fn delete_note_permanently(&self, id: i64) -> Result<(), IoError> {
+        self.lock()?.remove_note(id);
+        Ok(())
+    }
+
+    fn get_trash_notes(&self) -> Result<Vec<Note>, IoError> {
+        let data = self.lock()?;
+        let mut notes: Vec<Note> = data.notes.values()
+            .filter(|n| n.deleted_at.is_some())
+            .cloned()
+            .collect();
+        notes.sort_by(|a, b| b.deleted_at.cmp(&a.deleted_at));
+        Ok(notes)
+    }
+
+    fn cleanup_trash(&self, days: i64) -> Result<i32, IoError> {
+        let threshold = chrono::Utc::now().timestamp() - (days * 24 * 60 * 60);
+        let mut data = self.lock()?;
+
+        // 归档的便签即使在回收站中也不自动清理
+        let expired: Vec<i64> = data.notes.values()
+            .filter(|n| n.deleted_at.map_or(false, |t| t < threshold) && n.archived_at.is_none())
+            .filter_map(|n| n.id)
+            .collect();
+
+        for id in &expired {
+            data.remove_note(*id);
+        }
+
+        Ok(expired.len() as i32)
+    }
+
+    fn save_category(&self, category: &Category) -> Result<i64, IoError> {
+        let mut data = self.lock()?;
+
+        match category.id {
+            None => {
+                data.check_category_parent(None, category.parent_id)?;
+                let id = data.next_id();
+                // 新分类排在同级分类的最后
+                let position = data.categories.values()
+                    .filter(|c| c.parent_id == category.parent_id)
+                    .map(|c| c.position)
+                    .max()
+                    .unwrap_or(-1) + 1;
+                let mut new_category = category.clone();
+                new_category.id = Some(id);
+                new_category.position = position;
+                data.categories.insert(id, new_category);
+                Ok(id)
+            }
+            Some(id) => {
+                data.check_category_parent(Some(id), category.parent_id)?;
+                match data.categories.get_mut(&id) {
+                    Some(existing) => {
+                        existing.name = category.name.clone();
+                        existing.color = category.color.clone();
+                        existing.parent_id = category.parent_id;
+                        Ok(id)
+                    }
+                    None => Err(IoError::new(ErrorKind::NotFound, "分类不存在")),
+                }
+            }
+        }
+    }
+
+    fn get_all_categories(&self) -> Result<Vec<Category>, IoError> {
+        let data = self.lock()?;
+        let mut categories: Vec<Category> = data.categories.values().cloned().collect();
+        categories.sort_by(|a, b| a.position.cmp(&b.position).then_with(|| a.name.cmp(&b.name)));
+        Ok(categories)
+    }
+
+    fn move_category(&self, id: i64, parent_id: Option<i64>) -> Result<(), IoError> {
+        let mut data = self.lock()?;
+        data.check_category_parent(Some(id), parent_id)?;
+        match data.categories.get_mut(&id) {
+            Some(category) => {
+                category.parent_id = parent_id;
+                Ok(())
+            }
+            None => Err(IoError::new(ErrorKind::NotFound, "分类不存在")),
+        }
+    }
+
+    fn delete_category(&self, id: i64, children: ChildCategoryAction) -> Result<(), IoError> {
+        let mut data = self.lock()?;
+
+        let removed_ids = match children {
+            ChildCategoryAction::Reparent => {
+                let parent_id = data.categories.get(&id).and_then(|c| c.parent_id);
+                for category in data.categories.values_mut() {
+                    if category.parent_id == Some(id) {
+                        category.parent_id = parent_id;
+                    }
+                }
+                vec![id]
+            }
+            ChildCategoryAction::Delete => data.category_subtree_ids(id),
+        };
+
+        for removed_id in removed_ids {
+            for note in data.notes.values_mut() {
+                if note.category_id == Some(removed_id) {
+                    note.category_id = None;
+                }
+            }
+            data.categories.remove(&removed_id);
+        }
+
+        Ok(())
+    }
+
+    fn get_notes_in_category(&self, category_id: i64, include_descendants: bool, sort: &NoteSortOption) -> Result<Vec<Note>, IoError> {
+        let data = self.lock()?;
+        let category_ids = if include_descendants {
+            data.category_subtree_ids(category_id)
+        } else {
+            vec![category_id]
+        };
+
+        let mut notes: Vec<Note> = data.notes.values()
+            .filter(|n| n.deleted_at.is_none() && n.archived_at.is_none())
+            .filter(|n| n.category_id.map_or(false, |c| category_ids.contains(&c)))
+            .cloned()
+            .collect();
+        notes.sort_by(|a, b| compare_notes(sort, a, b));
+        Ok(notes)
+    }
+
+    fn save_manual_order(&self, category_ids: &[i64], note_ids: &[i64]) -> Result<(), IoError> {
+        let mut data = self.lock()?;
+
+        for (position, id) in category_ids.iter().enumerate() {
+            if let Some(category) = data.categories.get_mut(id) {
+                category.position = position as i64;
+            }
+        }
+        for (position, id) in note_ids.iter().enumerate() {
+            if let Some(note) = data.notes.get_mut(id) {
+                note.position = position as i64;
+            }
+        }
+
+        Ok(())
+    }
+
+    fn save_reminder(&self, reminder: &NoteReminder) -> Result<i64, IoError> {
+        let mut data = self.lock()?;
+
+        let id = match reminder.id {
+            Some(id) => id,
+            None => data.next_id(),
+        };
+        let mut saved = reminder.clone();
+        saved.id = Some(id);
+        data.reminders.insert(id, saved);
+
+        Ok(id)
+    }
+
+    fn get_reminders_by_note(&self, note_id: i64) -> Result<Vec<NoteReminder>, IoError> {
+        let data = self.lock()?;
+        let mut reminders: Vec<NoteReminder> = data.reminders.values()
+            .filter(|r| r.note_id == note_id)
+            .cloned()
+            .collect();
+        reminders.sort_by_key(|r| r.reminder_time);
+        Ok(reminders)
+    }
+
+    fn delete_reminder(&self, id: i64) -> Result<(), IoError> {
+        self.lock()?.reminders.remove(&id);
+        Ok(())
+    }
+
+    fn get_upcoming_reminders(&self, limit: Option<i64>) -> Result<Vec<(NoteReminder, Note)>, IoError> {
+        let data = self.lock()?;
+        let mut results: Vec<(NoteReminder, Note)> = data.reminders.values()
+            .filter(|r| !r.completed)
+            .filter_map(|r| {
+                data.notes.get(&r.note_id)
+                    .filter(|n| n.deleted_at.is_none())
+                    .map(|n| (r.clone(), n.clone()))
+            })
+            .collect();
+        results.sort_by_key(|(r, _)| r.reminder_time);
+
+        if let Some(limit) = limit {
+            results.truncate(limit.max(0) as usize);
+        }
+
+        Ok(results)
+    }
+
+    fn add_tag_to_note(&self, note_id: i64, name: &str) -> Result<i64, IoError> {
+        let name = name.trim().trim_start_matches('#');
+        if name.is_empty() {
+            return Err(IoError::new(ErrorKind::InvalidInput, "标签名不能为空"));
+        }
+
+        let mut data = self.lock()?;
+        let tag_id = data.ensure_tag(name);
+        data.note_tags.entry((note_id, tag_id)).or_insert(TagSource::Manual);
+        Ok(tag_id)
+    }
+
+    fn remove_tag_from_note(&self, note_id: i64, tag_id: i64) -> Result<(), IoError> {
+        self.lock()?.note_tags.remove(&(note_id, tag_id));
+        Ok(())
+    }
+
+    fn get_note_tags(&self, note_id: i64) -> Result<Vec<Tag>, IoError> {
+        let data = self.lock()?;
+        let tag_ids: Vec<i64> = data.note_tags.keys()
+            .filter(|&&(id, _)| id == note_id)
+            .map(|&(_, tag_id)| tag_id)
+            .collect();
+        Ok(data.sorted_tags(tag_ids.into_iter()))
+    }
+
+    fn get_all_tags(&self) -> Result<Vec<Tag>, IoError> {
+        let data = self.lock()?;
+        Ok(data.sorted_tags(data.tags.keys().copied()))
+    }
+
+    fn rename_tag(&self, id: i64, name: &str) -> Result<i64, IoError> {
+        let name = name.trim().trim_start_matches('#');
+        if name.is_empty() {
+            return Err(IoError::new(ErrorKind::InvalidInput, "标签名不能为空"));
+        }
+
+        let mut data = self.lock()?;
+        if !data.tags.contains_key(&id) {
+            return Err(IoError::new(ErrorKind::NotFound, "标签不存在"));
+        }
+        // 新名称已被其他标签使用时合并到该标签
+        if let Some(target_id) = data.find_tag(name).filter(|&target_id| target_id != id) {
+            data.merge_tags(&[id], target_id)?;
+            return Ok(target_id);
+        }
+
+        data.rewrite_hashtags(id, name);
+        data.tags.insert(id, name.to_string());
+        Ok(id)
+    }
+
+    fn merge_tags(&self, source_ids: &[i64], target_id: i64) -> Result<(), IoError> {
+        self.lock()?.merge_tags(source_ids, target_id)
+    }
+
+    fn get_note_revisions(&self, note_id: i64) -> Result<Vec<NoteRevision>, IoError> {
+        let data = self.lock()?;
+        Ok(data.revisions.values()
+            .rev()
+            .filter(|revision| revision.note_id == note_id)
+            .cloned()
+            .collect())
+    }
+
+    fn get_note_revision(&self, id: i64) -> Result<Option<NoteRevision>, IoError> {
+        Ok(self.lock()?.revisions.get(&id).cloned())
+    }
+
+    fn restore_note_revision(&self, revision_id: i64) -> Result<i64, IoError> {
+        let mut data = self.lock()?;
+        let revision = data.revisions.get(&revision_id).cloned()
+            .ok_or_else(|| IoError::new(ErrorKind::NotFound, "历史版本不存在"))?;
+        let mut note = data.notes.get(&revision.note_id).cloned()
+            .ok_or_else(|| IoError::new(ErrorKind::NotFound, "便签不存在"))?;
+
+        note.content = revision.content;
+        note.updated_at = Some(chrono::Utc::now().timestamp());
+        data.save_note(&note)
+    }
+
+    fn add_attachment(&self, bytes: &[u8], mime: &str, note_id: Option<i64>) -> Result<Attachment, IoError> {
+        let hash = attachments::hash_bytes(bytes);
+        let mut data = self.lock()?;
+
+        let now = chrono::Utc::now().timestamp();
+        // 已有相同内容时刷新创建时间，与 SqliteStore 一致
+        let existing = data.attachments.values_mut().find(|a| a.hash == hash);
+        let attachment_id = match existing {
+            Some(existing) => {
+                existing.created_at = now;
+                existing.id
+            }
+            None => {
+                let id = data.next_id();
+                data.attachments.insert(id, Attachment {
+                    id,
+                    hash,
+                    mime: mime.to_string(),
+                    extension: attachments::extension_for_mime(mime).to_string(),
+                    size: bytes.len() as i64,
+                    ref_count: 0,
+                    created_at: now,
+                });
+                id
+            }
+        };
+
+        if let Some(note_id) = note_id {
+            data.attach_to_note(note_id, attachment_id);
+        }
+        data.attachment_with_count(attachment_id)
+            .ok_or_else(|| IoError::new(ErrorKind::NotFound, "附件不存在"))
+    }
+
+    fn attach_to_note(&self, note_id: i64, attachment_id: i64) -> Result<(), IoError> {
+        self.lock()?.attach_to_note(note_id, attachment_id);
+        Ok(())
+    }
+
+    fn detach_from_note(&self, note_id: i64, attachment_id: i64) -> Result<(), IoError> {
+        self.lock()?.note_attachments.retain(|&link| link != (note_id, attachment_id));
+        Ok(())
+    }
+
+    fn get_note_attachments(&self, note_id: i64) -> Result<Vec<Attachment>, IoError> {
+        let data = self.lock()?;
+        Ok(data.note_attachments.iter()
+            .filter(|&&(id, _)| id == note_id)
+            .filter_map(|&(_, attachment_id)| data.attachment_with_count(attachment_id))
+            .collect())
+    }
+
+    fn collect_attachment_garbage(&self) -> Result<usize, IoError> {
+        let threshold = chrono::Utc::now().timestamp() - db::ATTACHMENT_GC_GRACE_SECS;
+        let mut data = self.lock()?;
+
+        let unreferenced: Vec<i64> = data.attachments.keys()
+            .filter_map(|&id| data.attachment_with_count(id))
+            .filter(|a| a.ref_count <= 0 && a.created_at < threshold)
+            .map(|a| a.id)
+            .collect();
+        for id in &unreferenced {
+            data.attachments.remove(id);
+        }
+        Ok(unreferenced.len())
+    }
+
+    fn search_notes(&self, query: &str, category_id: Option<i64>, limit: Option<i64>) -> Result<Vec<SearchResult>, IoError> {
+        let terms: Vec<&str> = query.split_whitespace().collect();
+        if terms.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        let data = self.lock()?;
+        let mut notes: Vec<&Note> = data.notes.values()
+            .filter(|n| n.deleted_at.is_none())
+            .filter(|n| category_id.map_or(true, |c| n.category_id == Some(c)))
+            .filter(|n| db::search_matches(&n.content, query))
+            .collect();
+        // 没有相关度评分，与 SqliteStore 无法使用索引时的排序相同
+        notes.sort_by(|a, b| compare_notes(&NoteSortOption::UpdatedTimeDesc, a, b));
+        if let Some(limit) = limit.filter(|&limit| limit >= 0) {
+            notes.truncate(limit as usize);
+        }
+
+        Ok(notes.into_iter().map(|note| SearchResult {
+            note: note.clone(),
+            snippet: db::search_snippet(&note.content, &terms),
+            rank: 0.0,
+        }).collect())
+    }
+
+    // 内存中的数据不加密，没有需要更换的密钥
+    fn rotate_encryption_key(&self, _password: Option<&str>) -> Result<Option<String>, IoError> {
+        Ok(None)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::test_support::{open_test_db, text_note};
+    use crate::store::SqliteStore;
+
+    // 同一组行为检查分别在 MemoryStore 和 SqliteStore 上运行
+    fn both_stores(check: impl Fn(&dyn NoteStore)) {
+        let _db = open_test_db();
+        check(&MemoryStore::new());
+        check(&SqliteStore);
+    }
+
+    fn content_of(store: &dyn NoteStore, id: i64) -> String {
+        store.get_note(id).unwrap().unwrap().content
+    }
+
+    fn tag_named(store: &dyn NoteStore, name: &str) -> i64 {
+        store.get_all_tags().unwrap().into_iter().find(|t| t.name == name).unwrap().id
+    }
+
+    #[test]
+    fn missing_ids_are_reported_as_not_found() {
+        both_stores(|store| {
+            let mut missing = text_note("不存在");
+            missing.id = Some(9999);
+            assert_eq!(store.save_note(&missing).unwrap_err().kind(), ErrorKind::NotFound);
+            assert_eq!(store.move_note_to_trash(9999, 1_700_000_000).unwrap_err().kind(), ErrorKind::NotFound);
+            assert_eq!(store.restore_note_from_trash(9999).unwrap_err().kind(), ErrorKind::NotFound);
+
+            let note_id = store.save_note(&text_note("周报 #工作")).unwrap();
+            let work = tag_named(store, "工作");
+            assert_eq!(store.rename_tag(9999, "其他").unwrap_err().kind(), ErrorKind::NotFound);
+            assert_eq!(store.rename_tag(work, " # ").unwrap_err().kind(), ErrorKind::InvalidInput);
+            assert_eq!(store.merge_tags(&[work, 9999], work).unwrap_err().kind(), ErrorKind::NotFound);
+            assert_eq!(store.merge_tags(&[work], 9999).unwrap_err().kind(), ErrorKind::NotFound);
+            assert_eq!(content_of(store, note_id), "周报 #工作");
+        });
+    }
+
+    #[test]
+    fn search_uses_the_same_matching_rules() {
+        both_stores(|store| {
+            let upper = store.save_note(&text_note("Ärger im Büro")).unwrap();
+            let lower = store.save_note(&text_note("ärger zu Hause")).unwrap();
+            let gym = store.save_note(&text_note("Go to the GYM")).unwrap();
+            let ids = |search: &str| {
+                let mut ids: Vec<i64> = store.get_notes_sorted(&NoteSortOption::CreatedTimeDesc, None, None, Some(search), &[]).unwrap()
+                    .into_iter()
+                    .filter_map(|n| n.id)
+                    .collect();
+                ids.sort();
+                ids
+            };
+
+            // 能使用 FTS 时忽略所有字母的大小写
+            assert_eq!(ids("ÄRGER"), vec![upper, lower]);
+            assert_eq!(ids("the gym"), vec![gym]);
+            // 关键词过短时与 LIKE 一样只忽略 ASCII 字母的大小写
+            assert_eq!(ids("Är"), vec![upper]);
+            assert_eq!(ids("go gy"), vec![gym]);
+            assert_eq!(ids("  "), vec![upper, lower, gym]);
+
+            let results = store.search_notes("Büro", None, None).unwrap();
+            assert_eq!(results.len(), 1);
+            assert!(results[0].snippet.contains("<mark>Büro</mark>"));
+        });
+    }
+
+    #[test]
+    fn links_are_stored_and_follow_title_changes() {
+        both_stores(|store| {
+            let target = store.save_note(&text_note("目标\n正文")).unwrap();
+            let source = store.save_note(&text_note("参见 [[目标]] 和 [[以后]]")).unwrap();
+
+            let links = store.get_outgoing_links(source).unwrap();
+            assert_eq!(links.len(), 2);
+            assert_eq!(links[0].target_id, Some(target));
+            assert!(!links[0].dangling);
+            assert_eq!(links[1].target_id, None);
+            assert!(links[1].dangling);
+
+            // 标题变化时改写链接
+            let mut note = store.get_note(target).unwrap().unwrap();
+            note.content = "新目标\n正文".to_string();
+            store.save_note(&note).unwrap();
+            assert_eq!(content_of(store, source), "参见 [[新目标]] 和 [[以后]]");
+            assert_eq!(store.get_backlinks(target).unwrap().iter().map(|l| l.source_id).collect::<Vec<_>>(), vec![source]);
+
+            // 标题变为空时链接仍指向原便签
+            note.content = "\n正文".to_string();
+            store.save_note(&note).unwrap();
+            assert_eq!(store.get_outgoing_links(source).unwrap()[0].target_id, Some(target));
+
+            // 之前找不到目标的链接指向新建的同名便签
+            let later = store.save_note(&text_note("以后")).unwrap();
+            assert_eq!(store.get_outgoing_links(source).unwrap()[1].target_id, Some(later));
+
+            store.move_note_to_trash(target, 1_700_000_000).unwrap();
+            assert!(store.get_outgoing_links(source).unwrap()[0].dangling);
+        });
+    }
+
+    #[test]
+    fn renaming_tag_in_title_rewrites_links() {
+        both_stores(|store| {
+            let target = store.save_note(&text_note("#项目 计划\n下周开始")).unwrap();
+            let source = store.save_note(&text_note("参见 [[项目 计划]]")).unwrap();
+
+            store.rename_tag(tag_named(store, "项目"), "工程").unwrap();
+
+            assert_eq!(content_of(store, target), "#工程 计划\n下周开始");
+            assert_eq!(content_of(store, source), "参见 [[工程 计划]]");
+            let backlinks = store.get_backlinks(target).unwrap();
+            assert_eq!(backlinks.len(), 1);
+            assert_eq!(backlinks[0].target_title, "工程 计划");
+        });
+    }
+
+    #[test]
+    fn manual_tags_typed_into_content_become_inline() {
+        both_stores(|store| {
+            let note_id = store.save_note(&text_note("计划")).unwrap();
+            store.add_tag_to_note(note_id, "计划").unwrap();
+
+            let mut note = store.get_note(note_id).unwrap().unwrap();
+            note.content = "#计划 下周".to_string();
+            store.save_note(&note).unwrap();
+            // 改为内联来源后，从内容中删除 #标签 会移除标签
+            note.content = "下周".to_string();
+            store.save_note(&note).unwrap();
+            assert!(store.get_note_tags(note_id).unwrap().is_empty());
+        });
+    }
+
+    #[test]
+    fn only_screenshots_in_attachment_store_are_linked() {
+        both_stores(|store| {
+            let attachment = store.add_attachment(b"screenshot", "image/png", None).unwrap();
+
+            let mut stored = text_note("截图");
+            stored.screenshot_path = Some(attachment.path().unwrap().to_string_lossy().to_string());
+            let stored_id = store.save_note(&stored).unwrap();
+            assert_eq!(store.get_note_attachments(stored_id).unwrap().len(), 1);
+
+            // 文件名相同但不在附件存储目录中
+            let mut outside = text_note("旧截图");
+            outside.screenshot_path = Some(format!("/tmp/{}.png", attachment.hash));
+            let outside_id = store.save_note(&outside).unwrap();
+            assert!(store.get_note_attachments(outside_id).unwrap().is_empty());
+        });
+    }
+}
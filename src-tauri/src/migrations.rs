@@ -0,0 +1,709 @@
+use rusqlite::{params, Connection, OptionalExtension, Transaction};
+use std::io::{Error as IoError, ErrorKind};
+use std::path::{Path, PathBuf};
+
+// 单个迁移步骤
+pub struct Migration {
+    pub version: u32,
+    pub description: &'static str,
+    pub up: fn(&Transaction) -> rusqlite::Result<()>,
+}
+
+// 所有迁移，必须按版本号递增排列，已发布的迁移不可修改
+pub static MIGRATIONS: &[Migration] = &[
+    Migration {
+        version: 1,
+        description: "初始表结构",
+        up: |tx| tx.execute_batch(
+            "CREATE TABLE IF NOT EXISTS notes (
+                id INTEGER PRIMARY KEY,
+                content TEXT NOT NULL,
+                screenshot_path TEXT,
+                created_at INTEGER NOT NULL,
+                updated_at INTEGER,
+                is_pinned BOOLEAN NOT NULL DEFAULT 0,
+                color TEXT,
+                category_id INTEGER,
+                deleted_at INTEGER,
+                FOREIGN KEY (category_id) REFERENCES categories (id)
+            );
+
+            CREATE TABLE IF NOT EXISTS note_reminders (
+                id INTEGER PRIMARY KEY,
+                note_id INTEGER NOT NULL,
+                reminder_time INTEGER NOT NULL,
+                completed BOOLEAN NOT NULL DEFAULT 0,
+                FOREIGN KEY (note_id) REFERENCES notes (id)
+            );
+
+            CREATE TABLE IF NOT EXISTS categories (
+                id INTEGER PRIMARY KEY,
+                name TEXT NOT NULL,
+                color TEXT
+            );
+
+            CREATE INDEX IF NOT EXISTS idx_notes_created_at ON notes (created_at);
+            CREATE INDEX IF NOT EXISTS idx_notes_is_pinned ON notes (is_pinned);
+            CREATE INDEX IF NOT EXISTS idx_notes_category_id ON notes (category_id);
+            CREATE INDEX IF NOT EXISTS idx_notes_deleted_at ON notes (deleted_at);
+            CREATE INDEX IF NOT EXISTS idx_reminders_time ON note_reminders (reminder_time);
+            CREATE INDEX IF NOT EXISTS idx_reminders_note_id ON note_reminders (note_id);"
+        ),
+    },
+    Migration {
+        version: 2,
+        description: "便签全文索引",
+        // trigram 分词器按三个字符切分，不依赖空格，适用于中文
+        up: |tx| tx.execute_batch(
+            "CREATE VIRTUAL TABLE IF NOT EXISTS notes_fts USING fts5(
+                content,
+                content = 'notes',
+                content_rowid = 'id',
+                tokenize = 'trigram'
+            );
+
+            CREATE TRIGGER IF NOT EXISTS notes_fts_insert AFTER INSERT ON notes BEGIN
+                INSERT INTO notes_fts (rowid, content) VALUES (new.id, new.content);
+            END;
+
+            CREATE TRIGGER IF NOT EXISTS notes_fts_delete AFTER DELETE ON notes BEGIN
+                INSERT INTO notes_fts (notes_fts, rowid, content) VALUES ('delete', old.id, old.content);
+            END;
+
+            CREATE TRIGGER IF NOT EXISTS notes_fts_update AFTER UPDATE OF content ON notes BEGIN
+                INSERT INTO notes_fts (notes_fts, rowid, content) VALUES ('delete', old.id, old.content);
+                INSERT INTO notes_fts (rowid, content) VALUES (new.id, new.content);
+            END;
+
+            INSERT INTO notes_fts (notes_fts) VALUES ('rebuild');"
+        ),
+    },
+    Migration {
+        version: 3,
+        description: "便签历史版本",
+        up: |tx| tx.execute_batch(
+            "CREATE TABLE IF NOT EXISTS note_revisions (
+                id INTEGER PRIMARY KEY,
+                note_id INTEGER NOT NULL,
+                content TEXT NOT NULL,
+                created_at INTEGER NOT NULL,
+                FOREIGN KEY (note_id) REFERENCES notes (id)
+            );
+
+            CREATE INDEX IF NOT EXISTS idx_revisions_note_id ON note_revisions (note_id, id);"
+        ),
+    },
+    Migration {
+        version: 4,
+        description: "便签标签",
+        up: migrate_v4_tags,
+    },
+    Migration {
+        version: 5,
+        description: "分类层级",
+        up: |tx| tx.execute_batch(
+            "ALTER TABLE categories ADD COLUMN parent_id INTEGER REFERENCES categories (id);
+
+            CREATE INDEX IF NOT EXISTS idx_categories_parent_id ON categories (parent_id);"
+        ),
+    },
+    Migration {
+        version: 6,
+        description: "手动排序",
+        // 按原有的显示顺序初始化位置：分类按名称，便签按置顶和创建时间
+        up: |tx| tx.execute_batch(
+            "ALTER TABLE categories ADD COLUMN position INTEGER NOT NULL DEFAULT 0;
+            ALTER TABLE notes ADD COLUMN position INTEGER NOT NULL DEFAULT 0;
+
+            UPDATE categories SET position = (
+                SELECT COUNT(*) FROM categories c
+                WHERE c.parent_id IS categories.parent_id
+                  AND (c.name < categories.name OR (c.name = categories.name AND c.id < categories.id))
+            );
+
+            UPDATE notes SET position = (
+                SELECT COUNT(*) FROM notes n
+                WHERE n.category_id IS notes.category_id
+                  AND (n.is_pinned > notes.is_pinned
+                       OR (n.is_pinned = notes.is_pinned AND n.created_at > notes.created_at)
+                       OR (n.is_pinned = notes.is_pinned AND n.created_at = notes.created_at AND n.id < notes.id))
+            );
+
+            CREATE INDEX IF NOT EXISTS idx_categories_position ON categories (parent_id, position);
+            CREATE INDEX IF NOT EXISTS idx_notes_position ON notes (category_id, position);"
+        ),
+    },
+    Migration {
+        version: 7,
+        description: "附件存储",
+        up: migrate_v7_attachments,
+    },
+    Migration {
+        version: 8,
+        description: "便签链接",
+        up: migrate_v8_note_links,
+    },
+    Migration {
+        version: 9,
+        description: "清单便签",
+        up: |tx| tx.execute_batch(
+            "ALTER TABLE notes ADD COLUMN note_type TEXT NOT NULL DEFAULT 'text';
+
+            CREATE TABLE IF NOT EXISTS checklist_items (
+                id INTEGER PRIMARY KEY,
+                note_id INTEGER NOT NULL,
+                content TEXT NOT NULL,
+                done BOOLEAN NOT NULL DEFAULT 0,
+                position INTEGER NOT NULL DEFAULT 0,
+                due_at INTEGER,
+                created_at INTEGER NOT NULL,
+                FOREIGN KEY (note_id) REFERENCES notes (id)
+            );
+
+            CREATE INDEX IF NOT EXISTS idx_checklist_items_note_id ON checklist_items (note_id, position);"
+        ),
+    },
+    Migration {
+        version: 10,
+        description: "便签内容格式",
+        up: |tx| tx.execute_batch(
+            "ALTER TABLE notes ADD COLUMN content_format TEXT NOT NULL DEFAULT 'plain';"
+        ),
+    },
+    Migration {
+        version: 11,
+        description: "便签归档",
+        up: |tx| tx.execute_batch(
+            "ALTER TABLE notes ADD COLUMN archived_at INTEGER;
+
+            CREATE INDEX IF NOT EXISTS idx_notes_archived_at ON notes (archived_at);"
+        ),
+    },
+    Migration {
+        version: 12,
+        description: "便签窗口状态",
+        up: |tx| tx.execute_batch(
+            "CREATE TABLE IF NOT EXISTS note_windows (
+                note_id INTEGER PRIMARY KEY,
+                x INTEGER NOT NULL,
+                y INTEGER NOT NULL,
+                width INTEGER NOT NULL,
+                height INTEGER NOT NULL,
+                monitor TEXT,
+                opacity REAL NOT NULL DEFAULT 1.0,
+                always_on_top BOOLEAN NOT NULL DEFAULT 1,
+                collapsed BOOLEAN NOT NULL DEFAULT 0,
+                is_open BOOLEAN NOT NULL DEFAULT 0,
+                updated_at INTEGER NOT NULL,
+                FOREIGN KEY (note_id) REFERENCES notes (id)
+            );"
+        ),
+    },
+    Migration {
+        version: 13,
+        description: "重复提醒",
+        up: |tx| tx.execute_batch(
+            "ALTER TABLE note_reminders ADD COLUMN rrule TEXT;          -- RRULE 子集，NULL 表示单次提醒
+             ALTER TABLE note_reminders ADD COLUMN timezone TEXT;       -- IANA 时区名称，NULL 表示系统时区
+             ALTER TABLE note_reminders ADD COLUMN dtstart INTEGER;     -- 重复规则的起始时间"
+        ),
+    },
+];
+
+// 创建标签表，并从已有便签内容中提取 #标签
+fn migrate_v4_tags(tx: &Transaction) -> rusqlite::Result<()> {
+    tx.execute_batch(
+        "CREATE TABLE IF NOT EXISTS tags (
+            id INTEGER PRIMARY KEY,
+            name TEXT NOT NULL UNIQUE COLLATE NOCASE,
+            created_at INTEGER NOT NULL
+        );
+
+        CREATE TABLE IF NOT EXISTS note_tags (
+            note_id INTEGER NOT NULL,
+            tag_id INTEGER NOT NULL,
+            source TEXT NOT NULL DEFAULT 'manual',  -- manual: 手动添加；inline: 来自内容中的 #标签
+            PRIMARY KEY (note_id, tag_id),
+            FOREIGN KEY (note_id) REFERENCES notes (id),
+            FOREIGN KEY (tag_id) REFERENCES tags (id)
+        );
+
+        CREATE INDEX IF NOT EXISTS idx_note_tags_tag_id ON note_tags (tag_id);"
+    )?;
+
+    let now = chrono::Utc::now().timestamp();
+    let mut stmt = tx.prepare("SELECT id, content FROM notes")?;
+    let notes = stmt.query_map([], |row| Ok((row.get::<_, i64>(0)?, row.get::<_, String>(1)?)))?
+        .collect::<rusqlite::Result<Vec<_>>>()?;
+
+    for (note_id, content) in notes {
+        for name in v4_parse_hashtags(&content) {
+            tx.execute("INSERT OR IGNORE INTO tags (name, created_at) VALUES (?1, ?2)", params![name, now])?;
+            tx.execute(
+                "INSERT OR IGNORE INTO note_tags (note_id, tag_id, source)
+                 SELECT ?1, id, 'inline' FROM tags WHERE name = ?2",
+                params![note_id, name],
+            )?;
+        }
+    }
+
+    Ok(())
+}
+
+// 创建附件表，并将 notes.screenshot_path 中的图片导入附件存储
+fn migrate_v7_attachments(tx: &Transaction) -> rusqlite::Result<()> {
+    tx.execute_batch(
+        "CREATE TABLE IF NOT EXISTS attachments (
+            id INTEGER PRIMARY KEY,
+            hash TEXT NOT NULL UNIQUE,       -- 内容的 SHA-256，相同内容只存一份
+            mime TEXT NOT NULL,
+            extension TEXT NOT NULL,
+            size INTEGER NOT NULL,
+            ref_count INTEGER NOT NULL DEFAULT 0,  -- 引用该附件的便签数量，由触发器维护
+            created_at INTEGER NOT NULL
+        );
+
+        CREATE TABLE IF NOT EXISTS note_attachments (
+            note_id INTEGER NOT NULL,
+            attachment_id INTEGER NOT NULL,
+            created_at INTEGER NOT NULL,
+            PRIMARY KEY (note_id, attachment_id),
+            FOREIGN KEY (note_id) REFERENCES notes (id),
+            FOREIGN KEY (attachment_id) REFERENCES attachments (id)
+        );
+
+        CREATE INDEX IF NOT EXISTS idx_note_attachments_attachment_id ON note_attachments (attachment_id);
+        CREATE INDEX IF NOT EXISTS idx_attachments_ref_count ON attachments (ref_count);
+
+        CREATE TRIGGER IF NOT EXISTS note_attachments_insert AFTER INSERT ON note_attachments BEGIN
+            UPDATE attachments SET ref_count = ref_count + 1 WHERE id = new.attachment_id;
+        END;
+
+        CREATE TRIGGER IF NOT EXISTS note_attachments_delete AFTER DELETE ON note_attachments BEGIN
+            UPDATE attachments SET ref_count = ref_count - 1 WHERE id = old.attachment_id;
+        END;"
+    )?;
+
+    let now = chrono::Utc::now().timestamp();
+    let mut stmt = tx.prepare("SELECT id, screenshot_path FROM notes WHERE screenshot_path IS NOT NULL AND screenshot_path != ''")?;
+    let notes = stmt.query_map([], |row| Ok((row.get::<_, i64>(0)?, row.get::<_, String>(1)?)))?
+        .collect::<rusqlite::Result<Vec<_>>>()?;
+
+    for (note_id, screenshot_path) in notes {
+        let path = resolve_legacy_image_path(&screenshot_path);
+        let bytes = match std::fs::read(&path) {
+            Ok(bytes) => bytes,
+            Err(e) => {
+                // 文件已丢失时保留原路径，不阻止升级
+                log::warn!("便签 {} 的截图 {} 无法导入附件存储: {}", note_id, path.display(), e);
+                continue;
+            }
+        };
+
+        let mime = crate::attachments::mime_for_path(&path);
+        let extension = crate::attachments::extension_for_mime(mime);
+        let (hash, _) = crate::attachments::write_blob(&bytes, extension)
+            .map_err(|e| rusqlite::Error::ToSqlConversionFailure(Box::new(e)))?;
+
+        tx.execute(
+            "INSERT OR IGNORE INTO attachments (hash, mime, extension, size, created_at) VALUES (?1, ?2, ?3, ?4, ?5)",
+            params![hash, mime, extension, bytes.len() as i64, now],
+        )?;
+        tx.execute(
+            "INSERT OR IGNORE INTO note_attachments (note_id, attachment_id, created_at)
+             SELECT ?1, id, ?2 FROM attachments WHERE hash = ?3",
+            params![note_id, now, hash],
+        )?;
+        tx.execute("UPDATE notes SET screenshot_path = NULL WHERE id = ?1", params![note_id])?;
+    }
+
+    Ok(())
+}
+
+// 解析旧版本保存的图片路径
+// 粘贴图片保存为相对于应用数据目录的 ./images/xxx.png，截图保存为绝对路径
+fn resolve_legacy_image_path(path: &str) -> PathBuf {
+    match path.strip_prefix("./") {
+        Some(relative) => dirs::data_dir()
+            .unwrap_or_else(|| PathBuf::from("."))
+            .join("com.stickynotes.app")
+            .join(relative),
+        None => PathBuf::from(path),
+    }
+}
+
+// 创建链接表，并解析已有便签内容中的 [[链接]]
+fn migrate_v8_note_links(tx: &Transaction) -> rusqlite::Result<()> {
+    tx.execute_batch(
+        "CREATE TABLE IF NOT EXISTS note_links (
+            source_id INTEGER NOT NULL,
+            target_id INTEGER,              -- 找不到目标便签时为 NULL
+            target_title TEXT NOT NULL,     -- 链接中书写的标题，或 #ID
+            position INTEGER NOT NULL,      -- 在来源便签内容中的顺序
+            PRIMARY KEY (source_id, position),
+            FOREIGN KEY (source_id) REFERENCES notes (id),
+            FOREIGN KEY (target_id) REFERENCES notes (id)
+        );
+
+        CREATE INDEX IF NOT EXISTS idx_note_links_target_id ON note_links (target_id);"
+    )?;
+
+    let mut stmt = tx.prepare("SELECT id, content FROM notes")?;
+    let notes = stmt.query_map([], |row| Ok((row.get::<_, i64>(0)?, row.get::<_, String>(1)?)))?
+        .collect::<rusqlite::Result<Vec<_>>>()?;
+
+    for (note_id, content) in notes {
+        v8_write_note_links(tx, note_id, &content)?;
+    }
+
+    Ok(())
+}
+
+// ======= 迁移使用的解析逻辑 =======
+// 以下函数是发布 v4、v8 时 db.rs 中解析逻辑的副本，之后 db.rs 的修改不会影响已发布的迁移
+
+// v4：查找内容中的 #标签，# 必须位于开头或空白之后，标签名由字母、数字、_ 和 - 组成，
+// 按首次出现顺序去重（不区分大小写）
+fn v4_parse_hashtags(content: &str) -> Vec<String> {
+    let mut tags: Vec<String> = Vec::new();
+    let mut prev: Option<char> = None;
+    let mut chars = content.char_indices().peekable();
+
+    while let Some((i, c)) = chars.next() {
+        if c == '#' && prev.map_or(true, char::is_whitespace) {
+            let start = i + c.len_utf8();
+            let mut end = start;
+            while let Some(&(j, next)) = chars.peek() {
+                if next.is_alphanumeric() || next == '_' || next == '-' {
+                    end = j + next.len_utf8();
+                    prev = Some(next);
+                    chars.next();
+                } else {
+                    break;
+                }
+            }
+            if end > start {
+                let name = &content[start..end];
+                if !tags.iter().any(|t| t.to_lowercase() == name.to_lowercase()) {
+                    tags.push(name.to_string());
+                }
+                continue;
+            }
+        }
+        prev = Some(c);
+    }
+
+    tags
+}
+
+// v8：便签标题（第一行去掉开头的 # 和首尾空白）的 SQL 表达式
+const V8_NOTE_TITLE_SQL: &str = "trim(ltrim(substr(content, 1, CASE WHEN instr(content, char(10)) > 0 THEN instr(content, char(10)) - 1 ELSE length(content) END), '#'), ' ' || char(9) || char(13))";
+
+// v8：解析内容中的 [[标题]] 和 [[#ID]]，链接不能跨行，按出现顺序去重；
+// 返回 (按ID链接的目标, 链接文本)
+fn v8_parse_wiki_links(content: &str) -> Vec<(Option<i64>, String)> {
+    let mut links: Vec<(Option<i64>, String)> = Vec::new();
+    let mut rest = 0;
+
+    while let Some(open) = content[rest..].find("[[") {
+        let start = rest + open + 2;
+        let inner_len = content[start..].find(|c| c == ']' || c == '[' || c == '\n');
+        match inner_len {
+            Some(len) if content[start + len..].starts_with("]]") => {
+                let text = content[start..start + len].trim();
+                if !text.is_empty() {
+                    let link = match text.strip_prefix('#').and_then(|id| id.parse::<i64>().ok()) {
+                        Some(id) => (Some(id), text.to_string()),
+                        None => (None, text.to_string()),
+                    };
+                    let duplicate = links.iter().any(|l| match (l.0, link.0) {
+                        (None, None) => l.1.to_lowercase() == link.1.to_lowercase(),
+                        (a, b) => a == b,
+                    });
+                    if !duplicate {
+                        links.push(link);
+                    }
+                }
+                rest = start + len + 2;
+            }
+            Some(len) => rest = start + len,
+            None => break,
+        }
+    }
+
+    links
+}
+
+// v8：写入便签的链接并解析每个链接的目标便签
+fn v8_write_note_links(tx: &Transaction, note_id: i64, content: &str) -> rusqlite::Result<()> {
+    tx.execute("DELETE FROM note_links WHERE source_id = ?1", params![note_id])?;
+
+    for (position, (link_id, text)) in v8_parse_wiki_links(content).into_iter().enumerate() {
+        let (target_id, target_title): (Option<i64>, String) = match link_id {
+            Some(id) => {
+                let exists: bool = tx.query_row(
+                    "SELECT EXISTS(SELECT 1 FROM notes WHERE id = ?1)", params![id], |row| row.get(0))?;
+                (if exists { Some(id) } else { None }, format!("#{}", id))
+            }
+            None => {
+                let target_id = tx.query_row(
+                    &format!(
+                        "SELECT id FROM notes WHERE deleted_at IS NULL AND {} = ?1 COLLATE NOCASE
+                         ORDER BY created_at ASC LIMIT 1",
+                        V8_NOTE_TITLE_SQL
+                    ),
+                    params![text],
+                    |row| row.get(0),
+                ).optional()?;
+                (target_id, text)
+            }
+        };
+
+        tx.execute(
+            "INSERT INTO note_links (source_id, target_id, target_title, position) VALUES (?1, ?2, ?3, ?4)",
+            params![note_id, target_id, target_title, position as i64],
+        )?;
+    }
+
+    Ok(())
+}
+
+// 当前程序支持的最新版本
+pub fn latest_version() -> u32 {
+    MIGRATIONS.last().map(|m| m.version).unwrap_or(0)
+}
+
+// 创建记录已执行迁移的版本表
+fn ensure_version_table(conn: &Connection) -> Result<(), IoError> {
+    conn.execute_batch(
+        "CREATE TABLE IF NOT EXISTS schema_version (
+            version INTEGER NOT NULL,
+            applied_at INTEGER NOT NULL
+        );"
+    ).map_err(|e| IoError::new(ErrorKind::Other, format!("创建版本表失败: {}", e)))
+}
+
+// 读取数据库当前的版本号，没有版本表时视为 0。只读取，不修改数据库，
+// 升级前的备份因此保持原样
+pub fn current_version(conn: &Connection) -> Result<u32, IoError> {
+    let has_table: bool = conn.query_row(
+        "SELECT EXISTS(SELECT 1 FROM sqlite_master WHERE type = 'table' AND name = 'schema_version')",
+        [],
+        |row| row.get(0),
+    ).map_err(|e| IoError::new(ErrorKind::Other, format!("读取数据库版本失败: {}", e)))?;
+    if !has_table {
+        return Ok(0);
+    }
+
+    conn.query_row("SELECT MAX(version) FROM schema_version", [], |row| row.get::<_, Option<u32>>(0))
+        .map(|v| v.unwrap_or(0))
+        .map_err(|e| IoError::new(ErrorKind::Other, format!("读取数据库版本失败: {}", e)))
+}
+
+// 将数据库升级到最新版本
+// db_path 为 None 时（例如内存数据库）不做备份
+pub fn migrate(conn: &mut Connection, db_path: Option<&Path>) -> Result<u32, IoError> {
+    let current = current_version(conn)?;
+    let latest = latest_version();
+
+    // 拒绝打开由更新版本程序创建的数据库，避免旧程序破坏新结构
+    if current > latest {
+        return Err(IoError::new(
+            ErrorKind::InvalidData,
+            format!("数据库版本 ({}) 高于当前程序支持的版本 ({})，请升级应用", current, latest),
+        ));
+    }
+
+    if current == latest {
+        return Ok(current);
+    }
+
+    // 已有数据的库在升级前先备份，包括没有版本表的旧版本数据库（版本为 0）
+    if current > 0 || has_user_tables(conn)? {
+        if let Some(path) = db_path {
+            // WAL 模式下先把日志写回主文件，保证复制的文件完整
+            conn.query_row("PRAGMA wal_checkpoint(TRUNCATE)", [], |_| Ok(()))
+                .map_err(|e| IoError::new(ErrorKind::Other, format!("写回WAL日志失败: {}", e)))?;
+            backup_before_upgrade(path, current)?;
+        }
+    }
+
+    // 备份完成后再创建版本表
+    ensure_version_table(conn)?;
+
+    for migration in MIGRATIONS.iter().filter(|m| m.version > current) {
+        apply_migration(conn, migration)?;
+    }
+
+    Ok(latest)
+}
+
+// 数据库中是否已有版本表以外的表
+fn has_user_tables(conn: &Connection) -> Result<bool, IoError> {
+    conn.query_row(
+        "SELECT EXISTS(SELECT 1 FROM sqlite_master
+                       WHERE type = 'table' AND name NOT LIKE 'sqlite_%' AND name != 'schema_version')",
+        [],
+        |row| row.get(0),
+    ).map_err(|e| IoError::new(ErrorKind::Other, format!("读取数据库结构失败: {}", e)))
+}
+
+// 在单个事务中执行一个迁移并记录版本号
+fn apply_migration(conn: &mut Connection, migration: &Migration) -> Result<(), IoError> {
+    let tx = conn.transaction()
+        .map_err(|e| IoError::new(ErrorKind::Other, format!("开始事务失败: {}", e)))?;
+
+    (migration.up)(&tx).map_err(|e| IoError::new(
+        ErrorKind::Other,
+        format!("执行迁移 v{} ({}) 失败: {}", migration.version, migration.description, e),
+    ))?;
+
+    tx.execute(
+        "INSERT INTO schema_version (version, applied_at) VALUES (?1, ?2)",
+        params![migration.version, chrono::Utc::now().timestamp()],
+    ).map_err(|e| IoError::new(ErrorKind::Other, format!("记录数据库版本失败: {}", e)))?;
+
+    // 事务在出错时随 tx 析构自动回滚
+    tx.commit()
+        .map_err(|e| IoError::new(ErrorKind::Other, format!("提交事务失败: {}", e)))
+}
+
+// 复制数据库文件作为升级前的备份，文件保持加密状态
+fn backup_before_upgrade(db_path: &Path, version: u32) -> Result<PathBuf, IoError> {
+    let file_name = db_path.file_name()
+        .ok_or_else(|| IoError::new(ErrorKind::InvalidInput, "无效的数据库路径"))?
+        .to_string_lossy();
+    let backup_path = db_path.with_file_name(format!(
+        "{}.v{}.{}.bak",
+        file_name,
+        version,
+        chrono::Local::now().format("%Y%m%d_%H%M%S"),
+    ));
+
+    std::fs::copy(db_path, &backup_path)
+        .map_err(|e| IoError::new(e.kind(), format!("备份数据库失败: {}", e)))?;
+
+    Ok(backup_path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::BTreeMap;
+
+    // 引入迁移之前的数据库结构和示例数据
+    const BASELINE_FIXTURE: &str = include_str!("../fixtures/baseline_v0.sql");
+
+    // 从基线数据库依次执行迁移，得到停留在 version 的数据库
+    fn database_at(version: u32) -> Connection {
+        let mut conn = Connection::open_in_memory().unwrap();
+        conn.execute_batch(BASELINE_FIXTURE).unwrap();
+        if version > 0 {
+            ensure_version_table(&conn).unwrap();
+            for migration in MIGRATIONS.iter().filter(|m| m.version <= version) {
+                apply_migration(&mut conn, migration).unwrap();
+            }
+        }
+        conn
+    }
+
+    // 每张表的列名，用于比较升级后的结构与新建数据库是否一致
+    fn table_columns(conn: &Connection) -> BTreeMap<String, Vec<String>> {
+        let mut stmt = conn.prepare("SELECT name FROM sqlite_master WHERE type = 'table' ORDER BY name").unwrap();
+        let tables: Vec<String> = stmt.query_map([], |row| row.get(0)).unwrap()
+            .collect::<rusqlite::Result<_>>().unwrap();
+
+        tables.into_iter().map(|table| {
+            let mut stmt = conn.prepare(&format!("PRAGMA table_info(\"{}\")", table)).unwrap();
+            let mut columns: Vec<String> = stmt.query_map([], |row| row.get(1)).unwrap()
+                .collect::<rusqlite::Result<_>>().unwrap();
+            columns.sort();
+            (table, columns)
+        }).collect()
+    }
+
+    fn count(conn: &Connection, sql: &str) -> i64 {
+        conn.query_row(sql, [], |row| row.get(0)).unwrap()
+    }
+
+    #[test]
+    fn upgrades_every_past_version_to_latest() {
+        let mut fresh = Connection::open_in_memory().unwrap();
+        migrate(&mut fresh, None).unwrap();
+        let expected = table_columns(&fresh);
+
+        for version in 0..latest_version() {
+            let mut conn = database_at(version);
+            assert_eq!(current_version(&conn).unwrap(), version);
+
+            assert_eq!(migrate(&mut conn, None).unwrap(), latest_version(), "从 v{} 升级", version);
+            assert_eq!(current_version(&conn).unwrap(), latest_version());
+            assert_eq!(table_columns(&conn), expected, "从 v{} 升级后的表结构", version);
+
+            // 原有数据保留，并补齐新表中的数据
+            assert_eq!(count(&conn, "SELECT COUNT(*) FROM notes"), 3);
+            assert_eq!(count(&conn, "SELECT COUNT(*) FROM categories"), 2);
+            assert_eq!(count(&conn, "SELECT COUNT(*) FROM note_reminders WHERE rrule IS NULL"), 1);
+            assert_eq!(count(&conn, "SELECT COUNT(*) FROM notes_fts WHERE notes_fts MATCH '周一开会'"), 1);
+            assert_eq!(count(&conn, "SELECT COUNT(*) FROM note_tags WHERE source = 'inline'"), 3);
+            assert_eq!(count(&conn, "SELECT COUNT(*) FROM note_links WHERE source_id = 1 AND target_id = 2"), 1);
+            assert_eq!(count(&conn, "SELECT position FROM notes WHERE id = 1"), 0);
+            assert_eq!(count(&conn, "SELECT COUNT(*) FROM notes WHERE note_type = 'text' AND content_format = 'plain'"), 3);
+        }
+    }
+
+    #[test]
+    fn latest_database_is_left_unchanged() {
+        let mut conn = database_at(latest_version());
+        let before = count(&conn, "SELECT COUNT(*) FROM schema_version");
+        assert_eq!(migrate(&mut conn, None).unwrap(), latest_version());
+        assert_eq!(count(&conn, "SELECT COUNT(*) FROM schema_version"), before);
+    }
+
+    #[test]
+    fn refuses_database_from_newer_version() {
+        let mut conn = database_at(latest_version());
+        conn.execute(
+            "INSERT INTO schema_version (version, applied_at) VALUES (?1, 0)",
+            params![latest_version() + 1],
+        ).unwrap();
+        let error = migrate(&mut conn, None).unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn backs_up_legacy_database_without_version_table() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("stickynotes.db");
+        let mut conn = Connection::open(&path).unwrap();
+        conn.execute_batch(BASELINE_FIXTURE).unwrap();
+
+        migrate(&mut conn, Some(&path)).unwrap();
+
+        let backups: Vec<String> = std::fs::read_dir(dir.path()).unwrap()
+            .map(|entry| entry.unwrap().file_name().to_string_lossy().to_string())
+            .filter(|name| name.starts_with("stickynotes.db.v0.") && name.ends_with(".bak"))
+            .collect();
+        assert_eq!(backups.len(), 1);
+
+        let backup = Connection::open(dir.path().join(&backups[0])).unwrap();
+        assert_eq!(count(&backup, "SELECT COUNT(*) FROM notes"), 3);
+        assert_eq!(current_version(&backup).unwrap(), 0);
+        // 备份与升级前的文件一致，没有多出版本表
+        assert_eq!(count(&backup, "SELECT COUNT(*) FROM sqlite_master WHERE name = 'schema_version'"), 0);
+    }
+
+    #[test]
+    fn new_database_is_not_backed_up() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("stickynotes.db");
+        let mut conn = Connection::open(&path).unwrap();
+
+        migrate(&mut conn, Some(&path)).unwrap();
+
+        let files = std::fs::read_dir(dir.path()).unwrap()
+            .filter(|entry| entry.as_ref().unwrap().file_name().to_string_lossy().ends_with(".bak"))
+            .count();
+        assert_eq!(files, 0);
+    }
+}
@@ -0,0 +1,445 @@
+use std::io::{Error as IoError, ErrorKind};
+use std::sync::atomic::{AtomicBool, Ordering};
+use serde::Serialize;
+use tauri::{AppHandle, Manager, PhysicalPosition, PhysicalSize, Window, WindowEvent};
+use crate::db::{self, ContentFormat, Note, NoteType, NoteWindowState};
+use crate::store::StoreState;
+
+// 便签窗口的标签为 note_<便签ID>，前端据此加载对应的便签
+const LABEL_PREFIX: &str = "note_";
+
+// 新窗口的默认尺寸（逻辑像素）
+const DEFAULT_WINDOW_SIZE: f64 = 300.0;
+
+// 折叠后只保留标题栏的高度（逻辑像素）
+const COLLAPSED_HEIGHT: f64 = 32.0;
+
+// 窗口在某个显示器内至少可见这么多像素才保持原位置，否则移回显示器内
+const MIN_VISIBLE_PX: i32 = 48;
+
+// 上次退出时打开的窗口只恢复一次，锁定再解锁时窗口仍然保持打开
+static WINDOWS_RESTORED: AtomicBool = AtomicBool::new(false);
+
+// 返回给前端的窗口状态
+#[derive(Serialize)]
+pub struct NoteWindowStateResponse {
+    pub note_id: i64,
+    pub opacity: f64,
+    pub always_on_top: bool,
+    pub collapsed: bool,
+}
+
+impl From<NoteWindowState> for NoteWindowStateResponse {
+    fn from(state: NoteWindowState) -> Self {
+        NoteWindowStateResponse {
+            note_id: state.note_id,
+            opacity: state.opacity,
+            always_on_top: state.always_on_top,
+            collapsed: state.collapsed,
+        }
+    }
+}
+
+// 显示器区域（物理像素）
+struct MonitorArea {
+    name: Option<String>,
+    x: i32,
+    y: i32,
+    width: u32,
+    height: u32,
+}
+
+pub fn window_label(note_id: i64) -> String {
+    format!("{}{}", LABEL_PREFIX, note_id)
+}
+
+pub fn note_id_from_label(label: &str) -> Option<i64> {
+    label.strip_prefix(LABEL_PREFIX)?.parse().ok()
+}
+
+// 新建空白便签并在浮动窗口中打开
+pub async fn open_new_note(app: AppHandle) -> Result<i64, IoError> {
+    let now = chrono::Utc::now().timestamp();
+    let note = Note {
+        id: None,
+        content: String::new(),
+        screenshot_path: None,
+        created_at: now,
+        updated_at: Some(now),
+        is_pinned: false,
+        color: None,
+        category_id: None,
+        deleted_at: None,
+        archived_at: None,
+        position: 0,
+        note_type: NoteType::Text,
+        content_format: ContentFormat::Plain,
+    };
+
+    let note_id = app.state::<StoreState>().run(move |store| store.save_note(&note)).await?;
+    open(app, note_id).await?;
+    Ok(note_id)
+}
+
+// 打开便签窗口并记录为已打开，窗口已存在时只聚焦
+pub async fn open(app: AppHandle, note_id: i64) -> Result<(), IoError> {
+    let saved = db::run_blocking(move || db::get_note_window_state(note_id)).await?;
+    let window = build_window(&app, note_id, saved.as_ref())
+        .map_err(|e| IoError::new(ErrorKind::Other, format!("创建便签窗口失败: {}", e)))?;
+
+    let mut state = saved.unwrap_or(NoteWindowState {
+        note_id,
+        x: 0,
+        y: 0,
+        width: 0,
+        height: 0,
+        monitor: None,
+        opacity: 1.0,
+        always_on_top: true,
+        collapsed: false,
+        is_open: true,
+    });
+    if let Some(geometry) = read_geometry(&window, note_id) {
+        state.x = geometry.x;
+        state.y = geometry.y;
+        state.width = geometry.width;
+        state.monitor = geometry.monitor;
+        if !state.collapsed {
+            state.height = geometry.height;
+        }
+    }
+    state.is_open = true;
+
+    db::run_blocking(move || db::save_note_window_state(&state)).await
+}
+
+// 恢复上次退出时仍打开的便签窗口，需要数据库已打开（启用主密码时在解锁后调用）
+pub fn restore_open_windows(app: &AppHandle) {
+    if WINDOWS_RESTORED.load(Ordering::SeqCst) {
+        return;
+    }
+    let windows = match db::get_open_note_windows() {
+        Ok(windows) => windows,
+        Err(e) => {
+            log::warn!("读取便签窗口状态失败: {}", e);
+            return;
+        }
+    };
+    WINDOWS_RESTORED.store(true, Ordering::SeqCst);
+
+    for state in windows {
+        if let Err(e) = build_window(app, state.note_id, Some(&state)) {
+            log::warn!("恢复便签 {} 的窗口失败: {}", state.note_id, e);
+        }
+    }
+}
+
+// 退出前保存所有便签窗口的位置，窗口保持打开状态以便下次恢复
+pub fn save_open_windows(app: &AppHandle) {
+    for (label, window) in app.windows() {
+        let Some(note_id) = note_id_from_label(&label) else { continue };
+        if let Some(geometry) = read_geometry(&window, note_id) {
+            if let Err(e) = db::update_note_window_geometry(&geometry) {
+                log::warn!("保存便签 {} 的窗口位置失败: {}", note_id, e);
+            }
+        }
+    }
+}
+
+fn build_window(app: &AppHandle, note_id: i64, saved: Option<&NoteWindowState>) -> tauri::Result<Window> {
+    let label = window_label(note_id);
+    if let Some(window) = app.get_window(&label) {
+        window.show()?;
+        window.set_focus()?;
+        return Ok(window);
+    }
+
+    // 先隐藏创建，调整好位置后再显示，避免窗口闪动
+    let window = tauri::WindowBuilder::new(
+        app,
+        &label,
+        tauri::WindowUrl::App("note.html".into()),
+    )
+    .title("便签")
+    .inner_size(DEFAULT_WINDOW_SIZE, DEFAULT_WINDOW_SIZE)
+    .resizable(true)
+    .decorations(false)
+    .always_on_top(saved.map_or(true, |s| s.always_on_top))
+    .transparent(true)
+    .visible(false)
+    .build()?;
+
+    if let Some(saved) = saved {
+        let monitors = monitor_areas(&window)?;
+        let (x, y, width, height) = clamp_to_monitors(saved, &monitors);
+        let height = if saved.collapsed {
+            (COLLAPSED_HEIGHT * window.scale_factor()?) as u32
+        } else {
+            height
+        };
+        window.set_size(PhysicalSize::new(width, height))?;
+        window.set_position(PhysicalPosition::new(x, y))?;
+    }
+
+    watch_window(&window, note_id);
+    window.show()?;
+    window.set_focus()?;
+    Ok(window)
+}
+
+// 失去焦点时保存位置（拖动和调整大小都会在之后失去焦点），关闭时记录为未打开
+fn watch_window(window: &Window, note_id: i64) {
+    let handle = window.clone();
+    window.on_window_event(move |event| match event {
+        WindowEvent::Focused(false) => {
+            if let Some(geometry) = read_geometry(&handle, note_id) {
+                tauri::async_runtime::spawn_blocking(move || {
+                    if let Err(e) = db::update_note_window_geometry(&geometry) {
+                        log::warn!("保存便签 {} 的窗口位置失败: {}", note_id, e);
+                    }
+                });
+            }
+        }
+        WindowEvent::CloseRequested { .. } => {
+            let geometry = read_geometry(&handle, note_id);
+            tauri::async_runtime::spawn_blocking(move || {
+                let result = match geometry {
+                    Some(geometry) => db::update_note_window_geometry(&geometry),
+                    None => Ok(()),
+                };
+                if let Err(e) = result.and_then(|_| db::set_note_window_open(note_id, false)) {
+                    log::warn!("保存便签 {} 的窗口状态失败: {}", note_id, e);
+                }
+            });
+        }
+        _ => {}
+    });
+}
+
+// 读取窗口当前的位置、尺寸和所在显示器，只填充几何信息
+fn read_geometry(window: &Window, note_id: i64) -> Option<NoteWindowState> {
+    let position = window.outer_position().ok()?;
+    let size = window.inner_size().ok()?;
+    let monitor = window.current_monitor().ok().flatten()
+        .and_then(|m| m.name().cloned());
+
+    Some(NoteWindowState {
+        note_id,
+        x: position.x,
+        y: position.y,
+        width: size.width,
+        height: size.height,
+        monitor,
+        opacity: 1.0,
+        always_on_top: true,
+        collapsed: false,
+        is_open: true,
+    })
+}
+
+// 当前连接的显示器，主显示器排在最前
+fn monitor_areas(window: &Window) -> tauri::Result<Vec<MonitorArea>> {
+    let primary = window.primary_monitor()?.and_then(|m| m.name().cloned());
+    let mut areas: Vec<MonitorArea> = window.available_monitors()?
+        .iter()
+        .map(|m| MonitorArea {
+            name: m.name().cloned(),
+            x: m.position().x,
+            y: m.position().y,
+            width: m.size().width,
+            height: m.size().height,
+        })
+        .collect();
+    areas.sort_by_key(|area| primary.is_none() || area.name != primary);
+    Ok(areas)
+}
+
+// 显示器变化后窗口可能落在屏幕外，此时移回原显示器（不存在时为主显示器）内，
+// 尺寸超出显示器时一并缩小
+fn clamp_to_monitors(state: &NoteWindowState, monitors: &[MonitorArea]) -> (i32, i32, u32, u32) {
+    let visible = monitors.iter().any(|m| {
+        overlap(state.x, state.width, m.x, m.width) >= MIN_VISIBLE_PX
+            && overlap(state.y, state.height, m.y, m.height) >= MIN_VISIBLE_PX
+    });
+    let target = match monitors.first() {
+        Some(primary) if !visible => monitors.iter()
+            .find(|m| m.name.is_some() && m.name == state.monitor)
+            .unwrap_or(primary),
+        _ => return (state.x, state.y, state.width, state.height),
+    };
+
+    let width = state.width.min(target.width);
+    let height = state.height.min(target.height);
+    let x = state.x.clamp(target.x, target.x + (target.width - width) as i32);
+    let y = state.y.clamp(target.y, target.y + (target.height - height) as i32);
+    (x, y, width, height)
+}
+
+// 一维区间的重叠长度，不重叠时为负数或零
+fn overlap(start: i32, len: u32, area_start: i32, area_len: u32) -> i32 {
+    let end = (start + len as i32).min(area_start + area_len as i32);
+    end - start.max(area_start)
+}
+
+// ======= 命令 =======
+
+#[tauri::command]
+pub async fn open_note_window(note_id: i64, app: AppHandle) -> Result<bool, String> {
+    open(app, note_id).await
+        .map(|_| true)
+        .map_err(|e| format!("打开便签窗口失败: {}", e))
+}
+
+#[tauri::command]
+pub async fn create_note_window(app: AppHandle) -> Result<i64, String> {
+    open_new_note(app).await
+        .map_err(|e| format!("创建便签窗口失败: {}", e))
+}
+
+#[tauri::command]
+pub async fn close_note_window(note_id: i64, app: AppHandle) -> Result<bool, String> {
+    if let Some(window) = app.get_window(&window_label(note_id)) {
+        window.close().map_err(|e| format!("关闭便签窗口失败: {}", e))?;
+    }
+    Ok(true)
+}
+
+#[tauri::command]
+pub async fn get_note_window_state(note_id: i64) -> Result<Option<NoteWindowStateResponse>, String> {
+    db::run_blocking(move || db::get_note_window_state(note_id)).await
+        .map(|state| state.map(NoteWindowStateResponse::from))
+        .map_err(|e| format!("获取便签窗口状态失败: {}", e))
+}
+
+// 更新透明度、置顶和折叠状态，未传入的选项保持不变
+#[tauri::command]
+pub async fn update_note_window_options(
+    note_id: i64,
+    opacity: Option<f64>,
+    always_on_top: Option<bool>,
+    collapsed: Option<bool>,
+    app: AppHandle,
+) -> Result<NoteWindowStateResponse, String> {
+    let mut state = db::run_blocking(move || db::get_note_window_state(note_id)).await
+        .map_err(|e| format!("获取便签窗口状态失败: {}", e))?
+        .ok_or_else(|| "便签窗口不存在".to_string())?;
+
+    if let Some(opacity) = opacity {
+        state.opacity = opacity.clamp(0.1, 1.0);
+    }
+
+    if let Some(window) = app.get_window(&window_label(note_id)) {
+        if let Some(always_on_top) = always_on_top {
+            window.set_always_on_top(always_on_top)
+                .map_err(|e| format!("设置窗口置顶失败: {}", e))?;
+        }
+
+        if let Some(collapsed) = collapsed.filter(|&c| c != state.collapsed) {
+            let size = window.inner_size().map_err(|e| e.to_string())?;
+            let height = if collapsed {
+                // 折叠前记录展开时的高度
+                state.height = size.height;
+                let scale = window.scale_factor().map_err(|e| e.to_string())?;
+                (COLLAPSED_HEIGHT * scale) as u32
+            } else {
+                state.height
+            };
+            window.set_size(PhysicalSize::new(size.width, height))
+                .map_err(|e| format!("调整窗口大小失败: {}", e))?;
+        }
+    }
+    if let Some(always_on_top) = always_on_top {
+        state.always_on_top = always_on_top;
+    }
+    if let Some(collapsed) = collapsed {
+        state.collapsed = collapsed;
+    }
+
+    let saved = state.clone();
+    db::run_blocking(move || db::save_note_window_state(&saved)).await
+        .map_err(|e| format!("保存便签窗口状态失败: {}", e))?;
+    Ok(state.into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn window_at(x: i32, y: i32, width: u32, height: u32, monitor: Option<&str>) -> NoteWindowState {
+        NoteWindowState {
+            note_id: 1,
+            x,
+            y,
+            width,
+            height,
+            monitor: monitor.map(String::from),
+            opacity: 1.0,
+            always_on_top: false,
+            collapsed: false,
+            is_open: true,
+        }
+    }
+
+    fn monitor(name: &str, x: i32, y: i32, width: u32, height: u32) -> MonitorArea {
+        MonitorArea { name: Some(name.to_string()), x, y, width, height }
+    }
+
+    #[test]
+    fn labels_round_trip_note_ids() {
+        assert_eq!(window_label(42), "note_42");
+        assert_eq!(note_id_from_label("note_42"), Some(42));
+        assert_eq!(note_id_from_label("main"), None);
+        assert_eq!(note_id_from_label("note_abc"), None);
+    }
+
+    #[test]
+    fn visible_windows_keep_their_geometry() {
+        let monitors = [monitor("主屏", 0, 0, 1920, 1080), monitor("副屏", 1920, 0, 1280, 1024)];
+
+        // 大部分在屏幕外，但仍有足够区域可见
+        let state = window_at(1900 - MIN_VISIBLE_PX, 100, 300, 300, Some("主屏"));
+        assert_eq!(clamp_to_monitors(&state, &monitors), (1852, 100, 300, 300));
+
+        // 跨两个显示器
+        let state = window_at(1800, 500, 300, 300, Some("副屏"));
+        assert_eq!(clamp_to_monitors(&state, &monitors), (1800, 500, 300, 300));
+    }
+
+    #[test]
+    fn offscreen_windows_move_back_to_their_monitor() {
+        let monitors = [monitor("主屏", 0, 0, 1920, 1080), monitor("副屏", 1920, 0, 1280, 1024)];
+
+        // 副屏分辨率变小后窗口落在屏幕外，移回副屏右下角
+        let state = window_at(3500, 1500, 300, 300, Some("副屏"));
+        assert_eq!(clamp_to_monitors(&state, &monitors), (2900, 724, 300, 300));
+
+        // 只露出不足最小可见宽度的部分
+        let state = window_at(-300 + MIN_VISIBLE_PX - 1, 100, 300, 300, Some("主屏"));
+        assert_eq!(clamp_to_monitors(&state, &monitors), (0, 100, 300, 300));
+    }
+
+    #[test]
+    fn windows_from_disconnected_monitors_move_to_primary() {
+        let monitors = [monitor("主屏", 0, 0, 1920, 1080)];
+
+        let state = window_at(-1500, 200, 300, 300, Some("已断开"));
+        assert_eq!(clamp_to_monitors(&state, &monitors), (0, 200, 300, 300));
+
+        let state = window_at(2500, -800, 300, 300, None);
+        assert_eq!(clamp_to_monitors(&state, &monitors), (1620, 0, 300, 300));
+    }
+
+    #[test]
+    fn oversized_windows_shrink_to_the_monitor() {
+        let monitors = [monitor("主屏", 0, 0, 1280, 720)];
+
+        let state = window_at(5000, 5000, 2000, 1000, Some("主屏"));
+        assert_eq!(clamp_to_monitors(&state, &monitors), (0, 0, 1280, 720));
+    }
+
+    #[test]
+    fn no_monitors_leaves_geometry_unchanged() {
+        let state = window_at(-5000, -5000, 300, 300, None);
+        assert_eq!(clamp_to_monitors(&state, &[]), (-5000, -5000, 300, 300));
+    }
+}
@@ -0,0 +1,408 @@
+use std::io::{Error as IoError, ErrorKind};
+use chrono::{Datelike, Duration, LocalResult, NaiveDate, NaiveDateTime, Offset, TimeZone, Weekday};
+use chrono_tz::Tz;
+use crate::db::{NoteReminder, Recurrence};
+
+// 重复间隔的上限
+const MAX_INTERVAL: u32 = 999;
+
+// 一次最多列出的提醒时间数量
+pub const MAX_OCCURRENCES: usize = 366;
+
+// 支持的 RRULE 子集：
+//   FREQ=DAILY|WEEKLY|MONTHLY|YEARLY（必填）
+//   INTERVAL=n
+//   BYDAY=MO,TU,...（DAILY、WEEKLY、MONTHLY，不支持 1MO 这类序号）
+//   BYMONTHDAY=1,15,-1（MONTHLY，负数表示从月末倒数）
+//   UNTIL=20241231 或 20241231T235959Z
+//   WKST=MO
+#[derive(Clone, Copy, PartialEq)]
+enum Frequency {
+    Daily,
+    Weekly,
+    Monthly,
+    Yearly,
+}
+
+#[derive(Clone, Copy)]
+enum Until {
+    Date(NaiveDate),  // 当地日期，当天的提醒仍然有效
+    Time(i64),        // UTC 时间戳
+}
+
+struct RecurrenceRule {
+    freq: Frequency,
+    interval: u32,
+    by_day: Vec<Weekday>,
+    by_month_day: Vec<i32>,
+    until: Option<Until>,
+}
+
+// 提醒使用的时区：指定的 IANA 时区或系统时区
+enum Zone {
+    Named(Tz),
+    System,
+}
+
+fn invalid(message: String) -> IoError {
+    IoError::new(ErrorKind::InvalidInput, message)
+}
+
+// ======= 规则解析 =======
+
+fn parse_rule(rrule: &str) -> Result<RecurrenceRule, IoError> {
+    let rrule = rrule.trim();
+    let rrule = rrule.strip_prefix("RRULE:").unwrap_or(rrule);
+
+    let mut freq = None;
+    let mut rule = RecurrenceRule {
+        freq: Frequency::Daily,
+        interval: 1,
+        by_day: Vec::new(),
+        by_month_day: Vec::new(),
+        until: None,
+    };
+
+    for part in rrule.split(';').filter(|part| !part.is_empty()) {
+        let (key, value) = part.split_once('=')
+            .ok_or_else(|| invalid(format!("无效的规则片段: {}", part)))?;
+        match key.to_ascii_uppercase().as_str() {
+            "FREQ" => {
+                freq = Some(match value.to_ascii_uppercase().as_str() {
+                    "DAILY" => Frequency::Daily,
+                    "WEEKLY" => Frequency::Weekly,
+                    "MONTHLY" => Frequency::Monthly,
+                    "YEARLY" => Frequency::Yearly,
+                    _ => return Err(invalid(format!("不支持的重复频率: {}", value))),
+                });
+            }
+            "INTERVAL" => {
+                rule.interval = value.parse().ok()
+                    .filter(|interval| (1..=MAX_INTERVAL).contains(interval))
+                    .ok_or_else(|| invalid(format!("INTERVAL 必须是 1 到 {} 之间的整数", MAX_INTERVAL)))?;
+            }
+            "BYDAY" => {
+                for day in value.split(',') {
+                    rule.by_day.push(parse_weekday(day)?);
+                }
+            }
+            "BYMONTHDAY" => {
+                for day in value.split(',') {
+                    let day: i32 = day.trim().parse().ok()
+                        .filter(|day: &i32| *day != 0 && day.abs() <= 31)
+                        .ok_or_else(|| invalid(format!("无效的 BYMONTHDAY: {}", day)))?;
+                    rule.by_month_day.push(day);
+                }
+            }
+            "UNTIL" => rule.until = Some(parse_until(value)?),
+            "WKST" if value.eq_ignore_ascii_case("MO") => {}
+            _ => return Err(invalid(format!("不支持的规则: {}", part))),
+        }
+    }
+
+    rule.freq = freq.ok_or_else(|| invalid("重复规则缺少 FREQ".to_string()))?;
+    if !rule.by_day.is_empty() && rule.freq == Frequency::Yearly {
+        return Err(invalid("BYDAY 只能用于 DAILY、WEEKLY 或 MONTHLY".to_string()));
+    }
+    if !rule.by_month_day.is_empty() && rule.freq != Frequency::Monthly {
+        return Err(invalid("BYMONTHDAY 只能用于 MONTHLY".to_string()));
+    }
+    Ok(rule)
+}
+
+fn parse_weekday(day: &str) -> Result<Weekday, IoError> {
+    match day.trim().to_ascii_uppercase().as_str() {
+        "MO" => Ok(Weekday::Mon),
+        "TU" => Ok(Weekday::Tue),
+        "WE" => Ok(Weekday::Wed),
+        "TH" => Ok(Weekday::Thu),
+        "FR" => Ok(Weekday::Fri),
+        "SA" => Ok(Weekday::Sat),
+        "SU" => Ok(Weekday::Sun),
+        _ => Err(invalid(format!("不支持的 BYDAY: {}", day))),
+    }
+}
+
+fn parse_until(value: &str) -> Result<Until, IoError> {
+    let value = value.trim();
+    if let Ok(date) = NaiveDate::parse_from_str(value, "%Y%m%d") {
+        return Ok(Until::Date(date));
+    }
+    NaiveDateTime::parse_from_str(value, "%Y%m%dT%H%M%SZ")
+        .map(|time| Until::Time(chrono::Utc.from_utc_datetime(&time).timestamp()))
+        .map_err(|_| invalid(format!("无效的 UNTIL: {}", value)))
+}
+
+impl Zone {
+    fn parse(name: Option<&str>) -> Result<Zone, IoError> {
+        match name {
+            Some(name) => name.parse::<Tz>()
+                .map(Zone::Named)
+                .map_err(|_| invalid(format!("未知的时区: {}", name))),
+            None => Ok(Zone::System),
+        }
+    }
+
+    fn to_local(&self, timestamp: i64) -> Option<NaiveDateTime> {
+        match self {
+            Zone::Named(tz) => tz.timestamp_opt(timestamp, 0).single().map(|time| time.naive_local()),
+            Zone::System => chrono::Local.timestamp_opt(timestamp, 0).single().map(|time| time.naive_local()),
+        }
+    }
+
+    fn timestamp_of(&self, local: NaiveDateTime) -> Option<i64> {
+        match self {
+            Zone::Named(tz) => resolve_local(tz, local),
+            Zone::System => resolve_local(&chrono::Local, local),
+        }
+    }
+}
+
+// 当地时间转为时间戳：夏令时结束时重复出现的时刻取较早的一次；
+// 夏令时开始时不存在的时刻按跳变前的时差换算，即顺延到跳变之后（如 02:30 → 03:30）
+fn resolve_local<Z: TimeZone>(zone: &Z, local: NaiveDateTime) -> Option<i64> {
+    match zone.from_local_datetime(&local) {
+        LocalResult::Single(time) => Some(time.timestamp()),
+        LocalResult::Ambiguous(earliest, _) => Some(earliest.timestamp()),
+        LocalResult::None => {
+            let before_gap = zone.from_local_datetime(&(local - Duration::hours(3))).earliest()?;
+            let offset = before_gap.offset().fix().local_minus_utc() as i64;
+            Some(chrono::Utc.from_utc_datetime(&local).timestamp() - offset)
+        }
+    }
+}
+
+// ======= 计算提醒时间 =======
+
+impl RecurrenceRule {
+    // 向后查找的最大天数，足以覆盖每 N 年 2 月 29 日这类稀疏的规则
+    fn search_days(&self) -> i64 {
+        366 * 4 * self.interval as i64 + 366
+    }
+
+    // 按 dtstart 的当地时刻，找出 after 之后（不含）的第一次提醒时间
+    fn next_after(&self, dtstart: i64, zone: &Zone, after: i64) -> Option<i64> {
+        let start = zone.to_local(dtstart)?;
+        let after_local = zone.to_local(after)?;
+
+        // 从 after 的前一天开始找，跨越夏令时跳变时当地日期可能比时间戳早
+        let mut date = start.date().max(after_local.date() - Duration::days(1));
+        let last_date = date + Duration::days(self.search_days());
+        while date <= last_date {
+            if self.matches(start.date(), date) {
+                let occurrence = zone.timestamp_of(date.and_time(start.time()))?;
+                match self.until {
+                    Some(Until::Date(until)) if date > until => return None,
+                    Some(Until::Time(until)) if occurrence > until => return None,
+                    _ => {}
+                }
+                if occurrence > after && occurrence >= dtstart {
+                    return Some(occurrence);
+                }
+            }
+            date = date.succ_opt()?;
+        }
+        None
+    }
+
+    fn matches(&self, start: NaiveDate, date: NaiveDate) -> bool {
+        if date < start {
+            return false;
+        }
+        let interval = self.interval as i64;
+
+        match self.freq {
+            Frequency::Daily => {
+                (date - start).num_days() % interval == 0
+                    && (self.by_day.is_empty() || self.by_day.contains(&date.weekday()))
+            }
+            Frequency::Weekly => {
+                let weeks = (week_start(date) - week_start(start)).num_days() / 7;
+                // 没有 BYDAY 时每周与第一次提醒同一天
+                weeks % interval == 0 && if self.by_day.is_empty() {
+                    date.weekday() == start.weekday()
+                } else {
+                    self.by_day.contains(&date.weekday())
+                }
+            }
+            Frequency::Monthly => {
+                let months = (date.year() - start.year()) as i64 * 12 + date.month() as i64 - start.month() as i64;
+                if months % interval != 0 {
+                    return false;
+                }
+                if !self.by_month_day.is_empty() {
+                    self.by_month_day.iter().any(|day| month_day_matches(date, *day))
+                        && (self.by_day.is_empty() || self.by_day.contains(&date.weekday()))
+                } else if !self.by_day.is_empty() {
+                    self.by_day.contains(&date.weekday())
+                } else {
+                    // 没有该日期的月份（如 31 日）跳过
+                    date.day() == start.day()
+                }
+            }
+            Frequency::Yearly => {
+                (date.year() - start.year()) as i64 % interval == 0
+                    && date.month() == start.month()
+                    && date.day() == start.day()
+            }
+        }
+    }
+}
+
+fn week_start(date: NaiveDate) -> NaiveDate {
+    date - Duration::days(date.weekday().num_days_from_monday() as i64)
+}
+
+fn month_day_matches(date: NaiveDate, day: i32) -> bool {
+    if day > 0 {
+        return date.day() == day as u32;
+    }
+    let next_month = match date.month() {
+        12 => NaiveDate::from_ymd_opt(date.year() + 1, 1, 1),
+        month => NaiveDate::from_ymd_opt(date.year(), month + 1, 1),
+    };
+    let days_in_month = next_month.and_then(|d| d.pred_opt()).map_or(31, |d| d.day() as i32);
+    date.day() as i32 == days_in_month + day + 1
+}
+
+// ======= 对外接口 =======
+
+// 检查规则和时区是否有效
+pub fn validate(recurrence: &Recurrence) -> Result<(), IoError> {
+    parse_rule(&recurrence.rrule)?;
+    Zone::parse(recurrence.timezone.as_deref())?;
+    Ok(())
+}
+
+// after 之后的下一次提醒时间，规则已结束时返回 None
+pub fn next_occurrence(recurrence: &Recurrence, after: i64) -> Result<Option<i64>, IoError> {
+    let rule = parse_rule(&recurrence.rrule)?;
+    let zone = Zone::parse(recurrence.timezone.as_deref())?;
+    Ok(rule.next_after(recurrence.dtstart, &zone, after))
+}
+
+// after 之后的至多 count 次提醒时间
+pub fn occurrences(recurrence: &Recurrence, after: i64, count: usize) -> Result<Vec<i64>, IoError> {
+    let rule = parse_rule(&recurrence.rrule)?;
+    let zone = Zone::parse(recurrence.timezone.as_deref())?;
+
+    let mut times = Vec::new();
+    let mut after = after;
+    while times.len() < count.min(MAX_OCCURRENCES) {
+        match rule.next_after(recurrence.dtstart, &zone, after) {
+            Some(time) => {
+                times.push(time);
+                after = time;
+            }
+            None => break,
+        }
+    }
+    Ok(times)
+}
+
+// 提醒触发后：单次提醒标记为已完成；重复提醒改为 now 之后的下一次，
+// 应用关闭期间错过的提醒不再补发，规则结束后标记为已完成
+pub fn advance(reminder: &mut NoteReminder, now: i64) {
+    let next = match &reminder.recurrence {
+        Some(recurrence) => match next_occurrence(recurrence, now.max(reminder.reminder_time)) {
+            Ok(next) => next,
+            Err(e) => {
+                log::warn!("提醒 {:?} 的重复规则无效: {}", reminder.id, e);
+                None
+            }
+        },
+        None => None,
+    };
+
+    match next {
+        Some(next) => reminder.reminder_time = next,
+        None => reminder.completed = true,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono_tz::Europe::Berlin;
+
+    fn local(y: i32, m: u32, d: u32, h: u32, min: u32) -> NaiveDateTime {
+        NaiveDate::from_ymd_opt(y, m, d).unwrap().and_hms_opt(h, min, 0).unwrap()
+    }
+
+    fn utc(y: i32, m: u32, d: u32, h: u32, min: u32) -> i64 {
+        chrono::Utc.from_utc_datetime(&local(y, m, d, h, min)).timestamp()
+    }
+
+    fn recurrence(rrule: &str, timezone: &str, dtstart: i64) -> Recurrence {
+        Recurrence {
+            rrule: rrule.to_string(),
+            timezone: Some(timezone.to_string()),
+            dtstart,
+        }
+    }
+
+    // 从第一次提醒开始列出 count 次提醒时间
+    fn first_occurrences(recurrence: &Recurrence, count: usize) -> Vec<i64> {
+        occurrences(recurrence, recurrence.dtstart - 1, count).unwrap()
+    }
+
+    #[test]
+    fn time_in_spring_forward_gap_moves_past_the_jump() {
+        // 2024-03-31 柏林 02:00 跳到 03:00，02:30 不存在，按 CET 换算即 CEST 03:30
+        assert_eq!(resolve_local(&Berlin, local(2024, 3, 31, 2, 30)), Some(utc(2024, 3, 31, 1, 30)));
+
+        let daily = recurrence("FREQ=DAILY", "Europe/Berlin", utc(2024, 3, 30, 1, 30));
+        assert_eq!(first_occurrences(&daily, 3), vec![
+            utc(2024, 3, 30, 1, 30),  // 02:30 CET
+            utc(2024, 3, 31, 1, 30),  // 03:30 CEST
+            utc(2024, 4, 1, 0, 30),   // 02:30 CEST
+        ]);
+    }
+
+    #[test]
+    fn repeated_time_in_fall_back_overlap_uses_the_earlier_one() {
+        // 2024-10-27 柏林 03:00 回到 02:00，02:30 出现两次
+        assert_eq!(resolve_local(&Berlin, local(2024, 10, 27, 2, 30)), Some(utc(2024, 10, 27, 0, 30)));
+
+        let daily = recurrence("FREQ=DAILY", "Europe/Berlin", utc(2024, 10, 26, 0, 30));
+        assert_eq!(first_occurrences(&daily, 3), vec![
+            utc(2024, 10, 26, 0, 30),  // 02:30 CEST
+            utc(2024, 10, 27, 0, 30),  // 第一次 02:30（CEST）
+            utc(2024, 10, 28, 1, 30),  // 02:30 CET
+        ]);
+    }
+
+    #[test]
+    fn negative_month_day_is_counted_from_month_end() {
+        let monthly = recurrence("FREQ=MONTHLY;BYMONTHDAY=-1", "UTC", utc(2024, 1, 31, 9, 0));
+        assert_eq!(first_occurrences(&monthly, 4), vec![
+            utc(2024, 1, 31, 9, 0),
+            utc(2024, 2, 29, 9, 0),
+            utc(2024, 3, 31, 9, 0),
+            utc(2024, 4, 30, 9, 0),
+        ]);
+    }
+
+    #[test]
+    fn until_date_includes_the_whole_local_day() {
+        // 柏林 09:00 即 08:00 UTC，UNTIL 为日期时当天的提醒仍然有效
+        let daily = recurrence("FREQ=DAILY;UNTIL=20240103", "Europe/Berlin", utc(2024, 1, 1, 8, 0));
+        assert_eq!(first_occurrences(&daily, 10), vec![
+            utc(2024, 1, 1, 8, 0),
+            utc(2024, 1, 2, 8, 0),
+            utc(2024, 1, 3, 8, 0),
+        ]);
+    }
+
+    #[test]
+    fn until_time_is_compared_in_utc() {
+        let inclusive = recurrence("FREQ=DAILY;UNTIL=20240103T080000Z", "Europe/Berlin", utc(2024, 1, 1, 8, 0));
+        assert_eq!(first_occurrences(&inclusive, 10).len(), 3);
+
+        let before_last = recurrence("FREQ=DAILY;UNTIL=20240103T075959Z", "Europe/Berlin", utc(2024, 1, 1, 8, 0));
+        assert_eq!(first_occurrences(&before_last, 10), vec![
+            utc(2024, 1, 1, 8, 0),
+            utc(2024, 1, 2, 8, 0),
+        ]);
+    }
+}
@@ -1,34 +1,39 @@
 use std::sync::Mutex;
 use std::time::Duration;
 use tauri::{AppHandle, Manager};
-use crate::commands::{DatabaseState, ReminderResponse};
+use crate::commands::reminder_to_response;
+use crate::recurrence;
+use crate::store::StoreState;
 
-pub fn start_reminder_service(app: AppHandle, db: DatabaseState) {
+pub fn start_reminder_service(app: AppHandle) {
     // 创建一个互斥锁来控制服务状态
     let running = std::sync::Arc::new(Mutex::new(true));
     let running_clone = running.clone();
+    let app_handle = app.clone();
 
     // 启动检查线程
     std::thread::spawn(move || {
         while *running_clone.lock().unwrap() {
-            // 检查待触发的提醒
-            if let Ok(reminders) = db.0.lock().unwrap().get_pending_reminders() {
-                for (reminder, content) in reminders {
+            let now = chrono::Utc::now().timestamp();
+            let store = app_handle.state::<StoreState>().0.clone();
+
+            // 检查到期的提醒
+            if let Ok(reminders) = store.get_upcoming_reminders(None) {
+                for (mut reminder, note) in reminders {
+                    if reminder.reminder_time > now {
+                        break;
+                    }
+
+                    // 标记为已触发，避免重复提醒；重复提醒改为下一次的时间
+                    let fired = reminder.clone();
+                    recurrence::advance(&mut reminder, now);
+                    if let Err(e) = store.save_reminder(&reminder) {
+                        log::warn!("更新提醒状态失败: {}", e);
+                        continue;
+                    }
+
                     // 发送提醒事件到前端
-                    let _ = app.emit_all("reminder", ReminderResponse {
-                        id: reminder.id,
-                        note_id: reminder.note_id,
-                        remind_at: reminder.remind_at
-                            .duration_since(std::time::UNIX_EPOCH)
-                            .unwrap()
-                            .as_secs(),
-                        is_triggered: reminder.is_triggered,
-                        created_at: reminder.created_at
-                            .duration_since(std::time::UNIX_EPOCH)
-                            .unwrap()
-                            .as_secs(),
-                        note_content: content,
-                    });
+                    let _ = app_handle.emit_all("reminder", reminder_to_response(fired, note));
                 }
             }
 
@@ -41,4 +46,4 @@ pub fn start_reminder_service(app: AppHandle, db: DatabaseState) {
     app.listen_global("tauri://close-requested", move |_| {
         *running.lock().unwrap() = false;
     });
-} 
\ No newline at end of file
+}
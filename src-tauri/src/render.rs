@@ -0,0 +1,120 @@
+use ammonia::Builder;
+use pulldown_cmark::{html, Options, Parser};
+use crate::db::ContentFormat;
+
+// 将便签内容渲染为可直接插入 webview 的 HTML
+pub fn render_html(content: &str, format: ContentFormat) -> String {
+    match format {
+        ContentFormat::Markdown => render_markdown(content),
+        ContentFormat::Plain => render_plain(content),
+    }
+}
+
+// 渲染 Markdown（含表格、任务列表、删除线和代码块），结果经过清理
+pub fn render_markdown(content: &str) -> String {
+    let mut options = Options::empty();
+    options.insert(Options::ENABLE_TABLES);
+    options.insert(Options::ENABLE_TASKLISTS);
+    options.insert(Options::ENABLE_STRIKETHROUGH);
+
+    let mut raw_html = String::with_capacity(content.len() * 3 / 2);
+    html::push_html(&mut raw_html, Parser::new_ext(content, options));
+
+    sanitizer().clean(&raw_html).to_string()
+}
+
+// 纯文本按空行分段，段内换行保留为 <br>
+pub fn render_plain(content: &str) -> String {
+    let mut html = String::with_capacity(content.len() + 16);
+    for paragraph in content.split("\n\n").map(|p| p.trim_matches('\n')).filter(|p| !p.trim().is_empty()) {
+        html.push_str("<p>");
+        for (i, line) in paragraph.lines().enumerate() {
+            if i > 0 {
+                html.push_str("<br>");
+            }
+            html.push_str(&escape_html(line));
+        }
+        html.push_str("</p>\n");
+    }
+    html
+}
+
+pub fn escape_html(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            _ => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+// 在 ammonia 默认规则（去掉 script、事件属性和 javascript: 链接）的基础上
+// 放行任务列表的复选框、代码块的语言标记和附件使用的 asset: 链接
+fn sanitizer() -> Builder<'static> {
+    let mut builder = Builder::default();
+    builder
+        .add_tags(&["input"])
+        .add_tag_attributes("input", &["type", "checked", "disabled"])
+        .add_tag_attributes("code", &["class"])
+        .add_url_schemes(&["asset"])
+        .attribute_filter(|element, attribute, value| match (element, attribute) {
+            ("input", "type") if value != "checkbox" => None,
+            ("code", "class") if !value.starts_with("language-") => None,
+            _ => Some(value.into()),
+        });
+    builder
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn markdown_strips_scripts_and_javascript_links() {
+        let html = render_markdown(
+            "<script>alert(1)</script>\n\n[点我](javascript:alert(1)) <img src=x onerror=\"alert(1)\"> <a href=\"vbscript:msgbox\">链接</a>",
+        );
+        assert!(!html.contains("<script"));
+        assert!(!html.contains("alert(1)</script>"));
+        assert!(!html.contains("javascript:"));
+        assert!(!html.contains("vbscript:"));
+        assert!(!html.contains("onerror"));
+        assert!(html.contains("点我"));
+    }
+
+    #[test]
+    fn markdown_keeps_task_lists_tables_and_attachments() {
+        let html = render_markdown(
+            "- [x] 完成\n- [ ] 待办\n\n| 名称 | 数量 |\n| --- | --- |\n| 牛奶 | 2 |\n\n~~删除~~\n\n```rust\nfn main() {}\n```\n\n![截图](asset://localhost/%2Ftmp%2Fa.png)",
+        );
+        assert!(html.contains("<input disabled=\"\" type=\"checkbox\" checked=\"\">"));
+        assert!(html.contains("<input disabled=\"\" type=\"checkbox\">"));
+        assert!(html.contains("<table>"));
+        assert!(html.contains("<td>牛奶</td>"));
+        assert!(html.contains("<del>删除</del>"));
+        assert!(html.contains("<code class=\"language-rust\">"));
+        assert!(html.contains("src=\"asset://localhost/%2Ftmp%2Fa.png\""));
+    }
+
+    #[test]
+    fn sanitizer_rejects_other_inputs_and_code_classes() {
+        let html = render_markdown("<input type=\"text\" value=\"x\"> <code class=\"evil\">x</code>");
+        assert!(!html.contains("type=\"text\""));
+        assert!(!html.contains("value="));
+        assert!(!html.contains("evil"));
+    }
+
+    #[test]
+    fn plain_text_is_escaped_and_split_into_paragraphs() {
+        let html = render_plain("第一行\n<b>第二行</b>\n\n\n第二段 & \"引号\"");
+        assert_eq!(html, "<p>第一行<br>&lt;b&gt;第二行&lt;/b&gt;</p>\n<p>第二段 &amp; &quot;引号&quot;</p>\n");
+        assert_eq!(render_html("**粗体**", ContentFormat::Plain), "<p>**粗体**</p>\n");
+        assert_eq!(render_html("**粗体**", ContentFormat::Markdown), "<p><strong>粗体</strong></p>\n");
+    }
+}
@@ -1,7 +1,5 @@
 use tauri::{AppHandle, Manager, Runtime, Window};
-use std::path::PathBuf;
-use std::fs;
-use chrono::Local;
+use crate::db;
 use image::{ImageBuffer, Rgba};
 use serde::{Deserialize, Serialize};
 use std::time::Duration;
@@ -45,35 +43,22 @@ static SCREENSHOT_STATE: Lazy<Arc<Mutex<ScreenshotState>>> = Lazy::new(|| {
     }))
 });
 
-// 获取截图保存目录
-fn get_screenshot_dir() -> PathBuf {
-    let app_data_dir = match dirs::data_dir() {
-        Some(dir) => dir,
-        None => PathBuf::from("."),
-    };
-    
-    let screenshots_dir = app_data_dir.join("stickynotes").join("screenshots");
-    
-    // 确保目录存在
-    if !screenshots_dir.exists() {
-        fs::create_dir_all(&screenshots_dir).expect("无法创建截图目录");
-    }
-    
-    screenshots_dir
-}
-
-// 生成截图文件名
-fn generate_screenshot_filename() -> String {
-    let now = Local::now();
-    format!("screenshot_{}.png", now.format("%Y%m%d_%H%M%S"))
+// 将图像编码为PNG并存入附件存储，返回文件路径
+fn store_png(img: &DynamicImage) -> Result<String, String> {
+    let mut buffer = Vec::new();
+    img.write_to(&mut Cursor::new(&mut buffer), image::ImageOutputFormat::Png)
+        .map_err(|e| format!("无法编码截图: {}", e))?;
+    
+    let attachment = db::add_attachment(&buffer, "image/png", None)
+        .map_err(|e| format!("无法保存截图: {}", e))?;
+    let path = attachment.path()
+        .map_err(|e| format!("无法获取截图路径: {}", e))?;
+    
+    Ok(path.to_string_lossy().to_string())
 }
 
 // 保存截图
 pub fn save_screenshot(data: &[u8], width: u32, height: u32) -> Result<ScreenshotResult, String> {
-    let dir = get_screenshot_dir();
-    let filename = generate_screenshot_filename();
-    let path = dir.join(&filename);
-    
     // 将二进制数据转换为图像
     let img = match image::load_from_memory(data) {
         Ok(img) => img,
@@ -81,18 +66,14 @@ pub fn save_screenshot(data: &[u8], width: u32, height: u32) -> Result<Screensho
     };
     
     // 保存图像
-    match img.save(&path) {
-        Ok(_) => {
-            let timestamp = chrono::Utc::now().timestamp() as u64;
-            Ok(ScreenshotResult {
-                path: path.to_string_lossy().to_string(),
-                width,
-                height, 
-                timestamp,
-            })
-        },
-        Err(e) => Err(format!("无法保存截图: {}", e)),
-    }
+    let path = store_png(&img)?;
+    let timestamp = chrono::Utc::now().timestamp() as u64;
+    Ok(ScreenshotResult {
+        path,
+        width,
+        height, 
+        timestamp,
+    })
 }
 
 // 触发截图功能
@@ -305,27 +286,7 @@ fn crop_image(base64_image: &str, x: i32, y: i32, width: u32, height: u32) -> Re
     Ok(cropped)
 }
 
-// 保存图像到文件
+// 保存图像到附件存储
 fn save_image_to_file(img: DynamicImage) -> Result<String, String> {
-    // 获取应用数据目录
-    let app_data_dir = dirs::data_dir()
-        .ok_or_else(|| "无法获取应用数据目录".to_string())?
-        .join("sticky-notes-app")
-        .join("screenshots");
-    
-    // 确保目录存在
-    std::fs::create_dir_all(&app_data_dir)
-        .map_err(|e| format!("无法创建截图目录: {}", e))?;
-    
-    // 生成唯一文件名
-    let timestamp = chrono::Local::now().format("%Y%m%d_%H%M%S");
-    let filename = format!("screenshot_{}.png", timestamp);
-    let file_path = app_data_dir.join(&filename);
-    
-    // 保存图像
-    img.save(&file_path)
-        .map_err(|e| format!("保存图像失败: {}", e))?;
-    
-    // 返回文件路径
-    Ok(file_path.to_string_lossy().to_string())
-} 
\ No newline at end of file
+    store_png(&img)
+}
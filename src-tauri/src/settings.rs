@@ -8,6 +8,10 @@ pub struct Settings {
     pub shortcuts: Shortcuts,
     pub appearance: Appearance,
     pub security: Security,
+    #[serde(default)]
+    pub history: History,
+    #[serde(default)]
+    pub backup: AutoBackup,
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
@@ -37,6 +41,46 @@ pub struct Security {
     pub encryption_enabled: bool,
 }
 
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct History {
+    pub max_revisions: u32,  // 每个便签保留的历史版本数量，0 表示不记录
+}
+
+impl Default for History {
+    fn default() -> Self {
+        History {
+            max_revisions: crate::db::DEFAULT_REVISION_LIMIT as u32,
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct AutoBackup {
+    pub enabled: bool,
+    pub interval: BackupInterval,
+    pub directory: Option<String>,  // 快照保存目录，未设置时使用数据目录下的 backups
+    pub keep_daily: u32,            // 保留最近几天的快照（每天一份）
+    pub keep_weekly: u32,           // 保留最近几周的快照（每周一份）
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy)]
+pub enum BackupInterval {
+    Daily,
+    Weekly,
+}
+
+impl Default for AutoBackup {
+    fn default() -> Self {
+        AutoBackup {
+            enabled: false,
+            interval: BackupInterval::Daily,
+            directory: None,
+            keep_daily: 7,
+            keep_weekly: 4,
+        }
+    }
+}
+
 impl Default for Settings {
     fn default() -> Self {
         Settings {
@@ -54,6 +98,8 @@ impl Default for Settings {
                 lock_timeout: 5,
                 encryption_enabled: true,
             },
+            history: History::default(),
+            backup: AutoBackup::default(),
         }
     }
 }
@@ -88,7 +134,9 @@ impl Settings {
 
 #[tauri::command]
 pub async fn update_settings(settings: Settings) -> Result<(), String> {
-    settings.save().map_err(|e| e.to_string())
+    settings.save().map_err(|e| e.to_string())?;
+    crate::db::set_revision_limit(settings.history.max_revisions as i64);
+    Ok(())
 }
 
 #[tauri::command]
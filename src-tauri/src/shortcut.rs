@@ -3,7 +3,7 @@ use tauri::{
     plugin::global_shortcut::{GlobalShortcut, GlobalShortcutEvent, GlobalShortcutManager},
     AppHandle, Manager, Runtime, Window,
 };
-use crate::commands::DatabaseState;
+use crate::lock;
 use crate::screenshot;
 use crate::settings::Settings;
 use std::sync::Mutex;
@@ -38,6 +38,7 @@ static SHORTCUT_MAP: Lazy<Mutex<HashMap<String, String>>> = Lazy::new(|| {
     // 默认快捷键
     map.insert("open_main_window".to_string(), "CommandOrControl+Shift+C".to_string());
     map.insert("trigger_screenshot".to_string(), "CommandOrControl+Shift+X".to_string());
+    map.insert("lock_app".to_string(), "CommandOrControl+Shift+L".to_string());
     Mutex::new(map)
 });
 
@@ -76,6 +77,16 @@ pub fn register_shortcuts<R: Runtime>(app: &AppHandle<R>) -> Result<(), String>
         ).map_err(|e| format!("注册截图快捷键失败: {}", e))?;
     }
     
+    // 注册"立即锁定"快捷键
+    if let Some(shortcut) = shortcuts.get("lock_app") {
+        register_shortcut(
+            &mut shortcut_manager, 
+            shortcut, 
+            app.clone(), 
+            |app| lock_app(app)
+        ).map_err(|e| format!("注册锁定快捷键失败: {}", e))?;
+    }
+    
     Ok(())
 }
 
@@ -160,6 +171,13 @@ fn trigger_screenshot<R: Runtime>(app: AppHandle<R>) {
     }
 }
 
+// 立即锁定应用的操作
+fn lock_app<R: Runtime>(app: AppHandle<R>) {
+    if let Err(e) = lock::lock(&app) {
+        show_notification("锁定失败", &e.to_string());
+    }
+}
+
 // 显示系统通知
 fn show_notification(title: &str, message: &str) {
     NotifyNotification::new()
@@ -0,0 +1,379 @@
+use crate::db::{self, Attachment, Category, CategoryNode, ChecklistItem, ChecklistProgress, ChildCategoryAction, Note, NoteLink, NoteReminder, NoteRevision, NoteSortOption, SearchResult, Tag};
+use std::collections::HashMap;
+use std::io::{Error as IoError, ErrorKind};
+use std::sync::Arc;
+
+// 便签存储接口，覆盖便签、归档、清单、链接、回收站、分类、提醒、标签、历史版本、附件、搜索和加密
+// SqliteStore 使用加密数据库，MemoryStore 将数据保存在内存中，用于测试命令逻辑
+pub trait NoteStore {
+    // ======= 便签 =======
+
+    // 创建或更新便签，返回便签ID
+    fn save_note(&self, note: &Note) -> Result<i64, IoError>;
+    fn get_note(&self, id: i64) -> Result<Option<Note>, IoError>;
+    fn get_notes_sorted(
+        &self,
+        sort: &NoteSortOption,
+        limit: Option<i64>,
+        offset: Option<i64>,
+        search: Option<&str>,
+        tag_ids: &[i64],
+    ) -> Result<Vec<Note>, IoError>;
+    // 切换置顶状态，返回新的状态
+    fn toggle_note_pin(&self, id: i64) -> Result<bool, IoError>;
+
+    // ======= 归档 =======
+
+    // 批量归档、取消归档，要么全部生效要么都不生效
+    fn archive_notes(&self, ids: &[i64], timestamp: i64) -> Result<(), IoError>;
+    fn unarchive_notes(&self, ids: &[i64]) -> Result<(), IoError>;
+    // 已归档且未删除的便签，最近归档的在前
+    fn get_archived_notes(&self) -> Result<Vec<Note>, IoError>;
+
+    // ======= 清单 =======
+
+    // 清单的所有条目，按顺序排列
+    fn get_checklist_items(&self, note_id: i64) -> Result<Vec<ChecklistItem>, IoError>;
+    // 在清单末尾添加条目，返回条目ID
+    fn add_checklist_item(&self, note_id: i64, content: &str, due_at: Option<i64>) -> Result<i64, IoError>;
+    fn update_checklist_item(&self, id: i64, content: &str, due_at: Option<i64>) -> Result<(), IoError>;
+    // 切换完成状态，返回新的状态
+    fn toggle_checklist_item(&self, id: i64) -> Result<bool, IoError>;
+    // 按列表顺序写入条目位置
+    fn reorder_checklist_items(&self, note_id: i64, item_ids: &[i64]) -> Result<(), IoError>;
+    fn delete_checklist_item(&self, id: i64) -> Result<(), IoError>;
+    // 多个清单的完成进度，没有条目的清单不在结果中
+    fn get_checklist_progress(&self, note_ids: &[i64]) -> Result<HashMap<i64, ChecklistProgress>, IoError>;
+
+    // 将文本便签转换为清单，任务列表行（- [ ] / - [x]）变为条目，其余内容保留，
+    // 必须整体完成或不做任何修改
+    fn convert_to_checklist(&self, note_id: i64) -> Result<(), IoError>;
+
+    // ======= 链接 =======
+
+    // 便签内容中的 [[链接]]，按出现顺序
+    fn get_outgoing_links(&self, note_id: i64) -> Result<Vec<NoteLink>, IoError>;
+    // 链接到该便签的未删除便签
+    fn get_backlinks(&self, note_id: i64) -> Result<Vec<NoteLink>, IoError>;
+
+    // ======= 回收站 =======
+
+    fn move_note_to_trash(&self, id: i64, timestamp: i64) -> Result<(), IoError>;
+    fn restore_note_from_trash(&self, id: i64) -> Result<(), IoError>;
+    fn delete_note_permanently(&self, id: i64) -> Result<(), IoError>;
+    fn get_trash_notes(&self) -> Result<Vec<Note>, IoError>;
+    // 永久删除在回收站中超过 days 天的便签，返回删除数量
+    fn cleanup_trash(&self, days: i64) -> Result<i32, IoError>;
+
+    // ======= 分类 =======
+
+    fn save_category(&self, category: &Category) -> Result<i64, IoError>;
+    // 按同级排序位置返回所有分类
+    fn get_all_categories(&self) -> Result<Vec<Category>, IoError>;
+    fn move_category(&self, id: i64, parent_id: Option<i64>) -> Result<(), IoError>;
+    fn delete_category(&self, id: i64, children: ChildCategoryAction) -> Result<(), IoError>;
+    fn get_notes_in_category(&self, category_id: i64, include_descendants: bool, sort: &NoteSortOption) -> Result<Vec<Note>, IoError>;
+    // 按列表顺序写入分类和便签的手动排序位置
+    fn save_manual_order(&self, category_ids: &[i64], note_ids: &[i64]) -> Result<(), IoError>;
+
+    fn get_category_tree(&self) -> Result<Vec<CategoryNode>, IoError> {
+        Ok(db::build_category_tree(self.get_all_categories()?))
+    }
+
+    // 将分类移动到同级分类中的指定位置，其他同级分类依次顺延
+    fn update_category_order(&self, id: i64, new_order: i32) -> Result<(), IoError> {
+        let categories = self.get_all_categories()?;
+        let parent_id = match categories.iter().find(|c| c.id == Some(id)) {
+            Some(category) => category.parent_id,
+            None => return Err(IoError::new(ErrorKind::NotFound, "分类不存在")),
+        };
+
+        let mut sibling_ids: Vec<i64> = categories.iter()
+            .filter(|c| c.parent_id == parent_id && c.id != Some(id))
+            .filter_map(|c| c.id)
+            .collect();
+        let index = (new_order.max(0) as usize).min(sibling_ids.len());
+        sibling_ids.insert(index, id);
+
+        self.save_manual_order(&sibling_ids, &[])
+    }
+
+    // ======= 提醒 =======
+
+    fn save_reminder(&self, reminder: &NoteReminder) -> Result<i64, IoError>;
+    fn get_reminders_by_note(&self, note_id: i64) -> Result<Vec<NoteReminder>, IoError>;
+    fn delete_reminder(&self, id: i64) -> Result<(), IoError>;
+    // 未完成的提醒及其便签，按提醒时间升序
+    fn get_upcoming_reminders(&self, limit: Option<i64>) -> Result<Vec<(NoteReminder, Note)>, IoError>;
+
+    // ======= 标签 =======
+
+    // 为便签手动添加标签，返回标签ID
+    fn add_tag_to_note(&self, note_id: i64, name: &str) -> Result<i64, IoError>;
+    fn remove_tag_from_note(&self, note_id: i64, tag_id: i64) -> Result<(), IoError>;
+    fn get_note_tags(&self, note_id: i64) -> Result<Vec<Tag>, IoError>;
+    // 所有标签及使用次数，按名称排列
+    fn get_all_tags(&self) -> Result<Vec<Tag>, IoError>;
+    // 重命名标签并改写内容中的 #标签，返回重命名后的标签ID（与已有标签重名时为合并后的标签）
+    fn rename_tag(&self, id: i64, name: &str) -> Result<i64, IoError>;
+    fn merge_tags(&self, source_ids: &[i64], target_id: i64) -> Result<(), IoError>;
+
+    // ======= 历史版本 =======
+
+    // 便签的历史版本，最新的在前
+    fn get_note_revisions(&self, note_id: i64) -> Result<Vec<NoteRevision>, IoError>;
+    fn get_note_revision(&self, id: i64) -> Result<Option<NoteRevision>, IoError>;
+    // 将历史版本恢复为便签当前内容，返回便签ID
+    fn restore_note_revision(&self, revision_id: i64) -> Result<i64, IoError>;
+
+    // ======= 附件 =======
+
+    // 保存附件内容，note_id 不为空时同时关联到便签
+    fn add_attachment(&self, bytes: &[u8], mime: &str, note_id: Option<i64>) -> Result<Attachment, IoError>;
+    fn attach_to_note(&self, note_id: i64, attachment_id: i64) -> Result<(), IoError>;
+    fn detach_from_note(&self, note_id: i64, attachment_id: i64) -> Result<(), IoError>;
+    fn get_note_attachments(&self, note_id: i64) -> Result<Vec<Attachment>, IoError>;
+    // 回收没有便签引用的附件，返回删除数量
+    fn collect_attachment_garbage(&self) -> Result<usize, IoError>;
+
+    // ======= 搜索 =======
+
+    // 全文搜索未删除的便签，按相关度排序并附带高亮摘要
+    fn search_notes(&self, query: &str, category_id: Option<i64>, limit: Option<i64>) -> Result<Vec<SearchResult>, IoError>;
+
+    // ======= 加密 =======
+
+    // 更换加密密钥，启用主密码时返回新的恢复密钥
+    fn rotate_encryption_key(&self, password: Option<&str>) -> Result<Option<String>, IoError>;
+}
+
+// Tauri 托管的存储状态，存储实现自身负责并发控制，多个命令可以同时读取
+pub struct StoreState(pub Arc<dyn NoteStore + Send + Sync>);
+
+impl StoreState {
+    pub fn new(store: impl NoteStore + Send + Sync + 'static) -> Self {
+        StoreState(Arc::new(store))
+    }
+
+    // 在阻塞线程池中执行存储操作，异步命令等待结果时不会阻塞运行时
+    pub async fn run<T, F>(&self, f: F) -> Result<T, IoError>
+    where
+        T: Send + 'static,
+        F: FnOnce(&dyn NoteStore) -> Result<T, IoError> + Send + 'static,
+    {
+        let store = self.0.clone();
+        db::run_blocking(move || f(store.as_ref())).await
+    }
+}
+
+// 基于 SQLCipher 加密数据库的存储，需先调用 db::init_db
+pub struct SqliteStore;
+
+impl NoteStore for SqliteStore {
+    fn save_note(&self, note: &Note) -> Result<i64, IoError> {
+        db::save_note(note)
+    }
+
+    fn get_note(&self, id: i64) -> Result<Option<Note>, IoError> {
+        db::get_note(id)
+    }
+
+    fn get_notes_sorted(
+        &self,
+        sort: &NoteSortOption,
+        limit: Option<i64>,
+        offset: Option<i64>,
+        search: Option<&str>,
+        tag_ids: &[i64],
+    ) -> Result<Vec<Note>, IoError> {
+        db::get_notes_sorted(sort, limit, offset, search, tag_ids)
+    }
+
+    fn toggle_note_pin(&self, id: i64) -> Result<bool, IoError> {
+        db::toggle_note_pin(id)
+    }
+
+    fn archive_notes(&self, ids: &[i64], timestamp: i64) -> Result<(), IoError> {
+        db::archive_notes(ids, timestamp)
+    }
+
+    fn unarchive_notes(&self, ids: &[i64]) -> Result<(), IoError> {
+        db::unarchive_notes(ids)
+    }
+
+    fn get_archived_notes(&self) -> Result<Vec<Note>, IoError> {
+        db::get_archived_notes()
+    }
+
+    fn get_checklist_items(&self, note_id: i64) -> Result<Vec<ChecklistItem>, IoError> {
+        db::get_checklist_items(note_id)
+    }
+
+    fn add_checklist_item(&self, note_id: i64, content: &str, due_at: Option<i64>) -> Result<i64, IoError> {
+        db::add_checklist_item(note_id, content, due_at)
+    }
+
+    fn update_checklist_item(&self, id: i64, content: &str, due_at: Option<i64>) -> Result<(), IoError> {
+        db::update_checklist_item(id, content, due_at)
+    }
+
+    fn toggle_checklist_item(&self, id: i64) -> Result<bool, IoError> {
+        db::toggle_checklist_item(id)
+    }
+
+    fn reorder_checklist_items(&self, note_id: i64, item_ids: &[i64]) -> Result<(), IoError> {
+        db::reorder_checklist_items(note_id, item_ids)
+    }
+
+    fn delete_checklist_item(&self, id: i64) -> Result<(), IoError> {
+        db::delete_checklist_item(id)
+    }
+
+    fn get_checklist_progress(&self, note_ids: &[i64]) -> Result<HashMap<i64, ChecklistProgress>, IoError> {
+        db::get_checklist_progress(note_ids)
+    }
+
+    fn convert_to_checklist(&self, note_id: i64) -> Result<(), IoError> {
+        db::convert_to_checklist(note_id)
+    }
+
+    fn get_outgoing_links(&self, note_id: i64) -> Result<Vec<NoteLink>, IoError> {
+        db::get_outgoing_links(note_id)
+    }
+
+    fn get_backlinks(&self, note_id: i64) -> Result<Vec<NoteLink>, IoError> {
+        db::get_backlinks(note_id)
+    }
+
+    fn move_note_to_trash(&self, id: i64, timestamp: i64) -> Result<(), IoError> {
+        db::move_note_to_trash(id, timestamp)
+    }
+
+    fn restore_note_from_trash(&self, id: i64) -> Result<(), IoError> {
+        db::restore_note_from_trash(id)
+    }
+
+    fn delete_note_permanently(&self, id: i64) -> Result<(), IoError> {
+        db::delete_note_permanently(id)
+    }
+
+    fn get_trash_notes(&self) -> Result<Vec<Note>, IoError> {
+        db::get_trash_notes()
+    }
+
+    fn cleanup_trash(&self, days: i64) -> Result<i32, IoError> {
+        db::cleanup_trash(days)
+    }
+
+    fn save_category(&self, category: &Category) -> Result<i64, IoError> {
+        db::save_category(category)
+    }
+
+    fn get_all_categories(&self) -> Result<Vec<Category>, IoError> {
+        db::get_all_categories()
+    }
+
+    fn move_category(&self, id: i64, parent_id: Option<i64>) -> Result<(), IoError> {
+        db::move_category(id, parent_id)
+    }
+
+    fn delete_category(&self, id: i64, children: ChildCategoryAction) -> Result<(), IoError> {
+        db::delete_category(id, children)
+    }
+
+    fn get_notes_in_category(&self, category_id: i64, include_descendants: bool, sort: &NoteSortOption) -> Result<Vec<Note>, IoError> {
+        db::get_notes_in_category(category_id, include_descendants, sort)
+    }
+
+    fn save_manual_order(&self, category_ids: &[i64], note_ids: &[i64]) -> Result<(), IoError> {
+        db::save_manual_order(category_ids, note_ids)
+    }
+
+    fn get_category_tree(&self) -> Result<Vec<CategoryNode>, IoError> {
+        db::get_category_tree()
+    }
+
+    fn update_category_order(&self, id: i64, new_order: i32) -> Result<(), IoError> {
+        db::update_category_order(id, new_order)
+    }
+
+    fn save_reminder(&self, reminder: &NoteReminder) -> Result<i64, IoError> {
+        db::save_reminder(reminder)
+    }
+
+    fn get_reminders_by_note(&self, note_id: i64) -> Result<Vec<NoteReminder>, IoError> {
+        db::get_reminders_by_note(note_id)
+    }
+
+    fn delete_reminder(&self, id: i64) -> Result<(), IoError> {
+        db::delete_reminder(id)
+    }
+
+    fn get_upcoming_reminders(&self, limit: Option<i64>) -> Result<Vec<(NoteReminder, Note)>, IoError> {
+        db::get_upcoming_reminders(limit)
+    }
+
+    fn add_tag_to_note(&self, note_id: i64, name: &str) -> Result<i64, IoError> {
+        db::add_tag_to_note(note_id, name)
+    }
+
+    fn remove_tag_from_note(&self, note_id: i64, tag_id: i64) -> Result<(), IoError> {
+        db::remove_tag_from_note(note_id, tag_id)
+    }
+
+    fn get_note_tags(&self, note_id: i64) -> Result<Vec<Tag>, IoError> {
+        db::get_note_tags(note_id)
+    }
+
+    fn get_all_tags(&self) -> Result<Vec<Tag>, IoError> {
+        db::get_all_tags()
+    }
+
+    fn rename_tag(&self, id: i64, name: &str) -> Result<i64, IoError> {
+        db::rename_tag(id, name)
+    }
+
+    fn merge_tags(&self, source_ids: &[i64], target_id: i64) -> Result<(), IoError> {
+        db::merge_tags(source_ids, target_id)
+    }
+
+    fn get_note_revisions(&self, note_id: i64) -> Result<Vec<NoteRevision>, IoError> {
+        db::get_note_revisions(note_id)
+    }
+
+    fn get_note_revision(&self, id: i64) -> Result<Option<NoteRevision>, IoError> {
+        db::get_note_revision(id)
+    }
+
+    fn restore_note_revision(&self, revision_id: i64) -> Result<i64, IoError> {
+        db::restore_note_revision(revision_id)
+    }
+
+    fn add_attachment(&self, bytes: &[u8], mime: &str, note_id: Option<i64>) -> Result<Attachment, IoError> {
+        db::add_attachment(bytes, mime, note_id)
+    }
+
+    fn attach_to_note(&self, note_id: i64, attachment_id: i64) -> Result<(), IoError> {
+        db::attach_to_note(note_id, attachment_id)
+    }
+
+    fn detach_from_note(&self, note_id: i64, attachment_id: i64) -> Result<(), IoError> {
+        db::detach_from_note(note_id, attachment_id)
+    }
+
+    fn get_note_attachments(&self, note_id: i64) -> Result<Vec<Attachment>, IoError> {
+        db::get_note_attachments(note_id)
+    }
+
+    fn collect_attachment_garbage(&self) -> Result<usize, IoError> {
+        db::collect_attachment_garbage()
+    }
+
+    fn search_notes(&self, query: &str, category_id: Option<i64>, limit: Option<i64>) -> Result<Vec<SearchResult>, IoError> {
+        db::search_notes(query, category_id, limit)
+    }
+
+    fn rotate_encryption_key(&self, password: Option<&str>) -> Result<Option<String>, IoError> {
+        db::rotate_encryption_key(password)
+    }
+}
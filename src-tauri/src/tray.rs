@@ -1,23 +1,33 @@
 use tauri::{
-    AppHandle, CustomMenuItem, Manager, Tray, TrayEvent,
+    AppHandle, CustomMenuItem, Manager, Runtime, Tray, TrayEvent,
     TrayMenu, TrayMenuItem, Window,
 };
+use chrono::TimeZone;
 use window_vibrancy::apply_blur;
+use crate::auto_backup;
+use crate::lock;
+use crate::note_window;
 
 /// 创建系统托盘菜单
 pub fn create_tray_menu() -> TrayMenu {
     // 创建菜单项
     let show = CustomMenuItem::new("show".to_string(), "显示主窗口");
     let new_note = CustomMenuItem::new("new_note".to_string(), "新建便签");
+    let lock = CustomMenuItem::new("lock".to_string(), "立即锁定");
     let settings = CustomMenuItem::new("settings".to_string(), "设置");
+    // 仅用于显示，不可点击
+    let last_backup_time = auto_backup::load_status().ok().and_then(|status| status.last_success);
+    let last_backup = CustomMenuItem::new("last_backup".to_string(), last_backup_title(last_backup_time)).disabled();
     let quit = CustomMenuItem::new("quit".to_string(), "退出");
 
     // 构建菜单
     TrayMenu::new()
         .add_item(show)
         .add_item(new_note)
+        .add_item(lock)
         .add_native_item(TrayMenuItem::Separator)
         .add_item(settings)
+        .add_item(last_backup)
         .add_native_item(TrayMenuItem::Separator)
         .add_item(quit)
 }
@@ -28,13 +38,28 @@ pub fn create_tray() -> Tray {
     Tray::new().with_menu(tray_menu)
 }
 
+/// 更新托盘菜单中显示的上次备份时间
+pub fn update_last_backup<R: Runtime>(app: &AppHandle<R>, last_success: Option<i64>) {
+    if let Err(e) = app.tray_handle().get_item("last_backup").set_title(last_backup_title(last_success)) {
+        log::warn!("更新托盘菜单失败: {}", e);
+    }
+}
+
+fn last_backup_title(last_success: Option<i64>) -> String {
+    match last_success.and_then(|ts| chrono::Local.timestamp_opt(ts, 0).single()) {
+        Some(time) => format!("上次备份：{}", time.format("%Y-%m-%d %H:%M")),
+        None => "上次备份：从未".to_string(),
+    }
+}
+
 /// 处理托盘事件
 pub fn handle_tray_event(app: &AppHandle, event: TrayEvent) {
     match event {
         // 托盘菜单项点击
         TrayEvent::MenuItemClick { id, .. } => match id.as_str() {
             "quit" => {
-                // 退出应用
+                // 保存便签窗口位置后退出应用，打开的便签窗口下次启动时恢复
+                note_window::save_open_windows(app);
                 std::process::exit(0);
             }
             "show" => {
@@ -56,6 +81,12 @@ pub fn handle_tray_event(app: &AppHandle, event: TrayEvent) {
                 // 创建新便签
                 create_note_window(app);
             }
+            "lock" => {
+                // 锁定应用
+                if let Err(e) = lock::lock(app) {
+                    log::warn!("锁定失败: {}", e);
+                }
+            }
             "settings" => {
                 // 打开设置窗口
                 open_settings_window(app);
@@ -102,23 +133,14 @@ fn create_main_window(app: &AppHandle) {
     }
 }
 
-// 创建新便签窗口
+// 新建空白便签并在浮动窗口中打开
 fn create_note_window(app: &AppHandle) {
-    let label = format!("note_{}", chrono::Utc::now().timestamp_millis());
-    
-    tauri::WindowBuilder::new(
-        app,
-        &label,
-        tauri::WindowUrl::App("note.html".into()),
-    )
-    .title("新便签")
-    .inner_size(300.0, 300.0)
-    .resizable(true)
-    .decorations(false)
-    .always_on_top(true)
-    .transparent(true)
-    .build()
-    .unwrap();
+    let app = app.clone();
+    tauri::async_runtime::spawn(async move {
+        if let Err(e) = note_window::open_new_note(app).await {
+            log::error!("创建便签窗口失败: {}", e);
+        }
+    });
 }
 
 // 打开设置窗口
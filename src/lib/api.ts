@@ -26,11 +26,6 @@ export interface Category {
     color?: string;
 }
 
-// 数据库初始化
-export async function initDatabase(): Promise<boolean> {
-    return invoke('init_database');
-}
-
 // 便签相关 API
 export async function createNote(
     content: string, 
@@ -8,6 +8,7 @@ export interface Note {
     screenshot_path: string | null;
     created_at: number;
     updated_at?: number;
+    is_pinned?: boolean;
     color?: string;
     category_id?: number;
     deleted_at?: number;
@@ -37,7 +38,7 @@ export const notesApi = {
     // 获取所有便签
     getAllNotes: async (): Promise<Note[]> => {
         try {
-            return await invoke<Note[]>("get_notes");
+            return await invoke<Note[]>("get_notes_sorted");
         } catch (error) {
             console.error('获取便签列表失败:', error);
             return [];
@@ -47,7 +48,12 @@ export const notesApi = {
     // 添加便签
     addNote: async (note: Omit<Note, 'id'>): Promise<Note | null> => {
         try {
-            return await invoke<Note>("add_note", { note });
+            const id = await invoke<number>("create_note", {
+                content: note.content,
+                screenshotPath: note.screenshot_path,
+                categoryId: note.category_id,
+            });
+            return await invoke<Note | null>("get_note_by_id", { id });
         } catch (error) {
             console.error('创建便签失败:', error);
             return null;
@@ -57,7 +63,14 @@ export const notesApi = {
     // 更新便签
     updateNote: async (note: Note): Promise<boolean> => {
         try {
-            await invoke("update_note", { note });
+            await invoke("update_note", {
+                id: note.id,
+                content: note.content,
+                screenshotPath: note.screenshot_path,
+                isPinned: note.is_pinned ?? false,
+                color: note.color,
+                categoryId: note.category_id,
+            });
             return true;
         } catch (error) {
             console.error('更新便签失败:', error);
@@ -65,10 +78,10 @@ export const notesApi = {
         }
     },
 
-    // 删除便签
+    // 删除便签（移入回收站）
     deleteNote: async (noteId: number): Promise<boolean> => {
         try {
-            await invoke("delete_note", { noteId });
+            await invoke("move_notes_to_trash", { ids: [noteId] });
             return true;
         } catch (error) {
             console.error('删除便签失败:', error);
@@ -79,7 +92,8 @@ export const notesApi = {
     // 搜索便签
     searchNotes: async (query: string): Promise<Note[]> => {
         try {
-            return await invoke<Note[]>("search_notes", { query });
+            const results = await invoke<{ note: Note }[]>("search_notes", { query });
+            return results.map((result) => result.note);
         } catch (error) {
             console.error('搜索便签失败:', error);
             return [];